@@ -0,0 +1,231 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use psst_core::{
+    error::Error,
+    player::{PlaybackConfig, PlayerEvent},
+    session::SessionService,
+};
+use serde_json::{json, Value};
+
+use crate::rpc::{self, Call, Request, Response, RpcError};
+
+/// Headless control server.  Accepts line-delimited JSON-RPC 2.0 requests on a
+/// Unix socket, translates them into player commands, and streams player
+/// events to every connection that called `subscribe`.
+pub struct Daemon {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+struct Subscriber {
+    connection_id: u64,
+    sender: Sender<String>,
+}
+
+struct Shared {
+    session: SessionService,
+    player: Sender<PlayerEvent>,
+    config: Mutex<PlaybackConfig>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Daemon {
+    pub fn bind(
+        socket_path: &Path,
+        session: SessionService,
+        player: Sender<PlayerEvent>,
+        config: PlaybackConfig,
+    ) -> Result<Self, Error> {
+        remove_stale_socket(socket_path)?;
+        let listener = UnixListener::bind(socket_path)?;
+        log::info!("listening on {:?}", socket_path);
+
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::new(Shared {
+            session,
+            player,
+            config: Mutex::new(config),
+            subscribers: subscribers.clone(),
+        });
+        thread::spawn(move || accept_connections(listener, shared));
+
+        Ok(Self { subscribers })
+    }
+
+    /// Send `event` to all subscribed connections, dropping the ones that have
+    /// gone away.
+    pub fn publish(&self, event: &PlayerEvent) {
+        let mut subscribers = rpc::lock(&self.subscribers);
+        if subscribers.is_empty() {
+            return;
+        }
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "event",
+            "params": rpc::event_params(event),
+        })
+        .to_string();
+        subscribers.retain(|sub| sub.sender.send(notification.clone()).is_ok());
+    }
+}
+
+/// A socket file left behind by a previous, crashed instance would make the
+/// bind fail, so remove it.  Refuse to touch anything that is not a socket, or
+/// a socket another instance is still listening on.
+fn remove_stale_socket(socket_path: &Path) -> Result<(), Error> {
+    let metadata = match fs::symlink_metadata(socket_path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(Error::ConfigError(format!(
+            "{:?} exists and is not a socket",
+            socket_path
+        )));
+    }
+    if UnixStream::connect(socket_path).is_ok() {
+        return Err(Error::ConfigError(format!(
+            "{:?} is in use by another instance",
+            socket_path
+        )));
+    }
+    fs::remove_file(socket_path)?;
+    Ok(())
+}
+
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join("psst.sock")
+}
+
+fn accept_connections(listener: UnixListener, shared: Arc<Shared>) {
+    static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let connection_id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve_connection(connection_id, stream, &shared) {
+                        log::warn!("connection {} failed: {}", connection_id, err);
+                    }
+                    unsubscribe(&shared, connection_id);
+                });
+            }
+            Err(err) => {
+                log::error!("failed to accept connection: {}", err);
+            }
+        }
+    }
+}
+
+fn serve_connection(connection_id: u64, stream: UnixStream, shared: &Shared) -> io::Result<()> {
+    log::info!("connection {} opened", connection_id);
+
+    // Responses and event notifications are interleaved on the same stream, so
+    // all writes go through a single writer thread.
+    let (out_send, out_recv) = unbounded();
+    let writer = stream.try_clone()?;
+    thread::spawn(move || write_lines(writer, out_recv));
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = handle_line(connection_id, &line, shared, &out_send) {
+            let response = serde_json::to_string(&response).expect("response is serializable");
+            if out_send.send(response).is_err() {
+                break;
+            }
+        }
+    }
+
+    log::info!("connection {} closed", connection_id);
+    Ok(())
+}
+
+fn write_lines(mut stream: UnixStream, lines: Receiver<String>) {
+    for line in lines {
+        if writeln!(stream, "{}", line).is_err() {
+            break;
+        }
+    }
+}
+
+fn handle_line(
+    connection_id: u64,
+    line: &str,
+    shared: &Shared,
+    out_send: &Sender<String>,
+) -> Option<Response> {
+    let request: Request = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(err) => {
+            return Some(Response::error(
+                Value::Null,
+                RpcError::new(RpcError::PARSE_ERROR, err),
+            ));
+        }
+    };
+    let result = match rpc::parse_call(&request, &shared.session, &shared.config) {
+        Ok(Call::Commands(commands)) => {
+            for command in commands {
+                if shared.player.send(PlayerEvent::Command(command)).is_err() {
+                    return request.id.map(|id| {
+                        Response::error(
+                            id,
+                            RpcError::new(RpcError::INTERNAL_ERROR, "player has shut down"),
+                        )
+                    });
+                }
+            }
+            Ok(Value::Null)
+        }
+        Ok(Call::Subscribe) => {
+            let mut subscribers = rpc::lock(&shared.subscribers);
+            if !subscribers
+                .iter()
+                .any(|sub| sub.connection_id == connection_id)
+            {
+                subscribers.push(Subscriber {
+                    connection_id,
+                    sender: out_send.clone(),
+                });
+            }
+            Ok(Value::Bool(true))
+        }
+        Ok(Call::Unsubscribe) => {
+            unsubscribe(shared, connection_id);
+            Ok(Value::Bool(true))
+        }
+        Ok(Call::Reply(value)) => Ok(value),
+        Err(err) => Err(err),
+    };
+    let id = request.id?;
+    Some(match result {
+        Ok(value) => Response::result(id, value),
+        Err(err) => Response::error(id, err),
+    })
+}
+
+fn unsubscribe(shared: &Shared, connection_id: u64) {
+    rpc::lock(&shared.subscribers).retain(|sub| sub.connection_id != connection_id);
+}
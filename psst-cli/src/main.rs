@@ -1,111 +1,328 @@
+#[cfg(unix)]
+mod daemon;
+#[cfg(target_os = "linux")]
+mod mpris;
+#[cfg(unix)]
+mod rpc;
+
 use psst_core::{
     audio::{
-        normalize::NormalizationLevel,
-        output::{AudioOutput, AudioSink, DefaultAudioOutput},
+        limiter::PeakLimiting,
+        mix::ChannelMixSettings,
+        output::{
+            file::{FileConfig, FileFormat, FileSampleFormat},
+            AudioOutput, AudioSink, DefaultAudioOutput, OutputBackend, OutputConfig,
+        },
+        silence::SilenceTrimming,
     },
-    cache::{Cache, CacheHandle},
-    cdn::{Cdn, CdnHandle},
+    cache::Cache,
+    cdn::Cdn,
+    connect::{transport::MercuryTransport, Connect, ConnectConfig},
     connection::Credentials,
     error::Error,
-    item_id::{ItemId, ItemIdType},
-    player::{item::PlaybackItem, PlaybackConfig, Player, PlayerCommand, PlayerEvent},
+    player::{persist::SavedPlayback, PlaybackConfig, Player, PlayerCommand, PlayerEvent},
     session::{SessionConfig, SessionService},
 };
-use std::{env, io, io::BufRead, path::PathBuf, thread};
+use std::{env, path::PathBuf, process, sync::Arc, time::Duration};
+
+struct Args {
+    socket_path: Option<PathBuf>,
+    cache_path: PathBuf,
+    device_name: String,
+    output: OutputConfig,
+    restore_path: Option<PathBuf>,
+    resume_tracks_longer_than: Option<Duration>,
+    trim_silence: bool,
+    channel_mix: ChannelMixSettings,
+    pregain: Option<f32>,
+    limit_peaks: Option<PeakLimiting>,
+    uris: Vec<String>,
+}
+
+const USAGE: &str = "\
+Usage: psst-cli [OPTIONS] [SPOTIFY_URI]...
+
+Plays the given track, episode, album or playlist URIs and listens for
+JSON-RPC requests on a control socket.  Credentials are read from the
+SPOTIFY_USERNAME and SPOTIFY_PASSWORD environment variables.
+
+Options:
+  --socket <path>           Control socket [default: $XDG_RUNTIME_DIR/psst.sock]
+  --cache <path>            Cache directory [default: cache]
+  --name <name>             Spotify Connect device name [default: Psst]
+  --restore <path>          Continue from a playback snapshot saved by the GUI
+  --resume-tracks <min>     Also resume tracks at least this many minutes long
+  --trim-silence            Shorten long pauses
+  --mono                    Play the same downmix on every speaker
+  --balance <-1.0..1.0>     Quiet down the right or the left side
+  --pregain <dB>            Normalization pregain [default: 3]
+  --ceiling <dB>            Peak limiter ceiling [default: -1]
+  --no-limiter              Lower the gain of loud tracks instead of limiting
+  --output-device <name>    Play on this audio device
+  --bit-perfect             Run the device at the sample rate of each track
+  --no-dither               Do not dither for devices with integer samples
+  --null-output             Play into the void, in real time
+  --file-output <path>      Write a WAV file, `-` for stdout
+  --file-format <wav|raw>   Format of the file output [default: wav]
+  --file-samples <s16|f32>  Sample format of the file output [default: s16]
+  --file-rate <hz>          Sample rate of the file output
+  -h, --help                Print this help
+";
+
+impl Args {
+    /// Parse the command line.  `Ok(None)` means the help was requested.
+    fn parse() -> Result<Option<Self>, String> {
+        let mut args = Args {
+            socket_path: None,
+            cache_path: PathBuf::from("cache"),
+            device_name: "Psst".to_string(),
+            output: OutputConfig::default(),
+            restore_path: None,
+            resume_tracks_longer_than: None,
+            trim_silence: false,
+            channel_mix: ChannelMixSettings::default(),
+            pregain: None,
+            limit_peaks: Some(PeakLimiting::default()),
+            uris: Vec::new(),
+        };
+        let mut iter = env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = |expected: &str| {
+                iter.next()
+                    .ok_or_else(|| format!("expected {} after {}", expected, arg))
+            };
+            let invalid = |expected: &str, value: &str| {
+                format!("expected {} after {}, got {:?}", expected, arg, value)
+            };
+            match arg.as_str() {
+                "-h" | "--help" => {
+                    return Ok(None);
+                }
+                "--socket" => {
+                    args.socket_path = Some(value("a path")?.into());
+                }
+                "--cache" => {
+                    args.cache_path = value("a path")?.into();
+                }
+                "--restore" => {
+                    args.restore_path = Some(value("a path")?.into());
+                }
+                "--resume-tracks" => {
+                    let minutes = value("minutes")?;
+                    let minutes: u64 = minutes.parse().map_err(|_| invalid("minutes", &minutes))?;
+                    args.resume_tracks_longer_than = Some(Duration::from_secs(minutes * 60));
+                }
+                "--no-dither" => {
+                    args.output.dither = false;
+                }
+                "--bit-perfect" => {
+                    args.output.bit_perfect = true;
+                }
+                "--trim-silence" => {
+                    args.trim_silence = true;
+                }
+                "--mono" => {
+                    args.channel_mix.mono = true;
+                }
+                "--balance" => {
+                    let balance = value("a balance in -1.0..=1.0")?;
+                    args.channel_mix.balance = balance
+                        .parse()
+                        .ok()
+                        .filter(|balance| (-1.0..=1.0).contains(balance))
+                        .ok_or_else(|| invalid("a balance in -1.0..=1.0", &balance))?;
+                }
+                "--pregain" => {
+                    let pregain = value("dB")?;
+                    args.pregain = Some(pregain.parse().map_err(|_| invalid("dB", &pregain))?);
+                }
+                "--ceiling" => {
+                    let ceiling = value("negative dB")?;
+                    let ceiling_db = ceiling
+                        .parse()
+                        .ok()
+                        .filter(|&ceiling: &f32| ceiling <= 0.0)
+                        .ok_or_else(|| invalid("negative dB", &ceiling))?;
+                    args.limit_peaks = Some(PeakLimiting { ceiling_db });
+                }
+                "--no-limiter" => {
+                    args.limit_peaks = None;
+                }
+                "--name" => {
+                    args.device_name = value("a name")?;
+                }
+                "--output-device" => {
+                    args.output.device = Some(value("a device name")?);
+                }
+                "--null-output" => {
+                    args.output.backend = OutputBackend::Null;
+                }
+                "--file-output" => {
+                    let path = value("a path")?;
+                    args.file_output().path = (path != "-").then(|| path.into());
+                }
+                "--file-format" => {
+                    let format = value("wav or raw")?;
+                    args.file_output().format = match format.as_str() {
+                        "wav" => FileFormat::Wav,
+                        "raw" => FileFormat::Raw,
+                        _ => return Err(invalid("wav or raw", &format)),
+                    };
+                }
+                "--file-samples" => {
+                    let samples = value("s16 or f32")?;
+                    args.file_output().sample_format = match samples.as_str() {
+                        "s16" => FileSampleFormat::S16,
+                        "f32" => FileSampleFormat::F32,
+                        _ => return Err(invalid("s16 or f32", &samples)),
+                    };
+                }
+                "--file-rate" => {
+                    let rate = value("a sample rate")?;
+                    args.file_output().sample_rate =
+                        rate.parse().map_err(|_| invalid("a sample rate", &rate))?;
+                }
+                _ if arg.starts_with('-') => {
+                    return Err(format!("unknown option {}", arg));
+                }
+                _ => args.uris.push(arg),
+            }
+        }
+        Ok(Some(args))
+    }
+
+    /// Switch to the file output, if not yet, and return its config.
+    fn file_output(&mut self) -> &mut FileConfig {
+        if !matches!(self.output.backend, OutputBackend::File(_)) {
+            self.output.backend = OutputBackend::File(FileConfig::default());
+        }
+        match &mut self.output.backend {
+            OutputBackend::File(file) => file,
+            _ => unreachable!(),
+        }
+    }
+}
 
 fn main() {
     env_logger::init();
 
-    let args: Vec<String> = env::args().collect();
-    let track_id = args
-        .get(1)
-        .expect("Expected <track_id> in the first parameter");
-    let login_creds = Credentials::from_username_and_password(
-        env::var("SPOTIFY_USERNAME").unwrap(),
-        env::var("SPOTIFY_PASSWORD").unwrap(),
-    );
+    let args = match Args::parse() {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    let (Ok(username), Ok(password)) = (env::var("SPOTIFY_USERNAME"), env::var("SPOTIFY_PASSWORD"))
+    else {
+        eprintln!("error: SPOTIFY_USERNAME and SPOTIFY_PASSWORD must be set");
+        process::exit(2);
+    };
+    let login_creds = Credentials::from_username_and_password(username, password);
     let session = SessionService::with_config(SessionConfig {
         login_creds,
         proxy_url: None,
     });
 
-    start(track_id, session).unwrap();
+    if let Err(err) = start(args, session) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 }
 
-fn start(track_id: &str, session: SessionService) -> Result<(), Error> {
+#[cfg(unix)]
+fn start(args: Args, session: SessionService) -> Result<(), Error> {
     let cdn = Cdn::new(session.clone(), None)?;
-    let cache = Cache::new(PathBuf::from("cache"))?;
-    let item_id = ItemId::from_base62(track_id, ItemIdType::Track).unwrap();
-    play_item(
-        session,
-        cdn,
-        cache,
-        PlaybackItem {
-            item_id,
-            norm_level: NormalizationLevel::Track,
-        },
-    )
-}
+    let cache = Cache::new(args.cache_path)?;
+    let output = DefaultAudioOutput::open(args.output)?;
+    let mut config = PlaybackConfig {
+        resume_tracks_longer_than: args.resume_tracks_longer_than,
+        trim_silence: args.trim_silence.then(SilenceTrimming::default),
+        channel_mix: args.channel_mix,
+        limit_peaks: args.limit_peaks,
+        ..PlaybackConfig::default()
+    };
+    if let Some(pregain) = args.pregain {
+        config.pregain = pregain;
+    }
 
-fn play_item(
-    session: SessionService,
-    cdn: CdnHandle,
-    cache: CacheHandle,
-    item: PlaybackItem,
-) -> Result<(), Error> {
-    let output = DefaultAudioOutput::open()?;
-    let config = PlaybackConfig::default();
+    let mut player = Player::new(session.clone(), cdn, cache, config.clone(), &output);
 
-    let mut player = Player::new(session, cdn, cache, config, &output);
+    let socket_path = args.socket_path.unwrap_or_else(daemon::default_socket_path);
+    let daemon = daemon::Daemon::bind(&socket_path, session.clone(), player.sender(), config)?;
 
-    let _ui_thread = thread::spawn({
-        let player_sender = player.sender();
+    #[cfg(target_os = "linux")]
+    let mpris = mpris::Mpris::connect(session.clone(), player.sender())
+        .map_err(|err| log::error!("failed to register MPRIS interface: {}", err))
+        .ok();
 
-        player_sender
-            .send(PlayerEvent::Command(PlayerCommand::LoadQueue {
-                items: vec![item, item, item],
-                position: 0,
-            }))
-            .unwrap();
+    let connect = Connect::start(
+        ConnectConfig::new(args.device_name),
+        Arc::new(MercuryTransport::new(session.clone())),
+        player.sender(),
+    );
 
-        move || {
-            for line in io::stdin().lock().lines() {
-                match line.as_ref().map(|s| s.as_str()) {
-                    Ok("p") => {
-                        player_sender
-                            .send(PlayerEvent::Command(PlayerCommand::Pause))
-                            .unwrap();
-                    }
-                    Ok("r") => {
-                        player_sender
-                            .send(PlayerEvent::Command(PlayerCommand::Resume))
-                            .unwrap();
-                    }
-                    Ok("s") => {
-                        player_sender
-                            .send(PlayerEvent::Command(PlayerCommand::Stop))
-                            .unwrap();
-                    }
-                    Ok("<") => {
-                        player_sender
-                            .send(PlayerEvent::Command(PlayerCommand::Previous))
-                            .unwrap();
-                    }
-                    Ok(">") => {
-                        player_sender
-                            .send(PlayerEvent::Command(PlayerCommand::Next))
-                            .unwrap();
-                    }
-                    _ => log::warn!("unknown command"),
+    if let Some(path) = &args.restore_path {
+        match SavedPlayback::load(path) {
+            Ok(Some(saved)) => {
+                let (items, position) = saved.playback_items();
+                let commands = [
+                    PlayerCommand::SetQueueBehavior {
+                        behavior: saved.behavior,
+                    },
+                    PlayerCommand::Restore {
+                        items,
+                        position,
+                        progress: saved.progress(),
+                    },
+                ];
+                for command in commands {
+                    player.sender().send(PlayerEvent::Command(command)).unwrap();
                 }
             }
+            Ok(None) => log::info!("no saved playback at {:?}", path),
+            Err(err) => log::error!("failed to restore playback from {:?}: {}", path, err),
         }
-    });
+    }
+
+    if !args.uris.is_empty() {
+        let mut items = Vec::new();
+        for uri in &args.uris {
+            match rpc::resolve_uri(uri, &session) {
+                Ok(resolved) => items.extend(resolved),
+                Err(err) => log::error!("skipping {}: {}", uri, serde_json::json!(err)),
+            }
+        }
+        player
+            .sender()
+            .send(PlayerEvent::Command(PlayerCommand::LoadQueue {
+                items,
+                position: 0,
+            }))
+            .unwrap();
+    }
 
     for event in player.receiver() {
+        daemon.publish(&event);
+        connect.publish(&event);
+        #[cfg(target_os = "linux")]
+        if let Some(mpris) = &mpris {
+            mpris.publish(&event);
+        }
         player.handle(event);
     }
     output.sink().close();
 
     Ok(())
 }
+
+#[cfg(not(unix))]
+fn start(_args: Args, _session: SessionService) -> Result<(), Error> {
+    Err(Error::ConfigError(
+        "The control socket is only supported on Unix platforms".to_string(),
+    ))
+}
@@ -0,0 +1,657 @@
+use std::{collections::HashMap, thread, time::Duration};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use psst_core::{
+    audio::stretch,
+    item_id::{FileId, ItemId, ItemIdType},
+    metadata::Fetch,
+    player::{PlayerCommand, PlayerEvent},
+    protocol::metadata::{Episode, ImageGroup, Track},
+    session::SessionService,
+};
+use zbus::{
+    blocking::{Connection, ConnectionBuilder},
+    dbus_interface,
+    zvariant::{ObjectPath, OwnedValue, Value},
+    InterfaceRef, SignalContext,
+};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.psst";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// MPRIS2 server exposing the player on the D-Bus session bus.  Player events
+/// are handed over with `publish` and applied on a separate thread, because
+/// resolving track metadata might block on the network.
+pub struct Mpris {
+    update_send: Sender<Update>,
+    _connection: Connection,
+}
+
+enum Update {
+    Playing {
+        item_id: ItemId,
+        duration: Duration,
+        position: Duration,
+    },
+    Paused {
+        position: Duration,
+    },
+    Resumed {
+        position: Duration,
+    },
+    Position {
+        position: Duration,
+    },
+    Seeked {
+        position: Duration,
+    },
+    Volume {
+        volume: f64,
+    },
+    Rate {
+        rate: f64,
+    },
+    Stopped,
+}
+
+impl Mpris {
+    /// Register on the user's session bus.
+    pub fn connect(
+        session: SessionService,
+        player: Sender<PlayerEvent>,
+    ) -> Result<Self, zbus::Error> {
+        Self::serve(ConnectionBuilder::session()?, session, player)
+    }
+
+    /// Register on the bus at `address`.  Mainly useful to run against a
+    /// private `dbus-daemon` in tests.
+    pub fn connect_to(
+        address: &str,
+        session: SessionService,
+        player: Sender<PlayerEvent>,
+    ) -> Result<Self, zbus::Error> {
+        Self::serve(ConnectionBuilder::address(address)?, session, player)
+    }
+
+    fn serve(
+        builder: ConnectionBuilder,
+        session: SessionService,
+        player: Sender<PlayerEvent>,
+    ) -> Result<Self, zbus::Error> {
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, MediaPlayer2)?
+            .serve_at(OBJECT_PATH, MediaPlayer2Player::new(player))?
+            .build()?;
+        let iface = connection
+            .object_server()
+            .interface::<_, MediaPlayer2Player>(OBJECT_PATH)?;
+
+        let (update_send, update_recv) = unbounded();
+        thread::spawn(move || apply_updates(update_recv, iface, session));
+
+        Ok(Self {
+            update_send,
+            _connection: connection,
+        })
+    }
+
+    pub fn publish(&self, event: &PlayerEvent) {
+        let update = match event {
+            PlayerEvent::Playing { path, position } => Update::Playing {
+                item_id: path.item_id,
+                duration: path.duration,
+                position: *position,
+            },
+            PlayerEvent::Pausing { position, .. } => Update::Paused {
+                position: *position,
+            },
+            PlayerEvent::Resuming { position, .. } => Update::Resumed {
+                position: *position,
+            },
+            PlayerEvent::Position { position, .. } => Update::Position {
+                position: *position,
+            },
+            PlayerEvent::Command(PlayerCommand::Seek { position }) => Update::Seeked {
+                position: *position,
+            },
+            PlayerEvent::Command(PlayerCommand::SetVolume { volume }) => {
+                Update::Volume { volume: *volume }
+            }
+            PlayerEvent::Command(PlayerCommand::SetSpeed { speed }) => Update::Rate {
+                rate: speed.clamp(stretch::MIN_SPEED, stretch::MAX_SPEED).into(),
+            },
+            PlayerEvent::Stopped => Update::Stopped,
+            _ => return,
+        };
+        self.update_send.send(update).ok();
+    }
+}
+
+fn apply_updates(
+    updates: Receiver<Update>,
+    iface: InterfaceRef<MediaPlayer2Player>,
+    session: SessionService,
+) {
+    for update in updates {
+        // Resolve the metadata of a new track before locking the interface, so
+        // incoming D-Bus calls are not blocked on the network.
+        let metadata = match update {
+            Update::Playing {
+                item_id, duration, ..
+            } if zbus::block_on(iface.get()).item_id != Some(item_id) => {
+                Some(track_metadata(&session, item_id, duration))
+            }
+            _ => None,
+        };
+        let result = zbus::block_on(async {
+            let mut player = iface.get_mut().await;
+            let ctxt = iface.signal_context();
+            match update {
+                Update::Playing {
+                    item_id, position, ..
+                } => {
+                    if let Some(metadata) = metadata {
+                        player.item_id = Some(item_id);
+                        player.metadata = metadata;
+                        player.metadata_changed(ctxt).await?;
+                    }
+                    player.position = position;
+                    player.status = "Playing";
+                    player.playback_status_changed(ctxt).await?;
+                }
+                Update::Paused { position } => {
+                    player.position = position;
+                    player.status = "Paused";
+                    player.playback_status_changed(ctxt).await?;
+                }
+                Update::Resumed { position } => {
+                    player.position = position;
+                    player.status = "Playing";
+                    player.playback_status_changed(ctxt).await?;
+                }
+                Update::Position { position } => {
+                    // MPRIS clients interpolate the position themselves, no
+                    // signal is expected here.
+                    player.position = position;
+                }
+                Update::Seeked { position } => {
+                    player.position = position;
+                    MediaPlayer2Player::seeked(ctxt, micros(position)).await?;
+                }
+                Update::Volume { volume } => {
+                    player.volume = volume;
+                    player.volume_changed(ctxt).await?;
+                }
+                Update::Rate { rate } => {
+                    player.rate = rate;
+                    player.rate_changed(ctxt).await?;
+                }
+                Update::Stopped => {
+                    player.item_id = None;
+                    player.metadata = HashMap::new();
+                    player.position = Duration::ZERO;
+                    player.status = "Stopped";
+                    player.metadata_changed(ctxt).await?;
+                    player.playback_status_changed(ctxt).await?;
+                }
+            }
+            Ok::<_, zbus::Error>(())
+        });
+        if let Err(err) = result {
+            log::error!("failed to update MPRIS state: {}", err);
+        }
+    }
+}
+
+struct MediaPlayer2;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer2 {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "Psst"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        vec!["spotify".to_string()]
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct MediaPlayer2Player {
+    player: Sender<PlayerEvent>,
+    item_id: Option<ItemId>,
+    metadata: HashMap<String, OwnedValue>,
+    status: &'static str,
+    position: Duration,
+    volume: f64,
+    rate: f64,
+}
+
+impl MediaPlayer2Player {
+    fn new(player: Sender<PlayerEvent>) -> Self {
+        Self {
+            player,
+            item_id: None,
+            metadata: HashMap::new(),
+            status: "Stopped",
+            position: Duration::ZERO,
+            volume: 1.0,
+            rate: 1.0,
+        }
+    }
+
+    fn send(&self, command: PlayerCommand) -> zbus::fdo::Result<()> {
+        self.player
+            .send(PlayerEvent::Command(command))
+            .map_err(|_| zbus::fdo::Error::Failed("Player has shut down".to_string()))
+    }
+
+    fn current_duration(&self) -> Option<Duration> {
+        let length = self.metadata.get("mpris:length")?.downcast_ref::<i64>()?;
+        Some(Duration::from_micros(*length as u64))
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl MediaPlayer2Player {
+    fn play(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::Resume)
+    }
+
+    fn pause(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::Pause)
+    }
+
+    fn play_pause(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::PauseOrResume)
+    }
+
+    fn stop(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::Stop)
+    }
+
+    fn next(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::Next)
+    }
+
+    fn previous(&self) -> zbus::fdo::Result<()> {
+        self.send(PlayerCommand::Previous)
+    }
+
+    /// Seek relative to the current position, `offset` is in microseconds.
+    fn seek(&self, offset: i64) -> zbus::fdo::Result<()> {
+        if self.item_id.is_none() {
+            return Ok(());
+        }
+        let position = micros(self.position).saturating_add(offset).max(0);
+        let position = Duration::from_micros(position as u64);
+        match self.current_duration() {
+            // Seeking past the end behaves like `Next`, as the spec requires.
+            Some(duration) if position >= duration => self.send(PlayerCommand::Next),
+            _ => self.send(PlayerCommand::Seek { position }),
+        }
+    }
+
+    /// Seek to an absolute position, ignored if `track_id` is not the current
+    /// track.
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> zbus::fdo::Result<()> {
+        let Some(item_id) = self.item_id else {
+            return Ok(());
+        };
+        if track_id.as_str() != track_path(item_id) || position < 0 {
+            return Ok(());
+        }
+        let position = Duration::from_micros(position as u64);
+        if self
+            .current_duration()
+            .is_some_and(|duration| position > duration)
+        {
+            return Ok(());
+        }
+        self.send(PlayerCommand::Seek { position })
+    }
+
+    fn open_uri(&self, _uri: &str) -> zbus::fdo::Result<()> {
+        Err(zbus::fdo::Error::NotSupported(
+            "Use the control socket to load items".to_string(),
+        ))
+    }
+
+    #[dbus_interface(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        self.status
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    #[dbus_interface(property)]
+    fn set_rate(&mut self, rate: f64) -> zbus::fdo::Result<()> {
+        if !rate.is_finite() {
+            return Err(zbus::fdo::Error::InvalidArgs(
+                "Rate must be finite".to_string(),
+            ));
+        }
+        // The spec asks to pause on a rate of zero.  Otherwise the new value is
+        // published once the player confirms the command.
+        if rate == 0.0 {
+            self.send(PlayerCommand::Pause)
+        } else {
+            self.send(PlayerCommand::SetSpeed {
+                speed: (rate as f32).clamp(stretch::MIN_SPEED, stretch::MAX_SPEED),
+            })
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        self.metadata.clone()
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+
+    #[dbus_interface(property)]
+    fn set_volume(&mut self, volume: f64) -> zbus::fdo::Result<()> {
+        // The new value is published once the player confirms the command.
+        self.send(PlayerCommand::SetVolume {
+            volume: volume.clamp(0.0, 1.0),
+        })
+    }
+
+    #[dbus_interface(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        micros(self.position)
+    }
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        stretch::MIN_SPEED.into()
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        stretch::MAX_SPEED.into()
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn micros(duration: Duration) -> i64 {
+    duration.as_micros() as i64
+}
+
+fn track_path(item_id: ItemId) -> String {
+    format!("/org/psst/track/{}", item_id.to_base62())
+}
+
+fn track_metadata(
+    session: &SessionService,
+    item_id: ItemId,
+    duration: Duration,
+) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    let mut insert = |key: &str, value: Value| {
+        metadata.insert(key.to_string(), OwnedValue::from(value));
+    };
+    insert(
+        "mpris:trackid",
+        ObjectPath::try_from(track_path(item_id))
+            .expect("base62 ids are valid path elements")
+            .into(),
+    );
+    insert("mpris:length", micros(duration).into());
+    if let Some(uri) = item_id.to_uri() {
+        insert("xesam:url", uri.into());
+    }
+
+    match item_id.id_type {
+        ItemIdType::Track => match Track::fetch(session, item_id) {
+            Ok(track) => {
+                if let Some(name) = track.name {
+                    insert("xesam:title", name.into());
+                }
+                let artists: Vec<String> =
+                    track.artist.into_iter().filter_map(|a| a.name).collect();
+                insert("xesam:artist", artists.into());
+                if let Some(album) = track.album {
+                    if let Some(name) = album.name {
+                        insert("xesam:album", name.into());
+                    }
+                    if let Some(url) = album.cover_group.as_ref().and_then(image_url) {
+                        insert("mpris:artUrl", url.into());
+                    }
+                }
+                if let Some(number) = track.number {
+                    insert("xesam:trackNumber", number.into());
+                }
+                if let Some(disc_number) = track.disc_number {
+                    insert("xesam:discNumber", disc_number.into());
+                }
+            }
+            Err(err) => log::warn!("failed to fetch track metadata: {}", err),
+        },
+        ItemIdType::Podcast => match Episode::fetch(session, item_id) {
+            Ok(episode) => {
+                if let Some(name) = episode.name {
+                    insert("xesam:title", name.into());
+                }
+                if let Some(url) = episode.covers.as_ref().and_then(image_url) {
+                    insert("mpris:artUrl", url.into());
+                }
+            }
+            Err(err) => log::warn!("failed to fetch episode metadata: {}", err),
+        },
+        _ => {}
+    }
+    metadata
+}
+
+/// Image groups are ordered from the smallest to the largest size, pick the
+/// largest one.
+fn image_url(group: &ImageGroup) -> Option<String> {
+    let file_id = group.image.last()?.file_id.as_ref()?;
+    let file_id = FileId::from_raw(file_id)?;
+    Some(format!("https://i.scdn.co/image/{}", file_id.to_base16()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    use psst_core::{connection::Credentials, session::SessionConfig};
+    use zbus::{
+        blocking::{fdo::PropertiesProxy, Proxy},
+        names::InterfaceName,
+    };
+
+    use super::*;
+
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn spawn() -> Self {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon is required for this test");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.as_mut().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Self {
+                daemon,
+                address: address.trim().to_string(),
+            }
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            self.daemon.kill().ok();
+        }
+    }
+
+    fn offline_session() -> SessionService {
+        SessionService::with_config(SessionConfig {
+            login_creds: Credentials::from_username_and_password(String::new(), String::new()),
+            proxy_url: None,
+        })
+    }
+
+    #[test]
+    #[ignore = "requires dbus-daemon"]
+    fn methods_map_to_player_commands() {
+        let bus = PrivateBus::spawn();
+        let (player_send, player_recv) = unbounded();
+        let _mpris = Mpris::connect_to(&bus.address, offline_session(), player_send).unwrap();
+
+        let client = ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let proxy = Proxy::new(
+            &client,
+            BUS_NAME,
+            OBJECT_PATH,
+            "org.mpris.MediaPlayer2.Player",
+        )
+        .unwrap();
+
+        proxy.call_method("PlayPause", &()).unwrap();
+        assert!(matches!(
+            player_recv.recv().unwrap(),
+            PlayerEvent::Command(PlayerCommand::PauseOrResume)
+        ));
+        proxy.call_method("Next", &()).unwrap();
+        assert!(matches!(
+            player_recv.recv().unwrap(),
+            PlayerEvent::Command(PlayerCommand::Next)
+        ));
+        proxy.set_property("Volume", 0.5).unwrap();
+        assert!(matches!(
+            player_recv.recv().unwrap(),
+            PlayerEvent::Command(PlayerCommand::SetVolume { volume }) if volume == 0.5
+        ));
+        proxy.set_property("Rate", 1.5).unwrap();
+        assert!(matches!(
+            player_recv.recv().unwrap(),
+            PlayerEvent::Command(PlayerCommand::SetSpeed { speed }) if speed == 1.5
+        ));
+    }
+
+    #[test]
+    #[ignore = "requires dbus-daemon"]
+    fn events_update_properties() {
+        let bus = PrivateBus::spawn();
+        let (player_send, _player_recv) = unbounded();
+        let mpris = Mpris::connect_to(&bus.address, offline_session(), player_send).unwrap();
+
+        let client = ConnectionBuilder::address(bus.address.as_str())
+            .unwrap()
+            .build()
+            .unwrap();
+        let properties = PropertiesProxy::builder(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .build()
+            .unwrap();
+        let interface = InterfaceName::try_from("org.mpris.MediaPlayer2.Player").unwrap();
+        let status = || -> String {
+            properties
+                .get(interface.clone(), "PlaybackStatus")
+                .unwrap()
+                .try_into()
+                .unwrap()
+        };
+
+        assert_eq!(status(), "Stopped");
+        mpris.publish(&PlayerEvent::Stopped);
+        mpris.publish(&PlayerEvent::Command(PlayerCommand::SetVolume {
+            volume: 0.25,
+        }));
+        // Updates are applied asynchronously, wait for the last one to land.
+        for _ in 0..50 {
+            let volume: f64 = properties
+                .get(interface.clone(), "Volume")
+                .unwrap()
+                .try_into()
+                .unwrap();
+            if volume == 0.25 {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("volume was not published");
+    }
+}
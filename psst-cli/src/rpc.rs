@@ -0,0 +1,936 @@
+use std::{
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use psst_core::{
+    audio::{
+        equalizer::{BandKind, EqualizerBand, EqualizerPreset, EqualizerSettings},
+        limiter::PeakLimiting,
+        mix::ChannelMixSettings,
+        normalize::NormalizationLevel,
+        output::DefaultAudioOutput,
+        resample::ResamplingQuality,
+        silence::SilenceTrimming,
+        source::CrossfadeCurve,
+        stretch,
+    },
+    error::Error,
+    item_id::{ItemId, ItemIdType},
+    metadata::{fetch_playlist_items, Fetch},
+    player::{
+        item::PlaybackItem, queue::QueueBehavior, shuffle::ItemGroup, sleep::SleepMode,
+        PlaybackConfig, PlayerCommand, PlayerEvent,
+    },
+    protocol::metadata::Album,
+    session::SessionService,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const MAX_CROSSFADE: Duration = Duration::from_secs(12);
+const DEFAULT_SLEEP_FADE: Duration = Duration::from_secs(30);
+
+/// Incoming JSON-RPC 2.0 message.  Requests without an `id` are treated as
+/// notifications and do not get a response.
+#[derive(Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn result(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: "2.0",
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+
+    pub fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(Self::INVALID_PARAMS, message)
+    }
+}
+
+/// Outcome of a successfully parsed request.
+pub enum Call {
+    /// Send these commands to the player, in order.
+    Commands(Vec<PlayerCommand>),
+    /// Start streaming `PlayerEvent`s to the calling connection.
+    Subscribe,
+    /// Stop streaming `PlayerEvent`s to the calling connection.
+    Unsubscribe,
+    /// Answer right away with this result.
+    Reply(Value),
+}
+
+/// Translate a JSON-RPC method call into player commands.  `config` is the
+/// currently active playback config, `configure` calls are merged into it.  It
+/// is locked only while being read or updated, not while resolving URIs.
+pub fn parse_call(
+    request: &Request,
+    session: &SessionService,
+    config: &Mutex<PlaybackConfig>,
+) -> Result<Call, RpcError> {
+    let commands = match request.method.as_str() {
+        "subscribe" => return Ok(Call::Subscribe),
+        "unsubscribe" => return Ok(Call::Unsubscribe),
+        "load_queue" => {
+            #[derive(Deserialize)]
+            struct Params {
+                uris: Vec<String>,
+                #[serde(default)]
+                position: usize,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut items = Vec::new();
+            for uri in &params.uris {
+                items.extend(resolve_uri(uri, session)?);
+            }
+            vec![PlayerCommand::LoadQueue {
+                items,
+                position: params.position,
+            }]
+        }
+        "restore" => {
+            #[derive(Deserialize)]
+            struct Params {
+                uris: Vec<String>,
+                #[serde(default)]
+                position: usize,
+                #[serde(default)]
+                progress_ms: u64,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut items = Vec::new();
+            for uri in &params.uris {
+                items.extend(resolve_uri(uri, session)?);
+            }
+            vec![PlayerCommand::Restore {
+                items,
+                position: params.position,
+                progress: Duration::from_millis(params.progress_ms),
+            }]
+        }
+        "load_and_play" => {
+            let item = resolve_single_uri(&parse_uri_params(&request.params)?, session)?;
+            vec![PlayerCommand::LoadAndPlay { item }]
+        }
+        "preload" => {
+            let item = resolve_single_uri(&parse_uri_params(&request.params)?, session)?;
+            vec![PlayerCommand::Preload { item }]
+        }
+        "add_to_queue" => resolve_uri(&parse_uri_params(&request.params)?, session)?
+            .into_iter()
+            .map(|item| PlayerCommand::AddToQueue { item })
+            .collect(),
+        // Every item is put in front of the user queue, so add them in reverse
+        // to keep their order.
+        "play_next" => resolve_uri(&parse_uri_params(&request.params)?, session)?
+            .into_iter()
+            .rev()
+            .map(|item| PlayerCommand::PlayNext { item })
+            .collect(),
+        "remove_from_queue" => {
+            #[derive(Deserialize)]
+            struct Params {
+                index: usize,
+            }
+            let params: Params = parse_params(&request.params)?;
+            vec![PlayerCommand::RemoveFromQueue {
+                index: params.index,
+            }]
+        }
+        "move_in_queue" => {
+            #[derive(Deserialize)]
+            struct Params {
+                from: usize,
+                to: usize,
+            }
+            let params: Params = parse_params(&request.params)?;
+            vec![PlayerCommand::MoveInQueue {
+                from: params.from,
+                to: params.to,
+            }]
+        }
+        "clear_queue" => vec![PlayerCommand::ClearQueue],
+        "pause" => vec![PlayerCommand::Pause],
+        "resume" => vec![PlayerCommand::Resume],
+        "pause_or_resume" => vec![PlayerCommand::PauseOrResume],
+        "previous" => vec![PlayerCommand::Previous],
+        "next" => vec![PlayerCommand::Next],
+        "stop" => vec![PlayerCommand::Stop],
+        "seek" => {
+            #[derive(Deserialize)]
+            struct Params {
+                position_ms: u64,
+            }
+            let params: Params = parse_params(&request.params)?;
+            vec![PlayerCommand::Seek {
+                position: Duration::from_millis(params.position_ms),
+            }]
+        }
+        "set_sleep_timer" => {
+            #[derive(Deserialize)]
+            struct Params {
+                after_ms: Option<u64>,
+                at: Option<String>,
+                fade_ms: Option<u64>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mode = match (params.after_ms, params.at.as_deref()) {
+                (Some(after_ms), None) => SleepMode::After(Duration::from_millis(after_ms)),
+                (None, Some("end_of_track")) => SleepMode::EndOfTrack,
+                (None, Some("end_of_queue")) => SleepMode::EndOfQueue,
+                (None, Some(other)) => {
+                    return Err(RpcError::invalid_params(format!(
+                        "unknown sleep timer end: {}",
+                        other
+                    )))
+                }
+                _ => {
+                    return Err(RpcError::invalid_params(
+                        "exactly one of after_ms and at is required",
+                    ))
+                }
+            };
+            vec![PlayerCommand::SetSleepTimer {
+                mode,
+                fade: params
+                    .fade_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_SLEEP_FADE),
+            }]
+        }
+        "cancel_sleep_timer" => vec![PlayerCommand::CancelSleepTimer],
+        "set_loop" => {
+            #[derive(Deserialize)]
+            struct Params {
+                start_ms: u64,
+                end_ms: u64,
+            }
+            let params: Params = parse_params(&request.params)?;
+            if params.start_ms >= params.end_ms {
+                return Err(RpcError::invalid_params("start_ms must be before end_ms"));
+            }
+            vec![PlayerCommand::SetLoop {
+                section: Some(
+                    Duration::from_millis(params.start_ms)..Duration::from_millis(params.end_ms),
+                ),
+            }]
+        }
+        "clear_loop" => vec![PlayerCommand::SetLoop { section: None }],
+        "list_output_devices" => {
+            let devices = DefaultAudioOutput::devices()
+                .map_err(|err| RpcError::new(RpcError::INTERNAL_ERROR, err))?;
+            return Ok(Call::Reply(json!(devices)));
+        }
+        "set_output_device" => {
+            #[derive(Deserialize)]
+            struct Params {
+                /// Name from `list_output_devices`, the default device if
+                /// missing.
+                #[serde(default)]
+                name: Option<String>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            vec![PlayerCommand::SetOutputDevice { name: params.name }]
+        }
+        "set_volume" => {
+            #[derive(Deserialize)]
+            struct Params {
+                volume: f64,
+            }
+            let params: Params = parse_params(&request.params)?;
+            if !(0.0..=1.0).contains(&params.volume) {
+                return Err(RpcError::invalid_params("volume must be in 0.0..=1.0"));
+            }
+            vec![PlayerCommand::SetVolume {
+                volume: params.volume,
+            }]
+        }
+        "set_channel_mix" => {
+            #[derive(Deserialize)]
+            struct Params {
+                mono: Option<bool>,
+                balance: Option<f32>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut config = lock(config);
+            let mut settings = config.channel_mix;
+            if let Some(mono) = params.mono {
+                settings.mono = mono;
+            }
+            if let Some(balance) = params.balance {
+                if !(-1.0..=1.0).contains(&balance) {
+                    return Err(RpcError::invalid_params("balance must be in -1.0..=1.0"));
+                }
+                settings.balance = balance;
+            }
+            config.channel_mix = settings;
+            vec![PlayerCommand::SetChannelMix { settings }]
+        }
+        "set_speed" => {
+            #[derive(Deserialize)]
+            struct Params {
+                speed: f32,
+            }
+            let params: Params = parse_params(&request.params)?;
+            if !(stretch::MIN_SPEED..=stretch::MAX_SPEED).contains(&params.speed) {
+                return Err(RpcError::invalid_params(format!(
+                    "speed must be in {}..={}",
+                    stretch::MIN_SPEED,
+                    stretch::MAX_SPEED
+                )));
+            }
+            vec![PlayerCommand::SetSpeed {
+                speed: params.speed,
+            }]
+        }
+        "set_queue_behavior" => {
+            #[derive(Deserialize)]
+            struct Params {
+                behavior: String,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let behavior = match params.behavior.as_str() {
+                "sequential" => QueueBehavior::Sequential,
+                "random" => QueueBehavior::Random,
+                "smart_shuffle" => QueueBehavior::SmartShuffle,
+                "loop_track" => QueueBehavior::LoopTrack,
+                "loop_all" => QueueBehavior::LoopAll,
+                other => {
+                    return Err(RpcError::invalid_params(format!(
+                        "unknown queue behavior: {}",
+                        other
+                    )))
+                }
+            };
+            vec![PlayerCommand::SetQueueBehavior { behavior }]
+        }
+        "set_item_groups" => {
+            #[derive(Deserialize)]
+            struct Group {
+                uri: String,
+                artist: Option<String>,
+                album: Option<String>,
+            }
+            #[derive(Deserialize)]
+            struct Params {
+                groups: Vec<Group>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let parse_id = |uri: &str| {
+                ItemId::from_uri(uri).ok_or_else(|| {
+                    RpcError::invalid_params(format!("invalid Spotify URI: {}", uri))
+                })
+            };
+            let groups = params
+                .groups
+                .iter()
+                .map(|group| {
+                    let group_id = |uri: &Option<String>| uri.as_deref().map(parse_id).transpose();
+                    Ok((
+                        parse_id(&group.uri)?,
+                        ItemGroup {
+                            artist: group_id(&group.artist)?,
+                            album: group_id(&group.album)?,
+                        },
+                    ))
+                })
+                .collect::<Result<_, RpcError>>()?;
+            vec![PlayerCommand::SetItemGroups { groups }]
+        }
+        "set_equalizer" => {
+            #[derive(Deserialize)]
+            struct Band {
+                kind: String,
+                frequency: f32,
+                gain_db: f32,
+                q: f32,
+            }
+            #[derive(Deserialize)]
+            struct Params {
+                preset: Option<String>,
+                #[serde(default)]
+                bands: Vec<Band>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let bands = if let Some(preset) = params.preset {
+                EqualizerPreset::ALL
+                    .into_iter()
+                    .find(|p| p.name().eq_ignore_ascii_case(&preset.replace('_', " ")))
+                    .ok_or_else(|| {
+                        RpcError::invalid_params(format!("unknown equalizer preset: {}", preset))
+                    })?
+                    .bands()
+            } else {
+                params
+                    .bands
+                    .into_iter()
+                    .map(|band| {
+                        let kind = match band.kind.as_str() {
+                            "low_shelf" => BandKind::LowShelf,
+                            "peaking" => BandKind::Peaking,
+                            "high_shelf" => BandKind::HighShelf,
+                            other => {
+                                return Err(RpcError::invalid_params(format!(
+                                    "unknown band kind: {}",
+                                    other
+                                )))
+                            }
+                        };
+                        Ok(EqualizerBand {
+                            kind,
+                            frequency: band.frequency,
+                            gain_db: band.gain_db,
+                            q: band.q,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?
+            };
+            let mut config = lock(config);
+            config.equalizer = EqualizerSettings { bands };
+            vec![PlayerCommand::SetEqualizer {
+                settings: config.equalizer.clone(),
+            }]
+        }
+        "set_silence_trimming" => {
+            #[derive(Deserialize)]
+            struct Params {
+                enabled: bool,
+                threshold_db: Option<f32>,
+                max_pause_ms: Option<u64>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut config = lock(config);
+            config.trim_silence = if params.enabled {
+                let mut trimming = SilenceTrimming::default();
+                if let Some(threshold_db) = params.threshold_db {
+                    if threshold_db >= 0.0 {
+                        return Err(RpcError::invalid_params("threshold_db must be negative"));
+                    }
+                    trimming.threshold_db = threshold_db;
+                }
+                if let Some(max_pause_ms) = params.max_pause_ms {
+                    trimming.max_pause = Duration::from_millis(max_pause_ms);
+                }
+                Some(trimming)
+            } else {
+                None
+            };
+            vec![PlayerCommand::SetSilenceTrimming {
+                trimming: config.trim_silence,
+            }]
+        }
+        "set_peak_limiting" => {
+            #[derive(Deserialize)]
+            struct Params {
+                enabled: bool,
+                ceiling_db: Option<f32>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut config = lock(config);
+            config.limit_peaks = if params.enabled {
+                let mut limiting = PeakLimiting::default();
+                if let Some(ceiling_db) = params.ceiling_db {
+                    if ceiling_db > 0.0 {
+                        return Err(RpcError::invalid_params("ceiling_db must not be positive"));
+                    }
+                    limiting.ceiling_db = ceiling_db;
+                }
+                Some(limiting)
+            } else {
+                None
+            };
+            vec![PlayerCommand::SetPeakLimiting {
+                limiting: config.limit_peaks,
+            }]
+        }
+        "configure" => {
+            #[derive(Deserialize)]
+            struct Params {
+                bitrate: Option<usize>,
+                pregain: Option<f32>,
+                crossfade_ms: Option<u64>,
+                crossfade_curve: Option<String>,
+                resampling_quality: Option<String>,
+            }
+            let params: Params = parse_params(&request.params)?;
+            let mut config = lock(config);
+            if let Some(bitrate) = params.bitrate {
+                if ![96, 160, 320].contains(&bitrate) {
+                    return Err(RpcError::invalid_params("bitrate must be 96, 160 or 320"));
+                }
+                config.bitrate = bitrate;
+            }
+            if let Some(pregain) = params.pregain {
+                config.pregain = pregain;
+            }
+            if let Some(crossfade_ms) = params.crossfade_ms {
+                if crossfade_ms > MAX_CROSSFADE.as_millis() as u64 {
+                    return Err(RpcError::invalid_params(format!(
+                        "crossfade must be at most {} ms",
+                        MAX_CROSSFADE.as_millis()
+                    )));
+                }
+                config.crossfade = Duration::from_millis(crossfade_ms);
+            }
+            if let Some(curve) = params.crossfade_curve {
+                config.crossfade_curve = match curve.as_str() {
+                    "linear" => CrossfadeCurve::Linear,
+                    "equal_power" => CrossfadeCurve::EqualPower,
+                    other => {
+                        return Err(RpcError::invalid_params(format!(
+                            "unknown crossfade curve: {}",
+                            other
+                        )))
+                    }
+                };
+            }
+            if let Some(quality) = params.resampling_quality {
+                config.resampling_quality = match quality.as_str() {
+                    "best" => ResamplingQuality::SincBestQuality,
+                    "medium" => ResamplingQuality::SincMediumQuality,
+                    "fastest" => ResamplingQuality::SincFastest,
+                    "linear" => ResamplingQuality::Linear,
+                    "zero_order_hold" => ResamplingQuality::ZeroOrderHold,
+                    other => {
+                        return Err(RpcError::invalid_params(format!(
+                            "unknown resampling quality: {}",
+                            other
+                        )))
+                    }
+                };
+            }
+            vec![PlayerCommand::Configure {
+                config: config.clone(),
+            }]
+        }
+        _ => {
+            return Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("unknown method: {}", request.method),
+            ))
+        }
+    };
+    Ok(Call::Commands(commands))
+}
+
+/// Lock `mutex`, carrying on with the data if another connection panicked
+/// while holding it.  Every update is a plain field assignment, so the data
+/// cannot be left half-written.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        log::warn!("lock poisoned by a panicked connection, recovering");
+        poisoned.into_inner()
+    })
+}
+
+fn parse_params<T: DeserializeOwned>(params: &Value) -> Result<T, RpcError> {
+    serde_json::from_value(params.clone()).map_err(RpcError::invalid_params)
+}
+
+fn parse_uri_params(params: &Value) -> Result<String, RpcError> {
+    #[derive(Deserialize)]
+    struct Params {
+        uri: String,
+    }
+    let params: Params = parse_params(params)?;
+    Ok(params.uri)
+}
+
+fn resolve_single_uri(uri: &str, session: &SessionService) -> Result<PlaybackItem, RpcError> {
+    let mut items = resolve_uri(uri, session)?;
+    if items.len() == 1 {
+        Ok(items.remove(0))
+    } else {
+        Err(RpcError::invalid_params(format!(
+            "expected a track or episode URI: {}",
+            uri
+        )))
+    }
+}
+
+/// Resolve a Spotify URI into a list of playable items.  Tracks and episodes
+/// resolve to a single item, albums are expanded into their tracks through the
+/// metadata service, and playlists into their tracks and episodes through the
+/// Web API.
+pub fn resolve_uri(uri: &str, session: &SessionService) -> Result<Vec<PlaybackItem>, RpcError> {
+    let invalid = || RpcError::invalid_params(format!("invalid Spotify URI: {}", uri));
+
+    let (kind, id) = match uri.split(':').collect::<Vec<_>>()[..] {
+        ["spotify", kind, id] => (kind, id),
+        // Older playlist URIs include the owner.
+        ["spotify", "user", _, "playlist", id] => ("playlist", id),
+        _ => return Err(invalid()),
+    };
+
+    match kind {
+        "track" => {
+            let item_id = ItemId::from_base62(id, ItemIdType::Track).ok_or_else(invalid)?;
+            Ok(vec![PlaybackItem {
+                item_id,
+                norm_level: NormalizationLevel::Track,
+            }])
+        }
+        "episode" => {
+            let item_id = ItemId::from_base62(id, ItemIdType::Podcast).ok_or_else(invalid)?;
+            Ok(vec![PlaybackItem {
+                item_id,
+                norm_level: NormalizationLevel::Track,
+            }])
+        }
+        "album" => {
+            let album_id = ItemId::from_base62(id, ItemIdType::Unknown).ok_or_else(invalid)?;
+            let album = Album::fetch(session, album_id)
+                .map_err(|err| RpcError::new(RpcError::INTERNAL_ERROR, err))?;
+            Ok(album
+                .disc
+                .iter()
+                .flat_map(|disc| disc.track.iter())
+                .filter_map(|track| ItemId::from_raw(track.gid.as_ref()?, ItemIdType::Track))
+                .map(|item_id| PlaybackItem {
+                    item_id,
+                    norm_level: NormalizationLevel::Album,
+                })
+                .collect())
+        }
+        "playlist" => {
+            let playlist_id = ItemId::from_base62(id, ItemIdType::Unknown).ok_or_else(invalid)?;
+            let items = fetch_playlist_items(session, playlist_id)
+                .map_err(|err| RpcError::new(RpcError::INTERNAL_ERROR, err))?;
+            Ok(items
+                .into_iter()
+                .map(|item_id| PlaybackItem {
+                    item_id,
+                    norm_level: NormalizationLevel::Track,
+                })
+                .collect())
+        }
+        _ => Err(RpcError::invalid_params(format!(
+            "unsupported URI type: {}",
+            kind
+        ))),
+    }
+}
+
+/// Serialize a player event into the `params` of an `event` notification.
+pub fn event_params(event: &PlayerEvent) -> Value {
+    match event {
+        PlayerEvent::Command(cmd) => json!({
+            "type": "command",
+            "command": command_name(cmd),
+        }),
+        PlayerEvent::Loading { item } => json!({
+            "type": "loading",
+            "uri": item_uri(item.item_id),
+        }),
+        PlayerEvent::Loaded { item, result } => json!({
+            "type": "loaded",
+            "uri": item_uri(item.item_id),
+            "error": result.as_ref().err().map(Error::to_string),
+        }),
+        PlayerEvent::Preloaded { item, result } => json!({
+            "type": "preloaded",
+            "uri": item_uri(item.item_id),
+            "error": result.as_ref().err().map(Error::to_string),
+        }),
+        PlayerEvent::Playing { path, position } => json!({
+            "type": "playing",
+            "uri": item_uri(path.item_id),
+            "position_ms": position.as_millis() as u64,
+            "duration_ms": path.duration.as_millis() as u64,
+        }),
+        PlayerEvent::Pausing { path, position } => json!({
+            "type": "pausing",
+            "uri": item_uri(path.item_id),
+            "position_ms": position.as_millis() as u64,
+        }),
+        PlayerEvent::Resuming { path, position } => json!({
+            "type": "resuming",
+            "uri": item_uri(path.item_id),
+            "position_ms": position.as_millis() as u64,
+        }),
+        PlayerEvent::Position { path, position } => json!({
+            "type": "position",
+            "uri": item_uri(path.item_id),
+            "position_ms": position.as_millis() as u64,
+        }),
+        PlayerEvent::Blocked { path, position } => json!({
+            "type": "blocked",
+            "uri": item_uri(path.item_id),
+            "position_ms": position.as_millis() as u64,
+        }),
+        PlayerEvent::EndOfTrack => json!({ "type": "end_of_track" }),
+        PlayerEvent::Stopped => json!({ "type": "stopped" }),
+        PlayerEvent::QueueChanged { queue } => json!({
+            "type": "queue_changed",
+            "current": queue.current.map(|item| item_uri(item.item_id)),
+            "upcoming": queue
+                .upcoming
+                .iter()
+                .map(|item| item_uri(item.item_id))
+                .collect::<Vec<_>>(),
+            "queued": queue
+                .queued
+                .iter()
+                .map(|item| item_uri(item.item_id))
+                .collect::<Vec<_>>(),
+        }),
+        PlayerEvent::ResumePointSaved { item_id, point } => json!({
+            "type": "resume_point_saved",
+            "uri": item_uri(*item_id),
+            "position_ms": point.position_ms,
+            "finished": point.finished,
+        }),
+        PlayerEvent::SilenceTrimmed { path, trimmed } => json!({
+            "type": "silence_trimmed",
+            "uri": item_uri(path.item_id),
+            "trimmed_ms": trimmed.as_millis() as u64,
+        }),
+        PlayerEvent::GainReduction { reduction_db } => json!({
+            "type": "gain_reduction",
+            "reduction_db": reduction_db,
+        }),
+        PlayerEvent::SleepTimer { mode, remaining } => json!({
+            "type": "sleep_timer",
+            "mode": mode.map(|mode| match mode {
+                SleepMode::After(_) => "after",
+                SleepMode::EndOfTrack => "end_of_track",
+                SleepMode::EndOfQueue => "end_of_queue",
+            }),
+            "remaining_ms": remaining.map(|remaining| remaining.as_millis() as u64),
+        }),
+        PlayerEvent::SleepTimerExpired => json!({ "type": "sleep_timer_expired" }),
+        PlayerEvent::LoopChanged { section } => json!({
+            "type": "loop_changed",
+            "start_ms": section.as_ref().map(|section| section.start.as_millis() as u64),
+            "end_ms": section.as_ref().map(|section| section.end.as_millis() as u64),
+        }),
+    }
+}
+
+fn command_name(cmd: &PlayerCommand) -> &'static str {
+    match cmd {
+        PlayerCommand::LoadQueue { .. } => "load_queue",
+        PlayerCommand::Restore { .. } => "restore",
+        PlayerCommand::LoadAndPlay { .. } => "load_and_play",
+        PlayerCommand::Preload { .. } => "preload",
+        PlayerCommand::Pause => "pause",
+        PlayerCommand::Resume => "resume",
+        PlayerCommand::PauseOrResume => "pause_or_resume",
+        PlayerCommand::Previous => "previous",
+        PlayerCommand::Next => "next",
+        PlayerCommand::Stop => "stop",
+        PlayerCommand::Seek { .. } => "seek",
+        PlayerCommand::Configure { .. } => "configure",
+        PlayerCommand::SetQueueBehavior { .. } => "set_queue_behavior",
+        PlayerCommand::SetItemGroups { .. } => "set_item_groups",
+        PlayerCommand::AddToQueue { .. } => "add_to_queue",
+        PlayerCommand::PlayNext { .. } => "play_next",
+        PlayerCommand::RemoveFromQueue { .. } => "remove_from_queue",
+        PlayerCommand::MoveInQueue { .. } => "move_in_queue",
+        PlayerCommand::ClearQueue => "clear_queue",
+        PlayerCommand::SetVolume { .. } => "set_volume",
+        PlayerCommand::SetEqualizer { .. } => "set_equalizer",
+        PlayerCommand::SetChannelMix { .. } => "set_channel_mix",
+        PlayerCommand::SetSpeed { .. } => "set_speed",
+        PlayerCommand::SetSilenceTrimming { .. } => "set_silence_trimming",
+        PlayerCommand::SetPeakLimiting { .. } => "set_peak_limiting",
+        PlayerCommand::SetSleepTimer { .. } => "set_sleep_timer",
+        PlayerCommand::CancelSleepTimer => "cancel_sleep_timer",
+        PlayerCommand::SetLoop { .. } => "set_loop",
+        PlayerCommand::SetOutputDevice { .. } => "set_output_device",
+    }
+}
+
+fn item_uri(item_id: ItemId) -> String {
+    match item_id.id_type {
+        // `ItemId::to_uri` uses the `podcast` type, but the clients expect `episode`.
+        ItemIdType::Podcast => format!("spotify:episode:{}", item_id.to_base62()),
+        _ => item_id
+            .to_uri()
+            .unwrap_or_else(|| format!("psst:local:{}", item_id.to_base62())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACK_URI: &str = "spotify:track:6rqhFgbbKwnb9MLmUQDhG6";
+    const EPISODE_URI: &str = "spotify:episode:512ojhOuo1ktJprKbVcKyQ";
+
+    fn call(method: &str, params: Value) -> Result<Call, RpcError> {
+        let request = Request {
+            id: Some(json!(1)),
+            method: method.to_string(),
+            params,
+        };
+        let config = Mutex::new(PlaybackConfig::default());
+        parse_call(&request, &SessionService::empty(), &config)
+    }
+
+    fn commands(method: &str, params: Value) -> Vec<PlayerCommand> {
+        match call(method, params) {
+            Ok(Call::Commands(commands)) => commands,
+            _ => panic!("expected commands for {}", method),
+        }
+    }
+
+    fn error_code(result: Result<Call, RpcError>) -> i64 {
+        match result {
+            Err(err) => err.code,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn parses_calls_into_commands() {
+        match &commands(
+            "load_queue",
+            json!({ "uris": [TRACK_URI, EPISODE_URI], "position": 1 }),
+        )[..]
+        {
+            [PlayerCommand::LoadQueue { items, position }] => {
+                assert_eq!(*position, 1);
+                assert_eq!(items[0].item_id.id_type, ItemIdType::Track);
+                assert_eq!(items[1].item_id.id_type, ItemIdType::Podcast);
+            }
+            _ => panic!("expected LoadQueue"),
+        }
+        assert!(matches!(
+            commands("seek", json!({ "position_ms": 1500 }))[..],
+            [PlayerCommand::Seek { position }] if position == Duration::from_millis(1500)
+        ));
+        assert!(matches!(
+            commands("pause", Value::Null)[..],
+            [PlayerCommand::Pause]
+        ));
+        assert!(matches!(
+            &commands("restore", json!({ "uris": [TRACK_URI], "progress_ms": 2000 }))[..],
+            [PlayerCommand::Restore { items, position: 0, progress }]
+                if items.len() == 1 && *progress == Duration::from_secs(2)
+        ));
+        let groups = json!({ "groups": [{
+            "uri": TRACK_URI,
+            "artist": "spotify:artist:0OdUWJ0sBjDrqHygGUXeCF"
+        }] });
+        match &commands("set_item_groups", groups)[..] {
+            [PlayerCommand::SetItemGroups { groups }] => {
+                assert_eq!(groups.len(), 1);
+                assert_eq!(groups[0].0.id_type, ItemIdType::Track);
+                assert!(groups[0].1.artist.is_some());
+                assert_eq!(groups[0].1.album, None);
+            }
+            _ => panic!("expected SetItemGroups"),
+        }
+        assert!(matches!(
+            call("subscribe", Value::Null),
+            Ok(Call::Subscribe)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_methods_and_bad_params() {
+        assert_eq!(
+            error_code(call("rewind", Value::Null)),
+            RpcError::METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            error_code(call("seek", json!({ "position": 1500 }))),
+            RpcError::INVALID_PARAMS
+        );
+        assert_eq!(
+            error_code(call("set_volume", json!({ "volume": 1.5 }))),
+            RpcError::INVALID_PARAMS
+        );
+        assert_eq!(
+            error_code(call("load_and_play", json!({ "uri": "spotify:artist:x" }))),
+            RpcError::INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn resolves_track_and_episode_uris() {
+        let session = SessionService::empty();
+        let items = resolve_uri(TRACK_URI, &session).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].item_id,
+            ItemId::from_base62("6rqhFgbbKwnb9MLmUQDhG6", ItemIdType::Track).unwrap()
+        );
+        let items = resolve_uri(EPISODE_URI, &session).unwrap();
+        assert_eq!(items[0].item_id.id_type, ItemIdType::Podcast);
+
+        for uri in [
+            "spotify:track",
+            "spotify:track:!!",
+            "track:6rqhFgbbKwnb9MLmUQDhG6",
+        ] {
+            assert!(resolve_uri(uri, &session).is_err(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn serializes_events() {
+        let item = resolve_single_uri(EPISODE_URI, &SessionService::empty()).unwrap();
+        assert_eq!(
+            event_params(&PlayerEvent::Loading { item }),
+            json!({ "type": "loading", "uri": EPISODE_URI })
+        );
+        assert_eq!(
+            event_params(&PlayerEvent::Command(PlayerCommand::Next)),
+            json!({ "type": "command", "command": "next" })
+        );
+        assert_eq!(
+            event_params(&PlayerEvent::Stopped),
+            json!({ "type": "stopped" })
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use rb::{Consumer, Producer, RbConsumer, RbInspector, RbProducer, SpscRb, RB};
+
+use crate::audio::source::AudioSource;
+
+/// Time between two analyses of the played signal.
+pub const ANALYSIS_INTERVAL: Duration = Duration::from_millis(33);
+/// Number of frequency bands the spectrum is split into.
+pub const BANDS: usize = 32;
+/// Level reported for silence, and the lowest one reported at all, in dBFS.
+pub const FLOOR_DB: f32 = -90.0;
+
+/// Frames that each analysis looks at, the latest ones.  Also the length of
+/// the FFT, so it has to be a power of two.
+const WINDOW: usize = 2048;
+const LOWEST_FREQUENCY: f32 = 30.0;
+const HIGHEST_FREQUENCY: f32 = 16_000.0;
+/// Samples the ring buffer can hold, enough for a few intervals of eight
+/// channels at 192 kHz.
+const RING_SIZE: usize = 256 * 1024;
+/// Analyses without any new samples, after which the playback is considered
+/// paused, and the signal silent.
+const IDLE_ANALYSES: u32 = 3;
+
+/// Spectrum and levels of the latest part of the played signal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    /// Level of each of the `BANDS` bands, from the lowest frequency up, in
+    /// dBFS.  A full-scale sine reads 0 dB in its band.
+    pub bands: Vec<f32>,
+    /// RMS level of all the channels together, in dBFS.
+    pub rms_db: f32,
+    /// Highest sample of any channel, in dBFS.
+    pub peak_db: f32,
+}
+
+impl Analysis {
+    fn silent() -> Self {
+        Self {
+            bands: vec![FLOOR_DB; BANDS],
+            rms_db: FLOOR_DB,
+            peak_db: FLOOR_DB,
+        }
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.log10()).max(FLOOR_DB)
+}
+
+struct Shared {
+    ring: SpscRb<f32>,
+    channels: AtomicUsize,
+    sample_rate: AtomicU32,
+}
+
+/// Writing side of the analysis, shared by the `AnalyzerSource`s.
+#[derive(Clone)]
+pub struct AnalyzerTap {
+    shared: Arc<Shared>,
+}
+
+/// Copies the samples of the inner source into the ring buffer of the
+/// `Analyzer`.  Never waits for it: while the ring buffer is full, the samples
+/// are skipped.
+pub struct AnalyzerSource<S> {
+    source: S,
+    shared: Arc<Shared>,
+    producer: Producer<f32>,
+}
+
+impl<S: AudioSource> AnalyzerSource<S> {
+    pub fn new(source: S, tap: &AnalyzerTap) -> Self {
+        let shared = tap.shared.clone();
+        shared
+            .channels
+            .store(source.channel_count(), Ordering::Relaxed);
+        shared
+            .sample_rate
+            .store(source.sample_rate(), Ordering::Relaxed);
+        Self {
+            producer: shared.ring.producer(),
+            shared,
+            source,
+        }
+    }
+}
+
+impl<S: AudioSource> AudioSource for AnalyzerSource<S> {
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        let written = self.source.write(output);
+        // Only whole buffers go in, so the ring never holds a partial frame.
+        if written > 0 && self.shared.ring.slots_free() >= written {
+            let _ = self.producer.write(&output[..written]);
+        }
+        written
+    }
+
+    fn channel_count(&self) -> usize {
+        self.source.channel_count()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
+
+/// Latest `WINDOW` frames of the signal, in a circular buffer.
+struct History {
+    /// Downmix of the channels, for the spectrum.
+    mono: Vec<f32>,
+    /// Mean square of the channels.
+    power: Vec<f32>,
+    /// Highest absolute sample of the channels.
+    peak: Vec<f32>,
+    /// Index of the oldest frame.
+    position: usize,
+    /// Sum, sum of squares and peak of the samples of the frame being read.
+    frame: (f32, f32, f32),
+    /// Channel of the next sample.
+    channel: usize,
+}
+
+impl History {
+    fn new() -> Self {
+        Self {
+            mono: vec![0.0; WINDOW],
+            power: vec![0.0; WINDOW],
+            peak: vec![0.0; WINDOW],
+            position: 0,
+            frame: (0.0, 0.0, 0.0),
+            channel: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn push(&mut self, sample: f32, channels: usize) {
+        let (sum, squares, peak) = &mut self.frame;
+        *sum += sample;
+        *squares += sample * sample;
+        *peak = peak.max(sample.abs());
+        self.channel += 1;
+        if self.channel < channels {
+            return;
+        }
+        let count = channels as f32;
+        self.mono[self.position] = *sum / count;
+        self.power[self.position] = *squares / count;
+        self.peak[self.position] = *peak;
+        self.position = (self.position + 1) % WINDOW;
+        self.frame = (0.0, 0.0, 0.0);
+        self.channel = 0;
+    }
+}
+
+/// Radix-2 FFT of the windowed mono history, summarized into the bands.
+struct Spectrum {
+    window: Vec<f32>,
+    window_sum: f32,
+    /// `exp(-2πik / WINDOW)` for the first half of `k`.
+    twiddles: Vec<(f32, f32)>,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl Spectrum {
+    fn new() -> Self {
+        let window: Vec<f32> = (0..WINDOW)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / WINDOW as f32).cos())
+            .collect();
+        Self {
+            window_sum: window.iter().sum(),
+            window,
+            twiddles: (0..WINDOW / 2)
+                .map(|k| {
+                    let (sin, cos) = (-2.0 * PI * k as f32 / WINDOW as f32).sin_cos();
+                    (cos, sin)
+                })
+                .collect(),
+            re: vec![0.0; WINDOW],
+            im: vec![0.0; WINDOW],
+        }
+    }
+
+    fn bands(&mut self, history: &History, sample_rate: u32) -> Vec<f32> {
+        // Oldest frame first.
+        let (newer, older) = history.mono.split_at(history.position);
+        for ((re, sample), window) in self
+            .re
+            .iter_mut()
+            .zip(older.iter().chain(newer))
+            .zip(&self.window)
+        {
+            *re = sample * window;
+        }
+        self.im.fill(0.0);
+        self.transform();
+
+        let bin_width = sample_rate as f32 / WINDOW as f32;
+        let highest = HIGHEST_FREQUENCY.min(sample_rate as f32 / 2.0);
+        let edge = |band: usize| {
+            let frequency =
+                LOWEST_FREQUENCY * (highest / LOWEST_FREQUENCY).powf(band as f32 / BANDS as f32);
+            ((frequency / bin_width).round() as usize).min(WINDOW / 2)
+        };
+        (0..BANDS)
+            .map(|band| {
+                // The lowest bands can be narrower than a bin, they take the
+                // nearest one.
+                let start = edge(band);
+                let end = edge(band + 1).max(start + 1).min(WINDOW / 2);
+                let magnitude = (start..end)
+                    .map(|bin| self.re[bin].hypot(self.im[bin]))
+                    .fold(0.0, f32::max);
+                to_db(2.0 * magnitude / self.window_sum)
+            })
+            .collect()
+    }
+
+    fn transform(&mut self) {
+        let (re, im) = (&mut self.re, &mut self.im);
+        let mut j = 0;
+        for i in 1..WINDOW {
+            let mut bit = WINDOW >> 1;
+            while j & bit != 0 {
+                j ^= bit;
+                bit >>= 1;
+            }
+            j |= bit;
+            if i < j {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+        let mut len = 2;
+        while len <= WINDOW {
+            let stride = WINDOW / len;
+            for start in (0..WINDOW).step_by(len) {
+                for k in 0..len / 2 {
+                    let (cos, sin) = self.twiddles[k * stride];
+                    let (a, b) = (start + k, start + k + len / 2);
+                    let t_re = re[b] * cos - im[b] * sin;
+                    let t_im = re[b] * sin + im[b] * cos;
+                    re[b] = re[a] - t_re;
+                    im[b] = im[a] - t_im;
+                    re[a] += t_re;
+                    im[a] += t_im;
+                }
+            }
+            len <<= 1;
+        }
+    }
+}
+
+/// Reading side of the analysis, computing the spectrum and the levels of what
+/// the `AnalyzerSource`s have played.
+pub struct Analyzer {
+    shared: Arc<Shared>,
+    consumer: Consumer<f32>,
+    buffer: Vec<f32>,
+    channels: usize,
+    history: History,
+    spectrum: Spectrum,
+    idle: u32,
+}
+
+impl Analyzer {
+    /// Create the analyzer, along with the tap feeding it.  The tap can be
+    /// cloned, but there can be only one analyzer reading from it.
+    pub fn new() -> (Self, AnalyzerTap) {
+        let shared = Arc::new(Shared {
+            ring: SpscRb::new(RING_SIZE),
+            channels: AtomicUsize::new(0),
+            sample_rate: AtomicU32::new(0),
+        });
+        let analyzer = Self {
+            consumer: shared.ring.consumer(),
+            shared: shared.clone(),
+            buffer: vec![0.0; 16 * 1024],
+            channels: 0,
+            history: History::new(),
+            spectrum: Spectrum::new(),
+            idle: 0,
+        };
+        (analyzer, AnalyzerTap { shared })
+    }
+
+    /// Take the samples played since the last call into account and analyze
+    /// the latest `WINDOW` frames.
+    pub fn analyze(&mut self) -> Analysis {
+        let channels = self.shared.channels.load(Ordering::Relaxed);
+        let sample_rate = self.shared.sample_rate.load(Ordering::Relaxed);
+        if channels == 0 || sample_rate == 0 {
+            return Analysis::silent();
+        }
+        if channels != self.channels {
+            self.channels = channels;
+            self.history.clear();
+        }
+
+        let mut received = false;
+        while let Ok(read) = self.consumer.read(&mut self.buffer) {
+            received = true;
+            for &sample in &self.buffer[..read] {
+                self.history.push(sample, channels);
+            }
+        }
+        if received {
+            self.idle = 0;
+        } else if self.idle < IDLE_ANALYSES {
+            self.idle += 1;
+            if self.idle == IDLE_ANALYSES {
+                self.history.clear();
+            }
+        }
+
+        let power = self.history.power.iter().sum::<f32>() / WINDOW as f32;
+        let peak = self.history.peak.iter().copied().fold(0.0, f32::max);
+        Analysis {
+            bands: self.spectrum.bands(&self.history, sample_rate),
+            rms_db: to_db(power.sqrt()),
+            peak_db: to_db(peak),
+        }
+    }
+
+    /// Analyze every `ANALYSIS_INTERVAL`, and pass the results that differ from
+    /// the previous one to `deliver`, until it returns false.
+    pub fn run(mut self, mut deliver: impl FnMut(Analysis) -> bool) {
+        let mut last = None;
+        let mut next = Instant::now();
+        loop {
+            next += ANALYSIS_INTERVAL;
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+            let analysis = self.analyze();
+            if last.as_ref() == Some(&analysis) {
+                continue;
+            }
+            if !deliver(analysis.clone()) {
+                break;
+            }
+            last = Some(analysis);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Samples(Vec<f32>, usize);
+
+    impl AudioSource for Samples {
+        fn write(&mut self, output: &mut [f32]) -> usize {
+            let len = output.len().min(self.0.len() - self.1);
+            output[..len].copy_from_slice(&self.0[self.1..self.1 + len]);
+            self.1 += len;
+            len
+        }
+
+        fn channel_count(&self) -> usize {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44_100
+        }
+    }
+
+    fn stereo_sine(frequency: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample = (2.0 * PI * frequency * i as f32 / 44_100.0).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn play(source: &mut impl AudioSource) -> Vec<f32> {
+        let mut played = Vec::new();
+        let mut buffer = [0.0; 1024];
+        loop {
+            let written = source.write(&mut buffer);
+            if written == 0 {
+                return played;
+            }
+            played.extend_from_slice(&buffer[..written]);
+        }
+    }
+
+    #[test]
+    fn measures_sine() {
+        let (mut analyzer, tap) = Analyzer::new();
+        let input = stereo_sine(1000.0, 4096);
+        let mut source = AnalyzerSource::new(Samples(input.clone(), 0), &tap);
+        assert_eq!(play(&mut source), input);
+
+        let analysis = analyzer.analyze();
+        assert!(analysis.peak_db.abs() < 0.01, "{:?}", analysis);
+        assert!((analysis.rms_db + 3.01).abs() < 0.1, "{:?}", analysis);
+        let (loudest, level) = analysis
+            .bands
+            .iter()
+            .enumerate()
+            .fold((0, FLOOR_DB), |a, (i, &l)| if l > a.1 { (i, l) } else { a });
+        assert!(level.abs() < 1.5, "{:?}", analysis);
+        assert!(analysis.bands[loudest - 3] < -60.0, "{:?}", analysis);
+        assert!(analysis.bands[BANDS - 1] < -60.0, "{:?}", analysis);
+    }
+
+    #[test]
+    fn falls_silent_when_idle() {
+        let (mut analyzer, tap) = Analyzer::new();
+        let mut source = AnalyzerSource::new(Samples(stereo_sine(440.0, 4096), 0), &tap);
+        play(&mut source);
+
+        assert!(analyzer.analyze().rms_db > -6.0);
+        for _ in 1..IDLE_ANALYSES {
+            assert!(analyzer.analyze().rms_db > -6.0);
+        }
+        assert_eq!(analyzer.analyze(), Analysis::silent());
+    }
+}
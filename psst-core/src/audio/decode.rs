@@ -3,7 +3,7 @@ use std::{io, time::Duration};
 use symphonia::{
     core::{
         audio::{SampleBuffer, SignalSpec},
-        codecs::{CodecParameters, Decoder, DecoderOptions},
+        codecs::{CodecParameters, CodecType, Decoder, DecoderOptions},
         conv::ConvertibleSample,
         errors::Error as SymphoniaError,
         formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
@@ -24,6 +24,17 @@ pub enum AudioCodecFormat {
 }
 
 impl AudioCodecFormat {
+    pub fn from_codec(codec: CodecType) -> Option<Self> {
+        use symphonia::core::codecs::{CODEC_TYPE_MP3, CODEC_TYPE_VORBIS};
+        if codec == CODEC_TYPE_MP3 {
+            Some(Self::Mp3)
+        } else if codec == CODEC_TYPE_VORBIS {
+            Some(Self::OggVorbis)
+        } else {
+            None
+        }
+    }
+
     fn format_reader(
         &self,
         mss: MediaSourceStream,
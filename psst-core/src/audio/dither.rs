@@ -0,0 +1,66 @@
+/// Adds triangular (TPDF) noise to samples that are about to be quantized to
+/// an integer format.  The rounding error then stops following the signal and
+/// turns into a constant, very quiet hiss, instead of distorting quiet parts.
+pub struct Dither {
+    /// Size of one quantization step, in the `-1.0..=1.0` range of the samples.
+    step: f32,
+    /// State of a xorshift generator.  It is plenty for noise, and cheap enough
+    /// to run on the audio thread.
+    state: u32,
+}
+
+impl Dither {
+    /// Dither for a signed integer format with `bits` bits.  Unsigned formats
+    /// have the same step, just shifted.
+    pub fn new(bits: u32) -> Self {
+        Self {
+            step: 2.0 / 2.0_f32.powi(bits as i32),
+            state: 0x9E37_79B9,
+        }
+    }
+
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        // Difference of two uniform values in `0.0..1.0` has a triangular
+        // distribution over `-1.0..1.0`.
+        let noise = self.next_uniform() - self.next_uniform();
+        (sample + noise * self.step).clamp(-1.0, 1.0)
+    }
+
+    fn next_uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: f32 = 1.0 / 32768.0;
+
+    fn quantize(sample: f32) -> i16 {
+        (sample * 32768.0).round() as i16
+    }
+
+    #[test]
+    fn noise_stays_within_one_step() {
+        let mut dither = Dither::new(16);
+        for _ in 0..10_000 {
+            assert!(dither.apply(0.0).abs() < STEP);
+        }
+    }
+
+    #[test]
+    fn preserves_level_below_one_step() {
+        let level = STEP / 4.0;
+        assert_eq!(quantize(level), 0);
+
+        let mut dither = Dither::new(16);
+        let n = 100_000;
+        let sum: i64 = (0..n).map(|_| quantize(dither.apply(level)) as i64).sum();
+        let mean = sum as f32 / n as f32;
+        assert!((mean - 0.25).abs() < 0.02, "mean {}", mean);
+    }
+}
@@ -0,0 +1,298 @@
+use std::f64::consts::PI;
+
+use crossbeam_channel::Receiver;
+
+use crate::audio::{fade::SettingsFade, source::AudioSource};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BandKind {
+    LowShelf,
+    Peaking,
+    HighShelf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqualizerBand {
+    pub kind: BandKind,
+    /// Center (or corner, for shelves) frequency in Hz.
+    pub frequency: f32,
+    /// Gain in dB, positive values boost, negative cut.
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+/// Settings of the parametric equalizer.  An empty list of bands disables the
+/// equalizer entirely.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EqualizerSettings {
+    pub bands: Vec<EqualizerBand>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EqualizerPreset {
+    Flat,
+    BassBoost,
+    TrebleBoost,
+    Vocal,
+    Loudness,
+}
+
+impl EqualizerPreset {
+    pub const ALL: [Self; 5] = [
+        Self::Flat,
+        Self::BassBoost,
+        Self::TrebleBoost,
+        Self::Vocal,
+        Self::Loudness,
+    ];
+
+    /// Center frequencies of the preset bands, a common ten-band layout.
+    const FREQUENCIES: [f32; 10] = [
+        32.0, 64.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Flat => "Flat",
+            Self::BassBoost => "Bass Boost",
+            Self::TrebleBoost => "Treble Boost",
+            Self::Vocal => "Vocal",
+            Self::Loudness => "Loudness",
+        }
+    }
+
+    pub fn bands(self) -> Vec<EqualizerBand> {
+        let gains: [f32; 10] = match self {
+            Self::Flat => [0.0; 10],
+            Self::BassBoost => [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Self::TrebleBoost => [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 3.5, 5.0, 6.0],
+            Self::Vocal => [-2.0, -2.0, -1.0, 1.0, 3.0, 3.5, 3.0, 1.5, 0.0, -1.0],
+            Self::Loudness => [5.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 1.5, 3.5, 4.5],
+        };
+        let last = Self::FREQUENCIES.len() - 1;
+        Self::FREQUENCIES
+            .iter()
+            .zip(gains)
+            .enumerate()
+            .map(|(i, (&frequency, gain_db))| EqualizerBand {
+                kind: match i {
+                    0 => BandKind::LowShelf,
+                    i if i == last => BandKind::HighShelf,
+                    _ => BandKind::Peaking,
+                },
+                frequency,
+                gain_db,
+                q: std::f32::consts::SQRT_2,
+            })
+            .collect()
+    }
+}
+
+/// Normalized biquad coefficients, computed according to the Audio EQ Cookbook
+/// by Robert Bristow-Johnson.
+#[derive(Clone, Copy)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn new(band: &EqualizerBand, sample_rate: u32) -> Self {
+        let nyquist = sample_rate as f64 / 2.0;
+        let frequency = (band.frequency as f64).clamp(10.0, nyquist * 0.95);
+        let q = (band.q as f64).max(0.05);
+        let a = 10.0_f64.powf(band.gain_db as f64 / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f64;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            BandKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BandKind::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - k),
+                    (a + 1.0) + (a - 1.0) * cos + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - k,
+                )
+            }
+            BandKind::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - k),
+                    (a + 1.0) - (a - 1.0) * cos + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - k,
+                )
+            }
+        };
+        Self {
+            b0: (b0 / a0) as f32,
+            b1: (b1 / a0) as f32,
+            b2: (b2 / a0) as f32,
+            a1: (a1 / a0) as f32,
+            a2: (a2 / a0) as f32,
+        }
+    }
+}
+
+/// Per-channel filter state, in transposed direct form II.
+#[derive(Clone, Copy, Default)]
+struct State {
+    z1: f32,
+    z2: f32,
+}
+
+impl State {
+    fn process(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+/// Cascade of biquad filters, one for each band.
+struct Filters {
+    coefficients: Vec<Coefficients>,
+    /// Indexed by `band * channels + channel`.
+    states: Vec<State>,
+    channels: usize,
+}
+
+impl Filters {
+    fn new(settings: &EqualizerSettings, sample_rate: u32, channels: usize) -> Self {
+        let coefficients: Vec<_> = settings
+            .bands
+            .iter()
+            // Bands without any gain are no-ops, skip them.
+            .filter(|band| band.gain_db != 0.0)
+            .map(|band| Coefficients::new(band, sample_rate))
+            .collect();
+        Self {
+            states: vec![State::default(); coefficients.len() * channels],
+            coefficients,
+            channels,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for (band, c) in self.coefficients.iter().enumerate() {
+            let states = &mut self.states[band * self.channels..(band + 1) * self.channels];
+            for frame in samples.chunks_exact_mut(self.channels) {
+                for (sample, state) in frame.iter_mut().zip(states.iter_mut()) {
+                    *sample = state.process(c, *sample);
+                }
+            }
+        }
+    }
+}
+
+/// Applies a multi-band parametric equalizer to the inner source.  New settings
+/// can be sent through `settings_recv` at any time, the output of the previous
+/// filters is then faded into the output of the new ones.
+pub struct EqualizerSource<S> {
+    source: S,
+    filters: SettingsFade<EqualizerSettings, Filters>,
+    buffer: Vec<f32>,
+}
+
+impl<S> EqualizerSource<S>
+where
+    S: AudioSource,
+{
+    pub fn new(
+        source: S,
+        settings: &EqualizerSettings,
+        settings_recv: Receiver<EqualizerSettings>,
+    ) -> Self {
+        const BUFFER_SIZE: usize = 16 * 1024;
+
+        let sample_rate = source.sample_rate();
+        let channels = source.channel_count();
+        Self {
+            filters: SettingsFade::new(
+                Filters::new(settings, sample_rate, channels),
+                settings_recv,
+                sample_rate,
+            ),
+            buffer: vec![0.0; BUFFER_SIZE],
+            source,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        let (filters, previous) = self.filters.stages_mut();
+        let Some(previous) = previous else {
+            if !filters.is_empty() {
+                filters.process(samples);
+            }
+            return;
+        };
+        // Both filter sets need the dry signal.
+        let old = &mut self.buffer[..samples.len()];
+        old.copy_from_slice(samples);
+        previous.process(old);
+        filters.process(samples);
+        let channels = filters.channels;
+        self.filters.fade(samples, old, channels);
+    }
+}
+
+impl<S> AudioSource for EqualizerSource<S>
+where
+    S: AudioSource,
+{
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        let (sample_rate, channels) = (self.source.sample_rate(), self.source.channel_count());
+        self.filters
+            .update(|settings, _| Filters::new(settings, sample_rate, channels));
+
+        let mut total = 0;
+        while total < output.len() {
+            // While fading, process in chunks of whole frames that fit into the
+            // buffer for the output of the previous filters.
+            let end = if self.filters.is_fading() {
+                (total + self.buffer.len() / channels * channels).min(output.len())
+            } else {
+                output.len()
+            };
+            let chunk = &mut output[total..end];
+            let len = chunk.len();
+            let written = self.source.write(chunk);
+            self.process(&mut chunk[..written]);
+            total += written;
+            if written < len {
+                break;
+            }
+        }
+        total
+    }
+
+    fn channel_count(&self) -> usize {
+        self.source.channel_count()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
@@ -0,0 +1,103 @@
+use std::mem;
+
+use crossbeam_channel::Receiver;
+
+const FADE_DURATION_MS: usize = 30;
+
+/// Processing stage of an audio source, built from settings that can be sent
+/// through a channel at any time.  When new settings arrive, the output of the
+/// previous stage is faded into the output of the new one over a short period
+/// of time, to avoid clicks.
+pub struct SettingsFade<T, P> {
+    settings_recv: Receiver<T>,
+    stage: P,
+    previous: Option<P>,
+    fade_position: usize,
+    fade_frames: usize,
+}
+
+impl<T, P> SettingsFade<T, P> {
+    pub fn new(stage: P, settings_recv: Receiver<T>, sample_rate: u32) -> Self {
+        Self {
+            settings_recv,
+            stage,
+            previous: None,
+            fade_position: 0,
+            fade_frames: sample_rate as usize * FADE_DURATION_MS / 1000,
+        }
+    }
+
+    /// Build a new stage with `build` if new settings have arrived.  The
+    /// previous stage is faded out from the position it is at now, possibly
+    /// already in the middle of another fade.
+    pub fn update(&mut self, build: impl FnOnce(&T, &P) -> P) {
+        // Only the latest settings matter.
+        let Some(settings) = self.settings_recv.try_iter().last() else {
+            return;
+        };
+        let stage = build(&settings, &self.stage);
+        self.previous = Some(mem::replace(&mut self.stage, stage));
+        self.fade_position = 0;
+    }
+
+    pub fn stage(&self) -> &P {
+        &self.stage
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// The current stage, and the previous one while fading.
+    pub fn stages_mut(&mut self) -> (&mut P, Option<&mut P>) {
+        (&mut self.stage, self.previous.as_mut())
+    }
+
+    /// Fade `old`, the output of the previous stage, into `new`, the output of
+    /// the current one, and drop the previous stage once the fade is over.
+    pub fn fade(&mut self, new: &mut [f32], old: &[f32], channels: usize) {
+        let new_frames = new.chunks_exact_mut(channels);
+        let old_frames = old.chunks_exact(channels);
+        let fade_frames = self.fade_frames - self.fade_position;
+        for (i, (new, old)) in new_frames.zip(old_frames).take(fade_frames).enumerate() {
+            let t = (self.fade_position + i) as f32 / self.fade_frames as f32;
+            for (n, o) in new.iter_mut().zip(old) {
+                *n = *o * (1.0 - t) + *n * t;
+            }
+        }
+        self.fade_position += new.len() / channels;
+        if self.fade_position >= self.fade_frames {
+            self.previous = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+
+    use super::*;
+
+    #[test]
+    fn fades_into_latest_settings() {
+        let (settings_send, settings_recv) = unbounded();
+        // 30 frames at 1 kHz.
+        let mut fade = SettingsFade::new(0.0, settings_recv, 1000);
+        settings_send.send(1.0).unwrap();
+        settings_send.send(2.0).unwrap();
+        fade.update(|&settings, _| settings);
+        assert_eq!(*fade.stage(), 2.0);
+
+        let mut new = vec![1.0; 20];
+        fade.fade(&mut new, &[0.0; 20], 1);
+        assert_eq!(new[0], 0.0);
+        assert!((new[15] - 0.5).abs() < 1e-6);
+        assert!(fade.is_fading());
+
+        let mut new = vec![1.0; 20];
+        fade.fade(&mut new, &[0.0; 20], 1);
+        assert!(new[9] < 1.0);
+        assert!(new[10..].iter().all(|&s| s == 1.0));
+        assert!(!fade.is_fading());
+    }
+}
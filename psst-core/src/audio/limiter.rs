@@ -0,0 +1,343 @@
+use std::{collections::VecDeque, f32::consts::PI, time::Duration};
+
+use crossbeam_channel::Receiver;
+
+use crate::audio::source::AudioSource;
+
+/// How far ahead the limiter looks for peaks, and so how long it takes to pull
+/// the gain down before one.
+const LOOKAHEAD: Duration = Duration::from_millis(5);
+/// Time constant of the gain recovering after a peak.
+const RELEASE: Duration = Duration::from_millis(150);
+/// How often the gain reduction is reported.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of points between two samples that the true peak is estimated at.
+const OVERSAMPLING: usize = 4;
+/// Samples taken into account when interpolating between two samples.
+const INTERPOLATION_TAPS: usize = 8;
+
+/// Settings of the peak limiter, which keeps the loudest parts of the output
+/// under the ceiling, instead of lowering the whole track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakLimiting {
+    /// Highest allowed level of the output, including the peaks between the
+    /// samples, in dBTP.
+    pub ceiling_db: f32,
+}
+
+impl Default for PeakLimiting {
+    fn default() -> Self {
+        Self { ceiling_db: -1.0 }
+    }
+}
+
+impl PeakLimiting {
+    fn ceiling(&self) -> f32 {
+        10.0_f32.powf(self.ceiling_db / 20.0)
+    }
+}
+
+/// Estimates the peaks of the analog signal reconstructed from the samples,
+/// which can be higher than the samples themselves, by interpolating the
+/// points between them.
+struct TruePeak {
+    /// Windowed sinc taps for each of the points between two samples.
+    taps: [[f32; INTERPOLATION_TAPS]; OVERSAMPLING - 1],
+}
+
+impl TruePeak {
+    fn new() -> Self {
+        let half_length = (INTERPOLATION_TAPS / 2) as f32;
+        let mut taps = [[0.0; INTERPOLATION_TAPS]; OVERSAMPLING - 1];
+        for (point, taps) in taps.iter_mut().enumerate() {
+            let offset = (point + 1) as f32 / OVERSAMPLING as f32;
+            for (tap, value) in taps.iter_mut().enumerate() {
+                // Tap `tap` is applied to the sample `half_length - 1` before
+                // the one the interpolated point follows.
+                let distance = tap as f32 - (half_length - 1.0) - offset;
+                let sinc = if distance == 0.0 {
+                    1.0
+                } else {
+                    (PI * distance).sin() / (PI * distance)
+                };
+                let window = 0.5 * (1.0 + (PI * distance / half_length).cos());
+                *value = sinc * window;
+            }
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|value| *value /= sum);
+        }
+        Self { taps }
+    }
+
+    /// Peak of the sample in the middle of `samples`, together with the
+    /// points between it and the next sample.
+    fn peak(&self, samples: &[f32; INTERPOLATION_TAPS]) -> f32 {
+        let sample = samples[INTERPOLATION_TAPS / 2 - 1].abs();
+        self.taps.iter().fold(sample, |peak, taps| {
+            let point: f32 = taps.iter().zip(samples).map(|(t, s)| t * s).sum();
+            peak.max(point.abs())
+        })
+    }
+}
+
+/// Look-ahead limiter of interleaved samples.  The signal is delayed, so the
+/// gain can smoothly go down before a peak, instead of distorting it.
+pub struct Limiter {
+    channels: usize,
+    true_peak: TruePeak,
+    /// The last `INTERPOLATION_TAPS` samples of each channel, oldest first.
+    recent: Vec<[f32; INTERPOLATION_TAPS]>,
+    /// Frames waiting to be let out, with the gain computed.
+    delay: VecDeque<f32>,
+    delay_frames: usize,
+    window: usize,
+    /// Gains needed by the frames in the window, as a rising sequence of
+    /// frame indices and gains, so the front is the minimum.
+    minimum: VecDeque<(u64, f32)>,
+    /// The minimum, after recovering slowly from peaks.
+    released: f32,
+    release_coefficient: f32,
+    /// Gains over the last `window` frames, averaged into the applied gain.
+    smoothing: VecDeque<f32>,
+    smoothing_sum: f64,
+    frame: u64,
+}
+
+impl Limiter {
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let frames = |duration: Duration| (duration.as_secs_f64() * sample_rate as f64) as usize;
+        let window = frames(LOOKAHEAD).max(1);
+        // The true peak of a frame is known once the interpolation has the
+        // following samples too.
+        let delay_frames = window - 1 + INTERPOLATION_TAPS / 2;
+        Self {
+            channels,
+            true_peak: TruePeak::new(),
+            recent: vec![[0.0; INTERPOLATION_TAPS]; channels],
+            delay: VecDeque::from(vec![0.0; delay_frames * channels]),
+            delay_frames,
+            window,
+            minimum: VecDeque::new(),
+            released: 1.0,
+            release_coefficient: 1.0 - (-1.0 / frames(RELEASE).max(1) as f32).exp(),
+            smoothing: VecDeque::from(vec![1.0; window]),
+            smoothing_sum: window as f64,
+            frame: 0,
+        }
+    }
+
+    /// Frames of latency the limiter adds.
+    pub fn delay_frames(&self) -> usize {
+        self.delay_frames
+    }
+
+    /// Limit the samples to `ceiling`, in place.  They come out delayed by
+    /// `delay_frames`.  Returns the lowest gain applied.
+    pub fn process(&mut self, samples: &mut [f32], ceiling: f32) -> f32 {
+        let mut lowest = 1.0_f32;
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let gain = self.next_gain(frame, ceiling);
+            lowest = lowest.min(gain);
+            // Let the oldest frame out, with the gain it needs, and keep the
+            // new one.
+            for sample in frame.iter_mut() {
+                let delayed = self.delay.pop_front().unwrap_or(0.0);
+                self.delay.push_back(*sample);
+                *sample = delayed * gain;
+            }
+        }
+        lowest
+    }
+
+    /// Takes a new frame, and returns the gain of the oldest delayed frame.
+    fn next_gain(&mut self, frame: &[f32], ceiling: f32) -> f32 {
+        let mut peak = 0.0_f32;
+        for (recent, &sample) in self.recent.iter_mut().zip(frame) {
+            recent.copy_within(1.., 0);
+            recent[INTERPOLATION_TAPS - 1] = sample;
+            peak = peak.max(self.true_peak.peak(recent));
+        }
+        let needed = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+        // Lowest gain needed within the window.
+        while matches!(self.minimum.back(), Some(&(_, gain)) if gain >= needed) {
+            self.minimum.pop_back();
+        }
+        self.minimum.push_back((self.frame, needed));
+        let first = (self.frame + 1).saturating_sub(self.window as u64);
+        while matches!(self.minimum.front(), Some(&(frame, _)) if frame < first) {
+            self.minimum.pop_front();
+        }
+        self.frame += 1;
+        let minimum = self.minimum.front().map_or(1.0, |&(_, gain)| gain);
+
+        // Drop right away, recover slowly.  The average over the window then
+        // makes the drop smooth, and it is complete by the time the peak
+        // leaves the delay.
+        if minimum < self.released {
+            self.released = minimum;
+        } else {
+            self.released += (minimum - self.released) * self.release_coefficient;
+        }
+        self.smoothing_sum += self.released as f64;
+        self.smoothing.push_back(self.released);
+        self.smoothing_sum -= self.smoothing.pop_front().unwrap_or(1.0) as f64;
+        (self.smoothing_sum / self.window as f64).min(1.0) as f32
+    }
+}
+
+/// Runs the inner source through a `Limiter`, if the limiting is enabled.  New
+/// settings can be sent through `limiting_recv` at any time.  `report` is
+/// called periodically with the strongest gain reduction since the last call,
+/// in dB, as long as there is any, and once more after it ends.
+pub struct LimiterSource<S, F> {
+    source: S,
+    limiting_recv: Receiver<Option<PeakLimiting>>,
+    ceiling: f32,
+    limiter: Limiter,
+    /// Frames of the source still in the delay of the limiter.
+    pending_frames: usize,
+    report: F,
+    report_frames: usize,
+    frames_since_report: usize,
+    lowest_gain: f32,
+    reported_gain: f32,
+}
+
+impl<S, F> LimiterSource<S, F>
+where
+    S: AudioSource,
+    F: FnMut(f32) + Send + 'static,
+{
+    pub fn new(
+        source: S,
+        limiting: Option<PeakLimiting>,
+        limiting_recv: Receiver<Option<PeakLimiting>>,
+        report: F,
+    ) -> Self {
+        let channels = source.channel_count();
+        let sample_rate = source.sample_rate();
+        Self {
+            ceiling: Self::ceiling(limiting),
+            limiter: Limiter::new(channels, sample_rate),
+            pending_frames: 0,
+            report,
+            report_frames: (REPORT_INTERVAL.as_secs_f64() * sample_rate as f64) as usize,
+            frames_since_report: 0,
+            lowest_gain: 1.0,
+            reported_gain: 1.0,
+            limiting_recv,
+            source,
+        }
+    }
+
+    fn ceiling(limiting: Option<PeakLimiting>) -> f32 {
+        // When disabled, the signal still passes through the limiter, so
+        // toggling it does not lose the delayed frames.
+        limiting.map_or(f32::INFINITY, |limiting| limiting.ceiling())
+    }
+
+    fn report_gain(&mut self, frames: usize, gain: f32) {
+        self.lowest_gain = self.lowest_gain.min(gain);
+        self.frames_since_report += frames;
+        if self.frames_since_report < self.report_frames {
+            return;
+        }
+        if self.lowest_gain < 1.0 || self.reported_gain < 1.0 {
+            (self.report)(-20.0 * self.lowest_gain.log10());
+        }
+        self.reported_gain = self.lowest_gain;
+        self.lowest_gain = 1.0;
+        self.frames_since_report = 0;
+    }
+}
+
+impl<S, F> AudioSource for LimiterSource<S, F>
+where
+    S: AudioSource,
+    F: FnMut(f32) + Send + 'static,
+{
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        if let Some(limiting) = self.limiting_recv.try_iter().last() {
+            self.ceiling = Self::ceiling(limiting);
+        }
+        let channels = self.channel_count();
+        let len = output.len() / channels * channels;
+        let mut written = self.source.write(&mut output[..len]);
+        self.pending_frames =
+            (self.pending_frames + written / channels).min(self.limiter.delay_frames());
+        if written < len {
+            // The source has run out, flush what is left in the delay.
+            let flushed = (self.pending_frames * channels).min(len - written);
+            output[written..written + flushed].fill(0.0);
+            written += flushed;
+            self.pending_frames -= flushed / channels;
+        }
+        let gain = self.limiter.process(&mut output[..written], self.ceiling);
+        self.report_gain(written / channels, gain);
+        written
+    }
+
+    fn channel_count(&self) -> usize {
+        self.source.channel_count()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| amplitude * (2.0 * PI * frequency * i as f32 / 44_100.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn passes_quiet_signal_delayed() {
+        let input = sine(440.0, 0.5, 4410);
+        let mut output = input.clone();
+        let mut limiter = Limiter::new(1, 44_100);
+        let gain = limiter.process(&mut output, 0.9);
+
+        assert_eq!(gain, 1.0);
+        let delay = limiter.delay_frames();
+        assert_eq!(&output[delay..], &input[..input.len() - delay]);
+    }
+
+    #[test]
+    fn keeps_loud_signal_under_ceiling() {
+        let ceiling = 10.0_f32.powf(-1.0 / 20.0);
+        // Quiet, then suddenly 12 dB over the ceiling.
+        let mut input = sine(1000.0, 0.1, 4410);
+        input.extend(sine(1000.0, 4.0 * ceiling, 44_100));
+        let mut output = input.clone();
+        let mut limiter = Limiter::new(1, 44_100);
+        let gain = limiter.process(&mut output, ceiling);
+
+        assert!(gain < 0.3, "gain {}", gain);
+        let peak = output.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!(peak <= ceiling + 1e-4, "peak {}", peak);
+    }
+
+    #[test]
+    fn catches_peaks_between_samples() {
+        // A sine at a quarter of the sample rate, sampled at its 45° points,
+        // has samples at 0.707 of its real amplitude.
+        let input: Vec<f32> = (0..4410)
+            .map(|i| (PI / 2.0 * i as f32 + PI / 4.0).sin())
+            .collect();
+        let mut output = input.clone();
+        let mut limiter = Limiter::new(1, 44_100);
+        let gain = limiter.process(&mut output, 0.9);
+
+        let sample_peak = input.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!(sample_peak < 0.9);
+        assert!(gain < 0.95, "gain {}", gain);
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crossbeam_channel::Sender;
+use symphonia::core::audio::SampleBuffer;
+
+use crate::{
+    actor::{Act, Actor},
+    audio::{
+        decode::{AudioCodecFormat, AudioDecoder},
+        normalize::NormalizationData,
+        probe::TrackProbe,
+    },
+    cache::CacheHandle,
+    error::Error,
+};
+
+/// Target loudness of the computed gains, the ReplayGain 2.0 reference level,
+/// so analysed files end up as loud as the ones with ReplayGain tags.
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// Coefficients of a biquad filter, normalized so that `a0` is 1.
+#[derive(Clone, Copy)]
+struct Coefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl Coefficients {
+    /// High-shelf boosting high frequencies by ~4 dB, modelling the acoustic
+    /// effect of the head.  This is the first stage of the K-weighting filter
+    /// from ITU-R BS.1770, re-derived for `sample_rate` the same way
+    /// libebur128 does.
+    fn pre_filter(sample_rate: u32) -> Self {
+        const F0: f64 = 1681.974450955533;
+        const G: f64 = 3.999843853973347;
+        const Q: f64 = 0.7071752369554196;
+
+        let k = (PI * F0 / sample_rate as f64).tan();
+        let vh = 10.0_f64.powf(G / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / Q + k * k;
+        Self {
+            b0: (vh + vb * k / Q + k * k) / a0,
+            b1: 2.0 * (k * k - vh) / a0,
+            b2: (vh - vb * k / Q + k * k) / a0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / Q + k * k) / a0,
+        }
+    }
+
+    /// Second stage of the K-weighting filter, a high-pass at ~38 Hz.
+    fn rlb_filter(sample_rate: u32) -> Self {
+        const F0: f64 = 38.13547087602444;
+        const Q: f64 = 0.5003270373238773;
+
+        let k = (PI * F0 / sample_rate as f64).tan();
+        let a0 = 1.0 + k / Q + k * k;
+        Self {
+            b0: 1.0,
+            b1: -2.0,
+            b2: 1.0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / Q + k * k) / a0,
+        }
+    }
+}
+
+/// Filter state, in transposed direct form II.
+#[derive(Clone, Copy, Default)]
+struct State {
+    z1: f64,
+    z2: f64,
+}
+
+impl State {
+    fn process(&mut self, c: &Coefficients, x: f64) -> f64 {
+        let y = c.b0 * x + self.z1;
+        self.z1 = c.b1 * x - c.a1 * y + self.z2;
+        self.z2 = c.b2 * x - c.a2 * y;
+        y
+    }
+}
+
+/// Measures integrated loudness of a signal according to EBU R128.  Feed it
+/// interleaved samples with `process` and call `finish` at the end.
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: [Coefficients; 2],
+    /// Two filter states for each channel.
+    states: Vec<[State; 2]>,
+    /// Number of frames in a 100ms sub-block.
+    sub_block_frames: usize,
+    /// Frames accumulated in the current sub-block.
+    frames: usize,
+    /// Sum of squares of the current sub-block, for each channel.
+    energy: Vec<f64>,
+    /// Weighted mean square of each complete sub-block.
+    sub_blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            channels,
+            weights: (0..channels)
+                .map(|channel| channel_weight(channel, channels))
+                .collect(),
+            filters: [
+                Coefficients::pre_filter(sample_rate),
+                Coefficients::rlb_filter(sample_rate),
+            ],
+            states: vec![Default::default(); channels],
+            sub_block_frames: (sample_rate as usize / 10).max(1),
+            frames: 0,
+            energy: vec![0.0; channels],
+            sub_blocks: Vec::new(),
+            peak: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.peak = self.peak.max(sample.abs());
+                let [pre, rlb] = &mut self.states[channel];
+                let filtered = rlb.process(
+                    &self.filters[1],
+                    pre.process(&self.filters[0], sample as f64),
+                );
+                self.energy[channel] += filtered * filtered;
+            }
+            self.frames += 1;
+            if self.frames == self.sub_block_frames {
+                let weighted: f64 = self
+                    .energy
+                    .iter()
+                    .zip(&self.weights)
+                    .map(|(energy, weight)| energy * weight)
+                    .sum();
+                self.sub_blocks.push(weighted / self.frames as f64);
+                self.energy.fill(0.0);
+                self.frames = 0;
+            }
+        }
+    }
+
+    pub fn finish(self) -> Loudness {
+        // Gating blocks are 400ms long and overlap by 75%, so each one spans
+        // four consecutive sub-blocks.  Incomplete blocks at the end are
+        // dropped.
+        let blocks = self
+            .sub_blocks
+            .windows(4)
+            .map(|window| window.iter().sum::<f64>() / 4.0)
+            .collect();
+        Loudness {
+            blocks,
+            peak: self.peak,
+        }
+    }
+}
+
+/// Channel weights from ITU-R BS.1770.  The LFE channel is left out of the
+/// measurement and the surround channels are boosted by ~1.5 dB.  Symphonia
+/// orders 5.1 audio as FL, FR, FC, LFE, RL, RR.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4 | 5) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Result of a loudness measurement.
+#[derive(Clone)]
+pub struct Loudness {
+    /// Weighted mean square of each gating block.
+    blocks: Vec<f64>,
+    peak: f32,
+}
+
+impl Loudness {
+    /// Loudness of a whole album.  This gates the blocks of all tracks
+    /// together, as opposed to averaging the loudness of each track.
+    pub fn combine<'a>(parts: impl IntoIterator<Item = &'a Loudness>) -> Self {
+        let mut combined = Self {
+            blocks: Vec::new(),
+            peak: 0.0,
+        };
+        for part in parts {
+            combined.blocks.extend_from_slice(&part.blocks);
+            combined.peak = combined.peak.max(part.peak);
+        }
+        combined
+    }
+
+    /// Integrated loudness in LUFS, or `None` for silence or signals that are
+    /// too short to be measured.
+    pub fn integrated(&self) -> Option<f64> {
+        const ABSOLUTE_GATE: f64 = -70.0;
+        const RELATIVE_GATE: f64 = -10.0;
+
+        let above_absolute: Vec<f64> = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|&block| loudness(block) > ABSOLUTE_GATE)
+            .collect();
+        if above_absolute.is_empty() {
+            return None;
+        }
+        let relative_gate = loudness(mean(&above_absolute)) + RELATIVE_GATE;
+        let above_relative: Vec<f64> = above_absolute
+            .into_iter()
+            .filter(|&block| loudness(block) > relative_gate)
+            .collect();
+        Some(loudness(mean(&above_relative)))
+    }
+
+    /// Sample peak, in linear scale.
+    pub fn peak(&self) -> f32 {
+        self.peak
+    }
+
+    /// Gain to apply to reach `REFERENCE_LOUDNESS`.
+    pub fn gain_db(&self) -> f32 {
+        self.integrated()
+            .map_or(0.0, |integrated| (REFERENCE_LOUDNESS - integrated) as f32)
+    }
+}
+
+fn loudness(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Decode the whole file at `path` and measure its loudness.
+pub fn analyze_file(path: &Path, codec: AudioCodecFormat) -> Result<Loudness, Error> {
+    const DEFAULT_MAX_FRAMES: u64 = 8 * 1024;
+
+    let mut decoder = AudioDecoder::new(fs::File::open(path)?, codec)?;
+    let spec = decoder.signal_spec();
+    let max_frames = decoder
+        .codec_params()
+        .max_frames_per_packet
+        .unwrap_or(DEFAULT_MAX_FRAMES);
+    let mut packet = SampleBuffer::new(max_frames, spec);
+    let mut meter = LoudnessMeter::new(spec.rate, spec.channels.count());
+    while decoder.read_packet(&mut packet).is_some() {
+        meter.process(packet.samples());
+    }
+    Ok(meter.finish())
+}
+
+/// Look up normalization data of a local file.  ReplayGain tags take
+/// precedence over results of our own analysis.  If neither is available, the
+/// file is sent to the scanner for analysis and `None` is returned.
+pub fn local_normalization(
+    path: &Path,
+    cache: &CacheHandle,
+    scanner: &Sender<LoudnessMsg>,
+) -> Option<NormalizationData> {
+    if let Some(replay_gain) = TrackProbe::new(path).ok().and_then(|p| p.replay_gain) {
+        return Some(replay_gain);
+    }
+    if let Some(analyzed) = cache.get_loudness(path) {
+        return Some(analyzed);
+    }
+    let _ = scanner.send(LoudnessMsg::Scan(path.to_path_buf()));
+    None
+}
+
+pub enum LoudnessMsg {
+    Scan(PathBuf),
+}
+
+/// Background worker analysing loudness of local files.  Files are scanned
+/// together with the other files of their album, i.e. files in the same
+/// directory tagged with the same album name, so the album gain can be
+/// computed as well.  Results are saved to the cache.
+pub struct LoudnessScanner {
+    cache: CacheHandle,
+}
+
+impl LoudnessScanner {
+    pub fn new(cache: CacheHandle) -> Self {
+        Self { cache }
+    }
+
+    fn scan(&self, path: &Path) -> Result<(), Error> {
+        // The same album might have been queued multiple times.
+        if self.cache.get_loudness(path).is_some() {
+            return Ok(());
+        }
+        let probe = TrackProbe::new(path)?;
+
+        let mut tracks = Vec::new();
+        for track_path in album_files(path, &probe) {
+            let Some(codec) = TrackProbe::new(&track_path)
+                .ok()
+                .and_then(|p| AudioCodecFormat::from_codec(p.codec))
+            else {
+                continue;
+            };
+            match analyze_file(&track_path, codec) {
+                Ok(loudness) => tracks.push((track_path, loudness)),
+                Err(err) => log::warn!("failed to analyze {:?}: {}", track_path, err),
+            }
+        }
+
+        let album = Loudness::combine(tracks.iter().map(|(_, loudness)| loudness));
+        for (track_path, loudness) in &tracks {
+            log::info!(
+                "analyzed loudness of {:?}: {:?} LUFS",
+                track_path,
+                loudness.integrated()
+            );
+            let data = NormalizationData::new(
+                loudness.gain_db(),
+                loudness.peak(),
+                album.gain_db(),
+                album.peak(),
+            );
+            if let Err(err) = self.cache.save_loudness(track_path, &data) {
+                log::warn!("failed to save loudness to cache: {:?}", err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Files belonging to the same album as the file at `path`, including itself.
+fn album_files(path: &Path, probe: &TrackProbe) -> Vec<PathBuf> {
+    let (Some(album), Some(dir)) = (&probe.album, path.parent()) else {
+        return vec![path.to_path_buf()];
+    };
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|sibling| {
+            sibling == path
+                || TrackProbe::new(sibling).is_ok_and(|p| p.album.as_ref() == Some(album))
+        })
+        .collect();
+    if !files.iter().any(|file| file == path) {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+impl Actor for LoudnessScanner {
+    type Message = LoudnessMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: LoudnessMsg) -> Result<Act<Self>, Self::Error> {
+        match msg {
+            LoudnessMsg::Scan(path) => {
+                if let Err(err) = self.scan(&path) {
+                    log::warn!("failed to scan loudness of {:?}: {}", path, err);
+                }
+            }
+        }
+        Ok(Act::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(sample_rate: u32, frequency: f32, amplitude: f32, seconds: usize) -> Vec<f32> {
+        (0..sample_rate as usize * seconds)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let sample = amplitude * (2.0 * std::f32::consts::PI * frequency * t).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_stereo_sine_at_its_level() {
+        // EBU Tech 3341: a 1 kHz stereo sine at -20 dBFS reads -20 LUFS.
+        for sample_rate in [44100, 48000] {
+            let mut meter = LoudnessMeter::new(sample_rate, 2);
+            meter.process(&sine(sample_rate, 1000.0, 0.1, 5));
+            let loudness = meter.finish();
+            let integrated = loudness.integrated().unwrap();
+            assert!((integrated + 20.0).abs() < 0.1, "{}", integrated);
+            assert!((loudness.gain_db() - 2.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn silence_is_not_measured() {
+        let mut meter = LoudnessMeter::new(44100, 2);
+        meter.process(&vec![0.0; 44100 * 2 * 2]);
+        let loudness = meter.finish();
+        assert_eq!(loudness.integrated(), None);
+        assert_eq!(loudness.gain_db(), 0.0);
+    }
+
+    #[test]
+    fn album_gates_blocks_of_all_tracks() {
+        let mut loud = LoudnessMeter::new(44100, 2);
+        loud.process(&sine(44100, 1000.0, 0.1, 5));
+        let mut quiet = LoudnessMeter::new(44100, 2);
+        quiet.process(&sine(44100, 1000.0, 0.001, 5));
+        let (loud, quiet) = (loud.finish(), quiet.finish());
+        // The quiet track falls below the relative gate of the album.
+        let album = Loudness::combine([&loud, &quiet]).integrated().unwrap();
+        assert!((album - loud.integrated().unwrap()).abs() < 0.1);
+    }
+}
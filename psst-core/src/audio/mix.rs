@@ -0,0 +1,451 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use crossbeam_channel::Receiver;
+
+use crate::audio::{fade::SettingsFade, source::AudioSource};
+
+/// Settings of the channel mixing, applied on top of the mapping between the
+/// channel layouts of the source and of the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelMixSettings {
+    /// Play the same downmixed signal on every speaker, for listeners who hear
+    /// only on one side.
+    pub mono: bool,
+    /// Left/right balance in the `-1.0..=1.0` range.  Negative values quiet
+    /// down the right side, positive the left one.
+    pub balance: f32,
+}
+
+impl Default for ChannelMixSettings {
+    fn default() -> Self {
+        Self {
+            mono: false,
+            balance: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Speaker {
+    /// The only channel of a mono signal.
+    Mono,
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    Lfe,
+    BackLeft,
+    BackRight,
+    SideLeft,
+    SideRight,
+    /// Channel of a layout we do not know, it is only kept if the other side
+    /// has the same count of channels.
+    Other,
+}
+
+impl Speaker {
+    /// Speakers of a layout with `channels` channels, in the order of the WAVE
+    /// format, which is also the one our decoders produce.
+    fn layout(channels: usize) -> Vec<Self> {
+        use Speaker::*;
+
+        match channels {
+            1 => vec![Mono],
+            2 => vec![FrontLeft, FrontRight],
+            3 => vec![FrontLeft, FrontRight, FrontCenter],
+            4 => vec![FrontLeft, FrontRight, BackLeft, BackRight],
+            5 => vec![FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+            6 => vec![FrontLeft, FrontRight, FrontCenter, Lfe, BackLeft, BackRight],
+            8 => vec![
+                FrontLeft,
+                FrontRight,
+                FrontCenter,
+                Lfe,
+                BackLeft,
+                BackRight,
+                SideLeft,
+                SideRight,
+            ],
+            // Take the first two channels of unknown layouts as the front ones.
+            n => [FrontLeft, FrontRight]
+                .into_iter()
+                .chain(std::iter::repeat(Other))
+                .take(n)
+                .collect(),
+        }
+    }
+
+    fn is_left(self) -> bool {
+        matches!(self, Self::FrontLeft | Self::BackLeft | Self::SideLeft)
+    }
+
+    fn is_right(self) -> bool {
+        matches!(self, Self::FrontRight | Self::BackRight | Self::SideRight)
+    }
+
+    /// Speakers of `layout` that play the signal meant for `self`, if it is
+    /// missing, with their gains.  Follows the downmix of ITU-R BS.775, the
+    /// LFE channel is dropped.
+    fn fold(self, layout: &[Self]) -> Vec<(Self, f32)> {
+        use Speaker::*;
+
+        let has = |speaker| layout.contains(&speaker);
+        let fold_into = |speakers: &[Self], gain| {
+            speakers
+                .iter()
+                .filter(|&&speaker| has(speaker))
+                .map(|&speaker| (speaker, gain))
+                .collect::<Vec<_>>()
+        };
+        if has(self) {
+            return vec![(self, 1.0)];
+        }
+        match self {
+            Mono if has(FrontCenter) => vec![(FrontCenter, 1.0)],
+            Mono => fold_into(&[FrontLeft, FrontRight], 1.0),
+            FrontLeft | BackLeft | SideLeft if has(Mono) => vec![(Mono, 0.5)],
+            FrontRight | BackRight | SideRight if has(Mono) => vec![(Mono, 0.5)],
+            FrontCenter if has(Mono) => vec![(Mono, FRAC_1_SQRT_2)],
+            FrontCenter => fold_into(&[FrontLeft, FrontRight], FRAC_1_SQRT_2),
+            BackLeft if has(SideLeft) => vec![(SideLeft, 1.0)],
+            BackRight if has(SideRight) => vec![(SideRight, 1.0)],
+            SideLeft if has(BackLeft) => vec![(BackLeft, 1.0)],
+            SideRight if has(BackRight) => vec![(BackRight, 1.0)],
+            BackLeft | SideLeft => fold_into(&[FrontLeft], FRAC_1_SQRT_2),
+            BackRight | SideRight => fold_into(&[FrontRight], FRAC_1_SQRT_2),
+            FrontLeft | FrontRight | Lfe | Other => Vec::new(),
+        }
+    }
+}
+
+/// Matrix mapping the channels of an input frame to the channels of an output
+/// frame.
+#[derive(Debug, Clone)]
+struct MixMatrix {
+    /// Indexed by `output * inputs + input`.
+    gains: Vec<f32>,
+    inputs: usize,
+    outputs: usize,
+}
+
+impl MixMatrix {
+    fn new(inputs: usize, outputs: usize, settings: &ChannelMixSettings) -> Self {
+        let mut matrix = if settings.mono {
+            // Downmix to mono first, then play it on every full-range speaker.
+            let mono = Self::between_layouts(inputs, 1);
+            let output_layout = Speaker::layout(outputs);
+            let mut matrix = Self::zero(inputs, outputs);
+            for (output, speaker) in output_layout.into_iter().enumerate() {
+                if speaker != Speaker::Lfe {
+                    matrix.row_mut(output).copy_from_slice(mono.row(0));
+                }
+            }
+            matrix
+        } else {
+            Self::between_layouts(inputs, outputs)
+        };
+        matrix.apply_balance(settings.balance);
+        matrix
+    }
+
+    fn zero(inputs: usize, outputs: usize) -> Self {
+        Self {
+            gains: vec![0.0; inputs * outputs],
+            inputs,
+            outputs,
+        }
+    }
+
+    fn between_layouts(inputs: usize, outputs: usize) -> Self {
+        let mut matrix = Self::zero(inputs, outputs);
+        if inputs == outputs {
+            // Same layout, nothing to mix.
+            for channel in 0..inputs {
+                matrix.gains[channel * inputs + channel] = 1.0;
+            }
+            return matrix;
+        }
+        let input_layout = Speaker::layout(inputs);
+        let output_layout = Speaker::layout(outputs);
+        for (input, speaker) in input_layout.into_iter().enumerate() {
+            for (target, gain) in speaker.fold(&output_layout) {
+                if let Some(output) = output_layout.iter().position(|&s| s == target) {
+                    matrix.gains[output * inputs + input] += gain;
+                }
+            }
+        }
+        // Scale down the rows summing up more than one input, so a downmix of
+        // loud channels does not clip.
+        for output in 0..outputs {
+            let sum: f32 = matrix.row(output).iter().sum();
+            if sum > 1.0 {
+                matrix
+                    .row_mut(output)
+                    .iter_mut()
+                    .for_each(|gain| *gain /= sum);
+            }
+        }
+        matrix
+    }
+
+    fn apply_balance(&mut self, balance: f32) {
+        let balance = balance.clamp(-1.0, 1.0);
+        let left = (1.0 - balance).min(1.0);
+        let right = (1.0 + balance).min(1.0);
+        for (output, speaker) in Speaker::layout(self.outputs).into_iter().enumerate() {
+            let gain = if speaker.is_left() {
+                left
+            } else if speaker.is_right() {
+                right
+            } else {
+                continue;
+            };
+            self.row_mut(output).iter_mut().for_each(|g| *g *= gain);
+        }
+    }
+
+    fn row(&self, output: usize) -> &[f32] {
+        &self.gains[output * self.inputs..(output + 1) * self.inputs]
+    }
+
+    fn row_mut(&mut self, output: usize) -> &mut [f32] {
+        &mut self.gains[output * self.inputs..(output + 1) * self.inputs]
+    }
+
+    fn process(&self, input: &[f32], output: &mut [f32]) {
+        for (i, o) in input
+            .chunks_exact(self.inputs)
+            .zip(output.chunks_exact_mut(self.outputs))
+        {
+            for (sample, row) in o.iter_mut().zip(self.gains.chunks_exact(self.inputs)) {
+                *sample = row.iter().zip(i).map(|(gain, s)| gain * s).sum();
+            }
+        }
+    }
+}
+
+/// Maps the channels of the inner source to `output_channels` channels,
+/// downmixing or upmixing between the standard layouts.  New settings can be
+/// sent through `settings_recv` at any time, the output of the previous gains
+/// is then faded into the output of the new ones.
+pub struct ChannelMixSource<S> {
+    source: S,
+    matrix: SettingsFade<ChannelMixSettings, MixMatrix>,
+    buffer: Vec<f32>,
+    /// Output of the previous matrix, while fading.
+    faded: Vec<f32>,
+}
+
+impl<S> ChannelMixSource<S>
+where
+    S: AudioSource,
+{
+    pub fn new(
+        source: S,
+        output_channels: usize,
+        settings: &ChannelMixSettings,
+        settings_recv: Receiver<ChannelMixSettings>,
+    ) -> Self {
+        const BUFFER_FRAMES: usize = 4 * 1024;
+
+        let input_channels = source.channel_count();
+        Self {
+            matrix: SettingsFade::new(
+                MixMatrix::new(input_channels, output_channels, settings),
+                settings_recv,
+                source.sample_rate(),
+            ),
+            buffer: vec![0.0; BUFFER_FRAMES * input_channels],
+            faded: vec![0.0; BUFFER_FRAMES * output_channels],
+            source,
+        }
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        let (matrix, previous) = self.matrix.stages_mut();
+        matrix.process(input, output);
+        let Some(previous) = previous else {
+            return;
+        };
+        let old = &mut self.faded[..output.len()];
+        previous.process(input, old);
+        let channels = matrix.outputs;
+        self.matrix.fade(output, old, channels);
+    }
+}
+
+impl<S> AudioSource for ChannelMixSource<S>
+where
+    S: AudioSource,
+{
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        self.matrix
+            .update(|settings, matrix| MixMatrix::new(matrix.inputs, matrix.outputs, settings));
+
+        let (inputs, outputs) = (self.matrix.stage().inputs, self.matrix.stage().outputs);
+        if inputs == 0 || outputs == 0 {
+            return 0;
+        }
+        let mut total = 0;
+        while total + outputs <= output.len() {
+            let frames = ((output.len() - total) / outputs).min(self.buffer.len() / inputs);
+            let mut input = std::mem::take(&mut self.buffer);
+            let written = self.source.write(&mut input[..frames * inputs]);
+            let written_frames = written / inputs;
+            let end = total + written_frames * outputs;
+            self.process(&input[..written_frames * inputs], &mut output[total..end]);
+            self.buffer = input;
+            total = end;
+            if written_frames < frames {
+                break;
+            }
+        }
+        total
+    }
+
+    fn channel_count(&self) -> usize {
+        self.matrix.stage().outputs
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mix(inputs: usize, outputs: usize, settings: ChannelMixSettings, frame: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.0; outputs];
+        MixMatrix::new(inputs, outputs, &settings).process(frame, &mut output);
+        output
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn keeps_same_layout() {
+        let frame = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6];
+        assert_close(&mix(6, 6, Default::default(), &frame), &frame);
+    }
+
+    #[test]
+    fn upmixes_mono() {
+        let settings = ChannelMixSettings::default();
+        assert_close(&mix(1, 2, settings, &[0.5]), &[0.5, 0.5]);
+        // Mono goes to the center speaker, if there is one.
+        assert_close(
+            &mix(1, 6, settings, &[0.5]),
+            &[0.0, 0.0, 0.5, 0.0, 0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn upmixes_stereo_to_front() {
+        let settings = ChannelMixSettings::default();
+        assert_close(
+            &mix(2, 6, settings, &[0.25, 0.5]),
+            &[0.25, 0.5, 0.0, 0.0, 0.0, 0.0],
+        );
+        assert_close(&mix(2, 4, settings, &[0.25, 0.5]), &[0.25, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn downmixes_stereo_to_mono() {
+        let settings = ChannelMixSettings::default();
+        assert_close(&mix(2, 1, settings, &[0.2, 0.6]), &[0.4]);
+    }
+
+    #[test]
+    fn downmixes_surround_to_stereo() {
+        let settings = ChannelMixSettings::default();
+        // The LFE channel is dropped, center and surrounds are folded in at
+        // -3 dB and each side is normalized by its total gain.
+        let norm = 1.0 + 2.0 * FRAC_1_SQRT_2;
+        let frame = [1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        assert_close(
+            &mix(6, 2, settings, &frame),
+            &[(1.0 + FRAC_1_SQRT_2) / norm, (2.0 * FRAC_1_SQRT_2) / norm],
+        );
+        // Silence on every channel but the LFE one stays silent.
+        assert_close(
+            &mix(6, 2, settings, &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]),
+            &[0.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn folds_side_into_back_speakers() {
+        let settings = ChannelMixSettings::default();
+        let frame = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        assert_close(
+            &mix(8, 6, settings, &frame),
+            &[0.0, 0.0, 0.0, 0.0, 0.5, 0.0],
+        );
+    }
+
+    #[test]
+    fn mono_plays_downmix_everywhere() {
+        let settings = ChannelMixSettings {
+            mono: true,
+            ..Default::default()
+        };
+        assert_close(&mix(2, 2, settings, &[1.0, 0.0]), &[0.5, 0.5]);
+        assert_close(
+            &mix(2, 6, settings, &[1.0, 0.0]),
+            &[0.5, 0.5, 0.5, 0.0, 0.5, 0.5],
+        );
+    }
+
+    #[test]
+    fn balance_quiets_down_one_side() {
+        let settings = ChannelMixSettings {
+            balance: -0.5,
+            ..Default::default()
+        };
+        assert_close(&mix(2, 2, settings, &[1.0, 1.0]), &[1.0, 0.5]);
+        assert_close(
+            &mix(6, 6, settings, &[1.0; 6]),
+            &[1.0, 0.5, 1.0, 1.0, 1.0, 0.5],
+        );
+        let settings = ChannelMixSettings {
+            balance: 1.0,
+            ..Default::default()
+        };
+        assert_close(&mix(2, 2, settings, &[1.0, 1.0]), &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn writes_whole_frames() {
+        struct Ramp(f32);
+
+        impl AudioSource for Ramp {
+            fn write(&mut self, output: &mut [f32]) -> usize {
+                for sample in output.iter_mut() {
+                    *sample = self.0;
+                    self.0 += 1.0;
+                }
+                output.len()
+            }
+
+            fn channel_count(&self) -> usize {
+                1
+            }
+
+            fn sample_rate(&self) -> u32 {
+                44_100
+            }
+        }
+
+        let (_send, recv) = crossbeam_channel::unbounded();
+        let mut source = ChannelMixSource::new(Ramp(0.0), 2, &Default::default(), recv);
+        let mut output = [0.0; 7];
+        assert_eq!(source.write(&mut output), 6);
+        assert_eq!(&output[..6], &[0.0, 0.0, 1.0, 1.0, 2.0, 2.0]);
+    }
+}
@@ -1,7 +1,16 @@
+pub mod analyzer;
 pub mod decode;
 pub mod decrypt;
+pub mod dither;
+pub mod equalizer;
+pub mod fade;
+pub mod limiter;
+pub mod loudness;
+pub mod mix;
 pub mod normalize;
 pub mod output;
 pub mod probe;
 pub mod resample;
+pub mod silence;
 pub mod source;
+pub mod stretch;
@@ -1,18 +1,19 @@
 use std::{
     io,
-    io::{Read, Seek, SeekFrom},
+    io::{Read, Seek, SeekFrom, Write},
 };
 
-use byteorder::{ReadBytesExt, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum NormalizationLevel {
     None,
     Track,
     Album,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct NormalizationData {
     track_gain_db: f32,
     track_peak: f32,
@@ -21,11 +22,26 @@ pub struct NormalizationData {
 }
 
 impl NormalizationData {
+    pub fn new(track_gain_db: f32, track_peak: f32, album_gain_db: f32, album_peak: f32) -> Self {
+        Self {
+            track_gain_db,
+            track_peak,
+            album_gain_db,
+            album_peak,
+        }
+    }
+
     pub fn parse(mut file: impl Read + Seek) -> io::Result<Self> {
         const NORMALIZATION_OFFSET: u64 = 144;
 
         file.seek(SeekFrom::Start(NORMALIZATION_OFFSET))?;
 
+        Self::read(file)
+    }
+
+    /// Read the normalization data in the same layout Spotify uses in the file
+    /// header, four little-endian `f32`s.
+    pub fn read(mut file: impl Read) -> io::Result<Self> {
         let track_gain_db = file.read_f32::<LE>()?;
         let track_peak = file.read_f32::<LE>()?;
         let album_gain_db = file.read_f32::<LE>()?;
@@ -39,17 +55,25 @@ impl NormalizationData {
         })
     }
 
-    pub fn factor_for_level(&self, level: NormalizationLevel, pregain: f32) -> f32 {
-        match level {
-            NormalizationLevel::None => 1.0,
-            NormalizationLevel::Track => Self::factor(pregain, self.track_gain_db, self.track_peak),
-            NormalizationLevel::Album => Self::factor(pregain, self.album_gain_db, self.album_peak),
-        }
+    pub fn write(&self, mut file: impl Write) -> io::Result<()> {
+        file.write_f32::<LE>(self.track_gain_db)?;
+        file.write_f32::<LE>(self.track_peak)?;
+        file.write_f32::<LE>(self.album_gain_db)?;
+        file.write_f32::<LE>(self.album_peak)?;
+        Ok(())
     }
 
-    fn factor(pregain: f32, gain: f32, peak: f32) -> f32 {
+    /// Returns the factor to apply to the samples.  Unless the peaks are
+    /// `limited` further down the line, it is lowered to keep them from
+    /// clipping.
+    pub fn factor_for_level(&self, level: NormalizationLevel, pregain: f32, limited: bool) -> f32 {
+        let (gain, peak) = match level {
+            NormalizationLevel::None => return 1.0,
+            NormalizationLevel::Track => (self.track_gain_db, self.track_peak),
+            NormalizationLevel::Album => (self.album_gain_db, self.album_peak),
+        };
         let mut nf = f32::powf(10.0, (pregain + gain) / 20.0);
-        if nf * peak > 1.0 {
+        if !limited && nf * peak > 1.0 {
             nf = 1.0 / peak;
         }
         nf
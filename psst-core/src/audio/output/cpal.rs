@@ -1,3 +1,8 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crossbeam_channel::{bounded, Receiver, Sender};
 use num_traits::Pow;
@@ -5,7 +10,11 @@ use num_traits::Pow;
 use crate::{
     actor::{Act, Actor, ActorHandle},
     audio::{
-        output::{AudioOutput, AudioSink},
+        dither::Dither,
+        output::{
+            switch::{DeviceStream, SwitchableStream},
+            AudioOutput, AudioSink, OutputConfig,
+        },
         source::{AudioSource, Empty},
     },
     error::Error,
@@ -17,30 +26,35 @@ pub struct CpalOutput {
 }
 
 impl CpalOutput {
-    pub fn open() -> Result<Self, Error> {
-        // Open the default output device.
-        let device = cpal::default_host()
-            .default_output_device()
-            .ok_or(cpal::DefaultStreamConfigError::DeviceNotAvailable)?;
-
-        if let Ok(name) = device.name() {
-            log::info!("using audio device: {:?}", name);
-        }
+    /// Open the output device named in `config`, or the default one if there
+    /// is no such device.
+    pub fn open(config: OutputConfig) -> Result<Self, Error> {
+        let device = Self::find_device(config.device.as_deref())?;
 
         // Get the default device config, so we know what sample format and sample rate
         // the device supports.
         let supported = Self::preferred_output_config(&device)?;
+        let settings = StreamSettings {
+            config: supported.config(),
+            format: supported.sample_format(),
+            dither: config.dither,
+            callback_frames: match supported.buffer_size() {
+                cpal::SupportedBufferSize::Range { max, .. } => {
+                    (*max as usize).clamp(1, MAX_CALLBACK_FRAMES)
+                }
+                cpal::SupportedBufferSize::Unknown => MAX_CALLBACK_FRAMES,
+            },
+        };
 
         let (callback_send, callback_recv) = bounded(16);
 
         let handle = Stream::spawn_with_default_cap("audio_output", {
-            let config = supported.config();
-            // TODO: Support additional sample formats.
-            move |this| Stream::open(device, config, callback_recv, this).unwrap()
+            move |this| Stream::open(device, settings, callback_recv, this).unwrap()
         });
         let sink = CpalSink {
             channel_count: supported.channels(),
-            sample_rate: supported.sample_rate(),
+            sample_rate: Arc::new(AtomicU32::new(supported.sample_rate().0)),
+            bit_perfect: config.bit_perfect,
             stream_send: handle.sender(),
             callback_send,
         };
@@ -51,20 +65,62 @@ impl CpalOutput {
         })
     }
 
+    /// Names of the available output devices.
+    pub fn devices() -> Result<Vec<String>, Error> {
+        let devices = cpal::default_host().output_devices()?;
+        Ok(devices.filter_map(|device| device.name().ok()).collect())
+    }
+
+    fn find_device(name: Option<&str>) -> Result<cpal::Device, Error> {
+        let host = cpal::default_host();
+        let found = match name {
+            Some(name) => {
+                let device = host
+                    .output_devices()?
+                    .find(|device| device.name().is_ok_and(|n| n == name));
+                if device.is_none() {
+                    log::warn!("audio device {:?} not found, using the default one", name);
+                }
+                device
+            }
+            None => None,
+        };
+        let device = match found {
+            Some(device) => device,
+            None => host
+                .default_output_device()
+                .ok_or(cpal::DefaultStreamConfigError::DeviceNotAvailable)?,
+        };
+        if let Ok(name) = device.name() {
+            log::info!("using audio device: {:?}", name);
+        }
+        Ok(device)
+    }
+
     fn preferred_output_config(
         device: &cpal::Device,
     ) -> Result<cpal::SupportedStreamConfig, Error> {
-        const PREFERRED_SAMPLE_FORMAT: cpal::SampleFormat = cpal::SampleFormat::F32;
+        // In the order of preference.  We convert to any of them, but the integer
+        // ones lose precision.
+        const PREFERRED_SAMPLE_FORMATS: [cpal::SampleFormat; 4] = [
+            cpal::SampleFormat::F32,
+            cpal::SampleFormat::I32,
+            cpal::SampleFormat::I16,
+            cpal::SampleFormat::U16,
+        ];
         const PREFERRED_SAMPLE_RATE: cpal::SampleRate = cpal::SampleRate(44_100);
         const PREFERRED_CHANNELS: cpal::ChannelCount = 2;
 
-        for s in device.supported_output_configs()? {
-            let rates = s.min_sample_rate()..=s.max_sample_rate();
-            if s.channels() == PREFERRED_CHANNELS
-                && s.sample_format() == PREFERRED_SAMPLE_FORMAT
-                && rates.contains(&PREFERRED_SAMPLE_RATE)
-            {
-                return Ok(s.with_sample_rate(PREFERRED_SAMPLE_RATE));
+        let supported: Vec<_> = device.supported_output_configs()?.collect();
+        for format in PREFERRED_SAMPLE_FORMATS {
+            for s in &supported {
+                let rates = s.min_sample_rate()..=s.max_sample_rate();
+                if s.channels() == PREFERRED_CHANNELS
+                    && s.sample_format() == format
+                    && rates.contains(&PREFERRED_SAMPLE_RATE)
+                {
+                    return Ok(s.clone().with_sample_rate(PREFERRED_SAMPLE_RATE));
+                }
             }
         }
 
@@ -83,7 +139,9 @@ impl AudioOutput for CpalOutput {
 #[derive(Clone)]
 pub struct CpalSink {
     channel_count: cpal::ChannelCount,
-    sample_rate: cpal::SampleRate,
+    /// Changes when a bit-perfect output follows the rate of the source.
+    sample_rate: Arc<AtomicU32>,
+    bit_perfect: bool,
     callback_send: Sender<CallbackMsg>,
     stream_send: Sender<StreamMsg>,
 }
@@ -108,7 +166,7 @@ impl AudioSink for CpalSink {
     }
 
     fn sample_rate(&self) -> u32 {
-        self.sample_rate.0
+        self.sample_rate.load(Ordering::Relaxed)
     }
 
     fn set_volume(&self, volume: f32) {
@@ -134,47 +192,204 @@ impl AudioSink for CpalSink {
         self.pause();
     }
 
+    fn set_device(&self, name: Option<String>) {
+        self.send_to_stream(StreamMsg::SetDevice(name));
+    }
+
+    fn prefer_sample_rate(&self, rate: u32) {
+        if !self.bit_perfect || rate == self.sample_rate() {
+            return;
+        }
+        let (reply_send, reply_recv) = bounded(1);
+        self.send_to_stream(StreamMsg::SetSampleRate(rate, reply_send));
+        if let Ok(rate) = reply_recv.recv() {
+            self.sample_rate.store(rate, Ordering::Relaxed);
+        }
+    }
+
     fn close(&self) {
         self.send_to_stream(StreamMsg::Close);
     }
 }
 
+/// Upper bound of the buffer the data callback converts the samples in.
+const MAX_CALLBACK_FRAMES: usize = 16 * 1024;
+
+/// How the stream is opened.  Kept when the stream moves to another device.
+#[derive(Clone)]
+struct StreamSettings {
+    config: cpal::StreamConfig,
+    format: cpal::SampleFormat,
+    dither: bool,
+    /// Frames the data callback is expected to ask for at most.
+    callback_frames: usize,
+}
+
 struct Stream {
-    stream: cpal::Stream,
-    _device: cpal::Device,
+    output: SwitchableStream<cpal::Stream, StreamCallback>,
+    device: cpal::Device,
+    settings: StreamSettings,
+    stream_send: Sender<StreamMsg>,
 }
 
 impl Stream {
     fn open(
         device: cpal::Device,
-        config: cpal::StreamConfig,
+        settings: StreamSettings,
         callback_recv: Receiver<CallbackMsg>,
         stream_send: Sender<StreamMsg>,
     ) -> Result<Self, Error> {
-        let mut callback = StreamCallback {
+        let callback = StreamCallback {
             callback_recv,
-            stream_send,
+            stream_send: stream_send.clone(),
             source: Box::new(Empty),
             volume: 1.0, // We start with the full volume.
             state: CallbackState::Paused,
         };
+        let output = SwitchableStream::open(callback, |callback, generation| {
+            Self::build(&device, &settings, callback, &stream_send, generation)
+        })?;
+
+        Ok(Self {
+            output,
+            device,
+            settings,
+            stream_send,
+        })
+    }
+
+    fn build(
+        device: &cpal::Device,
+        settings: &StreamSettings,
+        callback: &Arc<Mutex<StreamCallback>>,
+        stream_send: &Sender<StreamMsg>,
+        generation: u64,
+    ) -> Result<cpal::Stream, Error> {
+        log::info!(
+            "opening output stream: {:?}, {:?}",
+            settings.config,
+            settings.format
+        );
+        match settings.format {
+            cpal::SampleFormat::I8 => {
+                Self::build_with::<i8>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::I16 => {
+                Self::build_with::<i16>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::I32 => {
+                Self::build_with::<i32>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::U8 => {
+                Self::build_with::<u8>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::U16 => {
+                Self::build_with::<u16>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::U32 => {
+                Self::build_with::<u32>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::F32 => {
+                Self::build_with::<f32>(device, settings, callback, stream_send, generation)
+            }
+            cpal::SampleFormat::F64 => {
+                Self::build_with::<f64>(device, settings, callback, stream_send, generation)
+            }
+            _ => Err(cpal::BuildStreamError::StreamConfigNotSupported.into()),
+        }
+    }
 
-        log::info!("opening output stream: {:?}", config);
+    fn build_with<T>(
+        device: &cpal::Device,
+        settings: &StreamSettings,
+        callback: &Arc<Mutex<StreamCallback>>,
+        stream_send: &Sender<StreamMsg>,
+        generation: u64,
+    ) -> Result<cpal::Stream, Error>
+    where
+        T: cpal::SizedSample + cpal::FromSample<f32>,
+    {
+        let mut dither = (settings.dither && !T::FORMAT.is_float())
+            .then(|| Dither::new(T::FORMAT.sample_size() as u32 * 8));
+        // Sources write f32 samples, we convert them to the format of the device
+        // afterwards.  The buffer is allocated here, the callback must not
+        // allocate, so larger requests are filled in several passes.
+        let mut buffer = vec![0.0; settings.callback_frames * settings.config.channels as usize];
         let stream = device.build_output_stream(
-            &config,
-            move |output, _| {
-                callback.write_samples(output);
+            &settings.config,
+            {
+                let callback = Arc::clone(callback);
+                move |output: &mut [T], _| {
+                    for output in output.chunks_mut(buffer.len()) {
+                        let buffer = &mut buffer[..output.len()];
+                        match callback.try_lock() {
+                            Ok(mut callback) => callback.write_samples(buffer),
+                            // The stream we are replacing has not finished its
+                            // last callback yet.
+                            Err(_) => buffer.fill(0.0),
+                        }
+                        for (o, &s) in output.iter_mut().zip(buffer.iter()) {
+                            *o = match &mut dither {
+                                // Keep the silence digital, so a paused output
+                                // is really silent.
+                                Some(dither) if s != 0.0 => T::from_sample(dither.apply(s)),
+                                _ => T::from_sample(s),
+                            };
+                        }
+                    }
+                }
             },
-            |err| {
-                log::error!("audio output error: {}", err);
+            {
+                let stream_send = stream_send.clone();
+                move |err| {
+                    log::error!("audio output error: {}", err);
+                    if let cpal::StreamError::DeviceNotAvailable = err {
+                        let _ = stream_send.send(StreamMsg::DeviceLost(generation));
+                    }
+                }
             },
             None,
         )?;
+        Ok(stream)
+    }
 
-        Ok(Self {
-            _device: device,
-            stream,
-        })
+    /// Replace the stream with one opened on `device` with `settings`.
+    fn reopen(&mut self, device: cpal::Device, settings: StreamSettings) -> Result<(), Error> {
+        self.output.replace(|callback, generation| {
+            Self::build(&device, &settings, callback, &self.stream_send, generation)
+        })?;
+        self.device = device;
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Move the output to the device called `name`, or to the default one if
+    /// `None`.  The new device has to support the settings of the current
+    /// stream, because the sources are prepared for them.
+    fn switch_device(&mut self, name: Option<&str>) -> Result<(), Error> {
+        let device = CpalOutput::find_device(name)?;
+        self.reopen(device, self.settings.clone())
+    }
+
+    /// Run the device at `rate`, if it supports it with the current channel
+    /// count and sample format.
+    fn switch_sample_rate(&mut self, rate: u32) -> Result<(), Error> {
+        let rate = cpal::SampleRate(rate);
+        if rate == self.settings.config.sample_rate {
+            return Ok(());
+        }
+        let is_supported = self.device.supported_output_configs()?.any(|s| {
+            s.channels() == self.settings.config.channels
+                && s.sample_format() == self.settings.format
+                && (s.min_sample_rate()..=s.max_sample_rate()).contains(&rate)
+        });
+        if !is_supported {
+            log::info!("audio device does not support {} Hz, resampling", rate.0);
+            return Ok(());
+        }
+        let mut settings = self.settings.clone();
+        settings.config.sample_rate = rate;
+        self.reopen(self.device.clone(), settings)
     }
 }
 
@@ -186,30 +401,67 @@ impl Actor for Stream {
         match msg {
             StreamMsg::Pause => {
                 log::debug!("pausing audio output stream");
-                if let Err(err) = self.stream.pause() {
+                if let Err(err) = self.output.pause() {
                     log::error!("failed to stop stream: {}", err);
                 }
                 Ok(Act::Continue)
             }
             StreamMsg::Resume => {
                 log::debug!("resuming audio output stream");
-                if let Err(err) = self.stream.play() {
+                if let Err(err) = self.output.resume() {
                     log::error!("failed to start stream: {}", err);
                 }
                 Ok(Act::Continue)
             }
+            StreamMsg::SetDevice(name) => {
+                if let Err(err) = self.switch_device(name.as_deref()) {
+                    log::error!("failed to switch audio device: {}", err);
+                }
+                Ok(Act::Continue)
+            }
+            StreamMsg::SetSampleRate(rate, reply) => {
+                if let Err(err) = self.switch_sample_rate(rate) {
+                    log::error!("failed to switch sample rate: {}", err);
+                }
+                let _ = reply.send(self.settings.config.sample_rate.0);
+                Ok(Act::Continue)
+            }
+            StreamMsg::DeviceLost(generation) if self.output.is_current(generation) => {
+                log::warn!("audio device is gone, switching to the default one");
+                if let Err(err) = self.switch_device(None) {
+                    log::error!("failed to switch audio device: {}", err);
+                }
+                Ok(Act::Continue)
+            }
+            StreamMsg::DeviceLost(_) => Ok(Act::Continue),
             StreamMsg::Close => {
                 log::debug!("closing audio output stream");
-                let _ = self.stream.pause();
+                let _ = self.output.pause();
                 Ok(Act::Shutdown)
             }
         }
     }
 }
 
+impl DeviceStream for cpal::Stream {
+    fn start(&self) -> Result<(), Error> {
+        Ok(self.play()?)
+    }
+
+    fn stop(&self) -> Result<(), Error> {
+        Ok(self.pause()?)
+    }
+}
+
 enum StreamMsg {
     Pause,
     Resume,
+    SetDevice(Option<String>),
+    /// Reopen the stream at the given sample rate, if possible, and reply with
+    /// the rate in use.
+    SetSampleRate(u32, Sender<u32>),
+    /// Device of the stream of the given generation has been disconnected.
+    DeviceLost(u64),
     Close,
 }
 
@@ -281,6 +533,12 @@ impl From<cpal::DefaultStreamConfigError> for Error {
     }
 }
 
+impl From<cpal::DevicesError> for Error {
+    fn from(err: cpal::DevicesError) -> Error {
+        Error::AudioOutputError(Box::new(err))
+    }
+}
+
 impl From<cpal::SupportedStreamConfigsError> for Error {
     fn from(err: cpal::SupportedStreamConfigsError) -> Error {
         Error::AudioOutputError(Box::new(err))
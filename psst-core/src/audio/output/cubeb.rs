@@ -1,11 +1,19 @@
-use std::{env, ffi::CString, ops::Deref};
+use std::{
+    env,
+    ffi::CString,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
 
 use crossbeam_channel::{bounded, Receiver, Sender};
 
 use crate::{
     actor::{Act, Actor, ActorHandle},
     audio::{
-        output::{AudioOutput, AudioSink},
+        output::{
+            switch::{DeviceStream, SwitchableStream},
+            AudioOutput, AudioSink, OutputConfig,
+        },
         source::{AudioSource, Empty},
     },
     error::Error,
@@ -18,11 +26,15 @@ pub struct CubebOutput {
 }
 
 impl CubebOutput {
-    pub fn open() -> Result<Self, Error> {
+    /// Open the output device named in `config`, or the default one if there
+    /// is no such device.  Cubeb takes f32 samples and resamples them to the
+    /// rate of the device on its own, so there is nothing to dither, and the
+    /// output cannot be bit-perfect.
+    pub fn open(config: OutputConfig) -> Result<Self, Error> {
         let (callback_send, callback_recv) = bounded(16);
 
         let handle = Stream::spawn_with_default_cap("audio_output", {
-            move |_| Stream::open(callback_recv).unwrap()
+            move |this| Stream::open(config.device.as_deref(), callback_recv, this).unwrap()
         });
         let sink = CubebSink {
             callback_send,
@@ -31,6 +43,17 @@ impl CubebOutput {
 
         Ok(Self { handle, sink })
     }
+
+    /// Names of the available output devices.
+    pub fn devices() -> Result<Vec<String>, Error> {
+        let ctx = init_context()?;
+        let devices = ctx.enumerate_devices(cubeb::DeviceType::OUTPUT)?;
+        Ok(devices
+            .iter()
+            .filter_map(|device| device.friendly_name())
+            .map(str::to_owned)
+            .collect())
+    }
 }
 
 impl AudioOutput for CubebOutput {
@@ -47,33 +70,63 @@ const STREAM_CHANNELS: usize = 2;
 const SAMPLE_RATE: u32 = 44_100;
 const STREAM_LATENCY: u32 = 0x1000;
 
+fn init_context() -> Result<cubeb::Context, Error> {
+    // Call CoInitialize() before any other calls to the API.
+    #[cfg(target_os = "windows")]
+    unsafe {
+        let _ = windows::Win32::System::Com::CoInitialize(0 as *mut _);
+    };
+
+    let backend_name = env::var("CUBEB_BACKEND")
+        .ok()
+        .and_then(|s| CString::new(s).ok());
+    let ctx_name = CString::new("Psst").ok();
+    Ok(cubeb::Context::init(
+        ctx_name.as_deref(),
+        backend_name.as_deref(),
+    )?)
+}
+
 struct Stream {
-    #[allow(unused)]
     ctx: cubeb::Context,
-    stream: cubeb::Stream<Frame>,
+    output: SwitchableStream<cubeb::Stream<Frame>, StreamCallback>,
+    stream_send: Sender<StreamMsg>,
+    volume: f32,
 }
 
 impl Stream {
-    fn open(callback_recv: Receiver<CallbackMsg>) -> Result<Self, Error> {
-        // Call CoInitialize() before any other calls to the API.
-        #[cfg(target_os = "windows")]
-        unsafe {
-            let _ = windows::Win32::System::Com::CoInitialize(0 as *mut _);
-        };
-
-        let backend_name = env::var("CUBEB_BACKEND")
-            .ok()
-            .and_then(|s| CString::new(s).ok());
-        let ctx_name = CString::new("Psst").ok();
-        let ctx = cubeb::Context::init(ctx_name.as_deref(), backend_name.as_deref())?;
-
-        let mut callback = StreamCallback {
+    fn open(
+        device_name: Option<&str>,
+        callback_recv: Receiver<CallbackMsg>,
+        stream_send: Sender<StreamMsg>,
+    ) -> Result<Self, Error> {
+        let ctx = init_context()?;
+
+        let callback = StreamCallback {
             callback_recv,
             source: Box::new(Empty),
             state: CallbackState::Paused,
             buffer: vec![0.0; 1024 * 1024],
         };
+        let output = SwitchableStream::open(callback, |callback, generation| {
+            Self::build(&ctx, device_name, callback, &stream_send, generation)
+        })?;
+
+        Ok(Self {
+            ctx,
+            output,
+            stream_send,
+            volume: 1.0,
+        })
+    }
 
+    fn build(
+        ctx: &cubeb::Context,
+        device_name: Option<&str>,
+        callback: &Arc<Mutex<StreamCallback>>,
+        stream_send: &Sender<StreamMsg>,
+        generation: u64,
+    ) -> Result<cubeb::Stream<Frame>, Error> {
         let params = cubeb::StreamParamsBuilder::new()
             .format(cubeb::SampleFormat::Float32NE)
             .rate(SAMPLE_RATE)
@@ -81,21 +134,75 @@ impl Stream {
             .layout(cubeb::ChannelLayout::STEREO)
             .take();
 
+        // Device IDs point into the collection, so it has to outlive the stream
+        // initialization below.
+        let devices = ctx.enumerate_devices(cubeb::DeviceType::OUTPUT)?;
+        let device = device_name.and_then(|name| {
+            let device = devices
+                .iter()
+                .find(|device| device.friendly_name() == Some(name));
+            if device.is_none() {
+                log::warn!("audio device {:?} not found, using the default one", name);
+            }
+            device
+        });
+
         let mut builder = cubeb::StreamBuilder::new();
+        match device {
+            Some(device) => {
+                log::info!("using audio device: {:?}", device.friendly_name());
+                builder.output(device.devid(), &params)
+            }
+            None => builder.default_output(&params),
+        };
         builder
             .name("Psst")
-            .default_output(&params)
             .latency(STREAM_LATENCY)
-            .data_callback(move |_, output| {
-                callback.write_samples(output);
-                output.len() as isize
+            .data_callback({
+                let callback = Arc::clone(callback);
+                move |_, output| {
+                    match callback.try_lock() {
+                        Ok(mut callback) => callback.write_samples(output),
+                        // The stream we are replacing has not finished its last
+                        // callback yet.
+                        Err(_) => output.iter_mut().for_each(|s| {
+                            s.l = 0.0;
+                            s.r = 0.0;
+                        }),
+                    }
+                    output.len() as isize
+                }
             })
-            .state_callback(|state| {
-                log::debug!("stream state: {:?}", state);
+            .state_callback({
+                let stream_send = stream_send.clone();
+                move |state| {
+                    log::debug!("stream state: {:?}", state);
+                    if let cubeb::State::Error = state {
+                        let _ = stream_send.send(StreamMsg::DeviceLost(generation));
+                    }
+                }
             });
-        let stream = builder.init(&ctx)?;
+        Ok(builder.init(ctx)?)
+    }
+
+    /// Move the output to the device called `name`, or to the default one if
+    /// `None`.
+    fn switch_device(&mut self, name: Option<&str>) -> Result<(), Error> {
+        self.output.replace(|callback, generation| {
+            let stream = Self::build(&self.ctx, name, callback, &self.stream_send, generation)?;
+            stream.set_volume(self.volume)?;
+            Ok(stream)
+        })
+    }
+}
+
+impl DeviceStream for cubeb::Stream<Frame> {
+    fn start(&self) -> Result<(), Error> {
+        Ok(self.deref().start()?)
+    }
 
-        Ok(Self { ctx, stream })
+    fn stop(&self) -> Result<(), Error> {
+        Ok(self.deref().stop()?)
     }
 }
 
@@ -104,6 +211,9 @@ enum StreamMsg {
     Resume,
     Close,
     SetVolume(f32),
+    SetDevice(Option<String>),
+    /// Device of the stream of the given generation has been disconnected.
+    DeviceLost(u64),
 }
 
 impl Actor for Stream {
@@ -114,30 +224,45 @@ impl Actor for Stream {
         match msg {
             StreamMsg::Pause => {
                 log::debug!("pausing audio output stream");
-                if let Err(err) = self.stream.stop() {
+                if let Err(err) = self.output.pause() {
                     log::error!("failed to stop stream: {}", err);
                 }
                 Ok(Act::Continue)
             }
             StreamMsg::Resume => {
                 log::debug!("resuming audio output stream");
-                if let Err(err) = self.stream.start() {
+                if let Err(err) = self.output.resume() {
                     log::error!("failed to start stream: {}", err);
                 }
                 Ok(Act::Continue)
             }
             StreamMsg::Close => {
                 log::debug!("closing audio output stream");
-                let _ = self.stream.stop();
+                let _ = self.output.pause();
                 Ok(Act::Shutdown)
             }
             StreamMsg::SetVolume(volume) => {
                 log::debug!("setting volume");
-                if let Err(err) = self.stream.set_volume(volume) {
+                self.volume = volume;
+                if let Err(err) = self.output.stream().set_volume(volume) {
                     log::error!("failed to set volume: {}", err);
                 }
                 Ok(Act::Continue)
             }
+            StreamMsg::SetDevice(name) => {
+                if let Err(err) = self.switch_device(name.as_deref()) {
+                    log::error!("failed to switch audio device: {}", err);
+                }
+                Ok(Act::Continue)
+            }
+            StreamMsg::DeviceLost(generation) if self.output.is_current(generation) => {
+                log::warn!("audio device is gone, switching to the default one");
+                if let Err(err) = self.switch_device(None) {
+                    log::error!("failed to switch audio device: {}", err);
+                }
+                Ok(Act::Continue)
+            }
+            StreamMsg::DeviceLost(_) => Ok(Act::Continue),
         }
     }
 }
@@ -181,6 +306,12 @@ impl AudioSink for CubebSink {
         self.pause();
     }
 
+    fn set_device(&self, name: Option<String>) {
+        self.stream_send.send(StreamMsg::SetDevice(name)).unwrap();
+    }
+
+    fn prefer_sample_rate(&self, _rate: u32) {}
+
     fn close(&self) {
         self.stop();
     }
@@ -0,0 +1,261 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    audio::{
+        dither::Dither,
+        output::{
+            pull::{PullOutput, PullSink, SampleWriter},
+            AudioOutput,
+        },
+    },
+    error::Error,
+};
+
+const CHANNELS: usize = 2;
+/// Length written in the WAV header until the real one is known.  Streamed
+/// files keep it, which most readers take as "until the end of the stream".
+const UNKNOWN_LENGTH: u64 = u32::MAX as u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Wav,
+    /// Bare interleaved little-endian samples, without any header.
+    Raw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileSampleFormat {
+    S16,
+    F32,
+}
+
+#[derive(Clone, Debug)]
+pub struct FileConfig {
+    /// File to write to, stdout if `None`.
+    pub path: Option<PathBuf>,
+    pub format: FileFormat,
+    pub sample_format: FileSampleFormat,
+    pub sample_rate: u32,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            format: FileFormat::Wav,
+            sample_format: FileSampleFormat::S16,
+            sample_rate: 44_100,
+        }
+    }
+}
+
+/// Output that writes the samples to a file or to stdout, as fast as they are
+/// taken.  Writing into a pipe or a FIFO paces the playback by its reader.
+pub struct FileOutput(PullOutput);
+
+impl FileOutput {
+    pub fn open(config: &FileConfig, dither: bool) -> Result<Self, Error> {
+        let writer = FileWriter::create(config, dither)?;
+        Ok(Self(PullOutput::open(
+            writer,
+            CHANNELS,
+            config.sample_rate,
+            false,
+        )))
+    }
+}
+
+impl AudioOutput for FileOutput {
+    type Sink = PullSink;
+
+    fn sink(&self) -> Self::Sink {
+        self.0.sink()
+    }
+}
+
+enum Destination {
+    File(BufWriter<File>),
+    Stdout(io::Stdout),
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File(file) => file.write(buf),
+            Self::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File(file) => file.flush(),
+            Self::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+struct FileWriter {
+    dest: Destination,
+    format: FileFormat,
+    sample_format: FileSampleFormat,
+    sample_rate: u32,
+    dither: Option<Dither>,
+    /// Encoded samples, kept to avoid allocating for every chunk.
+    bytes: Vec<u8>,
+    /// Length of the encoded samples written so far, in bytes.
+    data_length: u64,
+}
+
+impl FileWriter {
+    fn create(config: &FileConfig, dither: bool) -> Result<Self, Error> {
+        let dest = match &config.path {
+            Some(path) => Destination::File(BufWriter::new(File::create(path)?)),
+            None => Destination::Stdout(io::stdout()),
+        };
+        let mut writer = Self {
+            dest,
+            format: config.format,
+            sample_format: config.sample_format,
+            sample_rate: config.sample_rate,
+            dither: (dither && config.sample_format == FileSampleFormat::S16)
+                .then(|| Dither::new(16)),
+            bytes: Vec::new(),
+            data_length: 0,
+        };
+        if writer.format == FileFormat::Wav {
+            let header = writer.wav_header(UNKNOWN_LENGTH);
+            writer.dest.write_all(&header)?;
+        }
+        Ok(writer)
+    }
+
+    fn wav_header(&self, data_length: u64) -> Vec<u8> {
+        const PCM: u16 = 1;
+        const IEEE_FLOAT: u16 = 3;
+
+        let (format_tag, bits) = match self.sample_format {
+            FileSampleFormat::S16 => (PCM, 16),
+            FileSampleFormat::F32 => (IEEE_FLOAT, 32),
+        };
+        let block_align = CHANNELS as u16 * bits / 8;
+        let data_length = u32::try_from(data_length).unwrap_or(u32::MAX);
+
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&data_length.saturating_add(36).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16_u32.to_le_bytes());
+        header.extend_from_slice(&format_tag.to_le_bytes());
+        header.extend_from_slice(&(CHANNELS as u16).to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_length.to_le_bytes());
+        header
+    }
+}
+
+impl SampleWriter for FileWriter {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.bytes.clear();
+        match self.sample_format {
+            FileSampleFormat::S16 => {
+                for &sample in samples {
+                    let sample = match &mut self.dither {
+                        Some(dither) if sample != 0.0 => dither.apply(sample),
+                        _ => sample,
+                    };
+                    let sample = (sample * 32768.0)
+                        .round()
+                        .clamp(i16::MIN as f32, i16::MAX as f32)
+                        as i16;
+                    self.bytes.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+            FileSampleFormat::F32 => {
+                for &sample in samples {
+                    self.bytes.extend_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        self.data_length += self.bytes.len() as u64;
+        self.dest.write_all(&self.bytes)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.format == FileFormat::Wav {
+            // Now that we know the length, fill it in.  Not possible on stdout.
+            let header = self.wav_header(self.data_length);
+            if let Destination::File(file) = &mut self.dest {
+                file.seek(SeekFrom::Start(0))?;
+                file.write_all(&header)?;
+            }
+        }
+        self.dest.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn write_file(config: FileConfig, samples: &[f32]) -> Vec<u8> {
+        let mut writer = FileWriter::create(&config, false).unwrap();
+        writer.write(samples).unwrap();
+        writer.finish().unwrap();
+        fs::read(config.path.unwrap()).unwrap()
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_wav_with_length() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = FileConfig {
+            path: Some(file.path().to_owned()),
+            ..FileConfig::default()
+        };
+        let bytes = write_file(config, &[0.5, -0.5, 0.0, 1.0]);
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 8);
+        assert_eq!(u32_at(&bytes, 24), 44_100);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 8);
+        let samples: Vec<i16> = bytes[44..]
+            .chunks(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, [16384, -16384, 0, i16::MAX]);
+    }
+
+    #[test]
+    fn writes_raw_samples() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let config = FileConfig {
+            path: Some(file.path().to_owned()),
+            format: FileFormat::Raw,
+            sample_format: FileSampleFormat::F32,
+            ..FileConfig::default()
+        };
+        let bytes = write_file(config, &[0.25, -1.0]);
+
+        let samples: Vec<f32> = bytes
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, [0.25, -1.0]);
+    }
+}
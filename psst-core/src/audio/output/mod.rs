@@ -1,16 +1,174 @@
-use crate::audio::source::AudioSource;
+use crate::{audio::source::AudioSource, error::Error};
 
 #[cfg(feature = "cpal")]
 pub mod cpal;
 #[cfg(feature = "cubeb")]
 pub mod cubeb;
+pub mod file;
+pub mod null;
+pub mod pull;
+#[cfg(any(feature = "cpal", feature = "cubeb"))]
+mod switch;
+
+use self::{
+    file::{FileConfig, FileOutput},
+    null::NullOutput,
+    pull::PullSink,
+};
 
 #[cfg(feature = "cubeb")]
-pub type DefaultAudioOutput = cubeb::CubebOutput;
+pub type DeviceAudioOutput = cubeb::CubebOutput;
 #[cfg(feature = "cpal")]
-pub type DefaultAudioOutput = cpal::CpalOutput;
+pub type DeviceAudioOutput = cpal::CpalOutput;
+
+#[derive(Clone, Debug)]
+pub enum OutputBackend {
+    /// Sound device, picked by `OutputConfig::device`.  Needs the `cpal` or
+    /// the `cubeb` feature.
+    Device,
+    File(FileConfig),
+    Null,
+}
+
+impl Default for OutputBackend {
+    fn default() -> Self {
+        if cfg!(any(feature = "cpal", feature = "cubeb")) {
+            Self::Device
+        } else {
+            Self::Null
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutputConfig {
+    pub backend: OutputBackend,
+    /// Name of the output device, `None` for the default one.
+    pub device: Option<String>,
+    /// Dither the samples when the device takes an integer sample format.
+    pub dither: bool,
+    /// Run the device at the sample rate of the played source whenever it
+    /// supports it, instead of resampling the source.
+    pub bit_perfect: bool,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            backend: OutputBackend::default(),
+            device: None,
+            dither: true,
+            bit_perfect: false,
+        }
+    }
+}
+
+/// Output of the backend chosen in `OutputConfig`.
+pub enum DefaultAudioOutput {
+    #[cfg(any(feature = "cpal", feature = "cubeb"))]
+    Device(DeviceAudioOutput),
+    File(FileOutput),
+    Null(NullOutput),
+}
+
+impl DefaultAudioOutput {
+    pub fn open(config: OutputConfig) -> Result<Self, Error> {
+        match config.backend.clone() {
+            #[cfg(any(feature = "cpal", feature = "cubeb"))]
+            OutputBackend::Device => Ok(Self::Device(DeviceAudioOutput::open(config)?)),
+            #[cfg(not(any(feature = "cpal", feature = "cubeb")))]
+            OutputBackend::Device => Err(Error::ConfigError(
+                "built without support for sound devices".to_string(),
+            )),
+            OutputBackend::File(file) => Ok(Self::File(FileOutput::open(&file, config.dither)?)),
+            OutputBackend::Null => Ok(Self::Null(NullOutput::open())),
+        }
+    }
+
+    /// Names of the available sound devices.
+    #[cfg(any(feature = "cpal", feature = "cubeb"))]
+    pub fn devices() -> Result<Vec<String>, Error> {
+        DeviceAudioOutput::devices()
+    }
+
+    #[cfg(not(any(feature = "cpal", feature = "cubeb")))]
+    pub fn devices() -> Result<Vec<String>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+impl AudioOutput for DefaultAudioOutput {
+    type Sink = DefaultAudioSink;
 
-pub type DefaultAudioSink = <DefaultAudioOutput as AudioOutput>::Sink;
+    fn sink(&self) -> Self::Sink {
+        match self {
+            #[cfg(any(feature = "cpal", feature = "cubeb"))]
+            Self::Device(output) => DefaultAudioSink::Device(output.sink()),
+            Self::File(output) => DefaultAudioSink::Pull(output.sink()),
+            Self::Null(output) => DefaultAudioSink::Pull(output.sink()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum DefaultAudioSink {
+    #[cfg(any(feature = "cpal", feature = "cubeb"))]
+    Device(<DeviceAudioOutput as AudioOutput>::Sink),
+    Pull(PullSink),
+}
+
+/// Call the same method on whichever sink is inside.
+macro_rules! dispatch {
+    ($self:ident, $sink:ident => $call:expr) => {
+        match $self {
+            #[cfg(any(feature = "cpal", feature = "cubeb"))]
+            DefaultAudioSink::Device($sink) => $call,
+            DefaultAudioSink::Pull($sink) => $call,
+        }
+    };
+}
+
+impl AudioSink for DefaultAudioSink {
+    fn channel_count(&self) -> usize {
+        dispatch!(self, sink => sink.channel_count())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        dispatch!(self, sink => sink.sample_rate())
+    }
+
+    fn set_volume(&self, volume: f32) {
+        dispatch!(self, sink => sink.set_volume(volume))
+    }
+
+    fn play(&self, source: impl AudioSource) {
+        dispatch!(self, sink => sink.play(source))
+    }
+
+    fn pause(&self) {
+        dispatch!(self, sink => sink.pause())
+    }
+
+    fn resume(&self) {
+        dispatch!(self, sink => sink.resume())
+    }
+
+    fn stop(&self) {
+        dispatch!(self, sink => sink.stop())
+    }
+
+    fn set_device(&self, name: Option<String>) {
+        dispatch!(self, sink => sink.set_device(name))
+    }
+
+    fn prefer_sample_rate(&self, rate: u32) {
+        dispatch!(self, sink => sink.prefer_sample_rate(rate))
+    }
+
+    fn close(&self) {
+        dispatch!(self, sink => sink.close())
+    }
+}
 
 pub trait AudioOutput {
     type Sink: AudioSink;
@@ -26,5 +184,11 @@ pub trait AudioSink {
     fn pause(&self);
     fn resume(&self);
     fn stop(&self);
+    /// Move the output to the device called `name`, or to the default one if
+    /// `None`, without interrupting the playing source.
+    fn set_device(&self, name: Option<String>);
+    /// Switch the output to `rate` if it is bit-perfect and the device supports
+    /// the rate.  Returns once `sample_rate` reports the rate in use.
+    fn prefer_sample_rate(&self, rate: u32);
     fn close(&self);
 }
@@ -0,0 +1,35 @@
+use std::io;
+
+use crate::audio::output::{
+    pull::{PullOutput, PullSink, SampleWriter},
+    AudioOutput,
+};
+
+const CHANNELS: usize = 2;
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Output that throws the samples away, taking them in real time like a sound
+/// device would.  Lets the player run where there is no audio hardware.
+pub struct NullOutput(PullOutput);
+
+impl NullOutput {
+    pub fn open() -> Self {
+        Self(PullOutput::open(Discard, CHANNELS, SAMPLE_RATE, true))
+    }
+}
+
+impl AudioOutput for NullOutput {
+    type Sink = PullSink;
+
+    fn sink(&self) -> Self::Sink {
+        self.0.sink()
+    }
+}
+
+struct Discard;
+
+impl SampleWriter for Discard {
+    fn write(&mut self, _samples: &[f32]) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    actor::{Act, Actor, ActorHandle},
+    audio::{
+        output::AudioSink,
+        source::{AudioSource, Empty},
+    },
+    error::Error,
+};
+
+/// Frames pulled from the source at once.
+const CHUNK_FRAMES: usize = 1024;
+/// How long to wait for the source after it has run out of samples, if the
+/// output is not paced.
+const STARVED_WAIT: Duration = Duration::from_millis(10);
+
+/// Destination of the samples of a `PullOutput`.
+pub trait SampleWriter: Send + 'static {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    /// Called once the output is closed.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Output without a sound device to ask for the samples.  Instead, a thread
+/// pulls them from the source and hands them over to a `SampleWriter`, either
+/// as fast as the writer takes them, or `paced` in real time.
+pub struct PullOutput {
+    handle: Option<ActorHandle<PullMsg>>,
+    sink: PullSink,
+}
+
+impl PullOutput {
+    pub fn open(
+        writer: impl SampleWriter,
+        channel_count: usize,
+        sample_rate: u32,
+        paced: bool,
+    ) -> Self {
+        let handle = PullStream::spawn_with_default_cap("audio_output", move |_| {
+            let chunk = Duration::from_secs_f64(CHUNK_FRAMES as f64 / sample_rate as f64);
+            PullStream {
+                writer: Box::new(writer),
+                source: Box::new(Empty),
+                buffer: vec![0.0; CHUNK_FRAMES * channel_count],
+                volume: 1.0, // We start with the full volume.
+                is_playing: false,
+                is_starved: false,
+                pacing: paced.then(|| Pacing {
+                    chunk,
+                    due: Instant::now(),
+                }),
+            }
+        });
+        let sink = PullSink {
+            channel_count,
+            sample_rate,
+            stream_send: handle.sender(),
+        };
+        Self {
+            handle: Some(handle),
+            sink,
+        }
+    }
+
+    pub fn sink(&self) -> PullSink {
+        self.sink.clone()
+    }
+}
+
+impl Drop for PullOutput {
+    fn drop(&mut self) {
+        // Let the writer finish, for example to complete the header of a file.
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.send(PullMsg::Close);
+            handle.join();
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PullSink {
+    channel_count: usize,
+    sample_rate: u32,
+    stream_send: Sender<PullMsg>,
+}
+
+impl PullSink {
+    fn send(&self, msg: PullMsg) {
+        if self.stream_send.send(msg).is_err() {
+            log::error!("output stream actor is dead");
+        }
+    }
+}
+
+impl AudioSink for PullSink {
+    fn channel_count(&self) -> usize {
+        self.channel_count
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn set_volume(&self, volume: f32) {
+        self.send(PullMsg::SetVolume(volume));
+    }
+
+    fn play(&self, source: impl AudioSource) {
+        self.send(PullMsg::PlaySource(Box::new(source)));
+    }
+
+    fn pause(&self) {
+        self.send(PullMsg::Pause);
+    }
+
+    fn resume(&self) {
+        self.send(PullMsg::Resume);
+    }
+
+    fn stop(&self) {
+        self.play(Empty);
+        self.pause();
+    }
+
+    fn set_device(&self, name: Option<String>) {
+        log::warn!("output has no devices, ignoring {:?}", name);
+    }
+
+    fn prefer_sample_rate(&self, _rate: u32) {}
+
+    fn close(&self) {
+        self.send(PullMsg::Close);
+    }
+}
+
+enum PullMsg {
+    PlaySource(Box<dyn AudioSource>),
+    SetVolume(f32),
+    Pause,
+    Resume,
+    Write,
+    Close,
+}
+
+struct Pacing {
+    /// Duration of one chunk of samples.
+    chunk: Duration,
+    /// When the next chunk should be written.
+    due: Instant,
+}
+
+struct PullStream {
+    writer: Box<dyn SampleWriter>,
+    source: Box<dyn AudioSource>,
+    buffer: Vec<f32>,
+    volume: f32,
+    is_playing: bool,
+    /// The source had no samples the last time we asked.
+    is_starved: bool,
+    pacing: Option<Pacing>,
+}
+
+impl PullStream {
+    fn write(&mut self) {
+        let mut written = self.source.write(&mut self.buffer);
+        self.is_starved = written == 0;
+        if let Some(pacing) = &mut self.pacing {
+            // Like a sound device, a paced output plays silence when the source
+            // is late.
+            self.buffer[written..].iter_mut().for_each(|s| *s = 0.0);
+            written = self.buffer.len();
+            pacing.due += pacing.chunk;
+        }
+
+        // Apply scaled global volume level, the same way the device outputs do.
+        let scaled_volume = self.volume.powi(4);
+        self.buffer[..written]
+            .iter_mut()
+            .for_each(|s| *s *= scaled_volume);
+
+        if let Err(err) = self.writer.write(&self.buffer[..written]) {
+            log::error!("failed to write audio output: {}", err);
+            self.is_playing = false;
+        }
+    }
+
+    /// Keep writing while playing, or wait for a message while paused.
+    fn next(&self) -> Act<Self> {
+        if !self.is_playing {
+            return Act::Continue;
+        }
+        let timeout = match &self.pacing {
+            Some(pacing) => pacing.due.saturating_duration_since(Instant::now()),
+            None if self.is_starved => STARVED_WAIT,
+            None => Duration::ZERO,
+        };
+        Act::WaitOr {
+            timeout,
+            timeout_msg: PullMsg::Write,
+        }
+    }
+}
+
+impl Actor for PullStream {
+    type Message = PullMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: Self::Message) -> Result<Act<Self>, Self::Error> {
+        match msg {
+            PullMsg::PlaySource(source) => {
+                self.source = source;
+                self.is_starved = false;
+            }
+            PullMsg::SetVolume(volume) => {
+                self.volume = volume;
+            }
+            PullMsg::Pause => {
+                self.is_playing = false;
+            }
+            PullMsg::Resume => {
+                if !self.is_playing {
+                    if let Some(pacing) = &mut self.pacing {
+                        pacing.due = Instant::now();
+                    }
+                }
+                self.is_playing = true;
+            }
+            PullMsg::Write => {
+                if self.is_playing {
+                    self.write();
+                }
+            }
+            PullMsg::Close => {
+                if let Err(err) = self.writer.finish() {
+                    log::error!("failed to finish audio output: {}", err);
+                }
+                return Ok(Act::Shutdown);
+            }
+        }
+        Ok(self.next())
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use crate::error::Error;
+
+/// Stream of a sound device backend, as seen by `SwitchableStream`.
+pub trait DeviceStream {
+    fn start(&self) -> Result<(), Error>;
+    fn stop(&self) -> Result<(), Error>;
+}
+
+/// Output stream that can be replaced while playing, for example to move the
+/// output to another device.  The data callback `C` is shared by the streams,
+/// so the playing source is handed over to the new one.
+pub struct SwitchableStream<S, C> {
+    stream: S,
+    callback: Arc<Mutex<C>>,
+    /// Counts the opened streams, so the errors reported by the previous ones
+    /// can be told apart and ignored.
+    generation: u64,
+    is_playing: bool,
+}
+
+impl<S, C> SwitchableStream<S, C>
+where
+    S: DeviceStream,
+{
+    /// Open the first stream with `build`, which gets the shared callback and
+    /// the generation of the stream.
+    pub fn open(
+        callback: C,
+        build: impl FnOnce(&Arc<Mutex<C>>, u64) -> Result<S, Error>,
+    ) -> Result<Self, Error> {
+        let callback = Arc::new(Mutex::new(callback));
+        let stream = build(&callback, 0)?;
+        Ok(Self {
+            stream,
+            callback,
+            generation: 0,
+            is_playing: false,
+        })
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        generation == self.generation
+    }
+
+    pub fn pause(&mut self) -> Result<(), Error> {
+        self.is_playing = false;
+        self.stream.stop()
+    }
+
+    pub fn resume(&mut self) -> Result<(), Error> {
+        self.is_playing = true;
+        self.stream.start()
+    }
+
+    /// Replace the stream with one opened by `build`, continuing with the same
+    /// source.  If the new stream cannot be opened or started, the current
+    /// one keeps playing.
+    pub fn replace(
+        &mut self,
+        build: impl FnOnce(&Arc<Mutex<C>>, u64) -> Result<S, Error>,
+    ) -> Result<(), Error> {
+        // Make sure only one of the streams pulls samples from the source.
+        let _ = self.stream.stop();
+        let generation = self.generation + 1;
+        let stream = build(&self.callback, generation).and_then(|stream| {
+            if self.is_playing {
+                stream.start()?;
+            }
+            Ok(stream)
+        });
+        match stream {
+            Ok(stream) => {
+                self.stream = stream;
+                self.generation = generation;
+                Ok(())
+            }
+            Err(err) => {
+                if self.is_playing {
+                    let _ = self.stream.start();
+                }
+                Err(err)
+            }
+        }
+    }
+}
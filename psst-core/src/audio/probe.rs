@@ -1,19 +1,22 @@
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Duration;
 
 use symphonia::core::codecs::CodecType;
 use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::{MediaSourceStream, MediaSourceStreamOptions};
-use symphonia::core::meta::MetadataOptions;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
 use symphonia::core::probe::{Hint, Probe};
-use symphonia::default::formats::{MpaReader, OggReader};
 
+use crate::audio::normalize::NormalizationData;
 use crate::error::Error;
 
 pub struct TrackProbe {
     pub codec: CodecType,
     pub duration: Option<Duration>,
+    pub album: Option<String>,
+    /// Normalization data read from ReplayGain tags, if the file has them.
+    pub replay_gain: Option<NormalizationData>,
 }
 
 macro_rules! probe_err {
@@ -30,11 +33,11 @@ macro_rules! probe_err {
 }
 
 impl TrackProbe {
-    pub fn new(path: &PathBuf) -> Result<Self, Error> {
-        // Register all supported file formats for detection.
+    pub fn new(path: &Path) -> Result<Self, Error> {
+        // Register all supported file formats for detection, together with the
+        // ID3v2 metadata reader.
         let mut probe = Probe::default();
-        probe.register_all::<MpaReader>();
-        probe.register_all::<OggReader>();
+        symphonia::default::register_enabled_formats(&mut probe);
 
         let mut hint = Hint::new();
         if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
@@ -47,7 +50,7 @@ impl TrackProbe {
 
         let fmt_opts = FormatOptions::default();
         let meta_opts = MetadataOptions::default();
-        let probe_result = probe
+        let mut probe_result = probe
             .format(&hint, mss, &fmt_opts, &meta_opts)
             .map_err(|_| probe_err!("failed to probe file"))?;
         let track = probe_result
@@ -66,9 +69,104 @@ impl TrackProbe {
                 None
             };
 
+        let codec = params.codec;
+
+        // ID3v2 tags are read by the probe, while Vorbis comments are part of
+        // the container and are exposed by the format reader.
+        let mut tags = Tags::default();
+        if let Some(metadata) = probe_result.metadata.get() {
+            if let Some(revision) = metadata.current() {
+                tags.add(revision.tags());
+            }
+        }
+        if let Some(revision) = probe_result.format.metadata().current() {
+            tags.add(revision.tags());
+        }
+
         Ok(Self {
-            codec: params.codec,
+            codec,
             duration,
+            album: tags.album.clone(),
+            replay_gain: tags.replay_gain(),
         })
     }
 }
+
+#[derive(Default)]
+struct Tags {
+    album: Option<String>,
+    track_gain_db: Option<f32>,
+    track_peak: Option<f32>,
+    album_gain_db: Option<f32>,
+    album_peak: Option<f32>,
+}
+
+impl Tags {
+    fn add(&mut self, tags: &[Tag]) {
+        for tag in tags {
+            let value = tag.value.to_string();
+            match tag_key(tag) {
+                Some(StandardTagKey::Album) => self.album = Some(value),
+                Some(StandardTagKey::ReplayGainTrackGain) => {
+                    self.track_gain_db = parse_gain(&value)
+                }
+                Some(StandardTagKey::ReplayGainTrackPeak) => self.track_peak = parse_peak(&value),
+                Some(StandardTagKey::ReplayGainAlbumGain) => {
+                    self.album_gain_db = parse_gain(&value)
+                }
+                Some(StandardTagKey::ReplayGainAlbumPeak) => self.album_peak = parse_peak(&value),
+                _ => {}
+            }
+        }
+    }
+
+    fn replay_gain(&self) -> Option<NormalizationData> {
+        let track_gain_db = self.track_gain_db?;
+        let track_peak = self.track_peak.unwrap_or(1.0);
+        // Without album tags, treat the track as an album of its own.
+        let (album_gain_db, album_peak) = match self.album_gain_db {
+            Some(album_gain_db) => (album_gain_db, self.album_peak.unwrap_or(1.0)),
+            None => (track_gain_db, track_peak),
+        };
+        Some(NormalizationData::new(
+            track_gain_db,
+            track_peak,
+            album_gain_db,
+            album_peak,
+        ))
+    }
+}
+
+fn tag_key(tag: &Tag) -> Option<StandardTagKey> {
+    if tag.std_key.is_some() {
+        return tag.std_key;
+    }
+    // ReplayGain values in ID3v2 live in user-defined `TXXX` frames, which are
+    // not always mapped to the standard keys.
+    let key = tag.key.to_ascii_uppercase();
+    if key.ends_with("REPLAYGAIN_TRACK_GAIN") {
+        Some(StandardTagKey::ReplayGainTrackGain)
+    } else if key.ends_with("REPLAYGAIN_TRACK_PEAK") {
+        Some(StandardTagKey::ReplayGainTrackPeak)
+    } else if key.ends_with("REPLAYGAIN_ALBUM_GAIN") {
+        Some(StandardTagKey::ReplayGainAlbumGain)
+    } else if key.ends_with("REPLAYGAIN_ALBUM_PEAK") {
+        Some(StandardTagKey::ReplayGainAlbumPeak)
+    } else {
+        None
+    }
+}
+
+/// Parse gain values in the usual `-6.54 dB` format.
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok()
+}
+
+fn parse_peak(value: &str) -> Option<f32> {
+    value.trim().parse().ok().filter(|peak: &f32| *peak > 0.0)
+}
@@ -0,0 +1,68 @@
+use crate::{
+    audio::resample::{ResamplingQuality, ResamplingSpec},
+    error::Error,
+};
+
+pub struct Resampler {
+    state: *mut libsamplerate::SRC_STATE,
+}
+
+impl Resampler {
+    pub fn new(quality: ResamplingQuality, spec: ResamplingSpec) -> Result<Self, Error> {
+        let converter = match quality {
+            ResamplingQuality::SincBestQuality => libsamplerate::SRC_SINC_BEST_QUALITY,
+            ResamplingQuality::SincMediumQuality => libsamplerate::SRC_SINC_MEDIUM_QUALITY,
+            ResamplingQuality::SincFastest => libsamplerate::SRC_SINC_FASTEST,
+            ResamplingQuality::ZeroOrderHold => libsamplerate::SRC_ZERO_ORDER_HOLD,
+            ResamplingQuality::Linear => libsamplerate::SRC_LINEAR,
+        };
+        let mut error_int = 0i32;
+        let state = unsafe {
+            libsamplerate::src_new(
+                converter as i32,
+                spec.channels as i32,
+                &mut error_int as *mut i32,
+            )
+        };
+        if error_int != 0 {
+            Err(Error::ResamplingError(error_int))
+        } else {
+            Ok(Self { state })
+        }
+    }
+
+    pub fn process(
+        &mut self,
+        spec: &ResamplingSpec,
+        input: &[f32],
+        output: &mut [f32],
+    ) -> Result<(usize, usize), Error> {
+        let mut src = libsamplerate::SRC_DATA {
+            data_in: input.as_ptr(),
+            data_out: output.as_mut_ptr(),
+            input_frames: (input.len() / spec.channels) as _,
+            output_frames: (output.len() / spec.channels) as _,
+            src_ratio: spec.ratio(),
+            // The input never ends, `ResampledSource` pads it with silence.
+            end_of_input: 0,
+            input_frames_used: 0,
+            output_frames_gen: 0,
+        };
+        let error_int = unsafe { libsamplerate::src_process(self.state, &mut src as *mut _) };
+        if error_int != 0 {
+            Err(Error::ResamplingError(error_int))
+        } else {
+            let processed_len = src.input_frames_used as usize * spec.channels;
+            let output_len = src.output_frames_gen as usize * spec.channels;
+            Ok((processed_len, output_len))
+        }
+    }
+}
+
+impl Drop for Resampler {
+    fn drop(&mut self) {
+        unsafe { libsamplerate::src_delete(self.state) };
+    }
+}
+
+unsafe impl Send for Resampler {}
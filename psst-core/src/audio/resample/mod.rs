@@ -0,0 +1,71 @@
+#[cfg(feature = "libsamplerate")]
+mod libsamplerate;
+#[cfg(not(feature = "libsamplerate"))]
+mod polyphase;
+
+use crate::error::Error;
+
+#[cfg(feature = "libsamplerate")]
+use self::libsamplerate::Resampler;
+#[cfg(not(feature = "libsamplerate"))]
+use self::polyphase::Resampler;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ResamplingQuality {
+    SincBestQuality,
+    #[default]
+    SincMediumQuality,
+    SincFastest,
+    ZeroOrderHold,
+    Linear,
+}
+
+#[derive(Copy, Clone)]
+pub struct ResamplingSpec {
+    pub input_rate: u32,
+    pub output_rate: u32,
+    pub channels: usize,
+}
+
+impl ResamplingSpec {
+    pub fn output_size(&self, input_size: usize) -> usize {
+        (self.output_rate as f64 / self.input_rate as f64 * input_size as f64) as usize
+    }
+
+    pub fn input_size(&self, output_size: usize) -> usize {
+        (self.input_rate as f64 / self.output_rate as f64 * output_size as f64) as usize
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.output_rate as f64 / self.input_rate as f64
+    }
+}
+
+/// Converts interleaved samples from one sample rate to another.  Uses
+/// libsamplerate with the `libsamplerate` feature, and a polyphase filter
+/// written in Rust without it.
+pub struct AudioResampler {
+    pub spec: ResamplingSpec,
+    resampler: Resampler,
+}
+
+impl AudioResampler {
+    pub fn new(quality: ResamplingQuality, spec: ResamplingSpec) -> Result<Self, Error> {
+        Ok(Self {
+            resampler: Resampler::new(quality, spec)?,
+            spec,
+        })
+    }
+
+    /// Resample as much of `input` as fits into `output`.  Returns the number
+    /// of samples taken from `input` and written into `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut [f32]) -> Result<(usize, usize), Error> {
+        if self.spec.input_rate == self.spec.output_rate {
+            // Bypass conversion completely in case the sample rates are equal.
+            let output = &mut output[..input.len()];
+            output.copy_from_slice(input);
+            return Ok((input.len(), output.len()));
+        }
+        self.resampler.process(&self.spec, input, output)
+    }
+}
@@ -0,0 +1,323 @@
+use std::f64::consts::PI;
+
+use crate::{
+    audio::resample::{ResamplingQuality, ResamplingSpec},
+    error::Error,
+};
+
+/// Interpolation kernel, as a function of the distance of an input frame from
+/// the output position, in input frames.
+#[derive(Clone, Copy)]
+enum Kernel {
+    Hold,
+    Linear,
+    /// Low-pass windowed sinc, with the cutoff relative to the input Nyquist
+    /// frequency.
+    Sinc {
+        half_length: usize,
+        cutoff: f64,
+        beta: f64,
+    },
+}
+
+impl Kernel {
+    fn new(quality: ResamplingQuality, spec: &ResamplingSpec) -> Self {
+        // When downsampling, the cutoff needs to move to the output Nyquist
+        // frequency.  A bit below it, so the transition band does not alias.
+        let cutoff = spec.ratio().min(1.0);
+        match quality {
+            ResamplingQuality::SincBestQuality => Self::Sinc {
+                half_length: 64,
+                cutoff: cutoff * 0.97,
+                beta: 10.0,
+            },
+            ResamplingQuality::SincMediumQuality => Self::Sinc {
+                half_length: 32,
+                cutoff: cutoff * 0.94,
+                beta: 8.6,
+            },
+            ResamplingQuality::SincFastest => Self::Sinc {
+                half_length: 12,
+                cutoff: cutoff * 0.88,
+                beta: 6.0,
+            },
+            ResamplingQuality::ZeroOrderHold => Self::Hold,
+            ResamplingQuality::Linear => Self::Linear,
+        }
+    }
+
+    /// Input frames on each side of the output position that the kernel
+    /// covers.
+    fn half_length(self) -> usize {
+        match self {
+            Self::Hold | Self::Linear => 1,
+            Self::Sinc { half_length, .. } => half_length,
+        }
+    }
+
+    /// Number of fractional positions between two input frames that the kernel
+    /// is tabulated at.  The positions in between are interpolated, apart from
+    /// the hold, which has a step right at the frames.
+    fn phases(self) -> usize {
+        match self {
+            Self::Hold | Self::Linear => 1,
+            Self::Sinc { half_length, .. } => half_length * 8,
+        }
+    }
+
+    fn value(self, distance: f64) -> f64 {
+        match self {
+            Self::Hold => {
+                if distance > -1.0 && distance <= 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Linear => (1.0 - distance.abs()).max(0.0),
+            Self::Sinc {
+                half_length,
+                cutoff,
+                beta,
+            } => {
+                let x = distance / half_length as f64;
+                if x.abs() >= 1.0 {
+                    return 0.0;
+                }
+                let window = bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta);
+                cutoff * sinc(cutoff * distance) * window
+            }
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Modified Bessel function of the first kind and order zero, needed by the
+/// Kaiser window.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..50 {
+        term *= (x / 2.0 / k as f64).powi(2);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Polyphase resampler, taking the kernel from a table computed up front for a
+/// number of fractional positions, and interpolating between them.  Works with
+/// any ratio of the sample rates.
+pub struct Resampler {
+    /// Taps of each of the `phases + 1` positions, in a row.
+    table: Vec<f32>,
+    taps: usize,
+    phases: usize,
+    interpolate_phases: bool,
+    /// Taps at the position of the current output frame.
+    current_taps: Vec<f32>,
+    /// Interleaved input frames, from the first one the kernel still needs.
+    history: Vec<f32>,
+    /// Position of the next output frame, in input frames since the start of
+    /// `history`.
+    position: f64,
+    /// Distance of two output frames, in input frames.
+    step: f64,
+}
+
+impl Resampler {
+    pub fn new(quality: ResamplingQuality, spec: ResamplingSpec) -> Result<Self, Error> {
+        let kernel = Kernel::new(quality, &spec);
+        let half_length = kernel.half_length();
+        let taps = half_length * 2;
+        let phases = kernel.phases();
+
+        let mut table = Vec::with_capacity((phases + 1) * taps);
+        for phase in 0..=phases {
+            let fraction = phase as f64 / phases as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|tap| kernel.value((tap as f64 - half_length as f64 + 1.0) - fraction))
+                .collect();
+            // Normalize, so constant signals keep their level exactly.
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|value| (value / sum) as f32));
+        }
+
+        Ok(Self {
+            table,
+            taps,
+            phases,
+            interpolate_phases: !matches!(kernel, Kernel::Hold),
+            current_taps: vec![0.0; taps],
+            // Start with silence before the first input frame, so the first
+            // output frame lines up with it.
+            history: vec![0.0; (half_length - 1) * spec.channels],
+            position: (half_length - 1) as f64,
+            step: 1.0 / spec.ratio(),
+        })
+    }
+
+    pub fn process(
+        &mut self,
+        spec: &ResamplingSpec,
+        input: &[f32],
+        output: &mut [f32],
+    ) -> Result<(usize, usize), Error> {
+        let channels = spec.channels;
+        let half_length = self.taps / 2;
+        let input_frames = input.len() / channels;
+        let output_frames = output.len() / channels;
+        let mut consumed = 0;
+        let mut written = 0;
+
+        while written < output_frames {
+            let first = self.position as usize + 1 - half_length;
+            let available = self.history.len() / channels;
+            if available < first + self.taps {
+                // Take only the input needed for this frame, the rest stays
+                // with the caller until there is space for the output.
+                let needed = (first + self.taps - available).min(input_frames - consumed);
+                if needed == 0 {
+                    break;
+                }
+                self.history
+                    .extend_from_slice(&input[consumed * channels..(consumed + needed) * channels]);
+                consumed += needed;
+                continue;
+            }
+
+            self.update_taps(self.position.fract());
+            let frames = &self.history[first * channels..(first + self.taps) * channels];
+            let out = &mut output[written * channels..(written + 1) * channels];
+            for (channel, sample) in out.iter_mut().enumerate() {
+                *sample = self
+                    .current_taps
+                    .iter()
+                    .zip(frames.chunks_exact(channels))
+                    .map(|(tap, frame)| tap * frame[channel])
+                    .sum();
+            }
+            written += 1;
+            self.position += self.step;
+        }
+
+        // Forget the frames the kernel has moved past.
+        let first = (self.position as usize + 1).saturating_sub(half_length);
+        let passed = first.min(self.history.len() / channels);
+        self.history.drain(..passed * channels);
+        self.position -= passed as f64;
+
+        Ok((consumed * channels, written * channels))
+    }
+
+    fn update_taps(&mut self, fraction: f64) {
+        let phase = fraction * self.phases as f64;
+        let index = phase as usize;
+        let row = &self.table[index * self.taps..(index + 1) * self.taps];
+        if !self.interpolate_phases {
+            self.current_taps.copy_from_slice(row);
+            return;
+        }
+        let next = &self.table[(index + 1) * self.taps..(index + 2) * self.taps];
+        let t = (phase - index as f64) as f32;
+        for ((tap, a), b) in self.current_taps.iter_mut().zip(row).zip(next) {
+            *tap = a + (b - a) * t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample(
+        quality: ResamplingQuality,
+        spec: ResamplingSpec,
+        mut input: &[f32],
+        chunk: usize,
+    ) -> Vec<f32> {
+        let mut resampler = Resampler::new(quality, spec).unwrap();
+        let mut output = Vec::new();
+        let mut buffer = vec![0.0; spec.output_size(chunk)];
+        while !input.is_empty() {
+            let end = chunk.min(input.len());
+            let (consumed, written) = resampler
+                .process(&spec, &input[..end], &mut buffer)
+                .unwrap();
+            output.extend_from_slice(&buffer[..written]);
+            input = &input[consumed..];
+            if consumed == 0 && written == 0 {
+                break;
+            }
+        }
+        output
+    }
+
+    fn sine(frequency: f64, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |peak, s| s.abs().max(peak))
+    }
+
+    #[test]
+    fn keeps_length_and_level() {
+        let spec = ResamplingSpec {
+            input_rate: 44_100,
+            output_rate: 48_000,
+            channels: 2,
+        };
+        let input = vec![0.5; 44_100 * 2];
+        let output = resample(ResamplingQuality::SincMediumQuality, spec, &input, 1024);
+
+        // All of the output but the last kernel length of input.
+        let frames = output.len() / 2;
+        assert!((47_900..=48_000).contains(&frames), "{} frames", frames);
+        // Past the ramp-up of the kernel, the level stays the same.
+        for sample in &output[200..] {
+            assert!((sample - 0.5).abs() < 1e-3, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn filters_above_output_nyquist() {
+        let spec = ResamplingSpec {
+            input_rate: 48_000,
+            output_rate: 44_100,
+            channels: 1,
+        };
+        let pass = sine(1_000.0, spec.input_rate, 48_000);
+        let output = resample(ResamplingQuality::SincMediumQuality, spec, &pass, 1024);
+        assert!((peak(&output[1000..]) - 1.0).abs() < 0.01);
+
+        let stop = sine(23_000.0, spec.input_rate, 48_000);
+        let output = resample(ResamplingQuality::SincMediumQuality, spec, &stop, 1024);
+        assert!(peak(&output[1000..]) < 0.01);
+    }
+
+    #[test]
+    fn interpolates_linearly() {
+        let spec = ResamplingSpec {
+            input_rate: 1,
+            output_rate: 2,
+            channels: 1,
+        };
+        let input = [0.0, 1.0, 0.0, 1.0];
+        let linear = resample(ResamplingQuality::Linear, spec, &input, 4);
+        assert_eq!(linear, [0.0, 0.5, 1.0, 0.5, 0.0, 0.5]);
+        let hold = resample(ResamplingQuality::ZeroOrderHold, spec, &input, 4);
+        assert_eq!(hold, [0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+/// Settings of the silence trimming, used to shorten long pauses in
+/// spoken-word content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceTrimming {
+    /// Samples quieter than this level, in dBFS, count as silence.
+    pub threshold_db: f32,
+    /// Pauses longer than this are shortened to it, shorter ones are kept.
+    pub max_pause: Duration,
+}
+
+impl Default for SilenceTrimming {
+    fn default() -> Self {
+        Self {
+            threshold_db: -45.0,
+            max_pause: Duration::from_millis(600),
+        }
+    }
+}
+
+/// Drops the frames of interleaved samples that make a pause longer than
+/// allowed, and counts how much time has been saved this way.  Frames are
+/// either kept or dropped as a whole, even if they are split between two
+/// calls to `trim`.
+pub struct SilenceTrimmer {
+    channels: usize,
+    sample_rate: u32,
+    trimming: Option<SilenceTrimming>,
+    threshold: f32,
+    max_pause_samples: u64,
+    /// Consecutive silent samples up to now.
+    silent_samples: u64,
+    /// Channel of the next sample.
+    channel: usize,
+    /// Whether the frame of the next sample is being dropped.
+    dropping: bool,
+    trimmed_samples: u64,
+}
+
+impl SilenceTrimmer {
+    pub fn new(channels: usize, sample_rate: u32, trimming: Option<SilenceTrimming>) -> Self {
+        let mut trimmer = Self {
+            channels,
+            sample_rate,
+            trimming: None,
+            threshold: 0.0,
+            max_pause_samples: 0,
+            silent_samples: 0,
+            channel: 0,
+            dropping: false,
+            trimmed_samples: 0,
+        };
+        trimmer.configure(trimming);
+        trimmer
+    }
+
+    pub fn configure(&mut self, trimming: Option<SilenceTrimming>) {
+        if trimming == self.trimming {
+            return;
+        }
+        if let Some(trimming) = trimming {
+            self.threshold = f32::powf(10.0, trimming.threshold_db / 20.0);
+            self.max_pause_samples = (trimming.max_pause.as_secs_f64()
+                * self.sample_rate as f64
+                * self.channels as f64) as u64;
+        }
+        self.trimming = trimming;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.trimming.is_some()
+    }
+
+    /// Total time dropped from the stream so far.
+    pub fn trimmed(&self) -> Duration {
+        let frames = self.trimmed_samples / self.channels as u64;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+
+    /// Remove the dropped frames from `samples`, moving the kept ones to the
+    /// front.  Returns the number of kept samples.
+    pub fn trim(&mut self, samples: &mut [f32]) -> usize {
+        if self.trimming.is_none() {
+            return samples.len();
+        }
+        let mut kept = 0;
+        for i in 0..samples.len() {
+            let sample = samples[i];
+            if sample.abs() <= self.threshold {
+                self.silent_samples += 1;
+            } else {
+                self.silent_samples = 0;
+            }
+            if self.channel == 0 {
+                self.dropping = self.silent_samples > self.max_pause_samples;
+            }
+            if self.dropping {
+                self.trimmed_samples += 1;
+            } else {
+                samples[kept] = sample;
+                kept += 1;
+            }
+            self.channel = (self.channel + 1) % self.channels;
+        }
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 1000;
+
+    fn trimmer() -> SilenceTrimmer {
+        SilenceTrimmer::new(
+            2,
+            RATE,
+            Some(SilenceTrimming {
+                threshold_db: -40.0,
+                max_pause: Duration::from_millis(100),
+            }),
+        )
+    }
+
+    /// Stereo signal with `loud` frames of a square wave, `quiet` frames of
+    /// silence and `loud` frames of the wave again.
+    fn signal(loud: usize, quiet: usize) -> Vec<f32> {
+        let wave = |frame: usize| [0.5, -0.5][frame % 2];
+        (0..loud)
+            .map(wave)
+            .chain((0..quiet).map(|_| 0.0))
+            .chain((0..loud).map(wave))
+            .flat_map(|s| [s, s])
+            .collect()
+    }
+
+    #[test]
+    fn shortens_long_pauses() {
+        let mut trimmer = trimmer();
+        let mut samples = signal(200, 1000);
+        let kept = trimmer.trim(&mut samples);
+        assert_eq!(kept, (200 + 100 + 200) * 2);
+        assert_eq!(trimmer.trimmed(), Duration::from_millis(900));
+    }
+
+    #[test]
+    fn keeps_short_pauses() {
+        let mut trimmer = trimmer();
+        let mut samples = signal(200, 80);
+        let expected = samples.clone();
+        let kept = trimmer.trim(&mut samples);
+        assert_eq!(&samples[..kept], &expected[..]);
+        assert_eq!(trimmer.trimmed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn keeps_frames_whole_across_calls() {
+        let mut whole = trimmer();
+        let mut expected = signal(200, 1000);
+        let kept = whole.trim(&mut expected);
+        expected.truncate(kept);
+
+        let mut split = trimmer();
+        let mut output = Vec::new();
+        for chunk in signal(200, 1000).chunks_mut(333) {
+            let kept = split.trim(chunk);
+            output.extend_from_slice(&chunk[..kept]);
+        }
+        assert_eq!(output, expected);
+    }
+}
@@ -1,3 +1,5 @@
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
 use crate::audio::resample::ResamplingSpec;
 
 use super::resample::{AudioResampler, ResamplingQuality};
@@ -30,55 +32,131 @@ impl AudioSource for Empty {
     }
 }
 
-pub struct StereoMappedSource<S> {
-    source: S,
-    input_channels: usize,
-    output_channels: usize,
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CrossfadeCurve {
+    #[default]
+    Linear,
+    /// Keeps the perceived loudness constant across the fade, better suited
+    /// for uncorrelated signals.
+    EqualPower,
+}
+
+impl CrossfadeCurve {
+    /// Returns the gains of the outgoing and the incoming signal at `progress`
+    /// in the `0.0..=1.0` range.
+    fn gains(self, progress: f32) -> (f32, f32) {
+        match self {
+            Self::Linear => (1.0 - progress, progress),
+            Self::EqualPower => {
+                let angle = progress * FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+/// Mixes the tail of `outgoing` with the head of `incoming`, fading between
+/// them over the given duration.  After the fade is over, only `incoming` is
+/// played.  Both sources need to have the same channel count and sample rate.
+pub struct CrossfadeSource<A, B> {
+    outgoing: A,
+    incoming: B,
+    curve: CrossfadeCurve,
+    fade_frames: usize,
+    position: usize,
     buffer: Vec<f32>,
 }
 
-impl<S> StereoMappedSource<S>
+impl<A, B> CrossfadeSource<A, B>
 where
-    S: AudioSource,
+    A: AudioSource,
+    B: AudioSource,
 {
-    pub fn new(source: S, output_channels: usize) -> Self {
+    pub fn new(outgoing: A, incoming: B, duration: Duration, curve: CrossfadeCurve) -> Self {
         const BUFFER_SIZE: usize = 16 * 1024;
 
-        let input_channels = source.channel_count();
+        let fade_frames = (duration.as_secs_f64() * incoming.sample_rate() as f64) as usize;
         Self {
-            source,
-            input_channels,
-            output_channels,
+            outgoing,
+            incoming,
+            curve,
+            fade_frames,
+            position: 0,
             buffer: vec![0.0; BUFFER_SIZE],
         }
     }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.fade_frames
+    }
+
+    pub fn into_incoming(self) -> B {
+        self.incoming
+    }
+
+    fn write_fading(&mut self, output: &mut [f32]) -> usize {
+        let channels = self.incoming.channel_count();
+        let remaining = (self.fade_frames - self.position) * channels;
+        let len = output.len().min(remaining).min(self.buffer.len());
+        let output = &mut output[..len];
+
+        let in_written = self.incoming.write(output);
+        let out_written = self.outgoing.write(&mut self.buffer[..len]);
+        let written = in_written.max(out_written);
+        output[in_written..written]
+            .iter_mut()
+            .for_each(|s| *s = 0.0);
+        self.buffer[out_written..written]
+            .iter_mut()
+            .for_each(|s| *s = 0.0);
+
+        // The fade advances with every frame written, whichever source produced
+        // it.  A source that falls short is padded with silence above, so the
+        // fade keeps its length even if one of them ends early.
+        let frames = output[..written].chunks_exact_mut(channels);
+        let buffer = self.buffer[..written].chunks_exact(channels);
+        for (i, (o, b)) in frames.zip(buffer).enumerate() {
+            let progress = (self.position + i) as f32 / self.fade_frames as f32;
+            let (out_gain, in_gain) = self.curve.gains(progress);
+            for (o, b) in o.iter_mut().zip(b) {
+                *o = *o * in_gain + *b * out_gain;
+            }
+        }
+        self.position += written / channels;
+
+        written
+    }
 }
 
-impl<S> AudioSource for StereoMappedSource<S>
+impl<A, B> AudioSource for CrossfadeSource<A, B>
 where
-    S: AudioSource,
+    A: AudioSource,
+    B: AudioSource,
 {
     fn write(&mut self, output: &mut [f32]) -> usize {
-        let input_max = (output.len() / self.output_channels) * self.input_channels;
-        let buffer_max = input_max.min(self.buffer.len());
-        let written = self.source.write(&mut self.buffer[..buffer_max]);
-        let input = &self.buffer[..written];
-        let input_frames = input.chunks_exact(self.input_channels);
-        let output_frames = output.chunks_exact_mut(self.output_channels);
-        for (i, o) in input_frames.zip(output_frames) {
-            o[0] = i[0];
-            o[1] = i[1];
-            // Assume the rest is is implicitly silence.
+        let mut total = 0;
+
+        while !self.is_finished() && total < output.len() {
+            let written = self.write_fading(&mut output[total..]);
+            if written == 0 {
+                // Neither source has any samples ready at the moment.
+                return total;
+            }
+            total += written;
+        }
+        if total < output.len() {
+            total += self.incoming.write(&mut output[total..]);
         }
-        output.len()
+
+        total
     }
 
     fn channel_count(&self) -> usize {
-        self.output_channels
+        self.incoming.channel_count()
     }
 
     fn sample_rate(&self) -> u32 {
-        self.source.sample_rate()
+        self.incoming.sample_rate()
     }
 }
 
@@ -182,3 +260,87 @@ impl Buf {
         self.start >= self.end
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct VecSource {
+        samples: Vec<f32>,
+        position: usize,
+    }
+
+    impl VecSource {
+        fn new(samples: Vec<f32>) -> Self {
+            Self {
+                samples,
+                position: 0,
+            }
+        }
+    }
+
+    impl AudioSource for VecSource {
+        fn write(&mut self, output: &mut [f32]) -> usize {
+            let len = output.len().min(self.samples.len() - self.position);
+            output[..len].copy_from_slice(&self.samples[self.position..self.position + len]);
+            self.position += len;
+            len
+        }
+
+        fn channel_count(&self) -> usize {
+            1
+        }
+
+        fn sample_rate(&self) -> u32 {
+            1000
+        }
+    }
+
+    #[test]
+    fn curves_meet_at_the_midpoint() {
+        let (out_gain, in_gain) = CrossfadeCurve::Linear.gains(0.5);
+        assert!((out_gain - 0.5).abs() < 1e-6);
+        assert!((out_gain + in_gain - 1.0).abs() < 1e-6);
+
+        let (out_gain, in_gain) = CrossfadeCurve::EqualPower.gains(0.5);
+        assert!((out_gain - in_gain).abs() < 1e-6);
+        assert!((out_gain.powi(2) + in_gain.powi(2) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn overlaps_the_sources_for_the_fade_length() {
+        // 100 frames of fade at 1 kHz.
+        let outgoing = VecSource::new(vec![1.0; 100]);
+        let incoming = VecSource::new(vec![1.0; 300]);
+        let mut source = CrossfadeSource::new(
+            outgoing,
+            incoming,
+            Duration::from_millis(100),
+            CrossfadeCurve::Linear,
+        );
+
+        let mut output = vec![0.0; 1000];
+        let written = source.write(&mut output);
+        assert_eq!(written, 300);
+        assert!(source.is_finished());
+        assert!(output[..written].iter().all(|s| (s - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn keeps_the_fade_length_when_the_outgoing_source_ends_early() {
+        let outgoing = VecSource::new(vec![1.0; 50]);
+        let incoming = VecSource::new(vec![0.0; 300]);
+        let mut source = CrossfadeSource::new(
+            outgoing,
+            incoming,
+            Duration::from_millis(100),
+            CrossfadeCurve::Linear,
+        );
+
+        let mut output = vec![0.0; 1000];
+        assert_eq!(source.write(&mut output), 300);
+        assert!((output[0] - 1.0).abs() < 1e-6);
+        assert!((output[25] - 0.75).abs() < 1e-6);
+        assert!(output[50..].iter().all(|&s| s == 0.0));
+    }
+}
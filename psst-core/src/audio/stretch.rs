@@ -0,0 +1,365 @@
+use std::f32::consts::PI;
+
+use crossbeam_channel::Receiver;
+
+use crate::audio::source::AudioSource;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Length of the overlapped segments.  Long enough to contain a couple of
+/// pitch periods of speech.
+const WINDOW_MS: usize = 30;
+/// How far from its nominal position a segment can be taken to line up with the
+/// previous one.
+const TOLERANCE_MS: usize = 8;
+/// Only every n-th frame is compared when searching for the best alignment.
+const SEARCH_DECIMATION: usize = 4;
+
+/// Changes the speed of the inner source without changing its pitch, using
+/// WSOLA (waveform similarity overlap-add).  Segments of the input are taken at
+/// a pace given by the speed, shifted slightly so they line up with the signal
+/// that would naturally follow the previous segment, and cross-faded into each
+/// other.  The speed can be changed at any time through `speed_recv`.  While it
+/// is 1.0, the samples are passed through untouched.
+pub struct TimeStretchSource<S> {
+    source: S,
+    speed_recv: Receiver<f32>,
+    speed: f32,
+    /// False while passing the samples through, possibly after writing out
+    /// what is left in `pending`.
+    stretching: bool,
+    channels: usize,
+    /// Frames per output step, half of the window length.
+    hop: usize,
+    tolerance: usize,
+    window: Vec<f32>,
+    /// Interleaved input samples, starting at frame 0 of the positions below.
+    input: Vec<f32>,
+    /// Nominal position of the next segment.
+    analysis: f64,
+    /// Position of the signal following the previous segment, `None` before
+    /// the first one.
+    natural: Option<usize>,
+    /// Second, fading out half of the previous segment.
+    overlap: Vec<f32>,
+    /// Output of the last step not written yet.
+    pending: Vec<f32>,
+    pending_start: usize,
+}
+
+impl<S> TimeStretchSource<S>
+where
+    S: AudioSource,
+{
+    pub fn new(source: S, speed: f32, speed_recv: Receiver<f32>) -> Self {
+        let channels = source.channel_count();
+        let frames_per_ms = source.sample_rate() as usize / 1000;
+        let hop = frames_per_ms * WINDOW_MS / 2;
+        // Periodic Hann window, two of them overlapping by half sum up to 1.0.
+        let window = (0..hop * 2)
+            .map(|i| 0.5 - 0.5 * (PI * i as f32 / hop as f32).cos())
+            .collect();
+        Self {
+            source,
+            speed_recv,
+            speed,
+            stretching: speed != 1.0,
+            channels,
+            hop,
+            tolerance: frames_per_ms * TOLERANCE_MS,
+            window,
+            input: Vec::new(),
+            analysis: 0.0,
+            natural: None,
+            overlap: vec![0.0; hop * channels],
+            pending: Vec::new(),
+            pending_start: 0,
+        }
+    }
+
+    fn input_frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        let start = frame * self.channels;
+        self.input[start..start + self.channels].iter().sum()
+    }
+
+    /// Read more input.  Returns false if the source has nothing to give.
+    fn fill(&mut self) -> bool {
+        let len = self.input.len();
+        self.input.resize(len + self.hop * 4 * self.channels, 0.0);
+        let written = self.source.write(&mut self.input[len..]);
+        self.input.truncate(len + written);
+        written > 0
+    }
+
+    /// Produce the next `hop` frames of output into `pending`.  Returns false
+    /// if there is not enough input.
+    fn step(&mut self) -> bool {
+        let nominal = self.analysis.round() as usize;
+        let mut needed = nominal + self.tolerance + self.hop * 2;
+        if let Some(natural) = self.natural {
+            needed = needed.max(natural + self.hop);
+        }
+        if self.input_frames() < needed {
+            return false;
+        }
+        let start = match self.natural {
+            Some(natural) => self.best_alignment(nominal, natural),
+            None => nominal,
+        };
+
+        let channels = self.channels;
+        let segment = &self.input[start * channels..(start + self.hop * 2) * channels];
+        let (fade_in, fade_out) = segment.split_at(self.hop * channels);
+        self.pending.clear();
+        self.pending_start = 0;
+        for (i, (sample, overlap)) in fade_in.iter().zip(&self.overlap).enumerate() {
+            self.pending
+                .push(sample * self.window[i / channels] + overlap);
+        }
+        for (i, (sample, overlap)) in fade_out.iter().zip(&mut self.overlap).enumerate() {
+            *overlap = sample * self.window[self.hop + i / channels];
+        }
+
+        self.natural = Some(start + self.hop);
+        self.analysis += self.hop as f64 * self.speed as f64;
+        self.discard_consumed();
+        true
+    }
+
+    /// Offset around `nominal` where the input is most similar to the signal
+    /// at `natural`, using normalized cross-correlation of the downmixed
+    /// signals.  Candidates closer to `nominal` win ties.
+    fn best_alignment(&self, nominal: usize, natural: usize) -> usize {
+        let candidates = (0..=self.tolerance).flat_map(|distance| {
+            let before = (distance > 0 && distance <= nominal).then(|| nominal - distance);
+            std::iter::once(nominal + distance).chain(before)
+        });
+        let mut best = nominal;
+        let mut best_score = f32::MIN;
+        for candidate in candidates {
+            let mut correlation = 0.0;
+            let mut energy = f32::EPSILON;
+            for i in (0..self.hop).step_by(SEARCH_DECIMATION) {
+                let x = self.mono(candidate + i);
+                correlation += x * self.mono(natural + i);
+                energy += x * x;
+            }
+            let score = correlation / energy.sqrt();
+            if score > best_score {
+                best = candidate;
+                best_score = score;
+            }
+        }
+        best
+    }
+
+    /// Go back to passing the samples through.  The rest of the input after
+    /// `natural` follows the last segment seamlessly, because the fade-out of
+    /// the segment and the fade-in of the same signal sum up to it, so it is
+    /// queued for writing after the output of the last step.
+    fn stop_stretching(&mut self) {
+        let start = self
+            .natural
+            .unwrap_or_else(|| self.analysis.round() as usize);
+        self.pending.drain(..self.pending_start);
+        self.pending_start = 0;
+        self.pending
+            .extend_from_slice(&self.input[start * self.channels..]);
+        self.input.clear();
+        self.analysis = 0.0;
+        self.natural = None;
+        self.overlap.fill(0.0);
+        self.stretching = false;
+    }
+
+    fn write_pending(&mut self, output: &mut [f32]) -> usize {
+        let pending = &self.pending[self.pending_start..];
+        let len = pending.len().min(output.len());
+        output[..len].copy_from_slice(&pending[..len]);
+        self.pending_start += len;
+        len
+    }
+
+    fn discard_consumed(&mut self) {
+        let nominal = self.analysis.round() as usize;
+        let mut keep_from = nominal.saturating_sub(self.tolerance);
+        if let Some(natural) = self.natural {
+            keep_from = keep_from.min(natural);
+        }
+        // Avoid shifting the buffer on every step.
+        if keep_from < self.hop * 4 {
+            return;
+        }
+        self.input.drain(..keep_from * self.channels);
+        self.analysis -= keep_from as f64;
+        self.natural = self.natural.map(|natural| natural - keep_from);
+    }
+}
+
+impl<S> AudioSource for TimeStretchSource<S>
+where
+    S: AudioSource,
+{
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        if let Some(speed) = self.speed_recv.try_iter().last() {
+            self.speed = speed;
+            if speed != 1.0 {
+                self.stretching = true;
+            } else if self.stretching {
+                self.stop_stretching();
+            }
+        }
+        if !self.stretching {
+            let written = self.write_pending(output);
+            return written + self.source.write(&mut output[written..]);
+        }
+
+        let mut total = 0;
+        loop {
+            total += self.write_pending(&mut output[total..]);
+            if total == output.len() {
+                break;
+            }
+            if !self.step() && !self.fill() {
+                // We cannot block, wait for the next write.
+                break;
+            }
+        }
+        total
+    }
+
+    fn channel_count(&self) -> usize {
+        self.source.channel_count()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+
+    use super::*;
+
+    struct VecSource {
+        samples: Vec<f32>,
+        position: usize,
+    }
+
+    impl AudioSource for VecSource {
+        fn write(&mut self, output: &mut [f32]) -> usize {
+            let len = output.len().min(self.samples.len() - self.position);
+            output[..len].copy_from_slice(&self.samples[self.position..self.position + len]);
+            self.position += len;
+            len
+        }
+
+        fn channel_count(&self) -> usize {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+    }
+
+    fn sine(frequency: f32, seconds: usize) -> Vec<f32> {
+        (0..44100 * seconds)
+            .flat_map(|i| {
+                let sample = 0.5 * (2.0 * PI * frequency * i as f32 / 44100.0).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    fn stretch(samples: Vec<f32>, speed: f32) -> Vec<f32> {
+        let (_speed_send, speed_recv) = unbounded();
+        let source = VecSource {
+            samples,
+            position: 0,
+        };
+        let mut stretch = TimeStretchSource::new(source, speed, speed_recv);
+        let mut output = Vec::new();
+        let mut buffer = vec![0.0; 1024];
+        loop {
+            let written = stretch.write(&mut buffer);
+            if written == 0 {
+                break output;
+            }
+            output.extend_from_slice(&buffer[..written]);
+        }
+    }
+
+    /// Count of rising zero crossings of the left channel, per second.
+    fn frequency(samples: &[f32]) -> f32 {
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let crossings = left
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        crossings as f32 / (left.len() as f32 / 44100.0)
+    }
+
+    #[test]
+    fn passes_samples_through_at_normal_speed() {
+        let input = sine(440.0, 1);
+        assert_eq!(stretch(input.clone(), 1.0), input);
+    }
+
+    #[test]
+    fn returns_to_pass_through_at_normal_speed() {
+        let input: Vec<f32> = (0..44100 * 2).map(|i| i as f32).collect();
+        let (speed_send, speed_recv) = unbounded();
+        let source = VecSource {
+            samples: input.clone(),
+            position: 0,
+        };
+        let mut stretch = TimeStretchSource::new(source, 2.0, speed_recv);
+        let mut buffer = vec![0.0; 1024];
+        for _ in 0..10 {
+            stretch.write(&mut buffer);
+        }
+
+        speed_send.send(1.0).unwrap();
+        let mut output = Vec::new();
+        loop {
+            let written = stretch.write(&mut buffer);
+            if written == 0 {
+                break;
+            }
+            output.extend_from_slice(&buffer[..written]);
+        }
+        assert!(!stretch.stretching);
+        // The input following the last stretched segment comes out unchanged,
+        // up to the end of the source.
+        let tail = input.len() / 2;
+        assert_eq!(output[output.len() - tail..], input[input.len() - tail..]);
+    }
+
+    #[test]
+    fn changes_duration_but_not_pitch() {
+        let input = sine(440.0, 2);
+        for speed in [0.5, 1.5, 2.0] {
+            let output = stretch(input.clone(), speed);
+            let expected = input.len() as f32 / speed;
+            let ratio = output.len() as f32 / expected;
+            assert!((ratio - 1.0).abs() < 0.02, "speed {}: {}", speed, ratio);
+            // Leave out the edges, faded in and cut short.
+            let margin = output.len() / 4;
+            let frequency = frequency(&output[margin..output.len() - margin]);
+            assert!(
+                (frequency - 440.0).abs() < 5.0,
+                "speed {}: {}",
+                speed,
+                frequency
+            );
+        }
+    }
+}
@@ -1,13 +1,21 @@
 use std::{
+    collections::HashSet,
     fs, io,
+    io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::SystemTime,
 };
 
+use crossbeam_channel::Sender;
+use sha1::{Digest, Sha1};
+
 use crate::{
-    audio::decrypt::AudioKey,
+    actor::{Act, Actor},
+    audio::{decrypt::AudioKey, normalize::NormalizationData},
     error::Error,
-    item_id::{FileId, ItemId},
+    item_id::{FileId, ItemId, ItemIdType},
+    player::resume::ResumePoint,
     protocol::metadata::{Episode, Track},
     util::{deserialize_protobuf, serialize_protobuf},
 };
@@ -25,6 +33,11 @@ fn create_cache_dirs(base: &Path) -> io::Result<()> {
     mkdir_if_not_exists(&base.join("episode"))?;
     mkdir_if_not_exists(&base.join("audio"))?;
     mkdir_if_not_exists(&base.join("key"))?;
+    mkdir_if_not_exists(&base.join("loudness"))?;
+    mkdir_if_not_exists(&base.join("resume"))?;
+    mkdir_if_not_exists(&base.join("pinned"))?;
+    mkdir_if_not_exists(&base.join("pinned").join("track"))?;
+    mkdir_if_not_exists(&base.join("pinned").join("episode"))?;
     Ok(())
 }
 
@@ -60,7 +73,7 @@ impl Cache {
 // Cache of `Track` protobuf structures.
 impl Cache {
     pub fn get_track(&self, item_id: ItemId) -> Option<Track> {
-        let buf = fs::read(self.track_path(item_id)).ok()?;
+        let buf = read_and_touch(&self.track_path(item_id))?;
         deserialize_protobuf(&buf).ok()
     }
 
@@ -78,7 +91,7 @@ impl Cache {
 // Cache of `Episode` protobuf structures.
 impl Cache {
     pub fn get_episode(&self, item_id: ItemId) -> Option<Episode> {
-        let buf = fs::read(self.episode_path(item_id)).ok()?;
+        let buf = read_and_touch(&self.episode_path(item_id))?;
         deserialize_protobuf(&buf).ok()
     }
 
@@ -96,7 +109,7 @@ impl Cache {
 // Cache of `AudioKey`s.
 impl Cache {
     pub fn get_audio_key(&self, item_id: ItemId, file_id: FileId) -> Option<AudioKey> {
-        let buf = fs::read(self.audio_key_path(item_id, file_id)).ok()?;
+        let buf = read_and_touch(&self.audio_key_path(item_id, file_id))?;
         AudioKey::from_raw(&buf)
     }
 
@@ -125,6 +138,17 @@ impl Cache {
         self.base.join("audio").join(file_id.to_base16())
     }
 
+    /// Path of the cached audio file, if it exists.  Marks the file as
+    /// recently used.
+    pub fn get_audio_file(&self, file_id: FileId) -> Option<PathBuf> {
+        let path = self.audio_file_path(file_id);
+        if !path.exists() {
+            return None;
+        }
+        touch(&path).ok();
+        Some(path)
+    }
+
     pub fn save_audio_file(&self, file_id: FileId, from_path: PathBuf) -> Result<(), Error> {
         log::debug!("saving audio file to cache: {:?}", file_id);
         fs::copy(from_path, self.audio_file_path(file_id))?;
@@ -132,6 +156,275 @@ impl Cache {
     }
 }
 
+// Cache of loudness analysis results of local files.
+impl Cache {
+    pub fn get_loudness(&self, path: &Path) -> Option<NormalizationData> {
+        let file = fs::File::open(self.loudness_path(path).ok()?).ok()?;
+        NormalizationData::read(file).ok()
+    }
+
+    pub fn save_loudness(&self, path: &Path, data: &NormalizationData) -> Result<(), Error> {
+        log::debug!("saving loudness to cache: {:?}", path);
+        let mut buf = Vec::new();
+        data.write(&mut buf)?;
+        fs::write(self.loudness_path(path)?, buf)?;
+        Ok(())
+    }
+
+    fn loudness_path(&self, path: &Path) -> io::Result<PathBuf> {
+        // Key the entry by the file size and modification time as well, so that
+        // the file is analysed again after it changes.
+        let metadata = fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut hasher = Sha1::new();
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(modified.as_nanos().to_le_bytes());
+        let key: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Ok(self.base.join("loudness").join(key))
+    }
+}
+
+// Resume points of partially played items.  Not subject to eviction.
+impl Cache {
+    pub fn get_resume_point(&self, item_id: ItemId) -> Option<ResumePoint> {
+        let buf = fs::read(self.resume_path(item_id)).ok()?;
+        serde_json::from_slice(&buf).ok()
+    }
+
+    pub fn save_resume_point(&self, item_id: ItemId, point: &ResumePoint) -> Result<(), Error> {
+        fs::write(self.resume_path(item_id), serde_json::to_vec(point)?)?;
+        Ok(())
+    }
+
+    fn resume_path(&self, item_id: ItemId) -> PathBuf {
+        self.base.join("resume").join(item_id.to_base62())
+    }
+}
+
+// Items pinned for offline playback.  Each pinned item is marked by a file
+// holding the IDs of its downloaded audio files, the content itself lives in the
+// other parts of the cache.
+impl Cache {
+    pub fn pin(&self, item_id: ItemId) -> Result<(), Error> {
+        if let Some(path) = self.pinned_path(item_id) {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+        }
+        Ok(())
+    }
+
+    /// Record `file_id` as the downloaded audio file of a pinned item, keeping
+    /// it and its audio key from being evicted.
+    pub fn pin_audio_file(&self, item_id: ItemId, file_id: FileId) -> Result<(), Error> {
+        if let Some(path) = self.pinned_path(item_id) {
+            let file_id = file_id.to_base16();
+            let marker = fs::read_to_string(&path).unwrap_or_default();
+            if !marker.lines().any(|line| line == file_id) {
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                writeln!(file, "{}", file_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unpin(&self, item_id: ItemId) -> Result<(), Error> {
+        if let Some(path) = self.pinned_path(item_id) {
+            if let Err(err) = fs::remove_file(path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_pinned(&self, item_id: ItemId) -> bool {
+        self.pinned_path(item_id).is_some_and(|path| path.exists())
+    }
+
+    pub fn pinned_items(&self) -> Vec<ItemId> {
+        let mut items = Vec::new();
+        for (dir, id_type) in [
+            ("track", ItemIdType::Track),
+            ("episode", ItemIdType::Podcast),
+        ] {
+            let Ok(entries) = fs::read_dir(self.base.join("pinned").join(dir)) else {
+                continue;
+            };
+            items.extend(
+                entries
+                    .flatten()
+                    .filter_map(|entry| ItemId::from_base62(entry.file_name().to_str()?, id_type)),
+            );
+        }
+        items
+    }
+
+    /// Paths of all cache entries belonging to pinned items.
+    fn pinned_entries(&self) -> HashSet<PathBuf> {
+        let mut entries = HashSet::new();
+        for item_id in self.pinned_items() {
+            entries.insert(self.track_path(item_id));
+            entries.insert(self.episode_path(item_id));
+            let Some(marker) = self
+                .pinned_path(item_id)
+                .and_then(|path| fs::read_to_string(path).ok())
+            else {
+                continue;
+            };
+            for file_id in marker.lines().filter_map(FileId::from_base16) {
+                entries.insert(self.audio_file_path(file_id));
+                entries.insert(self.audio_key_path(item_id, file_id));
+            }
+        }
+        entries
+    }
+
+    fn pinned_path(&self, item_id: ItemId) -> Option<PathBuf> {
+        let dir = match item_id.id_type {
+            ItemIdType::Track => "track",
+            ItemIdType::Podcast => "episode",
+            ItemIdType::LocalFile | ItemIdType::Unknown => return None,
+        };
+        Some(self.base.join("pinned").join(dir).join(item_id.to_base62()))
+    }
+}
+
+/// Sizes of the cache, as reported after an eviction pass.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheStats {
+    /// Size of all evictable entries, including the pinned ones.
+    pub total_size: u64,
+    /// Size of the entries exempt from eviction.
+    pub pinned_size: u64,
+    pub evicted_entries: usize,
+    pub evicted_size: u64,
+}
+
+// Eviction of least recently used entries.  The modification time of an entry
+// serves as its access time, as it is bumped on every cache hit, while the
+// real access time is not updated on many file systems.
+impl Cache {
+    pub fn evict(&self, max_size: u64) -> Result<CacheStats, Error> {
+        let pinned = self.pinned_entries();
+        let mut stats = CacheStats::default();
+        let mut candidates = Vec::new();
+        for dir in ["audio", "track", "episode", "key"] {
+            for entry in fs::read_dir(self.base.join(dir))?.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if !metadata.is_file() {
+                    continue;
+                }
+                let path = entry.path();
+                stats.total_size += metadata.len();
+                if pinned.contains(&path) {
+                    stats.pinned_size += metadata.len();
+                } else {
+                    let accessed = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    candidates.push((accessed, metadata.len(), path));
+                }
+            }
+        }
+        candidates.sort_by_key(|(accessed, _, _)| *accessed);
+        for (_, len, path) in candidates {
+            if stats.total_size <= max_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(_) => {
+                    stats.total_size -= len;
+                    stats.evicted_entries += 1;
+                    stats.evicted_size += len;
+                }
+                Err(err) => {
+                    // Files in use cannot be removed on some platforms.
+                    log::warn!("failed to evict {:?}: {}", path, err);
+                }
+            }
+        }
+        if stats.evicted_entries > 0 {
+            log::info!(
+                "evicted {} cache entries, {} bytes",
+                stats.evicted_entries,
+                stats.evicted_size
+            );
+        }
+        Ok(stats)
+    }
+}
+
+pub enum EvictorMsg {
+    /// Change the maximum size of the cache, `None` means unlimited.
+    SetLimit(Option<u64>),
+    /// Bring the cache back under its size limit, if there is one.
+    Evict,
+}
+
+/// Background worker keeping the cache under its size limit.  Reports the
+/// statistics of every eviction pass.
+pub struct CacheEvictor {
+    cache: CacheHandle,
+    limit: Option<u64>,
+    events: Sender<CacheStats>,
+}
+
+impl CacheEvictor {
+    pub fn new(cache: CacheHandle, limit: Option<u64>, events: Sender<CacheStats>) -> Self {
+        Self {
+            cache,
+            limit,
+            events,
+        }
+    }
+
+    fn evict(&self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        match self.cache.evict(limit) {
+            Ok(stats) => {
+                self.events.send(stats).ok();
+            }
+            Err(err) => {
+                log::error!("cache eviction failed: {}", err);
+            }
+        }
+    }
+}
+
+impl Actor for CacheEvictor {
+    type Message = EvictorMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: EvictorMsg) -> Result<Act<Self>, Self::Error> {
+        match msg {
+            EvictorMsg::SetLimit(limit) => {
+                self.limit = limit;
+                self.evict();
+            }
+            EvictorMsg::Evict => {
+                self.evict();
+            }
+        }
+        Ok(Act::Continue)
+    }
+}
+
 // Cache of user country code.
 impl Cache {
     pub fn get_country_code(&self) -> Option<String> {
@@ -148,6 +441,19 @@ impl Cache {
     }
 }
 
+fn read_and_touch(path: &Path) -> Option<Vec<u8>> {
+    let buf = fs::read(path).ok()?;
+    touch(path).ok();
+    Some(buf)
+}
+
+fn touch(path: &Path) -> io::Result<()> {
+    fs::OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .set_modified(SystemTime::now())
+}
+
 pub fn mkdir_if_not_exists(path: &Path) -> io::Result<()> {
     fs::create_dir(path).or_else(|err| {
         if err.kind() == io::ErrorKind::AlreadyExists {
@@ -157,3 +463,89 @@ pub fn mkdir_if_not_exists(path: &Path) -> io::Result<()> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn cache() -> (tempfile::TempDir, CacheHandle) {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path().to_path_buf()).unwrap();
+        (dir, cache)
+    }
+
+    /// Write an audio file of `len` bytes, last used `age_secs` ago.
+    fn add_audio_file(cache: &Cache, file_id: FileId, len: usize, age_secs: u64) -> PathBuf {
+        let path = cache.audio_file_path(file_id);
+        fs::write(&path, vec![0; len]).unwrap();
+        let used = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(used)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries_first() {
+        let (_dir, cache) = cache();
+        let oldest = add_audio_file(&cache, FileId([1; 20]), 100, 300);
+        let older = add_audio_file(&cache, FileId([2; 20]), 100, 200);
+        let newest = add_audio_file(&cache, FileId([3; 20]), 100, 100);
+        // The hit makes the oldest file the most recently used one.
+        assert_eq!(cache.get_audio_file(FileId([1; 20])), Some(oldest.clone()));
+        assert_eq!(cache.get_audio_file(FileId([4; 20])), None);
+
+        let stats = cache.evict(200).unwrap();
+        assert!(oldest.exists());
+        assert!(!older.exists());
+        assert!(newest.exists());
+        assert_eq!(stats.total_size, 200);
+        assert_eq!(stats.pinned_size, 0);
+        assert_eq!(stats.evicted_entries, 1);
+        assert_eq!(stats.evicted_size, 100);
+    }
+
+    #[test]
+    fn keeps_pinned_entries() {
+        let (_dir, cache) = cache();
+        let item_id = ItemId::new(1, ItemIdType::Track);
+        let pinned = add_audio_file(&cache, FileId([1; 20]), 100, 300);
+        let unpinned = add_audio_file(&cache, FileId([2; 20]), 50, 100);
+        cache.pin(item_id).unwrap();
+        cache.pin_audio_file(item_id, FileId([1; 20])).unwrap();
+
+        let stats = cache.evict(0).unwrap();
+        assert!(pinned.exists());
+        assert!(!unpinned.exists());
+        assert_eq!(stats.total_size, 100);
+        assert_eq!(stats.pinned_size, 100);
+        assert_eq!(stats.evicted_entries, 1);
+        assert_eq!(stats.evicted_size, 50);
+
+        cache.unpin(item_id).unwrap();
+        let stats = cache.evict(0).unwrap();
+        assert!(!pinned.exists());
+        assert_eq!(stats.total_size, 0);
+        assert_eq!(stats.evicted_size, 100);
+    }
+
+    #[test]
+    fn saves_resume_points() {
+        let (_dir, cache) = cache();
+        let item_id = ItemId::new(1, ItemIdType::Podcast);
+        assert_eq!(cache.get_resume_point(item_id), None);
+
+        let point = ResumePoint::new(Duration::from_secs(600), Duration::from_secs(3600));
+        cache.save_resume_point(item_id, &point).unwrap();
+        assert_eq!(cache.get_resume_point(item_id), Some(point));
+        assert_eq!(
+            cache.get_resume_point(ItemId::new(2, ItemIdType::Podcast)),
+            None
+        );
+    }
+}
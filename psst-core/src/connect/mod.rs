@@ -0,0 +1,981 @@
+pub mod transport;
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crossbeam_channel::Sender;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    actor::{Act, Actor, ActorHandle, Capacity},
+    audio::normalize::NormalizationLevel,
+    error::Error,
+    item_id::{ItemId, ItemIdType},
+    player::{item::PlaybackItem, queue::QueueBehavior, PlayerCommand, PlayerEvent},
+    protocol::spirc::{
+        Capability, CapabilityType, DeviceState, Frame, MessageType, PlayStatus, State, TrackRef,
+    },
+    util::{deserialize_protobuf, serialize_protobuf, Sequence},
+};
+
+use self::transport::ConnectTransport;
+
+const PROTOCOL_VERSION: &str = "2.0.0";
+const MAX_VOLUME: u32 = 0xffff;
+const VOLUME_STEPS: u32 = 64;
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct ConnectConfig {
+    /// Name shown in the device list of other Spotify clients.
+    pub name: String,
+    /// Identifier of the device, should stay the same across restarts.
+    pub device_id: String,
+    /// Initial volume in 0.0..=1.0 range.
+    pub volume: f64,
+}
+
+impl ConnectConfig {
+    pub fn new(name: String) -> Self {
+        let device_id = Sha1::digest(name.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        Self {
+            name,
+            device_id,
+            volume: 1.0,
+        }
+    }
+}
+
+/// Spotify Connect receiver.  Registers the player as a device of the
+/// logged-in user, translates the commands of remote controllers into
+/// `PlayerCommand`s and reports the playback state back to them.
+pub struct Connect {
+    device: ActorHandle<ConnectMsg>,
+}
+
+impl Connect {
+    pub fn start(
+        config: ConnectConfig,
+        transport: Arc<dyn ConnectTransport>,
+        player: Sender<PlayerEvent>,
+    ) -> Self {
+        let device = ConnectDevice::spawn(Capacity::Unbounded, "connect", {
+            let transport = transport.clone();
+            move |_| ConnectDevice::new(config, transport, player)
+        });
+        let device_send = device.sender();
+        thread::spawn(move || receive_frames(transport, device_send));
+        Self { device }
+    }
+
+    /// Report a player event to the remote controllers.
+    pub fn publish(&self, event: &PlayerEvent) {
+        let update = match event {
+            PlayerEvent::Playing { path, position } => PlayerUpdate::Playing {
+                item_id: path.item_id,
+                position: *position,
+            },
+            PlayerEvent::Pausing { position, .. } => PlayerUpdate::Paused {
+                position: *position,
+            },
+            PlayerEvent::Resuming { position, .. } => PlayerUpdate::Resumed {
+                position: *position,
+            },
+            PlayerEvent::Command(PlayerCommand::Seek { position }) => PlayerUpdate::Seeked {
+                position: *position,
+            },
+            PlayerEvent::Command(PlayerCommand::SetVolume { volume }) => {
+                PlayerUpdate::Volume { volume: *volume }
+            }
+            PlayerEvent::Stopped => PlayerUpdate::Stopped,
+            PlayerEvent::QueueChanged { queue } => {
+                let queued = queue.queued.iter().map(|item| item.item_id).collect();
+                self.device.send(ConnectMsg::Queued(queued)).ok();
+                return;
+            }
+            _ => return,
+        };
+        self.device.send(ConnectMsg::Player(update)).ok();
+    }
+}
+
+/// Keep a subscription to the remote frames open, re-subscribing after the
+/// connection drops.  Quits after the device has shut down.
+fn receive_frames(transport: Arc<dyn ConnectTransport>, device: Sender<ConnectMsg>) {
+    loop {
+        match transport.subscribe() {
+            Ok(frames) => {
+                if device.send(ConnectMsg::Subscribed).is_err() {
+                    break;
+                }
+                for frame in frames {
+                    if device.send(ConnectMsg::Frame(frame)).is_err() {
+                        return;
+                    }
+                }
+                log::info!("connect: subscription closed");
+            }
+            Err(err) => {
+                log::warn!("connect: failed to subscribe: {}", err);
+            }
+        }
+        if device.send(ConnectMsg::Disconnected).is_err() {
+            break;
+        }
+        thread::sleep(RESUBSCRIBE_DELAY);
+    }
+}
+
+enum ConnectMsg {
+    /// Subscription to the remote frames is open, announce ourselves.
+    Subscribed,
+    /// Subscription is closed, remote controllers cannot reach us anymore.
+    Disconnected,
+    /// Serialized frame from another device.
+    Frame(Vec<u8>),
+    /// State change of the local player.
+    Player(PlayerUpdate),
+    /// Items queued by the user in the local player.
+    Queued(Vec<ItemId>),
+}
+
+enum PlayerUpdate {
+    Playing { item_id: ItemId, position: Duration },
+    Paused { position: Duration },
+    Resumed { position: Duration },
+    Seeked { position: Duration },
+    Volume { volume: f64 },
+    Stopped,
+}
+
+/// Position and pause state requested by a `Load` frame.  Applied after the
+/// player starts playing the loaded track.
+struct PendingLoad {
+    position: Duration,
+    paused: bool,
+}
+
+struct ConnectDevice {
+    config: ConnectConfig,
+    transport: Arc<dyn ConnectTransport>,
+    player: Sender<PlayerEvent>,
+    sequence: Sequence<u32>,
+    device: DeviceState,
+    state: State,
+    pending_load: Option<PendingLoad>,
+    /// Tracks of the loaded context, without the ones queued by the user.
+    context: Vec<ItemId>,
+    norm_level: NormalizationLevel,
+    /// Items queued by the user in the player, as last reported by it and
+    /// updated by our own queue edits.
+    queued: Vec<ItemId>,
+}
+
+impl ConnectDevice {
+    fn new(
+        config: ConnectConfig,
+        transport: Arc<dyn ConnectTransport>,
+        player: Sender<PlayerEvent>,
+    ) -> Self {
+        let device = DeviceState {
+            sw_version: Some(crate::GIT_VERSION.to_string()),
+            is_active: Some(false),
+            can_play: Some(true),
+            volume: Some(volume_to_spirc(config.volume)),
+            name: Some(config.name.clone()),
+            capabilities: capabilities(&config),
+            ..DeviceState::default()
+        };
+        let state = State {
+            status: Some(PlayStatus::kPlayStatusStop),
+            ..State::default()
+        };
+        Self {
+            config,
+            transport,
+            player,
+            sequence: Sequence::new(1),
+            device,
+            state,
+            pending_load: None,
+            context: Vec::new(),
+            norm_level: NormalizationLevel::Track,
+            queued: Vec::new(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.device.is_active.unwrap_or(false)
+    }
+
+    fn handle_frame(&mut self, frame: Frame) -> Result<(), Error> {
+        let ident = frame.ident.clone().unwrap_or_default();
+        if ident == self.config.device_id
+            || (!frame.recipient.is_empty() && !frame.recipient.contains(&self.config.device_id))
+        {
+            return Ok(());
+        }
+        let typ = match frame.typ {
+            Some(typ) => typ,
+            None => return Ok(()),
+        };
+        log::debug!("connect: {:?} from {}", typ, ident);
+
+        match typ {
+            MessageType::kMessageTypeHello | MessageType::kMessageTypeProbe => {
+                self.notify(Some(ident))?;
+            }
+            MessageType::kMessageTypeNotify => {
+                let other_is_active = frame
+                    .device_state
+                    .as_ref()
+                    .and_then(|device| device.is_active)
+                    .unwrap_or(false);
+                if self.is_active() && other_is_active {
+                    // Playback has been transferred to another device.
+                    self.device.is_active = Some(false);
+                    self.state.status = Some(PlayStatus::kPlayStatusStop);
+                    self.pending_load = None;
+                    self.send_command(PlayerCommand::Stop);
+                }
+            }
+            MessageType::kMessageTypeLoad => {
+                let state = frame.state.unwrap_or_default();
+                self.load(state);
+                self.notify(None)?;
+            }
+            MessageType::kMessageTypePlay => {
+                self.send_command(PlayerCommand::Resume);
+            }
+            MessageType::kMessageTypePause => {
+                self.send_command(PlayerCommand::Pause);
+            }
+            MessageType::kMessageTypePlayPause => {
+                self.send_command(PlayerCommand::PauseOrResume);
+            }
+            MessageType::kMessageTypeSeek => {
+                let position = Duration::from_millis(frame.position.unwrap_or(0).into());
+                self.send_command(PlayerCommand::Seek { position });
+            }
+            MessageType::kMessageTypePrev => {
+                self.send_command(PlayerCommand::Previous);
+            }
+            MessageType::kMessageTypeNext => {
+                self.send_command(PlayerCommand::Next);
+            }
+            MessageType::kMessageTypeVolume => {
+                self.set_volume(frame.volume.unwrap_or(MAX_VOLUME));
+            }
+            MessageType::kMessageTypeVolumeUp => {
+                let volume = self.device.volume.unwrap_or(0);
+                self.set_volume(volume.saturating_add(MAX_VOLUME / VOLUME_STEPS));
+            }
+            MessageType::kMessageTypeVolumeDown => {
+                let volume = self.device.volume.unwrap_or(0);
+                self.set_volume(volume.saturating_sub(MAX_VOLUME / VOLUME_STEPS));
+            }
+            MessageType::kMessageTypeShuffle => {
+                self.state.shuffle = frame.state.and_then(|state| state.shuffle);
+                self.send_queue_behavior();
+                self.notify(None)?;
+            }
+            MessageType::kMessageTypeRepeat => {
+                self.state.repeat = frame.state.and_then(|state| state.repeat);
+                self.send_queue_behavior();
+                self.notify(None)?;
+            }
+            MessageType::kMessageTypeReplace => {
+                let state = frame.state.unwrap_or_default();
+                self.replace(state.track);
+                self.notify(None)?;
+            }
+            _ => {
+                log::debug!("connect: ignoring {:?}", typ);
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, state: State) {
+        let norm_level = match &state.context_uri {
+            Some(uri) if uri.starts_with("spotify:album:") => NormalizationLevel::Album,
+            _ => NormalizationLevel::Track,
+        };
+        let items: Vec<PlaybackItem> = state
+            .track
+            .iter()
+            .filter_map(track_item_id)
+            .map(|item_id| PlaybackItem {
+                item_id,
+                norm_level,
+            })
+            .collect();
+        // Count only the refs before the playing one that are kept.
+        let playing = state.playing_track_index.unwrap_or(0) as usize;
+        let position = state
+            .track
+            .iter()
+            .take(playing)
+            .filter(|track| track_item_id(track).is_some())
+            .count()
+            .min(items.len().saturating_sub(1));
+
+        self.context = items.iter().map(|item| item.item_id).collect();
+        self.norm_level = norm_level;
+        self.device.is_active = Some(true);
+        self.device.became_active_at = Some(now_millis());
+        self.pending_load = Some(PendingLoad {
+            position: Duration::from_millis(state.position_ms.unwrap_or(0).into()),
+            paused: state.status != Some(PlayStatus::kPlayStatusPlay),
+        });
+        self.state = State {
+            status: Some(PlayStatus::kPlayStatusLoading),
+            position_measured_at: Some(now_millis() as u64),
+            ..state
+        };
+
+        self.send_command(PlayerCommand::LoadQueue { items, position });
+        self.send_queue_behavior();
+    }
+
+    /// Bring the player in line with the updated track list.  Tracks marked as
+    /// queued, or not part of the loaded context, are applied to the user queue
+    /// with the queue editing commands.  If the context tracks themselves have
+    /// changed, the context is reloaded at the current position.
+    fn replace(&mut self, tracks: Vec<TrackRef>) {
+        let is_queued = |track: &TrackRef, id: &ItemId| {
+            track.queued == Some(true) || !self.context.contains(id)
+        };
+        let (queued, context): (Vec<_>, Vec<_>) = tracks
+            .iter()
+            .filter_map(|track| Some((track, track_item_id(track)?)))
+            .partition(|(track, id)| is_queued(track, id));
+        let queued: Vec<ItemId> = queued.into_iter().map(|(_, id)| id).collect();
+        let context: Vec<ItemId> = context.into_iter().map(|(_, id)| id).collect();
+
+        if context != self.context {
+            self.reload_context(context);
+        }
+        self.edit_queue(queued);
+        self.state.track = tracks;
+    }
+
+    /// Load the changed context, continuing the current track where it is.
+    fn reload_context(&mut self, context: Vec<ItemId>) {
+        let current = self
+            .state
+            .playing_track_index
+            .and_then(|index| self.state.track.get(index as usize))
+            .and_then(track_item_id);
+        let position = match current.and_then(|id| context.iter().position(|&c| c == id)) {
+            Some(position) => position,
+            None => {
+                // Reloading would interrupt the current track, it is going to be
+                // picked up by the next `Load`.
+                log::debug!("connect: playing track not in the replaced context");
+                self.context = context;
+                return;
+            }
+        };
+        self.pending_load = Some(PendingLoad {
+            position: self.progress(),
+            paused: self.state.status != Some(PlayStatus::kPlayStatusPlay),
+        });
+        let items = context
+            .iter()
+            .map(|&item_id| PlaybackItem {
+                item_id,
+                norm_level: self.norm_level,
+            })
+            .collect();
+        self.context = context;
+        self.send_command(PlayerCommand::LoadQueue { items, position });
+    }
+
+    /// Turn the user queue of the player into `wanted`, first removing the
+    /// items no longer wanted, then adding the new ones and finally moving them
+    /// into order.
+    fn edit_queue(&mut self, wanted: Vec<ItemId>) {
+        let mut queued = self.queued.clone();
+        for index in (0..queued.len()).rev() {
+            if !wanted.contains(&queued[index]) {
+                queued.remove(index);
+                self.send_command(PlayerCommand::RemoveFromQueue { index });
+            }
+        }
+        for &item_id in &wanted {
+            if !queued.contains(&item_id) {
+                queued.push(item_id);
+                self.send_command(PlayerCommand::AddToQueue {
+                    item: PlaybackItem {
+                        item_id,
+                        norm_level: NormalizationLevel::Track,
+                    },
+                });
+            }
+        }
+        for (to, item_id) in wanted.iter().enumerate() {
+            if let Some(from) = queued[to..].iter().position(|id| id == item_id) {
+                let from = to + from;
+                if from != to {
+                    let item = queued.remove(from);
+                    queued.insert(to, item);
+                    self.send_command(PlayerCommand::MoveInQueue { from, to });
+                }
+            }
+        }
+        self.queued = queued;
+    }
+
+    fn set_volume(&mut self, volume: u32) {
+        let volume = volume.min(MAX_VOLUME);
+        self.device.volume = Some(volume);
+        self.send_command(PlayerCommand::SetVolume {
+            volume: f64::from(volume) / f64::from(MAX_VOLUME),
+        });
+    }
+
+    fn send_queue_behavior(&self) {
+        let behavior = if self.state.shuffle.unwrap_or(false) {
+            QueueBehavior::Random
+        } else if self.state.repeat.unwrap_or(false) {
+            QueueBehavior::LoopAll
+        } else {
+            QueueBehavior::Sequential
+        };
+        self.send_command(PlayerCommand::SetQueueBehavior { behavior });
+    }
+
+    fn handle_player_update(&mut self, update: PlayerUpdate) -> Result<(), Error> {
+        // Local playback is reported only while some remote controller has
+        // made us the active device.
+        if !self.is_active() {
+            if let PlayerUpdate::Volume { volume } = update {
+                self.device.volume = Some(volume_to_spirc(volume));
+            }
+            return Ok(());
+        }
+        match update {
+            PlayerUpdate::Playing { item_id, position } => {
+                if let Some(index) = self
+                    .state
+                    .track
+                    .iter()
+                    .position(|track| track_item_id(track) == Some(item_id))
+                {
+                    self.state.playing_track_index = Some(index as u32);
+                }
+                self.set_position(PlayStatus::kPlayStatusPlay, position);
+                if let Some(pending) = self.pending_load.take() {
+                    if !pending.position.is_zero() {
+                        self.send_command(PlayerCommand::Seek {
+                            position: pending.position,
+                        });
+                        self.set_position(PlayStatus::kPlayStatusPlay, pending.position);
+                    }
+                    if pending.paused {
+                        self.send_command(PlayerCommand::Pause);
+                    }
+                }
+            }
+            PlayerUpdate::Paused { position } => {
+                self.set_position(PlayStatus::kPlayStatusPause, position);
+            }
+            PlayerUpdate::Resumed { position } => {
+                self.set_position(PlayStatus::kPlayStatusPlay, position);
+            }
+            PlayerUpdate::Seeked { position } => {
+                let status = self.state.status.unwrap_or(PlayStatus::kPlayStatusPlay);
+                self.set_position(status, position);
+            }
+            PlayerUpdate::Volume { volume } => {
+                self.device.volume = Some(volume_to_spirc(volume));
+            }
+            PlayerUpdate::Stopped => {
+                self.state.status = Some(PlayStatus::kPlayStatusStop);
+            }
+        }
+        self.notify(None)
+    }
+
+    fn set_position(&mut self, status: PlayStatus, position: Duration) {
+        self.state.status = Some(status);
+        self.state.position_ms = Some(position.as_millis() as u32);
+        self.state.position_measured_at = Some(now_millis() as u64);
+    }
+
+    /// Playback position, extrapolated from the last reported one.
+    fn progress(&self) -> Duration {
+        let position = Duration::from_millis(self.state.position_ms.unwrap_or(0).into());
+        if self.state.status != Some(PlayStatus::kPlayStatusPlay) {
+            return position;
+        }
+        let measured_at = self.state.position_measured_at.unwrap_or(0) as i64;
+        position + Duration::from_millis((now_millis() - measured_at).max(0) as u64)
+    }
+
+    fn send_command(&self, command: PlayerCommand) {
+        if self.player.send(PlayerEvent::Command(command)).is_err() {
+            log::warn!("connect: player is not running");
+        }
+    }
+
+    fn notify(&mut self, recipient: Option<String>) -> Result<(), Error> {
+        let frame = self.frame(MessageType::kMessageTypeNotify, recipient);
+        self.send_frame(frame)
+    }
+
+    fn frame(&mut self, typ: MessageType, recipient: Option<String>) -> Frame {
+        Frame {
+            version: Some(1),
+            ident: Some(self.config.device_id.clone()),
+            protocol_version: Some(PROTOCOL_VERSION.to_string()),
+            seq_nr: Some(self.sequence.advance()),
+            typ: Some(typ),
+            device_state: Some(self.device.clone()),
+            state: Some(self.state.clone()),
+            state_update_id: Some(now_millis()),
+            recipient: recipient.into_iter().collect(),
+            ..Frame::default()
+        }
+    }
+
+    fn send_frame(&self, frame: Frame) -> Result<(), Error> {
+        let bytes = serialize_protobuf(&frame)?;
+        self.transport.send(bytes)
+    }
+}
+
+impl Actor for ConnectDevice {
+    type Message = ConnectMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: Self::Message) -> Result<Act<Self>, Self::Error> {
+        let result = match msg {
+            ConnectMsg::Subscribed => {
+                let hello = self.frame(MessageType::kMessageTypeHello, None);
+                self.send_frame(hello)
+            }
+            ConnectMsg::Disconnected => {
+                self.device.is_active = Some(false);
+                Ok(())
+            }
+            ConnectMsg::Frame(bytes) => {
+                deserialize_protobuf(&bytes).and_then(|frame| self.handle_frame(frame))
+            }
+            ConnectMsg::Player(update) => self.handle_player_update(update),
+            ConnectMsg::Queued(queued) => {
+                self.queued = queued;
+                Ok(())
+            }
+        };
+        // Failures to talk to the other devices are not fatal, the next
+        // subscription announces the current state again.
+        if let Err(err) = result {
+            log::error!("connect: {}", err);
+        }
+        Ok(Act::Continue)
+    }
+}
+
+fn capabilities(config: &ConnectConfig) -> Vec<Capability> {
+    let int = |typ, value| Capability {
+        typ: Some(typ),
+        intValue: vec![value],
+        ..Capability::default()
+    };
+    let strings = |typ, values: &[&str]| Capability {
+        typ: Some(typ),
+        stringValue: values.iter().map(|value| value.to_string()).collect(),
+        ..Capability::default()
+    };
+    vec![
+        int(CapabilityType::kCanBePlayer, 1),
+        // Computer.
+        int(CapabilityType::kDeviceType, 1),
+        strings(
+            CapabilityType::kGaiaEqConnectId,
+            &[config.device_id.as_str()],
+        ),
+        int(CapabilityType::kSupportsLogout, 0),
+        int(CapabilityType::kIsObservable, 1),
+        int(CapabilityType::kVolumeSteps, VOLUME_STEPS.into()),
+        strings(
+            CapabilityType::kSupportedContexts,
+            &[
+                "album",
+                "playlist",
+                "search",
+                "inbox",
+                "toplist",
+                "starred",
+                "publishedstarred",
+                "track_set",
+            ],
+        ),
+        strings(
+            CapabilityType::kSupportedTypes,
+            &["audio/track", "audio/episode"],
+        ),
+        int(CapabilityType::kCommandAcks, 1),
+    ]
+}
+
+/// ID of a track or episode the player can load.  The type is taken from the
+/// URI, refs with only a GID are tracks.  Other refs, like local files, are
+/// left out.
+fn track_item_id(track: &TrackRef) -> Option<ItemId> {
+    let from_uri = match track.uri.as_deref() {
+        Some(uri) => Some(ItemId::from_uri(uri)?),
+        None => None,
+    };
+    let item_id = match (&track.gid, from_uri) {
+        (Some(gid), Some(from_uri)) => ItemId::from_raw(gid, from_uri.id_type)?,
+        (Some(gid), None) => ItemId::from_raw(gid, ItemIdType::Track)?,
+        (None, Some(from_uri)) => from_uri,
+        (None, None) => return None,
+    };
+    match item_id.id_type {
+        ItemIdType::Track | ItemIdType::Podcast => Some(item_id),
+        ItemIdType::LocalFile | ItemIdType::Unknown => None,
+    }
+}
+
+fn volume_to_spirc(volume: f64) -> u32 {
+    (volume.clamp(0.0, 1.0) * f64::from(MAX_VOLUME)).round() as u32
+}
+
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::{unbounded, Receiver};
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    /// In-process stand-in for the Mercury remote channel.  Frames pushed into
+    /// `incoming` are delivered to the device, frames sent by the device end
+    /// up in `outgoing`.
+    struct FakeServer {
+        incoming: Mutex<Option<Receiver<Vec<u8>>>>,
+        outgoing: Sender<Vec<u8>>,
+    }
+
+    impl ConnectTransport for FakeServer {
+        fn subscribe(&self) -> Result<Receiver<Vec<u8>>, Error> {
+            self.incoming
+                .lock()
+                .take()
+                .ok_or(Error::SessionDisconnected)
+        }
+
+        fn send(&self, frame: Vec<u8>) -> Result<(), Error> {
+            self.outgoing.send(frame).map_err(|_| Error::SendError)
+        }
+    }
+
+    struct Harness {
+        _connect: Connect,
+        remote: Sender<Vec<u8>>,
+        sent: Receiver<Vec<u8>>,
+        player: Receiver<PlayerEvent>,
+    }
+
+    impl Harness {
+        fn start() -> Self {
+            let (remote, incoming) = unbounded();
+            let (outgoing, sent) = unbounded();
+            let (player_send, player) = unbounded();
+            let server = FakeServer {
+                incoming: Mutex::new(Some(incoming)),
+                outgoing,
+            };
+            let connect = Connect::start(
+                ConnectConfig::new("Test".to_string()),
+                Arc::new(server),
+                player_send,
+            );
+            Self {
+                _connect: connect,
+                remote,
+                sent,
+                player,
+            }
+        }
+
+        fn send(&self, typ: MessageType, frame: Frame) {
+            let frame = Frame {
+                ident: Some("remote".to_string()),
+                typ: Some(typ),
+                ..frame
+            };
+            self.remote
+                .send(serialize_protobuf(&frame).unwrap())
+                .unwrap();
+        }
+
+        fn sent_frame(&self) -> Frame {
+            let bytes = self.sent.recv_timeout(Duration::from_secs(1)).unwrap();
+            deserialize_protobuf(&bytes).unwrap()
+        }
+
+        fn command(&self) -> PlayerCommand {
+            match self.player.recv_timeout(Duration::from_secs(1)).unwrap() {
+                PlayerEvent::Command(command) => command,
+                _ => panic!("expected a player command"),
+            }
+        }
+    }
+
+    fn track(n: u128) -> TrackRef {
+        TrackRef {
+            gid: Some(n.to_be_bytes().to_vec()),
+            ..TrackRef::default()
+        }
+    }
+
+    #[test]
+    fn announces_itself_and_answers_hello() {
+        let harness = Harness::start();
+        let hello = harness.sent_frame();
+        assert_eq!(hello.typ, Some(MessageType::kMessageTypeHello));
+        let device = hello.device_state.unwrap();
+        assert_eq!(device.name.as_deref(), Some("Test"));
+        assert_eq!(device.is_active, Some(false));
+
+        harness.send(MessageType::kMessageTypeHello, Frame::default());
+        let notify = harness.sent_frame();
+        assert_eq!(notify.typ, Some(MessageType::kMessageTypeNotify));
+        assert_eq!(notify.recipient, vec!["remote".to_string()]);
+    }
+
+    #[test]
+    fn translates_remote_commands() {
+        let harness = Harness::start();
+        harness.sent_frame();
+
+        harness.send(
+            MessageType::kMessageTypeLoad,
+            Frame {
+                state: Some(State {
+                    context_uri: Some("spotify:album:test".to_string()),
+                    playing_track_index: Some(1),
+                    status: Some(PlayStatus::kPlayStatusPlay),
+                    track: vec![track(1), track(2)],
+                    ..State::default()
+                }),
+                ..Frame::default()
+            },
+        );
+        match harness.command() {
+            PlayerCommand::LoadQueue { items, position } => {
+                assert_eq!(position, 1);
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[1].item_id, ItemId::new(2, ItemIdType::Track));
+                assert_eq!(items[1].norm_level, NormalizationLevel::Album);
+            }
+            _ => panic!("expected LoadQueue"),
+        }
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::SetQueueBehavior {
+                behavior: QueueBehavior::Sequential
+            }
+        ));
+        let notify = harness.sent_frame();
+        assert_eq!(notify.device_state.unwrap().is_active, Some(true));
+
+        harness.send(MessageType::kMessageTypePause, Frame::default());
+        assert!(matches!(harness.command(), PlayerCommand::Pause));
+
+        harness.send(
+            MessageType::kMessageTypeSeek,
+            Frame {
+                position: Some(1500),
+                ..Frame::default()
+            },
+        );
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::Seek { position } if position == Duration::from_millis(1500)
+        ));
+
+        harness.send(
+            MessageType::kMessageTypeVolume,
+            Frame {
+                volume: Some(MAX_VOLUME / 2),
+                ..Frame::default()
+            },
+        );
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::SetVolume { volume } if (volume - 0.5).abs() < 0.01
+        ));
+
+        harness.send(
+            MessageType::kMessageTypeReplace,
+            Frame {
+                state: Some(State {
+                    track: vec![track(1), track(2), track(3)],
+                    ..State::default()
+                }),
+                ..Frame::default()
+            },
+        );
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::AddToQueue { item } if item.item_id == ItemId::new(3, ItemIdType::Track)
+        ));
+    }
+
+    #[test]
+    fn loads_episodes_and_skips_unplayable_refs() {
+        let harness = Harness::start();
+        harness.sent_frame();
+
+        let uri = |uri: &str| TrackRef {
+            uri: Some(uri.to_string()),
+            ..TrackRef::default()
+        };
+        let episode = TrackRef {
+            uri: Some("spotify:episode:0000000000000000000003".to_string()),
+            ..track(3)
+        };
+        harness.send(
+            MessageType::kMessageTypeLoad,
+            Frame {
+                state: Some(State {
+                    playing_track_index: Some(2),
+                    status: Some(PlayStatus::kPlayStatusPlay),
+                    track: vec![
+                        uri("spotify:local:Artist:Album:Title:180"),
+                        track(1),
+                        episode,
+                        uri("spotify:track:0000000000000000000004"),
+                    ],
+                    ..State::default()
+                }),
+                ..Frame::default()
+            },
+        );
+        match harness.command() {
+            PlayerCommand::LoadQueue { items, position } => {
+                let ids: Vec<_> = items.iter().map(|item| item.item_id).collect();
+                assert_eq!(
+                    ids,
+                    vec![
+                        ItemId::new(1, ItemIdType::Track),
+                        ItemId::new(3, ItemIdType::Podcast),
+                        ItemId::new(4, ItemIdType::Track),
+                    ]
+                );
+                assert_eq!(position, 1);
+            }
+            _ => panic!("expected LoadQueue"),
+        }
+    }
+
+    #[test]
+    fn applies_replaced_track_list() {
+        let harness = Harness::start();
+        harness.sent_frame();
+
+        let replace = |tracks: Vec<TrackRef>| {
+            harness.send(
+                MessageType::kMessageTypeReplace,
+                Frame {
+                    state: Some(State {
+                        track: tracks,
+                        ..State::default()
+                    }),
+                    ..Frame::default()
+                },
+            );
+            harness.sent_frame();
+        };
+        let id = |n| ItemId::new(n, ItemIdType::Track);
+
+        harness.send(
+            MessageType::kMessageTypeLoad,
+            Frame {
+                state: Some(State {
+                    playing_track_index: Some(0),
+                    status: Some(PlayStatus::kPlayStatusPlay),
+                    track: vec![track(1), track(2), track(3)],
+                    ..State::default()
+                }),
+                ..Frame::default()
+            },
+        );
+        assert!(matches!(harness.command(), PlayerCommand::LoadQueue { .. }));
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::SetQueueBehavior { .. }
+        ));
+        harness.sent_frame();
+
+        replace(vec![track(1), track(2), track(3), track(4), track(5)]);
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::AddToQueue { item } if item.item_id == id(4)
+        ));
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::AddToQueue { item } if item.item_id == id(5)
+        ));
+
+        replace(vec![track(1), track(2), track(3), track(5), track(4)]);
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::MoveInQueue { from: 1, to: 0 }
+        ));
+
+        replace(vec![track(1), track(2), track(3), track(4)]);
+        assert!(matches!(
+            harness.command(),
+            PlayerCommand::RemoveFromQueue { index: 0 }
+        ));
+
+        replace(vec![track(1), track(3), track(4)]);
+        match harness.command() {
+            PlayerCommand::LoadQueue { items, position } => {
+                assert_eq!(position, 0);
+                let ids: Vec<_> = items.iter().map(|item| item.item_id).collect();
+                assert_eq!(ids, vec![id(1), id(3)]);
+            }
+            _ => panic!("expected LoadQueue"),
+        }
+        assert!(harness
+            .player
+            .recv_timeout(Duration::from_millis(100))
+            .is_err());
+    }
+
+    #[test]
+    fn ignores_frames_for_other_devices() {
+        let harness = Harness::start();
+        harness.sent_frame();
+
+        harness.send(
+            MessageType::kMessageTypePlay,
+            Frame {
+                recipient: vec!["somebody else".to_string()],
+                ..Frame::default()
+            },
+        );
+        harness.send(MessageType::kMessageTypeNext, Frame::default());
+        assert!(matches!(harness.command(), PlayerCommand::Next));
+    }
+}
@@ -0,0 +1,58 @@
+use std::thread;
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::{error::Error, session::SessionService};
+
+/// Channel carrying serialized Spirc frames between the Connect device and the
+/// other devices of the same user.
+pub trait ConnectTransport: Send + Sync + 'static {
+    /// Start receiving frames.  The returned channel disconnects when the
+    /// underlying connection goes away, and the caller is expected to
+    /// subscribe again.
+    fn subscribe(&self) -> Result<Receiver<Vec<u8>>, Error>;
+
+    /// Broadcast a frame to the other devices.
+    fn send(&self, frame: Vec<u8>) -> Result<(), Error>;
+}
+
+/// Spirc over the Mercury pub/sub channel of the session, as used by the
+/// official clients.
+pub struct MercuryTransport {
+    session: SessionService,
+}
+
+impl MercuryTransport {
+    pub fn new(session: SessionService) -> Self {
+        Self { session }
+    }
+
+    fn remote_uri(&self) -> Result<String, Error> {
+        let handle = self.session.connected()?;
+        let username = handle.username().ok_or(Error::SessionDisconnected)?;
+        Ok(format!("hm://remote/user/{}/", username))
+    }
+}
+
+impl ConnectTransport for MercuryTransport {
+    fn subscribe(&self) -> Result<Receiver<Vec<u8>>, Error> {
+        let uri = self.remote_uri()?;
+        let events = self.session.connected()?.subscribe_mercury(uri)?;
+        let (frame_send, frame_recv) = unbounded();
+        thread::spawn(move || {
+            for event in events {
+                if let Some(frame) = event.payload.into_iter().next() {
+                    if frame_send.send(frame).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(frame_recv)
+    }
+
+    fn send(&self, frame: Vec<u8>) -> Result<(), Error> {
+        let uri = self.remote_uri()?;
+        self.session.connected()?.send_mercury_bytes(uri, frame)
+    }
+}
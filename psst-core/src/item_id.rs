@@ -173,6 +173,19 @@ impl FileId {
         Some(FileId(data.try_into().ok()?))
     }
 
+    pub fn from_base16(id: &str) -> Option<Self> {
+        if id.len() != 40 {
+            return None;
+        }
+        let mut data = [0_u8; 20];
+        for (byte, pair) in data.iter_mut().zip(id.as_bytes().chunks(2)) {
+            let hi = BASE16_DIGITS.iter().position(|e| *e == pair[0])? as u8;
+            let lo = BASE16_DIGITS.iter().position(|e| *e == pair[1])? as u8;
+            *byte = hi * 16 + lo;
+        }
+        Some(FileId(data))
+    }
+
     pub fn to_base16(&self) -> String {
         self.0
             .iter()
@@ -1,13 +1,15 @@
 use std::time::Duration;
 
 use quick_protobuf::MessageRead;
+use serde::Deserialize;
 
 use crate::{
     error::Error,
     item_id::{FileId, ItemId, ItemIdType},
     player::file::{AudioFormat, MediaFile, MediaPath},
-    protocol::metadata::{AudioFile, Episode, Restriction, Track},
-    session::SessionService,
+    protocol::metadata::{Album, AudioFile, Episode, Restriction, Track},
+    session::{access_token::AccessToken, SessionService},
+    util::default_ureq_agent_builder,
 };
 
 pub trait Fetch: MessageRead<'static> {
@@ -29,6 +31,59 @@ impl Fetch for Episode {
     }
 }
 
+impl Fetch for Album {
+    fn uri(id: ItemId) -> String {
+        format!("hm://metadata/3/album/{}", id.to_base16())
+    }
+}
+
+/// Fetch the tracks and episodes of a playlist, in order.  Playlists are not
+/// served by the metadata service, so we go through the Web API, page by page.
+/// Local files and unavailable items are skipped.
+pub fn fetch_playlist_items(session: &SessionService, id: ItemId) -> Result<Vec<ItemId>, Error> {
+    #[derive(Deserialize)]
+    struct Page {
+        items: Vec<PlaylistItem>,
+        next: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct PlaylistItem {
+        track: Option<PlaylistTrack>,
+    }
+
+    #[derive(Deserialize)]
+    struct PlaylistTrack {
+        uri: Option<String>,
+    }
+
+    let agent: ureq::Agent = default_ureq_agent_builder(session.proxy_url().as_deref())
+        .build()
+        .into();
+    let access_token = AccessToken::request(session)?;
+    let mut items = Vec::new();
+    let mut next = Some(format!(
+        "https://api.spotify.com/v1/playlists/{}/tracks?fields=items(track(uri)),next&limit=100",
+        id.to_base62()
+    ));
+    while let Some(uri) = next {
+        let page: Page = agent
+            .get(&uri)
+            .header("Authorization", &format!("Bearer {}", access_token.token))
+            .call()?
+            .into_body()
+            .read_json()?;
+        items.extend(
+            page.items
+                .into_iter()
+                .filter_map(|item| ItemId::from_uri(&item.track?.uri?))
+                .filter(|id| matches!(id.id_type, ItemIdType::Track | ItemIdType::Podcast)),
+        );
+        next = page.next;
+    }
+    Ok(items)
+}
+
 pub trait ToMediaPath {
     fn is_restricted_in_region(&self, country: &str) -> bool;
     fn find_allowed_alternative(&self, country: &str) -> Option<ItemId>;
@@ -57,6 +112,10 @@ impl ToMediaPath for Track {
             file_id: FileId::from_raw(file.file_id.as_ref()?)?,
             file_format: AudioFormat::from_protocol(file.format?),
             duration: Duration::from_millis(self.duration? as u64),
+            album_id: self
+                .album
+                .as_ref()
+                .and_then(|album| ItemId::from_raw(album.gid.as_ref()?, ItemIdType::Unknown)),
         })
     }
 }
@@ -79,6 +138,7 @@ impl ToMediaPath for Episode {
             file_id: FileId::from_raw(file.file_id.as_ref()?)?,
             file_format: AudioFormat::from_protocol(file.format?),
             duration: Duration::from_millis(self.duration? as u64),
+            album_id: None,
         })
     }
 }
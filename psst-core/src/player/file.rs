@@ -32,6 +32,8 @@ pub struct MediaPath {
     pub file_id: FileId,
     pub file_format: AudioFormat,
     pub duration: Duration,
+    /// Album of the track, if known.
+    pub album_id: Option<ItemId>,
 }
 
 // possibly should be combined with AudioCodecFormat?
@@ -115,8 +117,7 @@ impl MediaFile {
     }
 
     pub fn open(path: MediaPath, cdn: CdnHandle, cache: CacheHandle) -> Result<Self, Error> {
-        let cached_path = cache.audio_file_path(path.file_id);
-        if cached_path.exists() {
+        if let Some(cached_path) = cache.get_audio_file(path.file_id) {
             let cached_file = CachedFile::open(path, cached_path)?;
             Ok(Self::Cached { cached_file })
         } else {
@@ -171,12 +172,13 @@ impl MediaFile {
         Ok((decoded, normalization))
     }
 
-    pub fn local_audio_source(&self) -> Result<(AudioDecoder, NormalizationData), Error> {
-        let mut reader = fs::File::open(self.path().item_id.to_local())?;
-        let normalization = NormalizationData::parse(&mut reader)?;
-        let encoded = OffsetFile::new(reader, self.header_length())?;
-        let decoded = AudioDecoder::new(encoded, self.codec_format())?;
-        Ok((decoded, normalization))
+    pub fn local_audio_source(&self) -> Result<AudioDecoder, Error> {
+        // Unlike the files served by Spotify, local files do not have any custom
+        // header in front of the audio data.  Normalization data for them is
+        // looked up separately, see `audio::loudness`.
+        let reader = fs::File::open(self.path().item_id.to_local())?;
+        let decoded = AudioDecoder::new(reader, self.codec_format())?;
+        Ok(decoded)
     }
 
     fn header_length(&self) -> u64 {
@@ -1,8 +1,14 @@
 use std::time::Duration;
 
+use crossbeam_channel::Sender;
+
 use crate::{
     audio::{
-        decode::AudioDecoder, decrypt::AudioKey, normalize::NormalizationLevel, probe::TrackProbe,
+        decode::AudioDecoder,
+        decrypt::AudioKey,
+        loudness::{self, LoudnessMsg},
+        normalize::NormalizationLevel,
+        probe::TrackProbe,
     },
     cache::CacheHandle,
     cdn::CdnHandle,
@@ -36,23 +42,37 @@ impl PlaybackItem {
         session: &SessionService,
         cdn: CdnHandle,
         cache: CacheHandle,
+        loudness_scanner: &Sender<LoudnessMsg>,
         config: &PlaybackConfig,
     ) -> Result<LoadedPlaybackItem, Error> {
         let path = load_media_path(self.item_id, session, &cache, config)?;
         let (file, source, norm_data) = match self.item_id.id_type {
             ItemIdType::LocalFile => {
                 let file = MediaFile::local(path);
-                let (source, norm_data) = file.local_audio_source()?;
+                let source = file.local_audio_source()?;
+                // Files that have not been analysed yet play without
+                // normalization until the background scan finishes.
+                let norm_data = loudness::local_normalization(
+                    &path.item_id.to_local(),
+                    &cache,
+                    loudness_scanner,
+                );
                 (file, source, norm_data)
             }
             _ => {
                 let key = load_audio_key(&path, session, &cache)?;
                 let file = MediaFile::open(path, cdn, cache)?;
                 let (source, norm_data) = file.remote_audio_source(key)?;
-                (file, source, norm_data)
+                (file, source, Some(norm_data))
             }
         };
-        let norm_factor = norm_data.factor_for_level(self.norm_level, config.pregain);
+        let norm_factor = norm_data.map_or(1.0, |norm_data| {
+            norm_data.factor_for_level(
+                self.norm_level,
+                config.pregain,
+                config.limit_peaks.is_some(),
+            )
+        });
         Ok(LoadedPlaybackItem {
             file,
             source,
@@ -61,7 +81,7 @@ impl PlaybackItem {
     }
 }
 
-fn load_media_path(
+pub(crate) fn load_media_path(
     item_id: ItemId,
     session: &SessionService,
     cache: &CacheHandle,
@@ -146,6 +166,7 @@ fn load_media_path_from_local(item_id: ItemId) -> Result<MediaPath, Error> {
         // duration from the codec params; in that case, default to 0 and let it
         // be calculated at runtime as we play the track.
         duration: probe.duration.unwrap_or(Duration::from_millis(0)),
+        album_id: None,
     })
 }
 
@@ -193,7 +214,7 @@ fn load_episode(
     }
 }
 
-fn load_audio_key(
+pub(crate) fn load_audio_key(
     path: &MediaPath,
     session: &SessionService,
     cache: &CacheHandle,
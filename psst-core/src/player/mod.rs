@@ -1,35 +1,81 @@
 pub mod file;
 pub mod item;
+pub mod offline;
+pub mod persist;
 pub mod queue;
+pub mod resume;
+pub mod shuffle;
+pub mod sleep;
 mod storage;
 mod worker;
 
-use std::{mem, thread, thread::JoinHandle, time::Duration};
+use std::{
+    mem,
+    ops::Range,
+    thread,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
 
 use crate::{
-    audio::output::{AudioOutput, AudioSink, DefaultAudioOutput, DefaultAudioSink},
+    actor::{Actor, ActorHandle, Capacity},
+    audio::{
+        analyzer::Analyzer,
+        equalizer::EqualizerSettings,
+        limiter::PeakLimiting,
+        loudness::{LoudnessMsg, LoudnessScanner},
+        mix::ChannelMixSettings,
+        output::{AudioOutput, AudioSink, DefaultAudioOutput, DefaultAudioSink},
+        resample::ResamplingQuality,
+        silence::SilenceTrimming,
+        source::CrossfadeCurve,
+        stretch,
+    },
     cache::CacheHandle,
     cdn::CdnHandle,
     error::Error,
+    item_id::ItemId,
     session::SessionService,
 };
 
 use self::{
     file::MediaPath,
     item::{LoadedPlaybackItem, PlaybackItem},
-    queue::{Queue, QueueBehavior},
-    worker::PlaybackManager,
+    queue::{Queue, QueueBehavior, QueueSnapshot},
+    resume::ResumePoint,
+    shuffle::ItemGroup,
+    sleep::{SleepMode, SleepMsg, SleepTimer},
+    worker::{Crossfade, PlaybackManager},
 };
 
 const PREVIOUS_TRACK_THRESHOLD: Duration = Duration::from_secs(3);
 const STOP_AFTER_CONSECUTIVE_LOADING_FAILURES: usize = 3;
+const RESUME_POINT_SAVE_INTERVAL: Duration = Duration::from_secs(15);
+const MIN_LOOP_LENGTH: Duration = Duration::from_millis(100);
 
 #[derive(Clone)]
 pub struct PlaybackConfig {
     pub bitrate: usize,
     pub pregain: f32,
+    /// Overlap of two consecutive tracks.  Zero disables the crossfade.
+    pub crossfade: Duration,
+    pub crossfade_curve: CrossfadeCurve,
+    pub equalizer: EqualizerSettings,
+    pub channel_mix: ChannelMixSettings,
+    /// Quality of the resampling, for outputs running at a different sample
+    /// rate than the played track.
+    pub resampling_quality: ResamplingQuality,
+    /// Keep the peaks of the output under a ceiling, which lets the pregain
+    /// raise tracks with loud peaks.  `None` lowers the normalization gain of
+    /// such tracks instead.
+    pub limit_peaks: Option<PeakLimiting>,
+    /// Remember the play-head of tracks at least this long, like the one of
+    /// episodes.  `None` only remembers it for episodes.
+    pub resume_tracks_longer_than: Option<Duration>,
+    /// Shorten long pauses, meant for spoken-word content.  `None` disables it.
+    pub trim_silence: Option<SilenceTrimming>,
 }
 
 impl Default for PlaybackConfig {
@@ -37,6 +83,14 @@ impl Default for PlaybackConfig {
         Self {
             bitrate: 320,
             pregain: 3.0,
+            crossfade: Duration::ZERO,
+            crossfade_curve: CrossfadeCurve::default(),
+            equalizer: EqualizerSettings::default(),
+            channel_mix: ChannelMixSettings::default(),
+            resampling_quality: ResamplingQuality::default(),
+            limit_peaks: Some(PeakLimiting::default()),
+            resume_tracks_longer_than: None,
+            trim_silence: None,
         }
     }
 }
@@ -53,7 +107,16 @@ pub struct Player {
     receiver: Receiver<PlayerEvent>,
     audio_output_sink: DefaultAudioSink,
     playback_mgr: PlaybackManager,
+    loudness_scanner: ActorHandle<LoudnessMsg>,
     consecutive_loading_failures: usize,
+    /// Play-head of a restored item, which should start paused there.
+    restore_at: Option<Duration>,
+    resume_point_saved_at: Instant,
+    /// Volume set by the user, restored after the sleep timer fades it out.
+    volume: f64,
+    sleep_timer: Option<(SleepMode, ActorHandle<SleepMsg>)>,
+    /// Section of the current track that is being looped.
+    looped_section: Option<Range<Duration>>,
 }
 
 impl Player {
@@ -66,7 +129,19 @@ impl Player {
     ) -> Self {
         let (sender, receiver) = unbounded();
         Self {
-            playback_mgr: PlaybackManager::new(audio_output.sink(), sender.clone()),
+            playback_mgr: PlaybackManager::new(
+                audio_output.sink(),
+                sender.clone(),
+                config.equalizer.clone(),
+                config.channel_mix,
+                config.resampling_quality,
+                config.limit_peaks,
+                config.trim_silence,
+            ),
+            loudness_scanner: LoudnessScanner::spawn(Capacity::Unbounded, "loudness_scanner", {
+                let cache = cache.clone();
+                move |_| LoudnessScanner::new(cache)
+            }),
             session,
             cdn,
             cache,
@@ -78,6 +153,11 @@ impl Player {
             preload: PreloadState::None,
             queue: Queue::new(),
             consecutive_loading_failures: 0,
+            restore_at: None,
+            resume_point_saved_at: Instant::now(),
+            volume: 1.0,
+            sleep_timer: None,
+            looped_section: None,
         }
     }
 
@@ -89,6 +169,11 @@ impl Player {
         self.receiver.clone()
     }
 
+    /// Take the analyzer of the played signal, see `PlaybackManager::take_analyzer`.
+    pub fn analyzer(&mut self) -> Option<Analyzer> {
+        self.playback_mgr.take_analyzer()
+    }
+
     pub fn handle(&mut self, event: PlayerEvent) {
         match event {
             PlayerEvent::Command(cmd) => self.handle_command(cmd),
@@ -96,18 +181,30 @@ impl Player {
             PlayerEvent::Preloaded { item, result } => self.handle_preloaded(item, result),
             PlayerEvent::Position { position, path } => self.handle_position(position, path),
             PlayerEvent::EndOfTrack => self.handle_end_of_track(),
+            PlayerEvent::SleepTimerExpired => self.handle_sleep_timer_expired(),
             PlayerEvent::Loading { .. }
             | PlayerEvent::Playing { .. }
             | PlayerEvent::Pausing { .. }
             | PlayerEvent::Resuming { .. }
             | PlayerEvent::Stopped
-            | PlayerEvent::Blocked { .. } => {}
+            | PlayerEvent::Blocked { .. }
+            | PlayerEvent::QueueChanged { .. }
+            | PlayerEvent::ResumePointSaved { .. }
+            | PlayerEvent::SilenceTrimmed { .. }
+            | PlayerEvent::GainReduction { .. }
+            | PlayerEvent::SleepTimer { .. }
+            | PlayerEvent::LoopChanged { .. } => {}
         };
     }
 
     fn handle_command(&mut self, cmd: PlayerCommand) {
         match cmd {
             PlayerCommand::LoadQueue { items, position } => self.load_queue(items, position),
+            PlayerCommand::Restore {
+                items,
+                position,
+                progress,
+            } => self.restore(items, position, progress),
             PlayerCommand::LoadAndPlay { item } => self.load_and_play(item),
             PlayerCommand::Preload { item } => self.preload(item),
             PlayerCommand::Pause => self.pause(),
@@ -118,9 +215,47 @@ impl Player {
             PlayerCommand::Stop => self.stop(),
             PlayerCommand::Seek { position } => self.seek(position),
             PlayerCommand::Configure { config } => self.configure(config),
-            PlayerCommand::SetQueueBehavior { behavior } => self.queue.set_behaviour(behavior),
-            PlayerCommand::AddToQueue { item } => self.queue.add(item),
+            PlayerCommand::SetQueueBehavior { behavior } => {
+                self.queue.set_behaviour(behavior);
+                self.queue_edited();
+            }
+            PlayerCommand::SetItemGroups { groups } => self.queue.set_groups(groups),
+            PlayerCommand::AddToQueue { item } => {
+                self.queue.add(item);
+                self.queue_edited();
+            }
+            PlayerCommand::PlayNext { item } => {
+                self.queue.add_next(item);
+                self.queue_edited();
+            }
+            PlayerCommand::RemoveFromQueue { index } => {
+                if self.queue.remove(index).is_some() {
+                    self.queue_edited();
+                } else {
+                    log::warn!("no queued item at {}", index);
+                }
+            }
+            PlayerCommand::MoveInQueue { from, to } => {
+                if self.queue.move_item(from, to) {
+                    self.queue_edited();
+                } else {
+                    log::warn!("cannot move queued item from {} to {}", from, to);
+                }
+            }
+            PlayerCommand::ClearQueue => {
+                self.queue.clear_user_items();
+                self.queue_edited();
+            }
             PlayerCommand::SetVolume { volume } => self.set_volume(volume),
+            PlayerCommand::SetEqualizer { settings } => self.set_equalizer(settings),
+            PlayerCommand::SetChannelMix { settings } => self.set_channel_mix(settings),
+            PlayerCommand::SetSpeed { speed } => self.set_speed(speed),
+            PlayerCommand::SetSilenceTrimming { trimming } => self.set_silence_trimming(trimming),
+            PlayerCommand::SetPeakLimiting { limiting } => self.set_peak_limiting(limiting),
+            PlayerCommand::SetSleepTimer { mode, fade } => self.set_sleep_timer(mode, fade),
+            PlayerCommand::CancelSleepTimer => self.cancel_sleep_timer(),
+            PlayerCommand::SetLoop { section } => self.set_loop(section),
+            PlayerCommand::SetOutputDevice { name } => self.audio_output_sink.set_device(name),
         }
     }
 
@@ -160,6 +295,7 @@ impl Player {
                 Ok(loaded_item) => {
                     log::info!("preloaded audio file");
                     self.preload = PreloadState::Preloaded { item, loaded_item };
+                    self.queue_preloaded();
                 }
                 Err(err) => {
                     log::error!("failed to preload audio file, error while opening: {}", err);
@@ -186,9 +322,14 @@ impl Player {
                 log::warn!("received unexpected position report");
             }
         }
+        if self.resume_point_saved_at.elapsed() >= RESUME_POINT_SAVE_INTERVAL {
+            self.save_resume_point();
+        }
+        self.update_sleep_deadline();
         const PRELOAD_BEFORE_END_OF_TRACK: Duration = Duration::from_secs(30);
         let time_until_end_of_track = path.duration.checked_sub(new_position).unwrap_or_default();
-        if time_until_end_of_track <= PRELOAD_BEFORE_END_OF_TRACK {
+        // With crossfade enabled, the next track needs to be ready earlier.
+        if time_until_end_of_track <= PRELOAD_BEFORE_END_OF_TRACK + self.config.crossfade {
             if let Some(&item_to_preload) = self.queue.get_following() {
                 self.preload(item_to_preload);
             }
@@ -196,17 +337,57 @@ impl Player {
     }
 
     fn handle_end_of_track(&mut self) {
+        if let PlayerState::Playing { path, .. } | PlayerState::Paused { path, .. } = self.state {
+            self.save_resume_point_at(path, path.duration);
+        }
+        if let Some((SleepMode::EndOfTrack, _)) = self.sleep_timer {
+            self.handle_sleep_timer_expired();
+            return;
+        }
         self.queue.skip_to_following();
+        self.queue_changed();
         if let Some(&item) = self.queue.get_current() {
-            self.load_and_play(item);
+            match self.preload {
+                PreloadState::Queued { item: queued_item } if queued_item == item => {
+                    // The playback manager has already continued with the queued item,
+                    // right after the last frame of the previous one.
+                    self.preload = PreloadState::None;
+                    if let Some(path) = self.playback_mgr.advance() {
+                        self.set_playing(path);
+                    } else {
+                        self.load_and_play(item);
+                    }
+                }
+                _ => self.load_and_play(item),
+            }
         } else {
             self.stop();
         }
     }
 
     fn load_queue(&mut self, items: Vec<PlaybackItem>, position: usize) {
+        self.fill_queue(items, position, None);
+    }
+
+    fn restore(&mut self, items: Vec<PlaybackItem>, position: usize, progress: Duration) {
+        self.fill_queue(items, position, Some(progress));
+    }
+
+    /// Replace the queue and start loading its current item.  With `restore_at`,
+    /// the item is paused at that position once loaded, instead of playing.
+    fn fill_queue(
+        &mut self,
+        items: Vec<PlaybackItem>,
+        position: usize,
+        restore_at: Option<Duration>,
+    ) {
+        self.save_resume_point();
         self.queue.fill(items, position);
+        self.queue_changed();
         if let Some(&item) = self.queue.get_current() {
+            // Has to be set before loading, as the item might be played right
+            // away.
+            self.restore_at = restore_at;
             self.load_and_play(item);
         } else {
             self.stop();
@@ -214,6 +395,19 @@ impl Player {
     }
 
     fn load_and_play(&mut self, item: PlaybackItem) {
+        // Check if the item has already been handed over to the playback manager.
+        if let PreloadState::Queued { item: queued_item } = self.preload {
+            self.preload = PreloadState::None;
+            if queued_item == item {
+                if let Some(path) = self.playback_mgr.play_queued() {
+                    self.set_playing(path);
+                    return;
+                }
+            } else {
+                self.playback_mgr.clear_queued();
+            }
+        }
+
         // Make sure to stop the sink, so any current audio source is cleared and the
         // playback stopped.
         self.audio_output_sink.stop();
@@ -245,9 +439,10 @@ impl Player {
                     let session = self.session.clone();
                     let cdn = self.cdn.clone();
                     let cache = self.cache.clone();
+                    let loudness_scanner = self.loudness_scanner.sender();
                     let config = self.config.clone();
                     move || {
-                        let result = item.load(&session, cdn, cache, &config);
+                        let result = item.load(&session, cdn, cache, &loudness_scanner, &config);
                         sender.send(PlayerEvent::Loaded { item, result }).unwrap();
                     }
                 })
@@ -270,9 +465,10 @@ impl Player {
             let session = self.session.clone();
             let cdn = self.cdn.clone();
             let cache = self.cache.clone();
+            let loudness_scanner = self.loudness_scanner.sender();
             let config = self.config.clone();
             move || {
-                let result = item.load(&session, cdn, cache, &config);
+                let result = item.load(&session, cdn, cache, &loudness_scanner, &config);
                 sender
                     .send(PlayerEvent::Preloaded { item, result })
                     .unwrap();
@@ -285,21 +481,187 @@ impl Player {
     }
 
     fn set_volume(&mut self, volume: f64) {
-        self.audio_output_sink.set_volume(volume as f32);
+        self.volume = volume;
+        match &self.sleep_timer {
+            // The timer might be fading out, let it apply the volume.
+            Some((_, timer)) => {
+                let _ = timer.send(SleepMsg::SetVolume(volume as f32));
+            }
+            None => self.audio_output_sink.set_volume(volume as f32),
+        }
+    }
+
+    fn set_equalizer(&mut self, settings: EqualizerSettings) {
+        self.config.equalizer = settings.clone();
+        self.playback_mgr.set_equalizer(settings);
+    }
+
+    fn set_channel_mix(&mut self, settings: ChannelMixSettings) {
+        self.config.channel_mix = settings;
+        self.playback_mgr.set_channel_mix(settings);
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.playback_mgr
+            .set_speed(speed.clamp(stretch::MIN_SPEED, stretch::MAX_SPEED));
+    }
+
+    fn set_silence_trimming(&mut self, trimming: Option<SilenceTrimming>) {
+        self.config.trim_silence = trimming;
+        self.playback_mgr.set_silence_trimming(trimming);
+    }
+
+    fn set_peak_limiting(&mut self, limiting: Option<PeakLimiting>) {
+        self.config.limit_peaks = limiting;
+        self.playback_mgr.set_peak_limiting(limiting);
     }
 
     fn play_loaded(&mut self, loaded_item: LoadedPlaybackItem) {
         log::info!("starting playback");
         let path = loaded_item.file.path();
-        let position = Duration::default();
         self.playback_mgr.play(loaded_item);
+        self.set_playing(path);
+        // Next item might have been preloaded while we were loading this one.
+        self.queue_preloaded();
+    }
+
+    fn set_playing(&mut self, path: MediaPath) {
+        let position = Duration::default();
         self.state = PlayerState::Playing { path, position };
         self.sender
             .send(PlayerEvent::Playing { path, position })
             .unwrap();
+        self.resume_point_saved_at = Instant::now();
+        self.update_sleep_deadline();
+        // Sections are looped only within the track they were set in.
+        if self.looped_section.take().is_some() {
+            self.sender
+                .send(PlayerEvent::LoopChanged { section: None })
+                .unwrap();
+        }
+        // Restored items stay paused at the play-head of the snapshot, others
+        // continue from their resume point.
+        if let Some(progress) = self.restore_at.take() {
+            if !progress.is_zero() {
+                self.seek(progress);
+            }
+            self.pause();
+        } else if self.is_resumable(&path) {
+            if let Some(position) = self
+                .cache
+                .get_resume_point(path.item_id)
+                .and_then(|point| point.start_at())
+            {
+                log::info!("resuming at {:?}", position);
+                self.seek(position);
+            }
+        }
+    }
+
+    fn is_resumable(&self, path: &MediaPath) -> bool {
+        resume::is_resumable(path, self.config.resume_tracks_longer_than)
+    }
+
+    /// Remember the play-head of the current item, if it is resumable.
+    fn save_resume_point(&mut self) {
+        if let PlayerState::Playing { path, position } | PlayerState::Paused { path, position } =
+            self.state
+        {
+            self.save_resume_point_at(path, position);
+        }
+    }
+
+    fn save_resume_point_at(&mut self, path: MediaPath, position: Duration) {
+        self.resume_point_saved_at = Instant::now();
+        if !self.is_resumable(&path) {
+            return;
+        }
+        let point = ResumePoint::new(position, path.duration);
+        if let Err(err) = self.cache.save_resume_point(path.item_id, &point) {
+            log::error!("failed to save resume point: {}", err);
+        }
+        self.sender
+            .send(PlayerEvent::ResumePointSaved {
+                item_id: path.item_id,
+                point,
+            })
+            .unwrap();
+    }
+
+    /// Hand the preloaded item over to the playback manager if it follows the
+    /// current one, so it can start without a gap.
+    fn queue_preloaded(&mut self) {
+        let is_following = match &self.preload {
+            PreloadState::Preloaded { item, loaded_item } => {
+                self.queue.get_following() == Some(item)
+                    && matches!(
+                        self.state,
+                        PlayerState::Playing { .. } | PlayerState::Paused { .. }
+                    )
+                    && self.playback_mgr.can_queue(loaded_item)
+                    && !matches!(self.sleep_timer, Some((SleepMode::EndOfTrack, _)))
+                    && self.looped_section.is_none()
+            }
+            _ => false,
+        };
+        if is_following {
+            if let PreloadState::Preloaded { item, loaded_item } =
+                mem::replace(&mut self.preload, PreloadState::None)
+            {
+                log::info!("queueing preloaded item for gapless playback");
+                let crossfade = self.crossfade_into(&loaded_item.file.path());
+                self.playback_mgr.queue(loaded_item, crossfade);
+                self.preload = PreloadState::Queued { item };
+            }
+        }
+    }
+
+    fn crossfade_into(&self, next: &MediaPath) -> Option<Crossfade> {
+        if self.config.crossfade.is_zero() {
+            return None;
+        }
+        let current = match &self.state {
+            PlayerState::Playing { path, .. } | PlayerState::Paused { path, .. } => path,
+            _ => return None,
+        };
+        if is_album_flow(current, next, self.queue.behavior()) {
+            return None;
+        }
+        Some(Crossfade {
+            duration: self.config.crossfade,
+            curve: self.config.crossfade_curve,
+        })
+    }
+
+    /// Drop the queued item if it does not follow the current one after an edit
+    /// of the queue, and report the new order.
+    fn queue_edited(&mut self) {
+        self.unqueue_if_stale();
+        self.queue_changed();
+    }
+
+    fn queue_changed(&self) {
+        self.sender
+            .send(PlayerEvent::QueueChanged {
+                queue: self.queue.snapshot(),
+            })
+            .unwrap();
+        self.update_sleep_deadline();
+    }
+
+    /// Drop the queued item if it no longer follows the current one, for example
+    /// after the queue has been changed.
+    fn unqueue_if_stale(&mut self) {
+        if let PreloadState::Queued { item } = self.preload {
+            if self.queue.get_following() != Some(&item) {
+                self.playback_mgr.clear_queued();
+                self.preload = PreloadState::None;
+            }
+        }
     }
 
     fn pause(&mut self) {
+        self.save_resume_point();
         match mem::replace(&mut self.state, PlayerState::Invalid) {
             PlayerState::Playing { path, position } | PlayerState::Paused { path, position } => {
                 log::info!("pausing playback");
@@ -308,6 +670,7 @@ impl Player {
                     .send(PlayerEvent::Pausing { path, position })
                     .unwrap();
                 self.state = PlayerState::Paused { path, position };
+                self.update_sleep_deadline();
             }
             _ => {
                 log::warn!("invalid state transition");
@@ -324,6 +687,7 @@ impl Player {
                     .send(PlayerEvent::Resuming { path, position })
                     .unwrap();
                 self.state = PlayerState::Playing { path, position };
+                self.update_sleep_deadline();
             }
             _ => {
                 log::warn!("invalid state transition");
@@ -343,7 +707,9 @@ impl Player {
 
     fn previous(&mut self) {
         if self.is_near_playback_start() {
+            self.save_resume_point();
             self.queue.skip_to_previous();
+            self.queue_changed();
             if let Some(&item) = self.queue.get_current() {
                 self.load_and_play(item);
             } else {
@@ -355,7 +721,9 @@ impl Player {
     }
 
     fn next(&mut self) {
+        self.save_resume_point();
         self.queue.skip_to_next();
+        self.queue_changed();
         if let Some(&item) = self.queue.get_current() {
             self.load_and_play(item);
         } else {
@@ -364,18 +732,165 @@ impl Player {
     }
 
     fn stop(&mut self) {
+        self.cancel_sleep_timer();
+        self.save_resume_point();
         self.sender.send(PlayerEvent::Stopped).unwrap();
         self.audio_output_sink.stop();
+        if let PreloadState::Queued { .. } = self.preload {
+            self.playback_mgr.clear_queued();
+            self.preload = PreloadState::None;
+        }
         self.state = PlayerState::Stopped;
+        self.restore_at = None;
         self.queue.clear();
+        self.queue_changed();
         self.consecutive_loading_failures = 0;
     }
 
-    fn seek(&mut self, position: Duration) {
-        self.playback_mgr.seek(position);
+    fn seek(&mut self, new_position: Duration) {
+        // Keep the play-head up to date even before the position is reported, so
+        // a resume point saved right after is not stale.
+        if let PlayerState::Playing { position, .. } | PlayerState::Paused { position, .. } =
+            &mut self.state
+        {
+            *position = new_position;
+        }
+        self.playback_mgr.seek(new_position);
+    }
+
+    fn set_sleep_timer(&mut self, mode: SleepMode, fade: Duration) {
+        if let Some((_, timer)) = self.sleep_timer.take() {
+            let _ = timer.send(SleepMsg::Cancel);
+        }
+        if mode == SleepMode::EndOfTrack {
+            // Nothing should follow the current track, not even its tail
+            // crossfaded into the next one.
+            if let PreloadState::Queued { .. } = self.preload {
+                self.playback_mgr.clear_queued();
+                self.preload = PreloadState::None;
+            }
+        }
+        let timer = SleepTimer::spawn(Capacity::Unbounded, "sleep_timer", {
+            let sink = self.audio_output_sink.clone();
+            let event_send = self.sender.clone();
+            let volume = self.volume as f32;
+            move |_| SleepTimer::new(sink, event_send, mode, fade, volume)
+        });
+        if let SleepMode::After(after) = mode {
+            let _ = timer.send(SleepMsg::SetDeadline(Some(Instant::now() + after)));
+        }
+        self.sleep_timer = Some((mode, timer));
+        self.sender
+            .send(PlayerEvent::SleepTimer {
+                mode: Some(mode),
+                remaining: None,
+            })
+            .unwrap();
+        self.update_sleep_deadline();
+    }
+
+    fn cancel_sleep_timer(&mut self) {
+        if let Some((_, timer)) = self.sleep_timer.take() {
+            let _ = timer.send(SleepMsg::Cancel);
+            self.sender
+                .send(PlayerEvent::SleepTimer {
+                    mode: None,
+                    remaining: None,
+                })
+                .unwrap();
+        }
+    }
+
+    /// Tell the sleep timer when the playback is going to end, for the modes
+    /// that wait for it.  While paused, the end is not known.
+    fn update_sleep_deadline(&self) {
+        let (ends_here, timer) = match &self.sleep_timer {
+            Some((SleepMode::EndOfTrack, timer)) => (true, timer),
+            Some((SleepMode::EndOfQueue, timer)) => (self.queue.get_following().is_none(), timer),
+            Some((SleepMode::After(_), _)) | None => return,
+        };
+        let deadline = match self.state {
+            PlayerState::Playing { path, position } if ends_here => {
+                Some(Instant::now() + path.duration.saturating_sub(position))
+            }
+            _ => None,
+        };
+        let _ = timer.send(SleepMsg::SetDeadline(deadline));
+    }
+
+    fn handle_sleep_timer_expired(&mut self) {
+        // Take the timer out first, so `stop` does not report it as cancelled.
+        // Dropping its handle shuts it down, if it has not done so already.
+        if self.sleep_timer.take().is_none() {
+            return;
+        }
+        log::info!("sleep timer expired, stopping playback");
+        self.stop();
+        self.audio_output_sink.set_volume(self.volume as f32);
+        self.sender
+            .send(PlayerEvent::SleepTimer {
+                mode: None,
+                remaining: None,
+            })
+            .unwrap();
+    }
+
+    fn set_loop(&mut self, section: Option<Range<Duration>>) {
+        let (path, position) = match self.state {
+            PlayerState::Playing { path, position } | PlayerState::Paused { path, position } => {
+                (path, position)
+            }
+            _ => {
+                log::warn!("nothing to loop");
+                return;
+            }
+        };
+        let section = match section {
+            Some(section) => {
+                let section = section.start..section.end.min(path.duration);
+                if section.end < section.start + MIN_LOOP_LENGTH {
+                    log::warn!("loop section too short: {:?}", section);
+                    return;
+                }
+                Some(section)
+            }
+            None => None,
+        };
+        if let Some(section) = &section {
+            // The end of the track is not going to be reached, so there is
+            // nothing to play after it.
+            if let PreloadState::Queued { .. } = self.preload {
+                self.playback_mgr.clear_queued();
+                self.preload = PreloadState::None;
+            }
+            if !section.contains(&position) {
+                self.seek(section.start);
+            }
+        }
+        self.playback_mgr.set_loop(section.clone());
+        self.looped_section = section.clone();
+        self.sender
+            .send(PlayerEvent::LoopChanged { section })
+            .unwrap();
     }
 
     fn configure(&mut self, config: PlaybackConfig) {
+        if config.equalizer != self.config.equalizer {
+            self.playback_mgr.set_equalizer(config.equalizer.clone());
+        }
+        if config.channel_mix != self.config.channel_mix {
+            self.playback_mgr.set_channel_mix(config.channel_mix);
+        }
+        if config.resampling_quality != self.config.resampling_quality {
+            self.playback_mgr
+                .set_resampling_quality(config.resampling_quality);
+        }
+        if config.limit_peaks != self.config.limit_peaks {
+            self.playback_mgr.set_peak_limiting(config.limit_peaks);
+        }
+        if config.trim_silence != self.config.trim_silence {
+            self.playback_mgr.set_silence_trimming(config.trim_silence);
+        }
         self.config = config;
     }
 
@@ -391,7 +906,8 @@ impl Player {
     fn is_in_preload(&self, item: PlaybackItem) -> bool {
         match self.preload {
             PreloadState::Preloading { item: p_item, .. }
-            | PreloadState::Preloaded { item: p_item, .. } => p_item == item,
+            | PreloadState::Preloaded { item: p_item, .. }
+            | PreloadState::Queued { item: p_item } => p_item == item,
             _ => false,
         }
     }
@@ -402,6 +918,13 @@ pub enum PlayerCommand {
         items: Vec<PlaybackItem>,
         position: usize,
     },
+    /// Fill the queue like `LoadQueue`, but start the item at `position`
+    /// paused at `progress`, for example to continue a saved session.
+    Restore {
+        items: Vec<PlaybackItem>,
+        position: usize,
+        progress: Duration,
+    },
     LoadAndPlay {
         item: PlaybackItem,
     },
@@ -423,13 +946,74 @@ pub enum PlayerCommand {
     SetQueueBehavior {
         behavior: QueueBehavior,
     },
+    /// Tell the smart shuffle the artists and albums of items that are about
+    /// to be queued.
+    SetItemGroups {
+        groups: Vec<(ItemId, ItemGroup)>,
+    },
     AddToQueue {
         item: PlaybackItem,
     },
+    /// Queue the item to play right after the current one, before the other
+    /// items queued by the user.
+    PlayNext {
+        item: PlaybackItem,
+    },
+    /// Remove an item queued by the user.  `index` points into
+    /// `QueueSnapshot::queued`.
+    RemoveFromQueue {
+        index: usize,
+    },
+    /// Reorder the items queued by the user.
+    MoveInQueue {
+        from: usize,
+        to: usize,
+    },
+    /// Remove all items queued by the user.
+    ClearQueue,
     /// Change playback volume to a value in 0.0..=1.0 range.
     SetVolume {
         volume: f64,
     },
+    /// Change the equalizer settings, crossfading from the current ones.
+    SetEqualizer {
+        settings: EqualizerSettings,
+    },
+    /// Change the mono downmix and the balance, fading from the current ones.
+    SetChannelMix {
+        settings: ChannelMixSettings,
+    },
+    /// Change playback speed, keeping the pitch.  1.0 is the normal speed, the
+    /// value is clamped to the `stretch::MIN_SPEED..=stretch::MAX_SPEED` range.
+    SetSpeed {
+        speed: f32,
+    },
+    /// Shorten long pauses from now on, or stop shortening them if `None`.
+    SetSilenceTrimming {
+        trimming: Option<SilenceTrimming>,
+    },
+    /// Limit the peaks from now on, or stop limiting them if `None`.  Tracks
+    /// loaded without limiting keep their lowered normalization gain.
+    SetPeakLimiting {
+        limiting: Option<PeakLimiting>,
+    },
+    /// Stop the playback as given by `mode`, lowering the volume over `fade`
+    /// before.  Replaces the previous sleep timer.
+    SetSleepTimer {
+        mode: SleepMode,
+        fade: Duration,
+    },
+    CancelSleepTimer,
+    /// Loop `section` of the current track seamlessly, or stop looping if
+    /// `None`.  Looping ends when another track starts.
+    SetLoop {
+        section: Option<Range<Duration>>,
+    },
+    /// Move the output to the audio device called `name`, or to the default
+    /// one if `None`.  The playback continues from the same position.
+    SetOutputDevice {
+        name: Option<String>,
+    },
 }
 
 pub enum PlayerEvent {
@@ -480,6 +1064,38 @@ pub enum PlayerEvent {
     EndOfTrack,
     /// The queue is empty.
     Stopped,
+    /// The queue has been edited or its position has changed.
+    QueueChanged {
+        queue: QueueSnapshot,
+    },
+    /// Play-head of an episode, or a long track, has been remembered.
+    ResumePointSaved {
+        item_id: ItemId,
+        point: ResumePoint,
+    },
+    /// Silence trimming has shortened the playing track by `trimmed` in total
+    /// so far.  Positions are not affected, they stay in the time of the track.
+    SilenceTrimmed {
+        path: MediaPath,
+        trimmed: Duration,
+    },
+    /// Peak limiter has lowered the gain by at most `reduction_db` since the
+    /// last report.  Reported while it is limiting, and once after it stops.
+    GainReduction {
+        reduction_db: f32,
+    },
+    /// Sleep timer has been set, is counting down, or is gone if `mode` is
+    /// `None`.  `remaining` is known once the end of the playback is.
+    SleepTimer {
+        mode: Option<SleepMode>,
+        remaining: Option<Duration>,
+    },
+    /// Sleep timer has faded out the playback, which is going to stop.
+    SleepTimerExpired,
+    /// Section of the current track that is being looped has changed.
+    LoopChanged {
+        section: Option<Range<Duration>>,
+    },
 }
 
 enum PlayerState {
@@ -508,5 +1124,59 @@ enum PreloadState {
         item: PlaybackItem,
         loaded_item: LoadedPlaybackItem,
     },
+    /// Preloaded item has been handed over to the playback manager and starts
+    /// right after the current one ends.
+    Queued {
+        item: PlaybackItem,
+    },
     None,
 }
+
+/// Albums are usually meant to flow from one track into the next, so we do not
+/// fade between tracks played in order from an album.
+fn is_album_flow(current: &MediaPath, next: &MediaPath, behavior: QueueBehavior) -> bool {
+    let is_in_order = matches!(behavior, QueueBehavior::Sequential | QueueBehavior::LoopAll);
+    let is_same_album = current.album_id.is_some() && current.album_id == next.album_id;
+    is_in_order && is_same_album
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        item_id::{FileId, ItemIdType},
+        player::file::AudioFormat,
+    };
+
+    fn path(album: Option<u128>) -> MediaPath {
+        MediaPath {
+            item_id: ItemId::new(1, ItemIdType::Track),
+            file_id: FileId::default(),
+            file_format: AudioFormat::OggVorbis,
+            duration: Duration::from_secs(180),
+            album_id: album.map(|id| ItemId::new(id, ItemIdType::Unknown)),
+        }
+    }
+
+    #[test]
+    fn skips_the_crossfade_within_an_album_played_in_order() {
+        let (current, same_album) = (path(Some(1)), path(Some(1)));
+        assert!(is_album_flow(
+            &current,
+            &same_album,
+            QueueBehavior::Sequential
+        ));
+        assert!(is_album_flow(&current, &same_album, QueueBehavior::LoopAll));
+        assert!(!is_album_flow(&current, &same_album, QueueBehavior::Random));
+        assert!(!is_album_flow(
+            &current,
+            &path(Some(2)),
+            QueueBehavior::Sequential
+        ));
+        assert!(!is_album_flow(
+            &path(None),
+            &path(None),
+            QueueBehavior::Sequential
+        ));
+    }
+}
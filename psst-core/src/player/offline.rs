@@ -0,0 +1,183 @@
+use std::io;
+
+use crossbeam_channel::Sender;
+use tempfile::NamedTempFile;
+
+use crate::{
+    actor::{Act, Actor},
+    cache::CacheHandle,
+    cdn::CdnHandle,
+    error::Error,
+    item_id::{ItemId, ItemIdType},
+    session::SessionService,
+};
+
+use super::{
+    file::MediaPath,
+    item::{load_audio_key, load_media_path},
+    PlaybackConfig,
+};
+
+pub enum OfflineMsg {
+    /// Pin the items and download everything needed to play them without a
+    /// connection: metadata, audio keys and the encrypted audio files.
+    Download(Vec<ItemId>),
+    /// Unpin the items.  Already downloaded content stays in the cache, but is
+    /// not kept there on purpose anymore.
+    Remove(Vec<ItemId>),
+    /// Download all pinned items that are not complete yet, for example after
+    /// an interrupted download.
+    ResumePinned,
+    /// Change the configuration, mainly the bitrate of downloaded files.
+    Configure(PlaybackConfig),
+}
+
+pub enum OfflineEvent {
+    Progress {
+        item_id: ItemId,
+        downloaded: u64,
+        total: u64,
+    },
+    Downloaded {
+        item_id: ItemId,
+    },
+    Removed {
+        item_id: ItemId,
+    },
+    Failed {
+        item_id: ItemId,
+        error: Error,
+    },
+}
+
+/// Background worker making pinned items available offline.  Items are
+/// downloaded one after another, and every step goes through the same cache
+/// `PlaybackItem::load` reads from, so playback of a completely downloaded
+/// item does not need a session connection.
+pub struct Downloader {
+    session: SessionService,
+    cdn: CdnHandle,
+    cache: CacheHandle,
+    config: PlaybackConfig,
+    events: Sender<OfflineEvent>,
+}
+
+impl Downloader {
+    pub fn new(
+        session: SessionService,
+        cdn: CdnHandle,
+        cache: CacheHandle,
+        config: PlaybackConfig,
+        events: Sender<OfflineEvent>,
+    ) -> Self {
+        Self {
+            session,
+            cdn,
+            cache,
+            config,
+            events,
+        }
+    }
+
+    fn download(&self, item_id: ItemId) {
+        let result = self.cache.pin(item_id).and_then(|_| self.fetch(item_id));
+        let event = match result {
+            Ok(_) => OfflineEvent::Downloaded { item_id },
+            Err(error) => {
+                log::error!("failed to download {:?}: {}", item_id, error);
+                OfflineEvent::Failed { item_id, error }
+            }
+        };
+        self.events.send(event).ok();
+    }
+
+    fn fetch(&self, item_id: ItemId) -> Result<(), Error> {
+        if item_id.id_type == ItemIdType::LocalFile {
+            // Local files are always available.
+            return Ok(());
+        }
+        let path = load_media_path(item_id, &self.session, &self.cache, &self.config)?;
+        load_audio_key(&path, &self.session, &self.cache)?;
+        self.fetch_audio_file(&path)?;
+        self.cache.pin_audio_file(item_id, path.file_id)
+    }
+
+    fn fetch_audio_file(&self, path: &MediaPath) -> Result<(), Error> {
+        // How many bytes we request at once.
+        const CHUNK_LENGTH: u64 = 512 * 1024;
+
+        if self.cache.audio_file_path(path.file_id).exists() {
+            return Ok(());
+        }
+        log::info!("downloading audio file: {:?}", path.file_id);
+
+        let mut url = self.cdn.resolve_audio_file_url(path.file_id)?;
+        let mut file = NamedTempFile::new()?;
+        let mut downloaded = 0;
+        loop {
+            if url.is_expired() {
+                url = self.cdn.resolve_audio_file_url(path.file_id)?;
+            }
+            let (total, mut reader) =
+                self.cdn
+                    .fetch_file_range(&url.url, downloaded, CHUNK_LENGTH)?;
+            let written = io::copy(&mut reader, &mut file)?;
+            downloaded += written;
+            self.events
+                .send(OfflineEvent::Progress {
+                    item_id: path.item_id,
+                    downloaded,
+                    total,
+                })
+                .ok();
+            if downloaded >= total {
+                break;
+            }
+            if written == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        self.cache
+            .save_audio_file(path.file_id, file.path().to_path_buf())
+    }
+
+    fn remove(&self, item_id: ItemId) {
+        match self.cache.unpin(item_id) {
+            Ok(_) => {
+                self.events.send(OfflineEvent::Removed { item_id }).ok();
+            }
+            Err(err) => {
+                log::error!("failed to unpin {:?}: {}", item_id, err);
+            }
+        }
+    }
+}
+
+impl Actor for Downloader {
+    type Message = OfflineMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: OfflineMsg) -> Result<Act<Self>, Self::Error> {
+        match msg {
+            OfflineMsg::Download(items) => {
+                for item_id in items {
+                    self.download(item_id);
+                }
+            }
+            OfflineMsg::Remove(items) => {
+                for item_id in items {
+                    self.remove(item_id);
+                }
+            }
+            OfflineMsg::ResumePinned => {
+                for item_id in self.cache.pinned_items() {
+                    self.download(item_id);
+                }
+            }
+            OfflineMsg::Configure(config) => {
+                self.config = config;
+            }
+        }
+        Ok(Act::Continue)
+    }
+}
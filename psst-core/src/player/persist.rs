@@ -0,0 +1,187 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::{
+    audio::normalize::NormalizationLevel,
+    error::Error,
+    item_id::{ItemId, ItemIdType},
+};
+
+use super::{item::PlaybackItem, queue::QueueBehavior};
+
+/// Snapshot of the player queue and play-head, saved by the frontends so a
+/// later run can continue where the last one stopped.  Stored as JSON, so all
+/// frontends can read each other's snapshots.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SavedPlayback {
+    pub items: Vec<SavedItem>,
+    /// Index of the current item in `items`.
+    pub position: usize,
+    /// Play-head within the current item.
+    pub progress_ms: u64,
+    pub behavior: QueueBehavior,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SavedItem {
+    pub uri: String,
+    pub norm_level: NormalizationLevel,
+    /// Frontend-specific data, for example where the item has been played from.
+    /// Other frontends ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl SavedItem {
+    /// Returns `None` for items that cannot be referred to by a URI, i.e. local
+    /// files.
+    pub fn new(item: PlaybackItem, metadata: Option<serde_json::Value>) -> Option<Self> {
+        let base62 = item.item_id.to_base62();
+        let uri = match item.item_id.id_type {
+            ItemIdType::Track => format!("spotify:track:{}", base62),
+            ItemIdType::Podcast => format!("spotify:episode:{}", base62),
+            ItemIdType::LocalFile | ItemIdType::Unknown => return None,
+        };
+        Some(Self {
+            uri,
+            norm_level: item.norm_level,
+            metadata,
+        })
+    }
+
+    /// Returns `None` unless the URI refers to a track or an episode.
+    pub fn playback_item(&self) -> Option<PlaybackItem> {
+        let item_id = ItemId::from_uri(&self.uri)?;
+        match item_id.id_type {
+            ItemIdType::Track | ItemIdType::Podcast => {}
+            ItemIdType::LocalFile | ItemIdType::Unknown => return None,
+        }
+        Some(PlaybackItem {
+            item_id,
+            norm_level: self.norm_level,
+        })
+    }
+}
+
+impl SavedPlayback {
+    /// Load a snapshot from `path`.  Returns `Ok(None)` if there is none.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        match File::open(path) {
+            Ok(file) => Ok(Some(serde_json::from_reader(BufReader::new(file))?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Atomically replace the snapshot at `path`.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(dir)?;
+        let mut file = NamedTempFile::new_in(dir)?;
+        {
+            let mut writer = BufWriter::new(file.as_file_mut());
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+        }
+        file.persist(path).map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    pub fn progress(&self) -> Duration {
+        Duration::from_millis(self.progress_ms)
+    }
+
+    /// Items to fill the player queue with, and the position of the current
+    /// one among them.  Items that are not tracks or episodes are left out.
+    pub fn playback_items(&self) -> (Vec<PlaybackItem>, usize) {
+        let items: Vec<PlaybackItem> = self
+            .items
+            .iter()
+            .filter_map(SavedItem::playback_item)
+            .collect();
+        let preceding = self.items[..self.position.min(self.items.len())]
+            .iter()
+            .filter(|saved| saved.playback_item().is_some())
+            .count();
+        let position = preceding.min(items.len().saturating_sub(1));
+        (items, position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(uri: &str) -> SavedItem {
+        SavedItem {
+            uri: uri.to_string(),
+            norm_level: NormalizationLevel::Track,
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_a_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("playback").join("saved.json");
+        assert!(SavedPlayback::load(&path).unwrap().is_none());
+
+        let playback = SavedPlayback {
+            items: vec![
+                saved("spotify:track:6rqhFgbbKwnb9MLmUQDhG6"),
+                SavedItem {
+                    metadata: Some(serde_json::json!({ "origin": "library" })),
+                    ..saved("spotify:episode:512ojhOuo1ktJprKbVcKyQ")
+                },
+            ],
+            position: 1,
+            progress_ms: 42_000,
+            behavior: QueueBehavior::LoopAll,
+        };
+        playback.save(&path).unwrap();
+
+        let loaded = SavedPlayback::load(&path).unwrap().unwrap();
+        let uris: Vec<_> = loaded.items.iter().map(|item| item.uri.as_str()).collect();
+        assert_eq!(
+            uris,
+            [
+                "spotify:track:6rqhFgbbKwnb9MLmUQDhG6",
+                "spotify:episode:512ojhOuo1ktJprKbVcKyQ"
+            ]
+        );
+        assert_eq!(loaded.items[1].metadata, playback.items[1].metadata);
+        assert_eq!(loaded.position, 1);
+        assert_eq!(loaded.progress(), Duration::from_secs(42));
+        assert_eq!(loaded.behavior, QueueBehavior::LoopAll);
+    }
+
+    #[test]
+    fn skips_items_that_cannot_be_played() {
+        let playback = SavedPlayback {
+            items: vec![
+                saved("spotify:local:Artist:Album:Title:180"),
+                saved("spotify:track:6rqhFgbbKwnb9MLmUQDhG6"),
+                saved("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+                saved("spotify:episode:512ojhOuo1ktJprKbVcKyQ"),
+                saved("spotify:track:3n3Ppam7vgaVa1iaRUc9Lp"),
+            ],
+            position: 3,
+            ..SavedPlayback::default()
+        };
+        let (items, position) = playback.playback_items();
+        let types: Vec<_> = items.iter().map(|item| item.item_id.id_type).collect();
+        assert_eq!(
+            types,
+            [ItemIdType::Track, ItemIdType::Podcast, ItemIdType::Track]
+        );
+        assert_eq!(position, 1);
+        assert_eq!(items[position].item_id.id_type, ItemIdType::Podcast);
+    }
+}
@@ -1,11 +1,25 @@
-use rand::prelude::SliceRandom;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use super::PlaybackItem;
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use crate::item_id::ItemId;
+
+use super::{
+    shuffle::{self, ItemGroup, ShuffleEntry},
+    PlaybackItem,
+};
+
+/// Number of played items remembered for the smart shuffle.
+const RECENT_ITEMS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum QueueBehavior {
     Sequential,
     Random,
+    /// Shuffle that spreads the items of an artist or album apart and plays
+    /// recently played items later.
+    SmartShuffle,
     LoopTrack,
     LoopAll,
 }
@@ -16,24 +30,53 @@ impl Default for QueueBehavior {
     }
 }
 
+/// Point-in-time view of the queue, in the order the player is going to use.
+#[derive(Debug, Clone, Default)]
+pub struct QueueSnapshot {
+    pub current: Option<PlaybackItem>,
+    /// Items following the current one, including the effect of shuffle.  With
+    /// `LoopTrack`, this is just the current item again.
+    pub upcoming: Vec<PlaybackItem>,
+    /// Items queued by the user, in the order they are going to be played.
+    /// Indices of the queue editing commands refer to these.
+    pub queued: Vec<PlaybackItem>,
+}
+
 pub struct Queue {
     items: Vec<PlaybackItem>,
+    /// Items queued by the user that have not started playing yet.  They are
+    /// moved into `items` one by one, right after the current position.
     user_items: Vec<PlaybackItem>,
     position: usize,
-    user_items_position: usize,
     positions: Vec<usize>,
     behavior: QueueBehavior,
+    /// Artists and albums of the queued items, used by the smart shuffle.
+    groups: HashMap<ItemId, ItemGroup>,
+    /// Recently played items, most recent first.
+    recent: VecDeque<ItemId>,
+    rng: StdRng,
 }
 
 impl Queue {
     pub fn new() -> Self {
+        Self::with_rng(StdRng::from_os_rng())
+    }
+
+    /// Queue with a deterministic shuffle order.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
         Self {
             items: Vec::new(),
             user_items: Vec::new(),
             position: 0,
-            user_items_position: 0,
             positions: Vec::new(),
             behavior: QueueBehavior::default(),
+            groups: HashMap::new(),
+            recent: VecDeque::new(),
+            rng,
         }
     }
 
@@ -47,25 +90,102 @@ impl Queue {
         self.positions.clear();
         self.items = items;
         self.position = position;
+        let queued: HashSet<ItemId> = self
+            .items
+            .iter()
+            .chain(&self.user_items)
+            .map(|item| item.item_id)
+            .collect();
+        self.groups.retain(|id, _| queued.contains(id));
         self.compute_positions();
+        self.remember_current();
+    }
+
+    /// Set the artists and albums of items, for the smart shuffle.  Takes effect
+    /// the next time the queue is filled or shuffled.
+    pub fn set_groups(&mut self, groups: impl IntoIterator<Item = (ItemId, ItemGroup)>) {
+        self.groups.extend(groups);
     }
 
     pub fn add(&mut self, item: PlaybackItem) {
         self.user_items.push(item);
     }
 
+    /// Queue the item in front of all other items queued by the user.
+    pub fn add_next(&mut self, item: PlaybackItem) {
+        self.user_items.insert(0, item);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<PlaybackItem> {
+        if index < self.user_items.len() {
+            Some(self.user_items.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Move a user-queued item from `from` to `to`.  Returns `false` if either
+    /// of the indices is out of bounds.
+    pub fn move_item(&mut self, from: usize, to: usize) -> bool {
+        if from >= self.user_items.len() || to >= self.user_items.len() {
+            return false;
+        }
+        let item = self.user_items.remove(from);
+        self.user_items.insert(to, item);
+        true
+    }
+
+    pub fn clear_user_items(&mut self) {
+        self.user_items.clear();
+    }
+
+    /// Follows the rules of `get_following`, so `upcoming` lists the items in
+    /// the order they are going to be played without skipping.
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let current = self.get_current().copied();
+        let upcoming = match self.behavior {
+            // The items queued by the user wait until the current one is skipped.
+            QueueBehavior::LoopTrack => current.into_iter().collect(),
+            _ => {
+                let ahead = self.positions.get(self.position + 1..).unwrap_or_default();
+                let behind = match self.behavior {
+                    QueueBehavior::LoopAll => {
+                        &self.positions[..self.position.min(self.positions.len())]
+                    }
+                    _ => &[],
+                };
+                self.user_items
+                    .iter()
+                    .copied()
+                    .chain(
+                        ahead
+                            .iter()
+                            .chain(behind)
+                            .map(|&position| self.items[position]),
+                    )
+                    .collect()
+            }
+        };
+        QueueSnapshot {
+            current,
+            upcoming,
+            queued: self.user_items.clone(),
+        }
+    }
+
     fn handle_added_queue(&mut self) {
-        if self.user_items.len() > self.user_items_position {
-            self.items.insert(
-                self.positions.len(),
-                self.user_items[self.user_items_position],
-            );
+        if !self.user_items.is_empty() {
+            let item = self.user_items.remove(0);
+            self.items.insert(self.positions.len(), item);
             self.positions
                 .insert(self.position + 1, self.positions.len());
-            self.user_items_position += 1;
         }
     }
 
+    pub fn behavior(&self) -> &QueueBehavior {
+        &self.behavior
+    }
+
     pub fn set_behaviour(&mut self, behavior: QueueBehavior) {
         self.behavior = behavior;
         self.compute_positions();
@@ -82,17 +202,56 @@ impl Queue {
         // Start with an ordered 1:1 mapping.
         self.positions = (0..self.items.len()).collect();
 
-        if let QueueBehavior::Random = self.behavior {
-            // Swap the current position with the first item, so we will start from the
-            // beginning, with the full queue ahead of us.  Then shuffle the rest of the
-            // items and set the position to 0.
-            if self.positions.len() > 1 {
-                self.positions.swap(0, self.position);
-                self.positions[1..].shuffle(&mut rand::rng());
+        match self.behavior {
+            QueueBehavior::Random => {
+                // Swap the current position with the first item, so we will start from the
+                // beginning, with the full queue ahead of us.  Then shuffle the rest of the
+                // items and set the position to 0.
+                if self.positions.len() > 1 {
+                    self.positions.swap(0, playlist_position);
+                    self.positions[1..].shuffle(&mut self.rng);
+                }
+                self.position = 0;
+            }
+            QueueBehavior::SmartShuffle => {
+                if self.positions.len() > 1 {
+                    self.positions.swap(0, playlist_position);
+                    let entries = self.positions[1..]
+                        .iter()
+                        .map(|&index| self.shuffle_entry(index))
+                        .collect();
+                    let shuffled = shuffle::smart_shuffle(entries, &mut self.rng);
+                    self.positions[1..].copy_from_slice(&shuffled);
+                }
+                self.position = 0;
+            }
+            QueueBehavior::Sequential | QueueBehavior::LoopTrack | QueueBehavior::LoopAll => {
+                self.position = playlist_position;
+            }
+        }
+    }
+
+    fn shuffle_entry(&self, index: usize) -> ShuffleEntry {
+        let item_id = self.items[index].item_id;
+        let recency = match self.recent.iter().position(|id| *id == item_id) {
+            Some(age) => 1.0 - age as f64 / RECENT_ITEMS as f64,
+            None => 0.0,
+        };
+        ShuffleEntry {
+            index,
+            group: self.groups.get(&item_id).copied().unwrap_or_default(),
+            recency,
+        }
+    }
+
+    fn remember_current(&mut self) {
+        if let Some(item) = self.get_current() {
+            let item_id = item.item_id;
+            if self.recent.front() != Some(&item_id) {
+                self.recent.retain(|id| *id != item_id);
+                self.recent.push_front(item_id);
+                self.recent.truncate(RECENT_ITEMS);
             }
-            self.position = 0;
-        } else {
-            self.position = playlist_position;
         }
     }
 
@@ -103,11 +262,16 @@ impl Queue {
     pub fn skip_to_next(&mut self) {
         self.handle_added_queue();
         self.position = self.next_position();
+        self.remember_current();
     }
 
     pub fn skip_to_following(&mut self) {
-        self.handle_added_queue();
+        // Like `get_following`, keep the user items for later when looping.
+        if !matches!(self.behavior, QueueBehavior::LoopTrack) {
+            self.handle_added_queue();
+        }
         self.position = self.following_position();
+        self.remember_current();
     }
 
     pub fn get_current(&self) -> Option<&PlaybackItem> {
@@ -116,20 +280,24 @@ impl Queue {
     }
 
     pub fn get_following(&self) -> Option<&PlaybackItem> {
-        if let Some(position) = self.positions.get(self.position).copied() {
-            if let Some(item) = self.items.get(position) {
+        if !matches!(self.behavior, QueueBehavior::LoopTrack) {
+            // Items added by the user get inserted right after the current one.
+            if let Some(item) = self.user_items.first() {
                 return Some(item);
             }
-        } else {
-            return self.user_items.first();
         }
-        None
+        if self.items.is_empty() {
+            return None;
+        }
+        let position = self.positions.get(self.following_position()).copied()?;
+        self.items.get(position)
     }
 
     fn previous_position(&self) -> usize {
         match self.behavior {
             QueueBehavior::Sequential
             | QueueBehavior::Random
+            | QueueBehavior::SmartShuffle
             | QueueBehavior::LoopTrack
             | QueueBehavior::LoopAll => self.position.saturating_sub(1),
         }
@@ -137,18 +305,124 @@ impl Queue {
 
     fn next_position(&self) -> usize {
         match self.behavior {
-            QueueBehavior::Sequential | QueueBehavior::Random | QueueBehavior::LoopTrack => {
-                self.position + 1
-            }
+            QueueBehavior::Sequential
+            | QueueBehavior::Random
+            | QueueBehavior::SmartShuffle
+            | QueueBehavior::LoopTrack => self.position + 1,
             QueueBehavior::LoopAll => (self.position + 1) % self.items.len(),
         }
     }
 
     fn following_position(&self) -> usize {
         match self.behavior {
-            QueueBehavior::Sequential | QueueBehavior::Random => self.position + 1,
+            QueueBehavior::Sequential | QueueBehavior::Random | QueueBehavior::SmartShuffle => {
+                self.position + 1
+            }
             QueueBehavior::LoopTrack => self.position,
             QueueBehavior::LoopAll => (self.position + 1) % self.items.len(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::normalize::NormalizationLevel, item_id::ItemIdType};
+
+    fn item(n: u128) -> PlaybackItem {
+        PlaybackItem {
+            item_id: ItemId::new(n, ItemIdType::Track),
+            norm_level: NormalizationLevel::Track,
+        }
+    }
+
+    fn ids(items: &[PlaybackItem]) -> Vec<u128> {
+        items.iter().map(|item| item.item_id.id).collect()
+    }
+
+    /// Items 10, 11 and 12 playing at 11, with 20 queued by the user.
+    fn filled_queue() -> Queue {
+        let mut queue = Queue::new();
+        queue.fill(vec![item(10), item(11), item(12)], 1);
+        queue.add(item(20));
+        queue
+    }
+
+    #[test]
+    fn edits_user_items() {
+        let mut queue = Queue::new();
+        queue.add(item(1));
+        queue.add(item(2));
+        queue.add_next(item(3));
+        assert_eq!(ids(&queue.snapshot().queued), vec![3, 1, 2]);
+
+        assert!(queue.move_item(0, 2));
+        assert_eq!(ids(&queue.snapshot().queued), vec![1, 2, 3]);
+        assert!(!queue.move_item(0, 3));
+        assert!(!queue.move_item(3, 0));
+        assert_eq!(ids(&queue.snapshot().queued), vec![1, 2, 3]);
+
+        assert_eq!(queue.remove(1), Some(item(2)));
+        assert_eq!(queue.remove(2), None);
+        assert_eq!(ids(&queue.snapshot().queued), vec![1, 3]);
+    }
+
+    #[test]
+    fn snapshot_follows_queue_behavior() {
+        let upcoming = |behavior| {
+            let mut queue = filled_queue();
+            queue.set_behaviour(behavior);
+            let snapshot = queue.snapshot();
+            assert_eq!(snapshot.current, Some(item(11)));
+            assert_eq!(ids(&snapshot.queued), vec![20]);
+            assert_eq!(snapshot.upcoming.first(), queue.get_following());
+            ids(&snapshot.upcoming)
+        };
+        assert_eq!(upcoming(QueueBehavior::Sequential), vec![20, 12]);
+        assert_eq!(upcoming(QueueBehavior::LoopAll), vec![20, 12, 10]);
+        assert_eq!(upcoming(QueueBehavior::LoopTrack), vec![11]);
+        let mut shuffled = upcoming(QueueBehavior::Random);
+        assert_eq!(shuffled[0], 20);
+        shuffled.sort_unstable();
+        assert_eq!(shuffled, vec![10, 12, 20]);
+    }
+
+    #[test]
+    fn smart_shuffle_is_deterministic_with_a_seed() {
+        let shuffled = |seed| {
+            let mut queue = Queue::with_seed(seed);
+            queue.set_groups((0..20).map(|n| {
+                let artist = ItemId::new(n % 2, ItemIdType::Unknown);
+                let group = ItemGroup {
+                    artist: Some(artist),
+                    album: Some(artist),
+                };
+                (item(n).item_id, group)
+            }));
+            queue.fill((0..20).map(item).collect(), 0);
+            queue.add(item(100));
+            queue.set_behaviour(QueueBehavior::SmartShuffle);
+            let snapshot = queue.snapshot();
+            assert_eq!(snapshot.current, Some(item(0)));
+            assert_eq!(snapshot.upcoming.first(), queue.get_following());
+            ids(&snapshot.upcoming)
+        };
+        let order = shuffled(7);
+        assert_eq!(order, shuffled(7));
+        assert_eq!(order[0], 100);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (1..20).chain([100]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_user_items_while_looping_a_track() {
+        let mut queue = filled_queue();
+        queue.set_behaviour(QueueBehavior::LoopTrack);
+        queue.skip_to_following();
+        assert_eq!(queue.get_current(), Some(&item(11)));
+        assert_eq!(ids(&queue.snapshot().queued), vec![20]);
+        queue.skip_to_next();
+        assert_eq!(queue.get_current(), Some(&item(20)));
+    }
+}
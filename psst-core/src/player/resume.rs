@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::item_id::ItemIdType;
+
+use super::file::MediaPath;
+
+/// Items played this close to their end count as finished.
+const FINISHED_MARGIN: Duration = Duration::from_secs(30);
+/// Items stopped before this point start from the beginning again.
+const MIN_RESUME_POSITION: Duration = Duration::from_secs(10);
+
+/// Episodes are always resumable, tracks only if they are at least
+/// `tracks_longer_than` long.
+pub fn is_resumable(path: &MediaPath, tracks_longer_than: Option<Duration>) -> bool {
+    match path.item_id.id_type {
+        ItemIdType::Podcast => true,
+        ItemIdType::Track => tracks_longer_than.is_some_and(|min| path.duration >= min),
+        // IDs of local files are not stable across runs.
+        ItemIdType::LocalFile | ItemIdType::Unknown => false,
+    }
+}
+
+/// Play-head of a partially played episode, or a long track, so that playing
+/// it again continues where it stopped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    pub position_ms: u64,
+    /// Played until close to the end.
+    pub finished: bool,
+}
+
+impl ResumePoint {
+    pub fn new(position: Duration, duration: Duration) -> Self {
+        Self {
+            position_ms: position.as_millis() as u64,
+            finished: position + FINISHED_MARGIN.min(duration / 10) >= duration,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_millis(self.position_ms)
+    }
+
+    /// Where the playback of the item should start, `None` for the beginning.
+    pub fn start_at(&self) -> Option<Duration> {
+        let position = self.position();
+        (!self.finished && position >= MIN_RESUME_POSITION).then_some(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        item_id::{FileId, ItemId},
+        player::file::AudioFormat,
+    };
+
+    fn path(id_type: ItemIdType, minutes: u64) -> MediaPath {
+        MediaPath {
+            item_id: ItemId::new(1, id_type),
+            file_id: FileId::default(),
+            file_format: AudioFormat::OggVorbis,
+            duration: Duration::from_secs(minutes * 60),
+            album_id: None,
+        }
+    }
+
+    #[test]
+    fn resumes_episodes_and_long_tracks() {
+        let min = Some(Duration::from_secs(20 * 60));
+        assert!(is_resumable(&path(ItemIdType::Podcast, 5), None));
+        assert!(!is_resumable(&path(ItemIdType::Track, 30), None));
+        assert!(!is_resumable(&path(ItemIdType::Track, 19), min));
+        assert!(is_resumable(&path(ItemIdType::Track, 20), min));
+        assert!(!is_resumable(&path(ItemIdType::LocalFile, 30), min));
+    }
+
+    #[test]
+    fn starts_from_the_resume_point() {
+        let duration = Duration::from_secs(3600);
+        let point = ResumePoint::new(Duration::from_secs(600), duration);
+        assert!(!point.finished);
+        assert_eq!(point.start_at(), Some(Duration::from_secs(600)));
+
+        // Too close to the start or the end, play from the beginning.
+        let point = ResumePoint::new(Duration::from_secs(5), duration);
+        assert_eq!(point.start_at(), None);
+        let point = ResumePoint::new(Duration::from_secs(3580), duration);
+        assert!(point.finished);
+        assert_eq!(point.start_at(), None);
+    }
+}
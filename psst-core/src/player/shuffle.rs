@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, Rng};
+
+use crate::item_id::ItemId;
+
+/// How much a just played item is pushed towards the end of the shuffled
+/// order, as a fraction of the whole queue.
+const RECENCY_WEIGHT: f64 = 0.5;
+
+/// Random displacement of an item from its evenly spread position, as a
+/// fraction of the distance between the items of one group.
+const JITTER: f64 = 0.1;
+
+/// Artist and album of a queued item, compared by the smart shuffle.  Items
+/// without an artist are considered unrelated to any other item.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ItemGroup {
+    pub artist: Option<ItemId>,
+    pub album: Option<ItemId>,
+}
+
+/// Item to be ordered by `smart_shuffle`.
+pub struct ShuffleEntry {
+    pub index: usize,
+    pub group: ItemGroup,
+    /// 1.0 for an item that has just been played, falling to 0.0 for items not
+    /// played recently.
+    pub recency: f64,
+}
+
+/// Balanced shuffle.  Items of every artist are spread evenly over the whole
+/// order, starting at a random offset, and items of every album are spread
+/// the same way over the share of their artist.  Recently played items are
+/// moved towards the end.  Returns the `index` fields in the shuffled order.
+pub fn smart_shuffle(entries: Vec<ShuffleEntry>, rng: &mut impl Rng) -> Vec<usize> {
+    // Group by artist, and then by album, keeping the order of first appearance
+    // so the result only depends on the RNG.
+    let mut artists: Vec<Vec<Vec<ShuffleEntry>>> = Vec::new();
+    let mut artist_slots = HashMap::new();
+    let mut album_slots = HashMap::new();
+    for entry in entries {
+        let artist = match entry.group.artist {
+            Some(artist) => *artist_slots.entry(artist).or_insert_with(|| {
+                artists.push(Vec::new());
+                artists.len() - 1
+            }),
+            None => {
+                artists.push(Vec::new());
+                artists.len() - 1
+            }
+        };
+        let albums = &mut artists[artist];
+        let album = match entry.group.album {
+            Some(album) => *album_slots.entry((artist, album)).or_insert_with(|| {
+                albums.push(Vec::new());
+                albums.len() - 1
+            }),
+            None => {
+                albums.push(Vec::new());
+                albums.len() - 1
+            }
+        };
+        albums[album].push(entry);
+    }
+
+    let artists = artists
+        .into_iter()
+        .map(|mut albums| {
+            for album in &mut albums {
+                album.shuffle(rng);
+            }
+            let mut spread = spread_evenly(albums, rng);
+            sort_by_key(&mut spread);
+            spread.into_iter().map(|(_, entry)| entry).collect()
+        })
+        .collect();
+    let mut spread: Vec<(f64, ShuffleEntry)> = spread_evenly(artists, rng)
+        .into_iter()
+        .map(|(position, entry)| (position + RECENCY_WEIGHT * entry.recency, entry))
+        .collect();
+    sort_by_key(&mut spread);
+    spread.into_iter().map(|(_, entry)| entry.index).collect()
+}
+
+/// Assign every item a position in the 0.0..1.0 range, so items of one group
+/// are evenly spaced, with a random offset and a bit of jitter.
+fn spread_evenly<T>(groups: Vec<Vec<T>>, rng: &mut impl Rng) -> Vec<(f64, T)> {
+    let mut spread = Vec::new();
+    for group in groups {
+        let step = 1.0 / group.len() as f64;
+        let offset = rng.random::<f64>() * step;
+        for (k, item) in group.into_iter().enumerate() {
+            let jitter = rng.random_range(-JITTER..JITTER) * step;
+            spread.push((offset + k as f64 * step + jitter, item));
+        }
+    }
+    spread
+}
+
+fn sort_by_key<T>(spread: &mut [(f64, T)]) {
+    spread.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::item_id::ItemIdType;
+
+    fn entry(index: usize, artist: u128, album: u128) -> ShuffleEntry {
+        ShuffleEntry {
+            index,
+            group: ItemGroup {
+                artist: Some(ItemId::new(artist, ItemIdType::Unknown)),
+                album: Some(ItemId::new(album, ItemIdType::Unknown)),
+            },
+            recency: 0.0,
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_order() {
+        let entries = || {
+            (0..50)
+                .map(|i| entry(i, i as u128 % 5, i as u128 % 7))
+                .collect()
+        };
+        let a = smart_shuffle(entries(), &mut StdRng::seed_from_u64(7));
+        let b = smart_shuffle(entries(), &mut StdRng::seed_from_u64(7));
+        assert_eq!(a, b);
+        let mut sorted = a.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn spreads_items_of_an_artist() {
+        // Four tracks by one artist among twelve by another.
+        let entries = || {
+            (0..16)
+                .map(|i| {
+                    if i < 4 {
+                        entry(i, 1, 1)
+                    } else {
+                        entry(i, 2, 2)
+                    }
+                })
+                .collect()
+        };
+        for seed in 0..20 {
+            let order = smart_shuffle(entries(), &mut StdRng::seed_from_u64(seed));
+            let positions: Vec<usize> = order
+                .iter()
+                .enumerate()
+                .filter(|(_, &index)| index < 4)
+                .map(|(position, _)| position)
+                .collect();
+            for pair in positions.windows(2) {
+                assert!(pair[1] - pair[0] >= 3, "seed {}: {:?}", seed, order);
+            }
+        }
+    }
+
+    #[test]
+    fn moves_recently_played_items_back() {
+        for seed in 0..20 {
+            let entries = (0..30)
+                .map(|i| ShuffleEntry {
+                    recency: if i == 0 { 1.0 } else { 0.0 },
+                    ..entry(i, i as u128, i as u128)
+                })
+                .collect();
+            let order = smart_shuffle(entries, &mut StdRng::seed_from_u64(seed));
+            let position = order.iter().position(|&index| index == 0).unwrap();
+            assert!(position >= 10, "seed {}: {}", seed, position);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+
+use crate::{
+    actor::{Act, Actor},
+    audio::output::{AudioSink, DefaultAudioSink},
+    error::Error,
+};
+
+use super::PlayerEvent;
+
+/// How often the volume is adjusted during the fade-out.
+const TICK: Duration = Duration::from_millis(50);
+
+/// When the sleep timer stops the playback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SleepMode {
+    /// After the given time from when the timer was set.
+    After(Duration),
+    /// At the end of the current track or episode.
+    EndOfTrack,
+    /// At the end of the last item in the queue.
+    EndOfQueue,
+}
+
+pub enum SleepMsg {
+    /// Stop at the given instant, or wait for it to be known if `None`.
+    SetDeadline(Option<Instant>),
+    /// Volume to fade out from, and to restore once the timer is gone.
+    SetVolume(f32),
+    Tick,
+    /// Restore the volume and shut down.
+    Cancel,
+}
+
+/// Counts down to the deadline given by the player, lowering the volume of
+/// `sink` during the last `fade` of it.  Once the deadline passes, it sends
+/// `PlayerEvent::SleepTimerExpired` and shuts down, leaving the volume down so
+/// the player can stop in silence and restore it afterwards.
+pub struct SleepTimer {
+    sink: DefaultAudioSink,
+    event_send: Sender<PlayerEvent>,
+    mode: SleepMode,
+    fade: Duration,
+    volume: f32,
+    deadline: Option<Instant>,
+    /// Whole seconds remaining in the last `SleepTimer` event.
+    reported: Option<u64>,
+}
+
+impl SleepTimer {
+    pub fn new(
+        sink: DefaultAudioSink,
+        event_send: Sender<PlayerEvent>,
+        mode: SleepMode,
+        fade: Duration,
+        volume: f32,
+    ) -> Self {
+        Self {
+            sink,
+            event_send,
+            mode,
+            fade,
+            volume,
+            deadline: None,
+            reported: None,
+        }
+    }
+
+    fn tick(&mut self) -> Act<Self> {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => {
+                self.sink.set_volume(self.volume);
+                return Act::Continue;
+            }
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            self.sink.set_volume(0.0);
+            let _ = self.event_send.send(PlayerEvent::SleepTimerExpired);
+            return Act::Shutdown;
+        }
+        let gain = if remaining < self.fade {
+            remaining.as_secs_f32() / self.fade.as_secs_f32()
+        } else {
+            1.0
+        };
+        self.sink.set_volume(self.volume * gain);
+
+        let seconds = remaining.as_secs();
+        if self.reported != Some(seconds) {
+            let _ = self.event_send.send(PlayerEvent::SleepTimer {
+                mode: Some(self.mode),
+                remaining: Some(remaining),
+            });
+            self.reported = Some(seconds);
+        }
+        Act::WaitOr {
+            timeout: TICK,
+            timeout_msg: SleepMsg::Tick,
+        }
+    }
+}
+
+impl Actor for SleepTimer {
+    type Message = SleepMsg;
+    type Error = Error;
+
+    fn handle(&mut self, msg: Self::Message) -> Result<Act<Self>, Self::Error> {
+        match msg {
+            SleepMsg::SetDeadline(deadline) => {
+                self.deadline = deadline;
+                Ok(self.tick())
+            }
+            SleepMsg::SetVolume(volume) => {
+                self.volume = volume;
+                Ok(self.tick())
+            }
+            SleepMsg::Tick => Ok(self.tick()),
+            SleepMsg::Cancel => {
+                self.sink.set_volume(self.volume);
+                Ok(Act::Shutdown)
+            }
+        }
+    }
+}
@@ -1,13 +1,15 @@
 use std::{
+    collections::VecDeque,
+    mem,
     ops::Range,
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{unbounded, Sender};
 use rb::{Consumer, Producer, RbConsumer, RbProducer, SpscRb, RB};
 use symphonia::core::{
     audio::{SampleBuffer, SignalSpec},
@@ -17,10 +19,16 @@ use symphonia::core::{
 use crate::{
     actor::{Act, Actor, ActorHandle},
     audio::{
+        analyzer::{Analyzer, AnalyzerSource, AnalyzerTap},
         decode::AudioDecoder,
+        equalizer::{EqualizerSettings, EqualizerSource},
+        limiter::{LimiterSource, PeakLimiting},
+        mix::{ChannelMixSettings, ChannelMixSource},
         output::{AudioSink, DefaultAudioSink},
         resample::ResamplingQuality,
-        source::{AudioSource, ResampledSource, StereoMappedSource},
+        silence::{SilenceTrimmer, SilenceTrimming},
+        source::{AudioSource, CrossfadeCurve, CrossfadeSource, ResampledSource},
+        stretch::TimeStretchSource,
     },
     error::Error,
 };
@@ -34,47 +42,240 @@ pub struct PlaybackManager {
     sink: DefaultAudioSink,
     event_send: Sender<PlayerEvent>,
     current: Option<(MediaPath, Sender<Msg>)>,
+    current_spec: Option<SignalSpec>,
+    queued: Option<(MediaPath, Sender<Msg>)>,
+    queued_source: Arc<Mutex<Option<QueuedSource>>>,
+    equalizer: EqualizerSettings,
+    equalizer_send: Option<Sender<EqualizerSettings>>,
+    speed: f32,
+    speed_send: Option<Sender<f32>>,
+    channel_mix: ChannelMixSettings,
+    channel_mix_send: Option<Sender<ChannelMixSettings>>,
+    resampling_quality: ResamplingQuality,
+    limiting: Option<PeakLimiting>,
+    limiting_send: Option<Sender<Option<PeakLimiting>>>,
+    analyzer_tap: AnalyzerTap,
+    /// Reading side of `analyzer_tap`, until someone takes it.
+    analyzer: Option<Analyzer>,
+    /// Shared with all the decoder sources, so a change applies to the playing
+    /// one as well as to the queued one.
+    trimming: Arc<Mutex<Option<SilenceTrimming>>>,
 }
 
 impl PlaybackManager {
-    pub fn new(sink: DefaultAudioSink, event_send: Sender<PlayerEvent>) -> Self {
+    pub fn new(
+        sink: DefaultAudioSink,
+        event_send: Sender<PlayerEvent>,
+        equalizer: EqualizerSettings,
+        channel_mix: ChannelMixSettings,
+        resampling_quality: ResamplingQuality,
+        limiting: Option<PeakLimiting>,
+        trimming: Option<SilenceTrimming>,
+    ) -> Self {
+        let (analyzer, analyzer_tap) = Analyzer::new();
         Self {
             sink,
             event_send,
             current: None,
+            current_spec: None,
+            queued: None,
+            queued_source: Arc::new(Mutex::new(None)),
+            equalizer,
+            equalizer_send: None,
+            speed: 1.0,
+            speed_send: None,
+            channel_mix,
+            channel_mix_send: None,
+            resampling_quality,
+            limiting,
+            limiting_send: None,
+            analyzer_tap,
+            analyzer: Some(analyzer),
+            trimming: Arc::new(Mutex::new(trimming)),
+        }
+    }
+
+    /// Change the equalizer settings, applied to the playing source right away
+    /// and kept for all the following ones.
+    pub fn set_equalizer(&mut self, settings: EqualizerSettings) {
+        if let Some(equalizer_send) = &self.equalizer_send {
+            let _ = equalizer_send.send(settings.clone());
+        }
+        self.equalizer = settings;
+    }
+
+    /// Change the playback speed, applied the same way as the equalizer.
+    pub fn set_speed(&mut self, speed: f32) {
+        if let Some(speed_send) = &self.speed_send {
+            let _ = speed_send.send(speed);
+        }
+        self.speed = speed;
+    }
+
+    /// Change the channel mixing, applied the same way as the equalizer.
+    pub fn set_channel_mix(&mut self, settings: ChannelMixSettings) {
+        if let Some(channel_mix_send) = &self.channel_mix_send {
+            let _ = channel_mix_send.send(settings);
+        }
+        self.channel_mix = settings;
+    }
+
+    /// Change the quality of the resampling, used from the next source that
+    /// starts playing.
+    pub fn set_resampling_quality(&mut self, quality: ResamplingQuality) {
+        self.resampling_quality = quality;
+    }
+
+    /// Limit the peaks of the output with `limiting`, or stop limiting them if
+    /// `None`, applied the same way as the equalizer.
+    pub fn set_peak_limiting(&mut self, limiting: Option<PeakLimiting>) {
+        if let Some(limiting_send) = &self.limiting_send {
+            let _ = limiting_send.send(limiting);
         }
+        self.limiting = limiting;
+    }
+
+    /// Shorten long pauses with `trimming`, or stop shortening them if `None`.
+    pub fn set_silence_trimming(&mut self, trimming: Option<SilenceTrimming>) {
+        *self.trimming.lock().unwrap() = trimming;
+    }
+
+    /// Take the analyzer of the played signal.  There is only one, so this
+    /// returns `None` after the first call.
+    pub fn take_analyzer(&mut self) -> Option<Analyzer> {
+        self.analyzer.take()
     }
 
     pub fn play(&mut self, loaded: LoadedPlaybackItem) {
-        let path = loaded.file.path();
-        let source = DecoderSource::new(
+        let source = self.decoder_source(loaded);
+        self.queued = None;
+        self.start(source);
+    }
+
+    /// Returns true if `loaded` can be played back right after the current
+    /// source without re-opening the output, meaning it can be fed through the
+    /// same resampling and channel mapping.
+    pub fn can_queue(&self, loaded: &LoadedPlaybackItem) -> bool {
+        self.current_spec == Some(loaded.source.signal_spec())
+    }
+
+    /// Hand over `loaded` to be played right after the last frame of the current
+    /// source, or overlapping with its tail if `crossfade` is given.  Decoding
+    /// starts immediately, so the next track is ready once the current one ends.
+    pub fn queue(&mut self, loaded: LoadedPlaybackItem, crossfade: Option<Crossfade>) {
+        let source = self.decoder_source(loaded);
+        self.queued = Some((source.file.path(), source.actor.sender()));
+        self.queued_source
+            .lock()
+            .unwrap()
+            .replace(QueuedSource { source, crossfade });
+    }
+
+    /// Forget about the queued source, if it has not started playing yet.
+    pub fn clear_queued(&mut self) {
+        self.queued_source.lock().unwrap().take();
+        self.queued = None;
+    }
+
+    /// Queued source has been chained after the current one, and the current one
+    /// has ended.  Make the queued source current, so seeking is routed to it.
+    pub fn advance(&mut self) -> Option<MediaPath> {
+        let (path, worker) = self.queued.take()?;
+        self.current = Some((path, worker));
+        Some(path)
+    }
+
+    /// Start playing the queued source right away, without waiting for the
+    /// current one to end.
+    pub fn play_queued(&mut self) -> Option<MediaPath> {
+        let queued = self.queued_source.lock().unwrap().take();
+        if let Some(queued) = queued {
+            self.start(queued.source);
+        }
+        // If the source was not in the slot anymore, the current one has already
+        // ended and the queued one has been chained after it.
+        self.advance()
+    }
+
+    fn decoder_source(&self, loaded: LoadedPlaybackItem) -> DecoderSource {
+        DecoderSource::new(
             loaded.file,
             loaded.source,
             loaded.norm_factor,
             self.event_send.clone(),
-        );
-        self.current = Some((path, source.actor.sender()));
-        if source.sample_rate() == self.sink.sample_rate()
-            && source.channel_count() == self.sink.channel_count()
-        {
+            Arc::clone(&self.trimming),
+        )
+    }
+
+    fn start(&mut self, source: DecoderSource) {
+        self.current = Some((source.file.path(), source.actor.sender()));
+        self.current_spec = Some(source.signal_spec);
+
+        // Any previously queued source belongs to the playback we are replacing.
+        self.queued_source = Arc::new(Mutex::new(None));
+        let source = ChainedSource {
+            current: Chained::Single(source),
+            next: Arc::clone(&self.queued_source),
+        };
+        let (equalizer_send, equalizer_recv) = unbounded();
+        let source = EqualizerSource::new(source, &self.equalizer, equalizer_recv);
+        self.equalizer_send = Some(equalizer_send);
+        // Stretching comes after the decoder sources, so the positions they
+        // report stay in the time of the track.
+        let (speed_send, speed_recv) = unbounded();
+        let source = TimeStretchSource::new(source, self.speed, speed_recv);
+        self.speed_send = Some(speed_send);
+
+        self.sink.prefer_sample_rate(source.sample_rate());
+
+        // The channels are always mixed, even into the same layout, so the
+        // balance and the mono downmix can change during the playback.
+        let (channel_mix_send, channel_mix_recv) = unbounded();
+        self.channel_mix_send = Some(channel_mix_send);
+        let channel_count = self.sink.channel_count();
+        if source.sample_rate() == self.sink.sample_rate() {
             // We can start playing the source right away.
-            self.sink.play(source);
+            let source =
+                ChannelMixSource::new(source, channel_count, &self.channel_mix, channel_mix_recv);
+            self.play_limited(source);
         } else {
             // Some output streams have different sample rate than the source, so we need to
             // resample before pushing to the sink.
-            let source = ResampledSource::new(
-                source,
-                self.sink.sample_rate(),
-                ResamplingQuality::SincMediumQuality,
-            );
-            // Source output streams also have a different channel count. Map the stereo
-            // channels and silence the others.
-            let source = StereoMappedSource::new(source, self.sink.channel_count());
-            self.sink.play(source);
+            let source =
+                ResampledSource::new(source, self.sink.sample_rate(), self.resampling_quality);
+            let source =
+                ChannelMixSource::new(source, channel_count, &self.channel_mix, channel_mix_recv);
+            self.play_limited(source);
         }
         self.sink.resume();
     }
 
+    /// Play `source` through the peak limiter, as the last stage before the
+    /// output.  The volume of the output only ever lowers the level, so the
+    /// ceiling holds for what reaches the device.  The analyzer taps the
+    /// limited signal, so it sees what is played.
+    fn play_limited(&mut self, source: impl AudioSource) {
+        let (limiting_send, limiting_recv) = unbounded();
+        self.limiting_send = Some(limiting_send);
+        let event_send = self.event_send.clone();
+        let report = move |reduction_db: f32| {
+            // We cannot block here, a missed report is followed by the next one.
+            let _ = event_send.try_send(PlayerEvent::GainReduction { reduction_db });
+        };
+        let source = LimiterSource::new(source, self.limiting, limiting_recv, report);
+        let source = AnalyzerSource::new(source, &self.analyzer_tap);
+        self.sink.play(source);
+    }
+
+    /// Loop the playback of the current source within `section`, or stop
+    /// looping if `None`.  The source continues from the start of the section
+    /// right after its last frame, without any gap.
+    pub fn set_loop(&self, section: Option<Range<Duration>>) {
+        if let Some((_, worker)) = &self.current {
+            let _ = worker.send(Msg::SetLoop(section));
+        }
+    }
+
     pub fn seek(&self, position: Duration) {
         if let Some((path, worker)) = &self.current {
             let _ = worker.send(Msg::Seek(position));
@@ -90,6 +291,147 @@ impl PlaybackManager {
     }
 }
 
+#[derive(Clone, Copy)]
+pub struct Crossfade {
+    pub duration: Duration,
+    pub curve: CrossfadeCurve,
+}
+
+struct QueuedSource<S = DecoderSource> {
+    source: S,
+    crossfade: Option<Crossfade>,
+}
+
+enum Chained<S> {
+    Single(S),
+    Crossfading(CrossfadeSource<S, S>),
+    /// Only present while switching between the other states.
+    Switching,
+}
+
+/// Plays `current` and, once it has reported the end of track, continues with
+/// the source waiting in `next` in the same `write` call, so there is no gap
+/// between the two.  If the queued source asks for a crossfade, it is started
+/// before the end instead, and the end of track is reported once the fade
+/// starts.  Sources in `next` always share the signal spec of `current`.
+struct ChainedSource<S> {
+    current: Chained<S>,
+    next: Arc<Mutex<Option<QueuedSource<S>>>>,
+}
+
+impl<S> ChainedSource<S>
+where
+    S: TrackSource,
+{
+    /// We cannot block in the audio thread.  If the lock is taken, the next
+    /// source is just being replaced, and we try again on the next write.
+    fn take_next(&self) -> Option<QueuedSource<S>> {
+        self.next.try_lock().ok()?.take()
+    }
+
+    fn start_crossfade_if_due(&mut self) {
+        let Chained::Single(current) = &mut self.current else {
+            return;
+        };
+        if current.is_end_of_track() {
+            return;
+        }
+        let next = Arc::clone(&self.next);
+        let Ok(mut slot) = next.try_lock() else {
+            return;
+        };
+        let Some(crossfade) = slot.as_ref().and_then(|queued| queued.crossfade) else {
+            return;
+        };
+        let remaining = current.remaining_samples();
+        if remaining > current.duration_to_samples(crossfade.duration) {
+            return;
+        }
+        if !current.detach() {
+            // Event channel is full, try again the next time.
+            return;
+        }
+        // We might be starting late, when the next track has been loaded close to
+        // the end of this one, so never fade for longer than what is left.
+        let duration = crossfade
+            .duration
+            .min(current.samples_to_duration(remaining));
+        let incoming = slot.take().unwrap().source;
+        if let Chained::Single(outgoing) = mem::replace(&mut self.current, Chained::Switching) {
+            self.current = Chained::Crossfading(CrossfadeSource::new(
+                outgoing,
+                incoming,
+                duration,
+                crossfade.curve,
+            ));
+        }
+    }
+}
+
+impl<S> AudioSource for ChainedSource<S>
+where
+    S: TrackSource,
+{
+    fn write(&mut self, output: &mut [f32]) -> usize {
+        self.start_crossfade_if_due();
+
+        let mut written = match &mut self.current {
+            Chained::Single(current) => current.write(output),
+            Chained::Crossfading(crossfade) => crossfade.write(output),
+            Chained::Switching => 0,
+        };
+        if matches!(&self.current, Chained::Crossfading(crossfade) if crossfade.is_finished()) {
+            if let Chained::Crossfading(crossfade) =
+                mem::replace(&mut self.current, Chained::Switching)
+            {
+                self.current = Chained::Single(crossfade.into_incoming());
+            }
+        } else if written < output.len()
+            && matches!(&self.current, Chained::Single(current) if current.is_end_of_track())
+        {
+            if let Some(next) = self.take_next() {
+                self.current = Chained::Single(next.source);
+                written += self.write(&mut output[written..]);
+            }
+        }
+        written
+    }
+
+    fn channel_count(&self) -> usize {
+        match &self.current {
+            Chained::Single(current) => current.channel_count(),
+            Chained::Crossfading(crossfade) => crossfade.channel_count(),
+            Chained::Switching => 0,
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match &self.current {
+            Chained::Single(current) => current.sample_rate(),
+            Chained::Crossfading(crossfade) => crossfade.sample_rate(),
+            Chained::Switching => 0,
+        }
+    }
+}
+
+/// Source of a single track, as chained by `ChainedSource`.
+trait TrackSource: AudioSource {
+    /// The last sample of the track has been written.
+    fn is_end_of_track(&self) -> bool;
+
+    /// Number of samples left until the end of the track.
+    fn remaining_samples(&self) -> u64;
+
+    fn samples_to_duration(&self, samples: u64) -> Duration;
+
+    fn duration_to_samples(&self, duration: Duration) -> u64;
+
+    /// Report the end of this track right away and stop sending any further
+    /// events, while still producing the rest of the samples.  Returns false if
+    /// the report could not be sent, in which case the caller should try again.
+    fn detach(&mut self) -> bool;
+}
+
 pub struct DecoderSource {
     file: MediaFile,
     actor: ActorHandle<Msg>,
@@ -97,9 +439,19 @@ pub struct DecoderSource {
     event_send: Sender<PlayerEvent>,
     total_samples: Arc<AtomicU64>,
     position: Arc<AtomicU64>,
+    /// Jumps of the position made by the worker when looping, as pairs of the
+    /// position of the last looped sample and the position the next one has.
+    jumps: Arc<Mutex<VecDeque<(u64, u64)>>>,
     precision: u64,
     reported: u64,
+    trimming: Arc<Mutex<Option<SilenceTrimming>>>,
+    trimmer: SilenceTrimmer,
+    /// Trimmed time included in the last `SilenceTrimmed` report.
+    reported_trimmed: Duration,
     end_of_track: bool,
+    /// Set when the upper layer has moved on to the next track while this one is
+    /// still fading out.  No more events are reported from a detached source.
+    detached: bool,
     norm_factor: f32,
     signal_spec: SignalSpec,
     time_base: TimeBase,
@@ -111,6 +463,7 @@ impl DecoderSource {
         decoder: AudioDecoder,
         norm_factor: f32,
         event_send: Sender<PlayerEvent>,
+        trimming: Arc<Mutex<Option<SilenceTrimming>>>,
     ) -> Self {
         const REPORT_PRECISION: Duration = Duration::from_millis(900);
 
@@ -137,15 +490,31 @@ impl DecoderSource {
         // the underlying decoder returns EOF.
         let total_samples = Arc::new(AtomicU64::new(u64::MAX));
 
+        // When looping a section, the worker goes back to its start right after
+        // writing its end, while the samples in between are still waiting in the
+        // ring-buffer.  It leaves us a note, so we can move the position once we
+        // actually read past the end.
+        let jumps = Arc::new(Mutex::new(VecDeque::new()));
+
         // Spawn the worker and kick-start the decoding.  The buffer will start filling
         // now.
         let actor = Worker::spawn_with_default_cap("audio_decoding", {
             let position = Arc::clone(&position);
             let total_samples = Arc::clone(&total_samples);
-            move |this| Worker::new(this, decoder, buffer, position, total_samples)
+            let jumps = Arc::clone(&jumps);
+            move |this| Worker::new(this, decoder, buffer, position, total_samples, jumps)
         });
         let _ = actor.send(Msg::Read);
 
+        // Trimming happens right after reading from the ring-buffer, so the
+        // position below keeps counting all the decoded samples, including the
+        // dropped ones, and stays in the time of the track.
+        let trimmer = SilenceTrimmer::new(
+            signal_spec.channels.count(),
+            signal_spec.rate,
+            *trimming.lock().unwrap(),
+        );
+
         Self {
             file,
             actor,
@@ -156,25 +525,80 @@ impl DecoderSource {
             time_base,
             total_samples,
             end_of_track: false,
+            detached: false,
             position,
+            jumps,
             precision,
             reported: u64::MAX, // Something sufficiently distinct from any position.
+            trimming,
+            trimmer,
+            reported_trimmed: Duration::ZERO,
         }
     }
 
     fn written_samples(&self, position: u64) -> u64 {
-        self.position.fetch_add(position, Ordering::Relaxed) + position
+        let position = self.position.fetch_add(position, Ordering::Relaxed) + position;
+        self.follow_jumps(position)
+    }
+
+    /// Move the position back to the start of the looped section, if we have
+    /// read past its end.
+    fn follow_jumps(&self, mut position: u64) -> u64 {
+        // The worker holds the lock only very briefly, let's check the next time.
+        let mut jumps = match self.jumps.try_lock() {
+            Ok(jumps) => jumps,
+            Err(_) => return position,
+        };
+        while let Some(&(from, to)) = jumps.front() {
+            if position < from {
+                break;
+            }
+            jumps.pop_front();
+            position = to + (position - from);
+            self.position.store(position, Ordering::Relaxed);
+        }
+        position
     }
 
     fn should_report(&self, pos: u64) -> bool {
         self.reported > pos || pos - self.reported >= self.precision
     }
+}
+
+impl TrackSource for DecoderSource {
+    fn is_end_of_track(&self) -> bool {
+        self.end_of_track
+    }
 
     fn samples_to_duration(&self, samples: u64) -> Duration {
         let frames = samples / self.signal_spec.channels.count() as u64;
         let time = self.time_base.calc_time(frames);
         Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
     }
+
+    fn duration_to_samples(&self, duration: Duration) -> u64 {
+        (duration.as_secs_f64() * self.signal_spec.rate as f64) as u64
+            * self.signal_spec.channels.count() as u64
+    }
+
+    /// Before the decoder reaches the end of the stream, this is estimated from
+    /// the track duration.
+    fn remaining_samples(&self) -> u64 {
+        let total_samples = match self.total_samples.load(Ordering::Relaxed) {
+            u64::MAX => self.duration_to_samples(self.file.path().duration),
+            total_samples => total_samples,
+        };
+        total_samples.saturating_sub(self.position.load(Ordering::Relaxed))
+    }
+
+    fn detach(&mut self) -> bool {
+        if self.event_send.try_send(PlayerEvent::EndOfTrack).is_ok() {
+            self.detached = true;
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl AudioSource for DecoderSource {
@@ -182,14 +606,33 @@ impl AudioSource for DecoderSource {
         if self.end_of_track {
             return 0;
         }
-        let written = self.consumer.read(output).unwrap_or(0);
+        // Pick up changed trimming settings, unless they are just being changed.
+        if let Ok(trimming) = self.trimming.try_lock() {
+            self.trimmer.configure(*trimming);
+        }
+
+        // Keep reading while the trimmer is dropping samples, so the output is
+        // not cut short by the silence.
+        let mut read = 0;
+        let mut written = 0;
+        loop {
+            let n = self.consumer.read(&mut output[written..]).unwrap_or(0);
+            read += n;
+            written += self.trimmer.trim(&mut output[written..written + n]);
+            if n == 0 || written == output.len() || !self.trimmer.is_enabled() {
+                break;
+            }
+        }
 
         // Apply the normalization factor.
         output[..written]
             .iter_mut()
             .for_each(|s| *s *= self.norm_factor);
 
-        let position = self.written_samples(written as u64);
+        let position = self.written_samples(read as u64);
+        if self.detached {
+            return written;
+        }
         if self.should_report(position) {
             // Send a position report, so the upper layers can visualize the playback
             // progress and preload the next track.  We cannot block here, so if the channel
@@ -204,6 +647,18 @@ impl AudioSource for DecoderSource {
             {
                 self.reported = position;
             }
+            let trimmed = self.trimmer.trimmed();
+            if trimmed != self.reported_trimmed
+                && self
+                    .event_send
+                    .try_send(PlayerEvent::SilenceTrimmed {
+                        path: self.file.path(),
+                        trimmed,
+                    })
+                    .is_ok()
+            {
+                self.reported_trimmed = trimmed;
+            }
         }
 
         let total_samples = self.total_samples.load(Ordering::Relaxed);
@@ -235,6 +690,7 @@ impl Drop for DecoderSource {
 
 enum Msg {
     Seek(Duration),
+    SetLoop(Option<Range<Duration>>),
     Read,
     Stop,
 }
@@ -256,6 +712,13 @@ struct Worker {
     position: Arc<AtomicU64>,
     /// Shared atomic for total number of samples.  We set this on EOF.
     total_samples: Arc<AtomicU64>,
+    /// Jumps of the position we made when looping, see `DecoderSource`.
+    jumps: Arc<Mutex<VecDeque<(u64, u64)>>>,
+    /// Looped section, as a range of sample positions.
+    loop_range: Option<Range<u64>>,
+    /// Samples up to the end of the looped section have been written, the next
+    /// ones come from its start.
+    loop_due: bool,
     /// Range of samples in `resampled` that are awaiting flush into `output`.
     samples_to_write: Range<usize>,
     /// Number of samples written into the output channel.
@@ -277,6 +740,7 @@ impl Worker {
         output: SpscRb<f32>,
         position: Arc<AtomicU64>,
         total_samples: Arc<AtomicU64>,
+        jumps: Arc<Mutex<VecDeque<(u64, u64)>>>,
     ) -> Self {
         const DEFAULT_MAX_FRAMES: u64 = 8 * 1024;
 
@@ -302,6 +766,9 @@ impl Worker {
             output,
             position,
             total_samples,
+            jumps,
+            loop_range: None,
+            loop_due: false,
             samples_written: 0,
             samples_to_write: 0..0, // Arbitrary empty range.
             is_reading: false,
@@ -316,6 +783,7 @@ impl Actor for Worker {
     fn handle(&mut self, msg: Msg) -> Result<Act<Self>, Self::Error> {
         match msg {
             Msg::Seek(time) => self.on_seek(time),
+            Msg::SetLoop(section) => self.on_set_loop(section),
             Msg::Read => self.on_read(),
             Msg::Stop => Ok(Act::Shutdown),
         }
@@ -335,6 +803,8 @@ impl Worker {
                 self.samples_written = position;
                 self.position.store(position, Ordering::Relaxed);
                 self.output.clear();
+                self.jumps.lock().unwrap().clear();
+                self.loop_due = false;
             }
             Err(err) => {
                 log::error!("failed to seek: {}", err);
@@ -343,6 +813,60 @@ impl Worker {
         Ok(Act::Continue)
     }
 
+    fn on_set_loop(&mut self, section: Option<Range<Duration>>) -> Result<Act<Self>, Error> {
+        self.loop_range = section
+            .map(|section| self.time_to_samples(section.start)..self.time_to_samples(section.end));
+        if let Some(range) = &self.loop_range {
+            if self.samples_written > range.end {
+                // We have already decoded past the end of the section.  Start over
+                // from the samples that are playing now, so the ones past the end
+                // are never heard.
+                let playing = self.position.load(Ordering::Relaxed);
+                return self.on_seek(self.samples_to_time(playing));
+            }
+        }
+        if !self.is_reading {
+            // Keep the read loop going, in case it has been waiting for space in
+            // the buffer.
+            self.this.send(Msg::Read)?;
+        }
+        Ok(Act::Continue)
+    }
+
+    /// Continue decoding from the start of the looped section.
+    fn loop_back(&mut self) -> Result<Act<Self>, Error> {
+        self.loop_due = false;
+        if let Some(range) = &self.loop_range {
+            let start = self.samples_to_time(range.start);
+            match self.input.seek(start) {
+                Ok(timestamp) => {
+                    let position = timestamp * self.input_spec.channels.count() as u64;
+                    self.jumps
+                        .lock()
+                        .unwrap()
+                        .push_back((self.samples_written, position));
+                    self.samples_written = position;
+                }
+                Err(err) => {
+                    log::error!("failed to loop: {}", err);
+                }
+            }
+        }
+        self.is_reading = true;
+        self.this.send(Msg::Read)?;
+        Ok(Act::Continue)
+    }
+
+    fn time_to_samples(&self, time: Duration) -> u64 {
+        (time.as_secs_f64() * self.input_spec.rate as f64) as u64
+            * self.input_spec.channels.count() as u64
+    }
+
+    fn samples_to_time(&self, samples: u64) -> Duration {
+        let frames = samples / self.input_spec.channels.count() as u64;
+        Duration::from_secs_f64(frames as f64 / self.input_spec.rate as f64)
+    }
+
     fn on_read(&mut self) -> Result<Act<Self>, Error> {
         if !self.samples_to_write.is_empty() {
             let writable = &self.input_packet.samples()[self.samples_to_write.clone()];
@@ -362,13 +886,28 @@ impl Worker {
                     timeout_msg: Msg::Read,
                 })
             }
+        } else if self.loop_due {
+            self.loop_back()
         } else {
             match self.input.read_packet(&mut self.input_packet) {
                 Some(_) => {
-                    self.samples_to_write = 0..self.input_packet.samples().len();
+                    let mut len = self.input_packet.samples().len();
+                    if let Some(range) = &self.loop_range {
+                        // Cut the packet at the end of the looped section.
+                        let until_end = range.end.saturating_sub(self.samples_written);
+                        if len as u64 >= until_end {
+                            len = until_end as usize;
+                            self.loop_due = true;
+                        }
+                    }
+                    self.samples_to_write = 0..len;
                     self.is_reading = true;
                     self.this.send(Msg::Read)?;
                 }
+                None if self.loop_range.is_some() => {
+                    // Section reaches to the end of the track.
+                    return self.loop_back();
+                }
                 None => {
                     self.is_reading = false;
                     self.total_samples
@@ -379,3 +918,92 @@ impl Worker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Track {
+        samples: Vec<f32>,
+        position: usize,
+        end_of_track: bool,
+    }
+
+    impl Track {
+        fn new(value: f32, len: usize) -> Self {
+            Self {
+                samples: vec![value; len],
+                position: 0,
+                end_of_track: false,
+            }
+        }
+    }
+
+    impl AudioSource for Track {
+        fn write(&mut self, output: &mut [f32]) -> usize {
+            if self.end_of_track {
+                return 0;
+            }
+            let len = output.len().min(self.samples.len() - self.position);
+            output[..len].copy_from_slice(&self.samples[self.position..self.position + len]);
+            self.position += len;
+            // Like `DecoderSource`, report the end with the last written samples.
+            self.end_of_track = self.position == self.samples.len();
+            len
+        }
+
+        fn channel_count(&self) -> usize {
+            2
+        }
+
+        fn sample_rate(&self) -> u32 {
+            44100
+        }
+    }
+
+    impl TrackSource for Track {
+        fn is_end_of_track(&self) -> bool {
+            self.end_of_track
+        }
+
+        fn remaining_samples(&self) -> u64 {
+            (self.samples.len() - self.position) as u64
+        }
+
+        fn samples_to_duration(&self, samples: u64) -> Duration {
+            Duration::from_secs_f64(samples as f64 / 2.0 / 44100.0)
+        }
+
+        fn duration_to_samples(&self, duration: Duration) -> u64 {
+            (duration.as_secs_f64() * 44100.0) as u64 * 2
+        }
+
+        fn detach(&mut self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn plays_chained_sources_back_to_back() {
+        let next = Arc::new(Mutex::new(Some(QueuedSource {
+            source: Track::new(2.0, 100),
+            crossfade: None,
+        })));
+        let mut source = ChainedSource {
+            current: Chained::Single(Track::new(1.0, 100)),
+            next: Arc::clone(&next),
+        };
+
+        let mut output = vec![0.0; 160];
+        assert_eq!(source.write(&mut output), 160);
+        assert!(output[..100].iter().all(|&s| s == 1.0));
+        assert!(output[100..].iter().all(|&s| s == 2.0));
+        assert!(next.lock().unwrap().is_none());
+        assert_eq!(source.channel_count(), 2);
+        assert_eq!(source.sample_rate(), 44100);
+
+        assert_eq!(source.write(&mut output), 40);
+        assert!(output[..40].iter().all(|&s| s == 2.0));
+        assert_eq!(source.write(&mut output), 0);
+    }
+}
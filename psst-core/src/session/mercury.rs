@@ -16,6 +16,8 @@ use crate::{
 pub struct MercuryDispatcher {
     sequence: Sequence<u64>,
     pending: HashMap<u64, Pending>,
+    pending_events: HashMap<u64, Vec<Msg>>,
+    subscriptions: Vec<Subscription>,
 }
 
 impl MercuryDispatcher {
@@ -23,9 +25,27 @@ impl MercuryDispatcher {
         Self {
             sequence: Sequence::new(0),
             pending: HashMap::new(),
+            pending_events: HashMap::new(),
+            subscriptions: Vec::new(),
         }
     }
 
+    /// Enqueue a subscription request.  Events published to URIs starting with
+    /// the requested URI are sent to `events` until the receiving side is
+    /// dropped.
+    pub fn subscribe(
+        &mut self,
+        req: MercuryRequest,
+        callback: Sender<MercuryResponse>,
+        events: Sender<MercuryResponse>,
+    ) -> ShannonMsg {
+        self.subscriptions.push(Subscription {
+            uri: req.uri.clone(),
+            events,
+        });
+        self.enqueue_request(req, callback)
+    }
+
     pub fn enqueue_request(
         &mut self,
         req: MercuryRequest,
@@ -39,7 +59,12 @@ impl MercuryDispatcher {
                 messages: Vec::new(),
             },
         );
-        ShannonMsg::new(ShannonMsg::MERCURY_REQ, req.encode_to_mercury_message(seq))
+        let cmd = match req.method.as_str() {
+            "SUB" => ShannonMsg::MERCURY_SUB,
+            "UNSUB" => ShannonMsg::MERCURY_UNSUB,
+            _ => ShannonMsg::MERCURY_REQ,
+        };
+        ShannonMsg::new(cmd, req.encode_to_mercury_message(seq))
     }
 
     pub fn handle_mercury_req(&mut self, shannon_msg: ShannonMsg) {
@@ -51,9 +76,20 @@ impl MercuryDispatcher {
             if msg_flags == Msg::FINAL {
                 // This is the final message.  Aggregate all pending parts and process further.
                 let parts = Msg::aggregate(pending.messages);
-                let response = MercuryResponse::decode_from_parts(parts);
-                // Send the response.  If the response channel is closed, ignore it.
-                let _ = pending.callback.send(response);
+                match MercuryResponse::decode_from_parts(parts) {
+                    Ok(response) => {
+                        // Send the response.  If the response channel is closed, ignore it.
+                        let _ = pending.callback.send(response);
+                    }
+                    Err(err) => {
+                        // Dropping the callback fails the request.
+                        log::error!(
+                            "failed to decode mercury response, seq: {}: {}",
+                            msg_seq,
+                            err
+                        );
+                    }
+                }
             } else {
                 // This is not the final message of this sequence, but it back as pending.
                 self.pending.insert(msg_seq, pending);
@@ -62,6 +98,35 @@ impl MercuryDispatcher {
             log::warn!("received unexpected mercury msg, seq: {}", msg_seq);
         }
     }
+
+    pub fn handle_mercury_event(&mut self, shannon_msg: ShannonMsg) {
+        let msg = Msg::decode(shannon_msg.payload);
+        let msg_seq = msg.seq;
+        let mut messages = self.pending_events.remove(&msg_seq).unwrap_or_default();
+        let is_final = msg.flags == Msg::FINAL;
+        messages.push(msg);
+        if !is_final {
+            self.pending_events.insert(msg_seq, messages);
+            return;
+        }
+        let parts = Msg::aggregate(messages);
+        let event = match MercuryResponse::decode_from_parts(parts) {
+            Ok(event) => event,
+            Err(err) => {
+                log::error!("failed to decode mercury event, seq: {}: {}", msg_seq, err);
+                return;
+            }
+        };
+        // Deliver to all matching subscriptions, forgetting the ones that have
+        // been dropped.
+        self.subscriptions.retain(|sub| {
+            if event.uri.starts_with(&sub.uri) {
+                sub.events.send(event.clone()).is_ok()
+            } else {
+                true
+            }
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -88,6 +153,14 @@ impl MercuryRequest {
         }
     }
 
+    pub fn sub(uri: String) -> Self {
+        Self {
+            uri,
+            method: "SUB".to_string(),
+            payload: Vec::new(),
+        }
+    }
+
     fn encode_to_mercury_message(self, seq: u64) -> Vec<u8> {
         let parts = self.encode_to_parts();
         let msg = Msg::new(seq, Msg::FINAL, parts);
@@ -115,18 +188,31 @@ pub struct MercuryResponse {
 }
 
 impl MercuryResponse {
-    fn decode_from_parts(mut parts: Vec<Vec<u8>>) -> Self {
+    fn decode_from_parts(mut parts: Vec<Vec<u8>>) -> Result<Self, Error> {
+        if parts.is_empty() {
+            return Err(Error::UnexpectedResponse);
+        }
         let header_part = parts.remove(0);
-        let header: Header =
-            deserialize_protobuf(&header_part).expect("Failed to deserialize message header");
-        Self {
-            uri: header.uri.unwrap(),
-            status_code: header.status_code.unwrap(),
+        let header: Header = deserialize_protobuf(&header_part)?;
+        Ok(Self {
+            // Published events come without a status code.
+            uri: header.uri.unwrap_or_default(),
+            status_code: header.status_code.unwrap_or(200),
             payload: parts,
-        }
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
     }
 }
 
+#[derive(Debug)]
+struct Subscription {
+    uri: String,
+    events: Sender<MercuryResponse>,
+}
+
 #[derive(Debug)]
 struct Pending {
     messages: Vec<Msg>,
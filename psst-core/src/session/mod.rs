@@ -76,6 +76,12 @@ impl SessionService {
         self.shutdown();
     }
 
+    /// Proxy URL from the active session config.  Web API requests made on
+    /// behalf of the session should go through it as well.
+    pub fn proxy_url(&self) -> Option<String> {
+        self.config.lock().as_ref()?.proxy_url.clone()
+    }
+
     /// Returns true if a session worker is actively servicing the connected
     /// session.  We return false here after any case of I/O errors or an
     /// explicit session shutdown.
@@ -100,7 +106,10 @@ impl SessionService {
                     .ok_or(Error::SessionDisconnected)?
                     .clone(),
             )?;
-            let worker = SessionWorker::run(connection.transport);
+            let worker = SessionWorker::run(
+                connection.transport,
+                connection.credentials.username.clone(),
+            );
             connected.replace(worker);
         }
         connected
@@ -145,6 +154,7 @@ impl SessionConnection {
 
 pub struct SessionWorker {
     sender: Sender<DispatchCmd>,
+    username: Option<String>,
     decoding_thread: JoinHandle<()>,
     encoding_thread: JoinHandle<()>,
     dispatching_thread: JoinHandle<()>,
@@ -152,7 +162,7 @@ pub struct SessionWorker {
 }
 
 impl SessionWorker {
-    pub fn run(transport: Transport) -> Self {
+    pub fn run(transport: Transport, username: Option<String>) -> Self {
         let (disp_send, disp_recv) = unbounded();
         let (msg_send, msg_recv) = unbounded();
         let terminated = Arc::new(AtomicBool::new(false));
@@ -176,6 +186,7 @@ impl SessionWorker {
                 })
             },
             sender: disp_send,
+            username,
             terminated,
         }
     }
@@ -183,6 +194,7 @@ impl SessionWorker {
     pub fn handle(&self) -> SessionHandle {
         SessionHandle {
             sender: self.sender.clone(),
+            username: self.username.clone(),
         }
     }
 
@@ -206,9 +218,15 @@ impl SessionWorker {
 #[derive(Clone)]
 pub struct SessionHandle {
     sender: Sender<DispatchCmd>,
+    username: Option<String>,
 }
 
 impl SessionHandle {
+    /// Canonical username of the authenticated user.
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
     pub fn get_mercury_protobuf<T>(&self, uri: String) -> Result<T, Error>
     where
         T: MessageRead<'static>,
@@ -243,6 +261,44 @@ impl SessionHandle {
         Ok(first_part)
     }
 
+    pub fn send_mercury_bytes(&self, uri: String, data: Vec<u8>) -> Result<(), Error> {
+        let (callback, receiver) = unbounded();
+        let request = MercuryRequest::send(uri, data);
+        self.sender
+            .send(DispatchCmd::MercuryReq { callback, request })
+            .ok()
+            .ok_or(Error::SessionDisconnected)?;
+        let response = receiver.recv().ok().ok_or(Error::SessionDisconnected)?;
+        if response.is_success() {
+            Ok(())
+        } else {
+            Err(Error::UnexpectedResponse)
+        }
+    }
+
+    /// Subscribe to events published to `uri` (and all URIs it prefixes).  The
+    /// subscription is dropped together with the returned receiver, or when
+    /// the session disconnects.
+    pub fn subscribe_mercury(&self, uri: String) -> Result<Receiver<MercuryResponse>, Error> {
+        let (callback, receiver) = unbounded();
+        let (events, events_receiver) = unbounded();
+        let request = MercuryRequest::sub(uri);
+        self.sender
+            .send(DispatchCmd::MercurySub {
+                request,
+                callback,
+                events,
+            })
+            .ok()
+            .ok_or(Error::SessionDisconnected)?;
+        let response = receiver.recv().ok().ok_or(Error::SessionDisconnected)?;
+        if response.is_success() {
+            Ok(events_receiver)
+        } else {
+            Err(Error::UnexpectedResponse)
+        }
+    }
+
     pub fn get_audio_key(&self, track: ItemId, file: FileId) -> Result<AudioKey, Error> {
         let (callback, receiver) = unbounded();
         self.sender
@@ -318,6 +374,11 @@ enum DispatchCmd {
         request: MercuryRequest,
         callback: Sender<MercuryResponse>,
     },
+    MercurySub {
+        request: MercuryRequest,
+        callback: Sender<MercuryResponse>,
+        events: Sender<MercuryResponse>,
+    },
     AudioKeyReq {
         track: ItemId,
         file: FileId,
@@ -347,6 +408,14 @@ fn dispatch_messages(
                 let msg = mercury.enqueue_request(request, callback);
                 let _ = messages.send(msg);
             }
+            DispatchCmd::MercurySub {
+                request,
+                callback,
+                events,
+            } => {
+                let msg = mercury.subscribe(request, callback, events);
+                let _ = messages.send(msg);
+            }
             DispatchCmd::AudioKeyReq {
                 track,
                 file,
@@ -370,9 +439,16 @@ fn dispatch_messages(
             DispatchCmd::DecodedMsg(msg) if msg.cmd == ShannonMsg::AES_KEY_ERROR => {
                 audio_key.handle_aes_key_error(msg)
             }
-            DispatchCmd::DecodedMsg(msg) if msg.cmd == ShannonMsg::MERCURY_REQ => {
+            DispatchCmd::DecodedMsg(msg)
+                if msg.cmd == ShannonMsg::MERCURY_REQ
+                    || msg.cmd == ShannonMsg::MERCURY_SUB
+                    || msg.cmd == ShannonMsg::MERCURY_UNSUB =>
+            {
                 mercury.handle_mercury_req(msg)
             }
+            DispatchCmd::DecodedMsg(msg) if msg.cmd == ShannonMsg::MERCURY_PUB => {
+                mercury.handle_mercury_event(msg)
+            }
             DispatchCmd::DecodedMsg(msg) => {
                 log::debug!("ignored message: {:?}", msg.cmd);
             }
@@ -1,11 +1,19 @@
 use crate::data::Track;
 use druid::{Selector, WidgetId};
-use psst_core::{item_id::ItemId, player::item::PlaybackItem};
+use psst_core::{
+    audio::analyzer::Analysis,
+    cache::CacheStats,
+    item_id::ItemId,
+    player::{item::PlaybackItem, resume::ResumePoint, sleep::SleepMode},
+};
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::{
-    data::{Nav, PlaybackPayload, QueueBehavior, QueueEntry, PlaylistLink, TrackId},
+    data::{
+        Nav, PlaybackPayload, PlaylistLink, QueueBehavior, QueueEntry, RestoredPlayback,
+        SleepTimer, TrackId,
+    },
     ui::find::Find,
 };
 
@@ -26,6 +34,9 @@ pub const TOGGLE_FINDER: Selector = Selector::new("app.show-finder");
 pub const FIND_IN_PLAYLIST: Selector<Find> = Selector::new("find-in-playlist");
 pub const FIND_IN_SAVED_TRACKS: Selector<Find> = Selector::new("find-in-saved-tracks");
 
+// Cache
+pub const CACHE_EVICTED: Selector<CacheStats> = Selector::new("app.cache-evicted");
+
 // Session
 pub const SESSION_CONNECT: Selector = Selector::new("app.session-connect");
 pub const LOG_OUT: Selector = Selector::new("app.log-out");
@@ -44,6 +55,14 @@ pub const PLAYBACK_PAUSING: Selector = Selector::new("app.playback-pausing");
 pub const PLAYBACK_RESUMING: Selector = Selector::new("app.playback-resuming");
 pub const PLAYBACK_BLOCKED: Selector = Selector::new("app.playback-blocked");
 pub const PLAYBACK_STOPPED: Selector = Selector::new("app.playback-stopped");
+pub const SILENCE_TRIMMED: Selector<Duration> = Selector::new("app.silence-trimmed");
+pub const AUDIO_ANALYZED: Selector<Analysis> = Selector::new("app.audio-analyzed");
+pub const GAIN_REDUCTION: Selector<f32> = Selector::new("app.gain-reduction");
+pub const LOOP_CHANGED: Selector<Option<(Duration, Duration)>> = Selector::new("app.loop-changed");
+pub const SLEEP_TIMER_CHANGED: Selector<Option<SleepTimer>> =
+    Selector::new("app.sleep-timer-changed");
+pub const RESUME_POINT_SAVED: Selector<(ItemId, ResumePoint)> =
+    Selector::new("app.resume-point-saved");
 
 // Playback control
 pub const PLAY: Selector<usize> = Selector::new("app.play-index");
@@ -56,7 +75,15 @@ pub const PLAY_STOP: Selector = Selector::new("app.play-stop");
 pub const ADD_TO_QUEUE: Selector<(QueueEntry, PlaybackItem)> = Selector::new("app.add-to-queue");
 pub const PLAY_QUEUE_BEHAVIOR: Selector<QueueBehavior> = Selector::new("app.play-queue-behavior");
 pub const PLAY_SEEK: Selector<f64> = Selector::new("app.play-seek");
+/// Loop the section between two fractions of the playing item, or stop looping
+/// if `None`.
+pub const PLAY_LOOP: Selector<Option<(f64, f64)>> = Selector::new("app.play-loop");
+pub const PLAY_SPEED: Selector<f64> = Selector::new("app.play-speed");
+pub const PLAY_TRIM_SILENCE: Selector<bool> = Selector::new("app.play-trim-silence");
+/// Set the sleep timer, or cancel it if `None`.
+pub const PLAY_SLEEP_TIMER: Selector<Option<SleepMode>> = Selector::new("app.play-sleep-timer");
 pub const SKIP_TO_POSITION: Selector<u64> = Selector::new("app.skip-to-position");
+pub const RESTORE_PLAYBACK: Selector<RestoredPlayback> = Selector::new("app.restore-playback");
 
 // Sorting control
 pub const SORT_BY_DATE_ADDED: Selector = Selector::new("app.sort-by-date-added");
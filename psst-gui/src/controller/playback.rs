@@ -1,20 +1,33 @@
 use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{unbounded, Receiver, Sender};
 use druid::{
     im::Vector,
     widget::{prelude::*, Controller},
-    Code, ExtEventSink, InternalLifeCycle, KbKey, WindowHandle,
+    Code, ExtEventSink, InternalLifeCycle, KbKey, Target, WindowHandle,
 };
 use psst_core::{
-    audio::{normalize::NormalizationLevel, output::DefaultAudioOutput},
-    cache::Cache,
+    actor::{Actor, ActorHandle, Capacity},
+    audio::{
+        analyzer::Analyzer,
+        output::{DefaultAudioOutput, OutputConfig},
+        silence::SilenceTrimming,
+    },
+    cache::{Cache, CacheEvictor, CacheStats, EvictorMsg},
     cdn::Cdn,
+    item_id::{ItemId, ItemIdType},
     lastfm::LastFmClient,
-    player::{item::PlaybackItem, PlaybackConfig, Player, PlayerCommand, PlayerEvent},
+    player::{
+        item::PlaybackItem,
+        offline::{Downloader, OfflineEvent, OfflineMsg},
+        persist::{SavedItem, SavedPlayback},
+        PlaybackConfig, Player, PlayerCommand, PlayerEvent,
+    },
     session::SessionService,
 };
 use rustfm_scrobble::Scrobbler;
@@ -27,21 +40,28 @@ use crate::{
     cmd,
     data::Nav,
     data::{
-        AppState, Config, NowPlaying, Playable, Playback, PlaybackOrigin, PlaybackState,
-        QueueBehavior, QueueEntry,
+        AppState, Config, EpisodeId, NowPlaying, OfflineCollection, Playable, Playback,
+        PlaybackState, QueueBehavior, QueueEntry, RestoredPlayback, SleepTimer, TrackId,
     },
-    ui::lyrics,
+    error::Error,
+    ui::{lyrics, offline},
+    webapi::WebApi,
 };
 
 pub struct PlaybackController {
     sender: Option<Sender<PlayerEvent>>,
     thread: Option<JoinHandle<()>>,
     output: Option<DefaultAudioOutput>,
+    offline: Option<ActorHandle<OfflineMsg>>,
+    evictor: Option<ActorHandle<EvictorMsg>>,
     media_controls: Option<MediaControls>,
     has_scrobbled: bool,
     scrobbler: Option<Scrobbler>,
     startup: bool,
+    playback_saved_at: Instant,
 }
+
+const PLAYBACK_SAVE_INTERVAL: Duration = Duration::from_secs(30);
 fn init_scrobbler_instance(data: &AppState) -> Option<Scrobbler> {
     if data.config.lastfm_enable {
         if let (Some(api_key), Some(api_secret), Some(session_key)) = (
@@ -74,10 +94,13 @@ impl PlaybackController {
             sender: None,
             thread: None,
             output: None,
+            offline: None,
+            evictor: None,
             media_controls: None,
             has_scrobbled: false,
             scrobbler: None,
             startup: true,
+            playback_saved_at: Instant::now(),
         }
     }
 
@@ -85,20 +108,64 @@ impl PlaybackController {
         &mut self,
         session: SessionService,
         config: PlaybackConfig,
+        output_config: OutputConfig,
         event_sink: ExtEventSink,
         widget_id: WidgetId,
         #[allow(unused_variables)] window: &WindowHandle,
     ) {
-        let output = DefaultAudioOutput::open().unwrap();
+        let output = DefaultAudioOutput::open(output_config).unwrap();
         let cache_dir = Config::cache_dir().unwrap();
         let proxy_url = Config::proxy();
-        let player = Player::new(
-            session.clone(),
-            Cdn::new(session, proxy_url.as_deref()).unwrap(),
-            Cache::new(cache_dir).unwrap(),
-            config,
-            &output,
-        );
+        let cdn = Cdn::new(session.clone(), proxy_url.as_deref()).unwrap();
+        let cache = Cache::new(cache_dir).unwrap();
+
+        let (offline_send, offline_recv) = unbounded();
+        let downloader = {
+            let session = session.clone();
+            let cdn = cdn.clone();
+            let cache = cache.clone();
+            let config = config.clone();
+            move |_| Downloader::new(session, cdn, cache, config, offline_send)
+        };
+        self.offline = Some(Downloader::spawn(
+            Capacity::Unbounded,
+            "offline_downloader",
+            downloader,
+        ));
+        thread::spawn({
+            let event_sink = event_sink.clone();
+            move || {
+                Self::service_offline_events(offline_recv, event_sink, widget_id);
+            }
+        });
+
+        let (evictor_send, evictor_recv) = unbounded();
+        let evictor = {
+            let cache = cache.clone();
+            move |_| CacheEvictor::new(cache, None, evictor_send)
+        };
+        self.evictor = Some(CacheEvictor::spawn(
+            Capacity::Unbounded,
+            "cache_evictor",
+            evictor,
+        ));
+        thread::spawn({
+            let event_sink = event_sink.clone();
+            move || {
+                Self::service_cache_stats(evictor_recv, event_sink, widget_id);
+            }
+        });
+
+        let mut player = Player::new(session, cdn, cache, config, &output);
+
+        if let Some(analyzer) = player.analyzer() {
+            thread::spawn({
+                let event_sink = event_sink.clone();
+                move || {
+                    Self::service_analysis(analyzer, event_sink);
+                }
+            });
+        }
 
         self.media_controls = Self::create_media_controls(player.sender(), window)
             .map_err(|err| log::error!("failed to connect to media control interface: {:?}", err))
@@ -152,6 +219,36 @@ impl PlaybackController {
                         .submit_command(cmd::PLAYBACK_STOPPED, (), widget_id)
                         .unwrap();
                 }
+                PlayerEvent::SilenceTrimmed { trimmed, .. } => {
+                    event_sink
+                        .submit_command(cmd::SILENCE_TRIMMED, *trimmed, widget_id)
+                        .unwrap();
+                }
+                PlayerEvent::GainReduction { reduction_db } => {
+                    event_sink
+                        .submit_command(cmd::GAIN_REDUCTION, *reduction_db, widget_id)
+                        .unwrap();
+                }
+                PlayerEvent::LoopChanged { section } => {
+                    let section = section.as_ref().map(|section| (section.start, section.end));
+                    event_sink
+                        .submit_command(cmd::LOOP_CHANGED, section, widget_id)
+                        .unwrap();
+                }
+                PlayerEvent::SleepTimer { mode, remaining } => {
+                    let timer = mode.map(|mode| SleepTimer {
+                        mode,
+                        remaining: *remaining,
+                    });
+                    event_sink
+                        .submit_command(cmd::SLEEP_TIMER_CHANGED, timer, widget_id)
+                        .unwrap();
+                }
+                PlayerEvent::ResumePointSaved { item_id, point } => {
+                    event_sink
+                        .submit_command(cmd::RESUME_POINT_SAVED, (*item_id, *point), widget_id)
+                        .unwrap();
+                }
                 _ => {}
             }
 
@@ -160,6 +257,45 @@ impl PlaybackController {
         }
     }
 
+    fn service_offline_events(
+        events: Receiver<OfflineEvent>,
+        event_sink: ExtEventSink,
+        widget_id: WidgetId,
+    ) {
+        for event in events {
+            let (item_id, success) = match event {
+                OfflineEvent::Downloaded { item_id } => (item_id, true),
+                OfflineEvent::Failed { item_id, .. } => (item_id, false),
+                OfflineEvent::Progress { .. } | OfflineEvent::Removed { .. } => continue,
+            };
+            event_sink
+                .submit_command(offline::ITEM_DOWNLOADED, (item_id, success), widget_id)
+                .unwrap();
+        }
+    }
+
+    fn service_cache_stats(
+        stats: Receiver<CacheStats>,
+        event_sink: ExtEventSink,
+        widget_id: WidgetId,
+    ) {
+        for stats in stats {
+            event_sink
+                .submit_command(cmd::CACHE_EVICTED, stats, widget_id)
+                .unwrap();
+        }
+    }
+
+    fn service_analysis(analyzer: Analyzer, event_sink: ExtEventSink) {
+        // Every window can show a visualizer, so the analysis goes to all of
+        // them.  Stops once the app is gone.
+        analyzer.run(|analysis| {
+            event_sink
+                .submit_command(cmd::AUDIO_ANALYZED, analysis, Target::Global)
+                .is_ok()
+        });
+    }
+
     fn create_media_controls(
         sender: Sender<PlayerEvent>,
         #[allow(unused_variables)] window: &WindowHandle,
@@ -262,6 +398,74 @@ impl PlaybackController {
         }
     }
 
+    fn send_offline(&self, msg: OfflineMsg) {
+        if let Some(offline) = &self.offline {
+            if offline.send(msg).is_err() {
+                log::error!("offline downloader is not running");
+            }
+        }
+    }
+
+    fn send_evictor(&self, msg: EvictorMsg) {
+        if let Some(evictor) = &self.evictor {
+            if evictor.send(msg).is_err() {
+                log::error!("cache evictor is not running");
+            }
+        }
+    }
+
+    fn download_collection(
+        &self,
+        collection: OfflineCollection,
+        event_sink: ExtEventSink,
+        widget_id: WidgetId,
+    ) {
+        let sender = match &self.offline {
+            Some(offline) => offline.sender(),
+            None => return,
+        };
+        thread::spawn(move || {
+            let items = collection_items(&collection).map_err(|err| err.to_string());
+            let download = items.clone().ok();
+            // Report the tracks first, so the download progress is tracked before
+            // the first item finishes.
+            event_sink
+                .submit_command(offline::TRACKS_LOADED, (collection, items), widget_id)
+                .unwrap();
+            if let Some(items) = download {
+                sender.send(OfflineMsg::Download(items)).ok();
+            }
+        });
+    }
+
+    fn remove_collection(
+        &self,
+        collection: OfflineCollection,
+        remaining: Vector<OfflineCollection>,
+    ) {
+        let sender = match &self.offline {
+            Some(offline) => offline.sender(),
+            None => return,
+        };
+        thread::spawn(move || {
+            // Keep the tracks that are also part of another offline collection.
+            let kept: HashSet<ItemId> = remaining
+                .iter()
+                .filter_map(|c| collection_items(c).ok())
+                .flatten()
+                .collect();
+            match collection_items(&collection) {
+                Ok(items) => {
+                    let items = items.into_iter().filter(|id| !kept.contains(id)).collect();
+                    sender.send(OfflineMsg::Remove(items)).ok();
+                }
+                Err(err) => {
+                    log::error!("failed to load offline collection: {}", err);
+                }
+            }
+        });
+    }
+
     fn report_now_playing(&mut self, playback: &Playback) {
         if let Some(now_playing) = playback.now_playing.as_ref() {
             if let Playable::Track(track) = &now_playing.item {
@@ -316,14 +520,8 @@ impl PlaybackController {
     }
 
     fn play(&mut self, items: &Vector<QueueEntry>, position: usize) {
-        let playback_items = items.iter().map(|queued| PlaybackItem {
-            item_id: queued.item.id(),
-            norm_level: match queued.origin {
-                PlaybackOrigin::Album(_) => NormalizationLevel::Album,
-                _ => NormalizationLevel::Track,
-            },
-        });
-        let playback_items_vec: Vec<PlaybackItem> = playback_items.collect();
+        let playback_items_vec: Vec<PlaybackItem> =
+            items.iter().map(QueueEntry::playback_item).collect();
 
         // Make sure position is within bounds
         let position = if position >= playback_items_vec.len() {
@@ -332,12 +530,97 @@ impl PlaybackController {
             position
         };
 
+        self.set_item_groups(items);
         self.send(PlayerEvent::Command(PlayerCommand::LoadQueue {
             items: playback_items_vec,
             position,
         }));
     }
 
+    fn set_item_groups(&mut self, items: &Vector<QueueEntry>) {
+        self.send(PlayerEvent::Command(PlayerCommand::SetItemGroups {
+            groups: items
+                .iter()
+                .map(|entry| (entry.item.id(), entry.item_group()))
+                .collect(),
+        }));
+    }
+
+    /// Fetch the items of the snapshot, as only their IDs are saved.  Returns
+    /// `None` if none of them are available anymore.
+    fn fetch_saved_playback(saved: &SavedPlayback) -> Result<Option<RestoredPlayback>, Error> {
+        // Both endpoints take at most 50 IDs.
+        const IDS_PER_REQUEST: usize = 50;
+
+        let webapi = WebApi::global();
+        let (tracks, episodes): (Vec<_>, Vec<_>) = saved
+            .items
+            .iter()
+            .filter_map(SavedItem::playback_item)
+            .map(|item| item.item_id)
+            .partition(|id| id.id_type == ItemIdType::Track);
+        let mut fetched = HashMap::new();
+        for ids in tracks.chunks(IDS_PER_REQUEST) {
+            for track in webapi.get_tracks(ids.iter().copied().map(TrackId))? {
+                fetched.insert(track.id.0, Playable::Track(track));
+            }
+        }
+        for ids in episodes.chunks(IDS_PER_REQUEST) {
+            for episode in webapi.get_episodes(ids.iter().copied().map(EpisodeId))? {
+                fetched.insert(episode.id.0, Playable::Episode(episode));
+            }
+        }
+
+        let mut queue = Vector::new();
+        let mut position = 0;
+        for (index, saved_item) in saved.items.iter().enumerate() {
+            if index == saved.position {
+                position = queue.len();
+            }
+            let item = saved_item
+                .playback_item()
+                .and_then(|item| fetched.get(&item.item_id));
+            if let (Some(item), Some(origin)) = (item, QueueEntry::saved_origin(saved_item)) {
+                queue.push_back(QueueEntry {
+                    item: item.clone(),
+                    origin,
+                });
+            }
+        }
+        if queue.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(RestoredPlayback {
+            position: position.min(queue.len() - 1),
+            queue,
+            progress: saved.progress(),
+            behavior: QueueBehavior::from(saved.behavior),
+        }))
+    }
+
+    fn restore(&mut self, data: &mut AppState, restored: &RestoredPlayback) {
+        data.set_queue_behavior(restored.behavior);
+        self.set_queue_behavior(restored.behavior);
+        self.set_item_groups(&restored.queue);
+        data.playback.queue = restored.queue.clone();
+        self.send(PlayerEvent::Command(PlayerCommand::Restore {
+            items: restored
+                .queue
+                .iter()
+                .map(QueueEntry::playback_item)
+                .collect(),
+            position: restored.position,
+            progress: restored.progress,
+        }));
+    }
+
+    /// Save the playback snapshot in the background, so a crash does not lose
+    /// more than the last few seconds.
+    fn save_playback(&mut self, data: &AppState) {
+        self.playback_saved_at = Instant::now();
+        Config::save_playback_in_background(data.saved_playback());
+    }
+
     fn pause(&mut self) {
         self.send(PlayerEvent::Command(PlayerCommand::Pause));
     }
@@ -394,12 +677,7 @@ impl PlaybackController {
 
     fn set_queue_behavior(&mut self, behavior: QueueBehavior) {
         self.send(PlayerEvent::Command(PlayerCommand::SetQueueBehavior {
-            behavior: match behavior {
-                QueueBehavior::Sequential => psst_core::player::queue::QueueBehavior::Sequential,
-                QueueBehavior::Random => psst_core::player::queue::QueueBehavior::Random,
-                QueueBehavior::LoopTrack => psst_core::player::queue::QueueBehavior::LoopTrack,
-                QueueBehavior::LoopAll => psst_core::player::queue::QueueBehavior::LoopAll,
-            },
+            behavior: behavior.into(),
         }));
     }
 
@@ -445,6 +723,9 @@ where
                 self.has_scrobbled = false;
                 self.report_now_playing(&data.playback);
 
+                // The previous track might have been added to the cache.
+                self.send_evictor(EvictorMsg::Evict);
+
                 if let Some(queued) = data.queued_entry(*item) {
                     data.start_playback(queued.item, queued.origin, progress.to_owned());
                     self.update_media_control_playback(&data.playback);
@@ -463,11 +744,15 @@ where
 
                 self.report_scrobble(&data.playback);
                 self.update_media_control_playback(&data.playback);
+                if self.playback_saved_at.elapsed() >= PLAYBACK_SAVE_INTERVAL {
+                    self.save_playback(data);
+                }
                 ctx.set_handled();
             }
             Event::Command(cmd) if cmd.is(cmd::PLAYBACK_PAUSING) => {
                 data.pause_playback();
                 self.update_media_control_playback(&data.playback);
+                self.save_playback(data);
                 ctx.set_handled();
             }
             Event::Command(cmd) if cmd.is(cmd::PLAYBACK_RESUMING) => {
@@ -484,6 +769,29 @@ where
                 self.update_media_control_playback(&data.playback);
                 ctx.set_handled();
             }
+            Event::Command(cmd) if cmd.is(cmd::SILENCE_TRIMMED) => {
+                data.playback.time_saved = *cmd.get_unchecked(cmd::SILENCE_TRIMMED);
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::GAIN_REDUCTION) => {
+                data.playback.gain_reduction = *cmd.get_unchecked(cmd::GAIN_REDUCTION) as f64;
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::LOOP_CHANGED) => {
+                if let Some(now_playing) = &mut data.playback.now_playing {
+                    now_playing.looped = *cmd.get_unchecked(cmd::LOOP_CHANGED);
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::SLEEP_TIMER_CHANGED) => {
+                data.playback.sleep_timer = cmd.get_unchecked(cmd::SLEEP_TIMER_CHANGED).clone();
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::RESUME_POINT_SAVED) => {
+                let (item_id, point) = cmd.get_unchecked(cmd::RESUME_POINT_SAVED);
+                data.update_resume_point(*item_id, point.to_owned().into());
+                ctx.set_handled();
+            }
             Event::Command(cmd) if cmd.is(cmd::PLAY_TRACKS) => {
                 let payload = cmd.get_unchecked(cmd::PLAY_TRACKS);
                 data.playback.queue = payload
@@ -498,6 +806,11 @@ where
                 self.play(&data.playback.queue, payload.position);
                 ctx.set_handled();
             }
+            Event::Command(cmd) if cmd.is(cmd::RESTORE_PLAYBACK) => {
+                let restored = cmd.get_unchecked(cmd::RESTORE_PLAYBACK);
+                self.restore(data, restored);
+                ctx.set_handled();
+            }
             Event::Command(cmd) if cmd.is(cmd::PLAY_PAUSE) => {
                 self.pause();
                 ctx.set_handled();
@@ -532,6 +845,27 @@ where
                 self.set_queue_behavior(behavior.to_owned());
                 ctx.set_handled();
             }
+            Event::Command(cmd) if cmd.is(cmd::PLAY_SPEED) => {
+                let speed = cmd.get_unchecked(cmd::PLAY_SPEED);
+                data.set_speed(*speed);
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::PLAY_SLEEP_TIMER) => {
+                let command = match cmd.get_unchecked(cmd::PLAY_SLEEP_TIMER) {
+                    Some(mode) => PlayerCommand::SetSleepTimer {
+                        mode: *mode,
+                        fade: Duration::from_secs_f64(data.config.sleep_timer_fade.round()),
+                    },
+                    None => PlayerCommand::CancelSleepTimer,
+                };
+                self.send(PlayerEvent::Command(command));
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(cmd::PLAY_TRIM_SILENCE) => {
+                let trim_silence = cmd.get_unchecked(cmd::PLAY_TRIM_SILENCE);
+                data.set_trim_silence(*trim_silence);
+                ctx.set_handled();
+            }
             Event::Command(cmd) if cmd.is(cmd::PLAY_SEEK) => {
                 if let Some(now_playing) = &data.playback.now_playing {
                     let fraction = cmd.get_unchecked(cmd::PLAY_SEEK);
@@ -542,11 +876,85 @@ where
                 }
                 ctx.set_handled();
             }
+            Event::Command(cmd) if cmd.is(cmd::PLAY_LOOP) => {
+                if let Some(now_playing) = &data.playback.now_playing {
+                    let duration = now_playing.item.duration().as_secs_f64();
+                    let section = cmd.get_unchecked(cmd::PLAY_LOOP).map(|(start, end)| {
+                        Duration::from_secs_f64(duration * start)
+                            ..Duration::from_secs_f64(duration * end)
+                    });
+                    self.send(PlayerEvent::Command(PlayerCommand::SetLoop { section }));
+                }
+                ctx.set_handled();
+            }
             Event::Command(cmd) if cmd.is(cmd::SKIP_TO_POSITION) => {
                 let location = cmd.get_unchecked(cmd::SKIP_TO_POSITION);
                 self.seek(Duration::from_millis(*location));
                 ctx.set_handled();
             }
+            Event::Command(cmd) if cmd.is(cmd::CACHE_EVICTED) => {
+                let stats = cmd.get_unchecked(cmd::CACHE_EVICTED);
+                data.preferences.cache_stats = Some(Arc::new(*stats));
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(offline::MAKE_AVAILABLE_OFFLINE) => {
+                let collection = cmd.get_unchecked(offline::MAKE_AVAILABLE_OFFLINE);
+                data.add_offline_collection(collection.clone());
+                self.download_collection(
+                    collection.clone(),
+                    ctx.get_external_handle(),
+                    ctx.widget_id(),
+                );
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(offline::TRACKS_LOADED) => {
+                let (collection, result) = cmd.get_unchecked(offline::TRACKS_LOADED);
+                match result {
+                    Ok(items) => {
+                        data.common_ctx_mut()
+                            .offline
+                            .start_download(collection.clone(), items);
+                    }
+                    Err(err) => {
+                        data.remove_offline_collection(collection);
+                        data.error_alert(err);
+                    }
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(offline::ITEM_DOWNLOADED) => {
+                let (item_id, success) = cmd.get_unchecked(offline::ITEM_DOWNLOADED);
+                let finished = data
+                    .common_ctx_mut()
+                    .offline
+                    .finish_item(*item_id, *success);
+                if !finished.is_empty() {
+                    self.send_evictor(EvictorMsg::Evict);
+                }
+                for (collection, progress) in finished {
+                    if progress.failed == 0 {
+                        data.info_alert(format!("\"{}\" is available offline.", collection.name()));
+                    } else {
+                        data.error_alert(format!(
+                            "{} of {} tracks of \"{}\" failed to download.",
+                            progress.failed,
+                            progress.total,
+                            collection.name()
+                        ));
+                    }
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(offline::REMOVE_FROM_OFFLINE) => {
+                let collection = cmd.get_unchecked(offline::REMOVE_FROM_OFFLINE);
+                data.remove_offline_collection(collection);
+                self.remove_collection(
+                    collection.clone(),
+                    data.common_ctx.offline.collections.clone(),
+                );
+                data.info_alert(format!("Removed \"{}\" from offline.", collection.name()));
+                ctx.set_handled();
+            }
             // Keyboard shortcuts.
             Event::KeyDown(key) if key.code == Code::Space => {
                 self.pause_or_resume();
@@ -593,6 +1001,7 @@ where
                 self.open_audio_output_and_start_threads(
                     data.session.clone(),
                     data.config.playback(),
+                    data.config.output(),
                     ctx.get_external_handle(),
                     ctx.widget_id(),
                     ctx.window(),
@@ -601,6 +1010,27 @@ where
                 // Initialize values loaded from the config.
                 self.set_volume(data.playback.volume);
                 self.set_queue_behavior(data.playback.queue_behavior);
+                self.send_evictor(EvictorMsg::SetLimit(data.config.cache_size_limit));
+
+                // Continue where the last run has stopped, paused.
+                if let Some(saved) = Config::load_playback() {
+                    let event_sink = ctx.get_external_handle();
+                    let widget_id = ctx.widget_id();
+                    thread::spawn(move || match Self::fetch_saved_playback(&saved) {
+                        Ok(Some(restored)) => {
+                            event_sink
+                                .submit_command(cmd::RESTORE_PLAYBACK, restored, widget_id)
+                                .unwrap();
+                        }
+                        Ok(None) => {}
+                        Err(err) => log::error!("failed to restore playback: {}", err),
+                    });
+                }
+
+                // Continue downloads interrupted by the last shutdown.
+                if !data.config.offline_mode {
+                    self.send_offline(OfflineMsg::ResumePinned);
+                }
 
                 // Request focus so we can receive keyboard events.
                 ctx.submit_command(cmd::SET_FOCUS.to(ctx.widget_id()));
@@ -631,6 +1061,64 @@ where
             self.set_volume(data.playback.volume);
         }
 
+        if !old_data.playback.speed.same(&data.playback.speed) {
+            self.send(PlayerEvent::Command(PlayerCommand::SetSpeed {
+                speed: data.playback.speed as f32,
+            }));
+        }
+
+        if old_data.playback.trim_silence != data.playback.trim_silence {
+            self.send(PlayerEvent::Command(PlayerCommand::SetSilenceTrimming {
+                trimming: data.playback.trim_silence.then(SilenceTrimming::default),
+            }));
+        }
+
+        if !old_data.config.equalizer.same(&data.config.equalizer) {
+            self.send(PlayerEvent::Command(PlayerCommand::SetEqualizer {
+                settings: data.config.equalizer.settings(),
+            }));
+        }
+
+        if old_data.config.mono_audio != data.config.mono_audio
+            || !old_data.config.balance.same(&data.config.balance)
+        {
+            self.send(PlayerEvent::Command(PlayerCommand::SetChannelMix {
+                settings: data.config.channel_mix(),
+            }));
+        }
+
+        if old_data.config.limit_peaks != data.config.limit_peaks
+            || !old_data
+                .config
+                .limiter_ceiling
+                .same(&data.config.limiter_ceiling)
+        {
+            self.send(PlayerEvent::Command(PlayerCommand::SetPeakLimiting {
+                limiting: data.config.peak_limiting(),
+            }));
+        }
+
+        if old_data.config.output_device != data.config.output_device {
+            self.send(PlayerEvent::Command(PlayerCommand::SetOutputDevice {
+                name: data.config.output_device.clone(),
+            }));
+        }
+
+        if old_data.config.offline_mode != data.config.offline_mode {
+            WebApi::global().set_offline(data.config.offline_mode);
+            if !data.config.offline_mode {
+                self.send_offline(OfflineMsg::ResumePinned);
+            }
+        }
+
+        if old_data.config.cache_size_limit != data.config.cache_size_limit {
+            self.send_evictor(EvictorMsg::SetLimit(data.config.cache_size_limit));
+        }
+
+        if old_data.config.audio_quality != data.config.audio_quality {
+            self.send_offline(OfflineMsg::Configure(data.config.playback()));
+        }
+
         let lastfm_changed = old_data.config.lastfm_api_key != data.config.lastfm_api_key
             || old_data.config.lastfm_api_secret != data.config.lastfm_api_secret
             || old_data.config.lastfm_session_key != data.config.lastfm_session_key
@@ -644,6 +1132,13 @@ where
     }
 }
 
+/// IDs of all tracks of an offline collection.  The track lists are served
+/// from the web API cache, so this also works in offline mode.
+fn collection_items(collection: &OfflineCollection) -> Result<Vec<ItemId>, Error> {
+    let tracks = WebApi::global().get_offline_collection_tracks(collection)?;
+    Ok(tracks.iter().map(|track| track.id.0).collect())
+}
+
 // This uses the current system time to generate a random lowercase string of a given length.
 fn random_lowercase_string(len: usize) -> String {
     let now = SystemTime::now()
@@ -1,25 +1,39 @@
 use std::{
     env,
     fs::{self, File, OpenOptions},
-    io::{BufReader, BufWriter},
+    io::{self, BufReader, BufWriter},
+    iter,
     path::{Path, PathBuf},
     sync::Arc,
+    thread,
 };
 
 #[cfg(target_family = "unix")]
 use std::os::unix::fs::OpenOptionsExt;
 
-use druid::{Data, Lens, Size, im::Vector};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use druid::{
+    im::{HashMap, Vector},
+    Data, Lens, Size,
+};
+use once_cell::sync::OnceCell;
 use platform_dirs::AppDirs;
 use psst_core::{
-    cache::{mkdir_if_not_exists, CacheHandle},
+    audio::{
+        equalizer::{BandKind, EqualizerBand as CoreBand, EqualizerPreset, EqualizerSettings},
+        limiter::PeakLimiting,
+        mix::ChannelMixSettings,
+        output::OutputConfig,
+        resample::ResamplingQuality as CoreResamplingQuality,
+    },
+    cache::{mkdir_if_not_exists, CacheHandle, CacheStats},
     connection::Credentials,
-    player::PlaybackConfig,
+    player::{persist::SavedPlayback, PlaybackConfig},
     session::{SessionConfig, SessionConnection},
 };
 use serde::{Deserialize, Serialize};
 
-use super::{Nav, Promise, QueueBehavior, SliderScrollScale};
+use super::{Nav, OfflineCollection, Promise, QueueBehavior, SliderScrollScale};
 use crate::ui::theme;
 
 #[derive(Clone, Debug, Data, Lens)]
@@ -28,6 +42,8 @@ pub struct Preferences {
     #[data(ignore)]
     pub cache: Option<CacheHandle>,
     pub cache_size: Promise<u64, (), ()>,
+    /// Result of the last eviction pass of the core cache.
+    pub cache_stats: Option<Arc<CacheStats>>,
     pub auth: Authentication,
     pub lastfm_auth_result: Option<String>,
 }
@@ -104,8 +120,18 @@ impl Authentication {
 
 const APP_NAME: &str = "Psst";
 const CONFIG_FILENAME: &str = "config.json";
+const PLAYBACK_FILENAME: &str = "playback.json";
 const PROXY_ENV_VAR: &str = "SOCKS_PROXY";
 
+static PLAYBACK_SAVER: OnceCell<Sender<PlaybackSave>> = OnceCell::new();
+
+/// Message to the thread writing the playback snapshots.
+enum PlaybackSave {
+    Snapshot(Option<SavedPlayback>),
+    /// Reply once the snapshots sent before have been written.
+    Flush(Sender<()>),
+}
+
 #[derive(Clone, Debug, Data, Lens, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
@@ -128,6 +154,38 @@ pub struct Config {
     pub lastfm_api_secret: Option<String>,
     pub lastfm_enable: bool,
     pub favorite_playlists: Vector<Arc<str>>,
+    pub equalizer: Equalizer,
+    pub offline_mode: bool,
+    pub offline_collections: Vector<OfflineCollection>,
+    /// Maximum size of the core cache in bytes, `None` for no limit.
+    pub cache_size_limit: Option<u64>,
+    /// Playback speed of podcast shows, by show ID.
+    pub show_speeds: HashMap<Arc<str>, f64>,
+    /// Shorten long pauses in podcast episodes.
+    pub trim_silence: bool,
+    /// Shows that differ from `trim_silence`, by show ID.
+    pub show_trim_silence: HashMap<Arc<str>, bool>,
+    /// Name of the audio output device, `None` for the system default.
+    pub output_device: Option<String>,
+    /// Dither when the output device takes integer samples.
+    pub dither: bool,
+    /// Play at the sample rate of the track, if the output device supports it.
+    pub bit_perfect: bool,
+    /// Quality of the resampling, when the device runs at another sample rate.
+    pub resampling_quality: ResamplingQuality,
+    /// Play the same downmix on every speaker.
+    pub mono_audio: bool,
+    /// Left/right balance, from -1.0 for only left to 1.0 for only right.
+    pub balance: f64,
+    /// Gain added to the normalized level, in dB.
+    pub pregain: f64,
+    /// Keep the true peaks under `limiter_ceiling`, instead of lowering the
+    /// gain of tracks that would clip.
+    pub limit_peaks: bool,
+    pub limiter_ceiling: f64,
+    /// Length of the fade-out before the sleep timer stops the playback, in
+    /// seconds.
+    pub sleep_timer_fade: f64,
 }
 
 impl Default for Config {
@@ -151,6 +209,23 @@ impl Default for Config {
             lastfm_api_secret: None,
             lastfm_enable: false,
             favorite_playlists: Default::default(),
+            equalizer: Default::default(),
+            offline_mode: false,
+            offline_collections: Default::default(),
+            cache_size_limit: None,
+            show_speeds: Default::default(),
+            trim_silence: false,
+            show_trim_silence: Default::default(),
+            output_device: None,
+            dither: true,
+            bit_perfect: false,
+            resampling_quality: Default::default(),
+            mono_audio: false,
+            balance: 0.0,
+            pregain: 3.0,
+            limit_peaks: true,
+            limiter_ceiling: -1.0,
+            sleep_timer_fade: 30.0,
         }
     }
 }
@@ -181,6 +256,84 @@ impl Config {
         Self::config_dir().map(|dir| dir.join(CONFIG_FILENAME))
     }
 
+    /// Location of the queue and play-head saved between runs.
+    pub fn playback_path() -> Option<PathBuf> {
+        Self::config_dir().map(|dir| dir.join(PLAYBACK_FILENAME))
+    }
+
+    /// Save the playback snapshot on the saver thread.  Only the latest of the
+    /// snapshots waiting there is written.
+    pub fn save_playback_in_background(saved: Option<SavedPlayback>) {
+        let _ = Self::playback_saver().send(PlaybackSave::Snapshot(saved));
+    }
+
+    /// Save the playback snapshot, and wait until it is written.
+    pub fn save_playback(saved: Option<SavedPlayback>) {
+        let (done_send, done_recv) = bounded(1);
+        Self::save_playback_in_background(saved);
+        if Self::playback_saver()
+            .send(PlaybackSave::Flush(done_send))
+            .is_ok()
+        {
+            let _ = done_recv.recv();
+        }
+    }
+
+    fn playback_saver() -> &'static Sender<PlaybackSave> {
+        PLAYBACK_SAVER.get_or_init(|| {
+            let (save_send, save_recv) = unbounded();
+            thread::spawn(move || Self::service_playback_saves(save_recv));
+            save_send
+        })
+    }
+
+    fn service_playback_saves(save_recv: Receiver<PlaybackSave>) {
+        while let Ok(first) = save_recv.recv() {
+            let mut snapshot = None;
+            let mut flushed = Vec::new();
+            for save in iter::once(first).chain(save_recv.try_iter()) {
+                match save {
+                    PlaybackSave::Snapshot(saved) => snapshot = Some(saved),
+                    PlaybackSave::Flush(done) => flushed.push(done),
+                }
+            }
+            if let Some(saved) = snapshot {
+                Self::write_playback(saved.as_ref());
+            }
+            for done in flushed {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    /// Write the playback snapshot, or remove the previous one if there is
+    /// nothing to continue.
+    fn write_playback(saved: Option<&SavedPlayback>) {
+        let path = match Self::playback_path() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = match saved {
+            Some(saved) => saved.save(&path),
+            None => match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            },
+        };
+        if let Err(err) = result {
+            log::error!("failed to save playback: {}", err);
+        }
+    }
+
+    /// Load the playback snapshot of the last run, if any.
+    pub fn load_playback() -> Option<SavedPlayback> {
+        let path = Self::playback_path()?;
+        SavedPlayback::load(&path)
+            .map_err(|err| log::error!("failed to load playback: {}", err))
+            .ok()
+            .flatten()
+    }
+
     pub fn load() -> Option<Config> {
         let path = Self::config_path().expect("Failed to get config path");
         if let Ok(file) = File::open(&path) {
@@ -237,10 +390,37 @@ impl Config {
     pub fn playback(&self) -> PlaybackConfig {
         PlaybackConfig {
             bitrate: self.audio_quality.as_bitrate(),
+            equalizer: self.equalizer.settings(),
+            channel_mix: self.channel_mix(),
+            resampling_quality: self.resampling_quality.to_core(),
+            pregain: self.pregain as f32,
+            limit_peaks: self.peak_limiting(),
             ..PlaybackConfig::default()
         }
     }
 
+    pub fn channel_mix(&self) -> ChannelMixSettings {
+        ChannelMixSettings {
+            mono: self.mono_audio,
+            balance: self.balance as f32,
+        }
+    }
+
+    pub fn peak_limiting(&self) -> Option<PeakLimiting> {
+        self.limit_peaks.then(|| PeakLimiting {
+            ceiling_db: self.limiter_ceiling as f32,
+        })
+    }
+
+    pub fn output(&self) -> OutputConfig {
+        OutputConfig {
+            device: self.output_device.clone(),
+            dither: self.dither,
+            bit_perfect: self.bit_perfect,
+            ..OutputConfig::default()
+        }
+    }
+
     pub fn proxy() -> Option<String> {
         env::var(PROXY_ENV_VAR).ok()
     }
@@ -264,6 +444,18 @@ impl Config {
     pub fn get_favorite_playlists(&self) -> &Vector<Arc<str>> {
         &self.favorite_playlists
     }
+
+    pub fn add_offline_collection(&mut self, collection: OfflineCollection) {
+        if !self.offline_collections.contains(&collection) {
+            self.offline_collections.push_back(collection);
+            self.save();
+        }
+    }
+
+    pub fn remove_offline_collection(&mut self, collection: &OfflineCollection) {
+        self.offline_collections.retain(|c| c != collection);
+        self.save();
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Data, Serialize, Deserialize)]
@@ -289,6 +481,114 @@ impl Default for AudioQuality {
     }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Data, Serialize, Deserialize)]
+pub enum ResamplingQuality {
+    Best,
+    Medium,
+    Fastest,
+    Linear,
+}
+
+impl ResamplingQuality {
+    fn to_core(self) -> CoreResamplingQuality {
+        match self {
+            ResamplingQuality::Best => CoreResamplingQuality::SincBestQuality,
+            ResamplingQuality::Medium => CoreResamplingQuality::SincMediumQuality,
+            ResamplingQuality::Fastest => CoreResamplingQuality::SincFastest,
+            ResamplingQuality::Linear => CoreResamplingQuality::Linear,
+        }
+    }
+}
+
+impl Default for ResamplingQuality {
+    fn default() -> Self {
+        Self::Medium
+    }
+}
+
+#[derive(Clone, Debug, Data, Lens, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Equalizer {
+    pub enabled: bool,
+    pub bands: Vector<EqualizerBand>,
+}
+
+impl Equalizer {
+    pub fn apply_preset(&mut self, preset: EqualizerPreset) {
+        self.bands = preset
+            .bands()
+            .into_iter()
+            .map(EqualizerBand::from)
+            .collect();
+    }
+
+    pub fn settings(&self) -> EqualizerSettings {
+        EqualizerSettings {
+            bands: if self.enabled {
+                self.bands.iter().map(EqualizerBand::to_core).collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+impl Default for Equalizer {
+    fn default() -> Self {
+        let mut equalizer = Self {
+            enabled: false,
+            bands: Vector::new(),
+        };
+        equalizer.apply_preset(EqualizerPreset::Flat);
+        equalizer
+    }
+}
+
+#[derive(Clone, Debug, Data, Lens, Serialize, Deserialize)]
+pub struct EqualizerBand {
+    pub kind: EqualizerBandKind,
+    pub frequency: f64,
+    pub gain: f64,
+    pub q: f64,
+}
+
+impl EqualizerBand {
+    fn to_core(&self) -> CoreBand {
+        CoreBand {
+            kind: match self.kind {
+                EqualizerBandKind::LowShelf => BandKind::LowShelf,
+                EqualizerBandKind::Peaking => BandKind::Peaking,
+                EqualizerBandKind::HighShelf => BandKind::HighShelf,
+            },
+            frequency: self.frequency as f32,
+            gain_db: self.gain as f32,
+            q: self.q as f32,
+        }
+    }
+}
+
+impl From<CoreBand> for EqualizerBand {
+    fn from(band: CoreBand) -> Self {
+        Self {
+            kind: match band.kind {
+                BandKind::LowShelf => EqualizerBandKind::LowShelf,
+                BandKind::Peaking => EqualizerBandKind::Peaking,
+                BandKind::HighShelf => EqualizerBandKind::HighShelf,
+            },
+            frequency: band.frequency.into(),
+            gain: band.gain_db.into(),
+            q: band.q.into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Data, Serialize, Deserialize)]
+pub enum EqualizerBandKind {
+    LowShelf,
+    Peaking,
+    HighShelf,
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Data, Serialize, Deserialize)]
 pub enum Theme {
     Light,
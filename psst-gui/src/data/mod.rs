@@ -5,6 +5,7 @@ mod ctx;
 mod find;
 mod id;
 mod nav;
+mod offline;
 mod playback;
 mod playlist;
 mod promise;
@@ -32,20 +33,24 @@ use druid::{
     im::{HashSet, Vector},
     Data, Lens,
 };
-use psst_core::{item_id::ItemId, session::SessionService};
+use psst_core::{item_id::ItemId, player::persist::SavedPlayback, session::SessionService};
 
 pub use crate::data::{
     album::{Album, AlbumDetail, AlbumLink, AlbumType},
     artist::{
         Artist, ArtistAlbums, ArtistDetail, ArtistInfo, ArtistLink, ArtistStats, ArtistTracks,
     },
-    config::{AudioQuality, Authentication, Config, Preferences, PreferencesTab, Theme},
+    config::{
+        AudioQuality, Authentication, Config, Equalizer, EqualizerBand, Preferences,
+        PreferencesTab, ResamplingQuality, Theme,
+    },
     ctx::Ctx,
     find::{FindQuery, Finder, MatchFindQuery},
     nav::{Nav, Route, SpotifyUrl},
+    offline::{Offline, OfflineCollection, OfflineProgress},
     playback::{
         NowPlaying, Playable, PlayableMatcher, Playback, PlaybackOrigin, PlaybackPayload,
-        PlaybackState, QueueBehavior, QueueEntry,
+        PlaybackState, QueueBehavior, QueueEntry, RestoredPlayback, SleepTimer,
     },
     playlist::{
         Playlist, PlaylistAddTrack, PlaylistDetail, PlaylistLink, PlaylistRemoveTrack,
@@ -57,7 +62,9 @@ pub use crate::data::{
         RecommendationsRequest, Toggled,
     },
     search::{Search, SearchResults, SearchTopic},
-    show::{Episode, EpisodeId, EpisodeLink, Show, ShowDetail, ShowEpisodes, ShowLink},
+    show::{
+        Episode, EpisodeId, EpisodeLink, ResumePoint, Show, ShowDetail, ShowEpisodes, ShowLink,
+    },
     slider_scroll_scale::SliderScrollScale,
     track::{AudioAnalysis, Track, TrackId, TrackLines},
     user::{PublicUser, UserProfile},
@@ -108,6 +115,10 @@ impl AppState {
             library: Arc::clone(&library),
             show_track_cover: config.show_track_cover,
             nav: Nav::Home,
+            offline: Offline {
+                collections: config.offline_collections.clone(),
+                downloads: Default::default(),
+            },
         });
         let playback = Playback {
             state: PlaybackState::Stopped,
@@ -115,6 +126,11 @@ impl AppState {
             queue_behavior: config.queue_behavior,
             queue: Vector::new(),
             volume: config.volume,
+            speed: 1.0,
+            trim_silence: false,
+            time_saved: Duration::ZERO,
+            gain_reduction: 0.0,
+            sleep_timer: None,
         };
         Self {
             session: SessionService::empty(),
@@ -125,6 +141,7 @@ impl AppState {
                 active: PreferencesTab::General,
                 cache: None,
                 cache_size: Promise::Empty,
+                cache_stats: None,
                 auth: Authentication::new(),
                 lastfm_auth_result: None,
             },
@@ -245,6 +262,7 @@ impl AppState {
             item,
             origin,
             progress: Duration::default(),
+            looped: None,
             library: Arc::clone(&self.library),
         });
     }
@@ -252,10 +270,31 @@ impl AppState {
     pub fn start_playback(&mut self, item: Playable, origin: PlaybackOrigin, progress: Duration) {
         self.common_ctx_mut().now_playing.replace(item.clone());
         self.playback.state = PlaybackState::Playing;
+        self.playback.speed = match &item {
+            Playable::Episode(episode) => self
+                .config
+                .show_speeds
+                .get(&episode.show.id)
+                .copied()
+                .unwrap_or(1.0),
+            Playable::Track(_) => 1.0,
+        };
+        self.playback.trim_silence = match &item {
+            Playable::Episode(episode) => self
+                .config
+                .show_trim_silence
+                .get(&episode.show.id)
+                .copied()
+                .unwrap_or(self.config.trim_silence),
+            Playable::Track(_) => false,
+        };
+        self.playback.time_saved = Duration::ZERO;
+        self.playback.gain_reduction = 0.0;
         self.playback.now_playing.replace(NowPlaying {
             item,
             origin,
             progress,
+            looped: None,
             library: Arc::clone(&self.library),
         });
     }
@@ -284,11 +323,106 @@ impl AppState {
         self.common_ctx_mut().now_playing.take();
     }
 
+    /// Change the speed of the playing episode, and remember it for its show.
+    pub fn set_speed(&mut self, speed: f64) {
+        let episode = match self.playback.now_playing.as_ref().map(|np| &np.item) {
+            Some(Playable::Episode(episode)) => episode,
+            _ => return,
+        };
+        if speed == 1.0 {
+            self.config.show_speeds.remove(&episode.show.id);
+        } else {
+            self.config
+                .show_speeds
+                .insert(episode.show.id.clone(), speed);
+        }
+        self.config.save();
+        self.playback.speed = speed;
+    }
+
+    /// Overlay the locally saved resume points onto `episodes`.
+    pub fn apply_resume_points(&self, episodes: &mut Vector<Arc<Episode>>) {
+        let cache = match &self.preferences.cache {
+            Some(cache) => cache,
+            None => return,
+        };
+        for episode in episodes.iter_mut() {
+            if let Some(point) = cache.get_resume_point(episode.id.0) {
+                Arc::make_mut(episode).resume_point = Some(point.into());
+            }
+        }
+    }
+
+    pub fn update_resume_point(&mut self, item_id: ItemId, point: ResumePoint) {
+        if let Some(episodes) = self.show_detail.episodes.resolved_mut() {
+            for episode in episodes.episodes.iter_mut() {
+                if episode.id.0 == item_id {
+                    Arc::make_mut(episode).resume_point = Some(point.clone());
+                }
+            }
+        }
+    }
+
+    /// Turn the silence trimming of the playing episode on or off, and
+    /// remember it for its show.
+    pub fn set_trim_silence(&mut self, trim_silence: bool) {
+        let episode = match self.playback.now_playing.as_ref().map(|np| &np.item) {
+            Some(Playable::Episode(episode)) => episode,
+            _ => return,
+        };
+        if trim_silence == self.config.trim_silence {
+            self.config.show_trim_silence.remove(&episode.show.id);
+        } else {
+            self.config
+                .show_trim_silence
+                .insert(episode.show.id.clone(), trim_silence);
+        }
+        self.config.save();
+        self.playback.trim_silence = trim_silence;
+    }
+
     pub fn set_queue_behavior(&mut self, queue_behavior: QueueBehavior) {
         self.playback.queue_behavior = queue_behavior;
         self.config.queue_behavior = queue_behavior;
         self.config.save();
     }
+
+    /// Snapshot of the queue and play-head, or `None` if nothing is playing.
+    pub fn saved_playback(&self) -> Option<SavedPlayback> {
+        let now_playing = self.playback.now_playing.as_ref()?;
+        let current = now_playing.item.id();
+        let entries = if self
+            .playback
+            .queue
+            .iter()
+            .any(|entry| entry.item.id() == current)
+        {
+            self.playback.queue.iter().cloned().collect()
+        } else {
+            // Items added by the user are not part of the playback queue, keep at
+            // least the current one.
+            vec![QueueEntry {
+                item: now_playing.item.clone(),
+                origin: now_playing.origin.clone(),
+            }]
+        };
+        let mut items = Vec::with_capacity(entries.len());
+        let mut position = 0;
+        for entry in &entries {
+            if let Some(saved) = entry.to_saved() {
+                if entry.item.id() == current {
+                    position = items.len();
+                }
+                items.push(saved);
+            }
+        }
+        Some(SavedPlayback {
+            items,
+            position,
+            progress_ms: now_playing.progress.as_millis() as u64,
+            behavior: self.playback.queue_behavior.into(),
+        })
+    }
 }
 
 impl AppState {
@@ -296,6 +430,19 @@ impl AppState {
         Arc::make_mut(&mut self.common_ctx)
     }
 
+    pub fn add_offline_collection(&mut self, collection: OfflineCollection) {
+        self.config.add_offline_collection(collection);
+        self.common_ctx_mut().offline.collections = self.config.offline_collections.clone();
+    }
+
+    pub fn remove_offline_collection(&mut self, collection: &OfflineCollection) {
+        self.config.remove_offline_collection(collection);
+        let collections = self.config.offline_collections.clone();
+        let offline = &mut self.common_ctx_mut().offline;
+        offline.collections = collections;
+        offline.downloads.remove(collection);
+    }
+
     pub fn with_library_mut(&mut self, func: impl FnOnce(&mut Library)) {
         func(Arc::make_mut(&mut self.library));
         self.library_updated();
@@ -692,6 +839,7 @@ pub struct CommonCtx {
     pub library: Arc<Library>,
     pub show_track_cover: bool,
     pub nav: Nav,
+    pub offline: Offline,
 }
 
 impl CommonCtx {
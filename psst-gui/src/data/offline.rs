@@ -0,0 +1,123 @@
+use std::{
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    mem,
+    sync::Arc,
+};
+
+use druid::{
+    im::{HashMap, Vector},
+    Data, Lens,
+};
+use psst_core::item_id::ItemId;
+use serde::{Deserialize, Serialize};
+
+use crate::data::{AlbumLink, PlaylistLink};
+
+/// A set of tracks the user made available offline.
+#[derive(Clone, Debug, Data, Serialize, Deserialize)]
+pub enum OfflineCollection {
+    Album(AlbumLink),
+    Playlist(PlaylistLink),
+    SavedTracks,
+}
+
+impl OfflineCollection {
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Album(link) => &link.id,
+            Self::Playlist(link) => &link.id,
+            Self::SavedTracks => "saved-tracks",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Album(link) => &link.name,
+            Self::Playlist(link) => &link.name,
+            Self::SavedTracks => "Saved Tracks",
+        }
+    }
+}
+
+// Collections are identified by their ID only, names and images of the links
+// can change over time.
+impl PartialEq for OfflineCollection {
+    fn eq(&self, other: &Self) -> bool {
+        mem::discriminant(self) == mem::discriminant(other) && self.id() == other.id()
+    }
+}
+
+impl Eq for OfflineCollection {}
+
+impl Hash for OfflineCollection {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        self.id().hash(state);
+    }
+}
+
+#[derive(Clone, Debug, Default, Data, Lens)]
+pub struct Offline {
+    /// Collections made available offline, mirrors the config.
+    pub collections: Vector<OfflineCollection>,
+    /// Collections that are still being downloaded.
+    pub downloads: HashMap<OfflineCollection, OfflineProgress>,
+}
+
+impl Offline {
+    pub fn contains(&self, collection: &OfflineCollection) -> bool {
+        self.collections.contains(collection)
+    }
+
+    pub fn progress(&self, collection: &OfflineCollection) -> Option<&OfflineProgress> {
+        self.downloads.get(collection)
+    }
+
+    pub fn start_download(&mut self, collection: OfflineCollection, items: &[ItemId]) {
+        self.downloads.insert(
+            collection,
+            OfflineProgress {
+                total: items.len(),
+                downloaded: 0,
+                failed: 0,
+                remaining: Arc::new(items.iter().copied().collect()),
+            },
+        );
+    }
+
+    /// Account for a finished download of `item_id`.  Returns the collections
+    /// that have been completely downloaded with this item.
+    pub fn finish_item(
+        &mut self,
+        item_id: ItemId,
+        success: bool,
+    ) -> Vec<(OfflineCollection, OfflineProgress)> {
+        let mut finished = Vec::new();
+        for (collection, progress) in self.downloads.iter_mut() {
+            if Arc::make_mut(&mut progress.remaining).remove(&item_id) {
+                if success {
+                    progress.downloaded += 1;
+                } else {
+                    progress.failed += 1;
+                }
+                if progress.remaining.is_empty() {
+                    finished.push((collection.clone(), progress.clone()));
+                }
+            }
+        }
+        for (collection, _) in &finished {
+            self.downloads.remove(collection);
+        }
+        finished
+    }
+}
+
+#[derive(Clone, Debug, Data)]
+pub struct OfflineProgress {
+    pub total: usize,
+    pub downloaded: usize,
+    pub failed: usize,
+    #[data(ignore)]
+    remaining: Arc<HashSet<ItemId>>,
+}
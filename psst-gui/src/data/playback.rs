@@ -2,7 +2,11 @@ use std::{fmt, sync::Arc, time::Duration};
 
 use druid::{im::Vector, Data, Lens};
 use druid_enums::Matcher;
-use psst_core::item_id::ItemId;
+use psst_core::{
+    audio::normalize::NormalizationLevel,
+    item_id::{ItemId, ItemIdType},
+    player::{item::PlaybackItem, persist::SavedItem, queue, shuffle::ItemGroup, sleep::SleepMode},
+};
 use serde::{Deserialize, Serialize};
 
 use super::{
@@ -17,15 +21,82 @@ pub struct Playback {
     pub queue_behavior: QueueBehavior,
     pub queue: Vector<QueueEntry>,
     pub volume: f64,
+    /// Playback speed, 1.0 being normal.  Only episodes are played at other
+    /// speeds.
+    pub speed: f64,
+    /// Whether long pauses of the playing episode are shortened.
+    pub trim_silence: bool,
+    /// Time saved by shortening the pauses of the playing episode.
+    pub time_saved: Duration,
+    /// Level the limiter takes off the loudest peaks at the moment, in dB.
+    pub gain_reduction: f64,
+    pub sleep_timer: Option<SleepTimer>,
+}
+
+#[derive(Clone, Debug, Data)]
+pub struct SleepTimer {
+    #[data(same_fn = "PartialEq::eq")]
+    pub mode: SleepMode,
+    /// Time until the playback stops, once it is known.
+    pub remaining: Option<Duration>,
 }
 
-#[derive(Clone, Debug, Data, Lens)]
+#[derive(Clone, Debug, Data, Lens, Serialize, Deserialize)]
 pub struct QueueEntry {
     pub item: Playable,
     pub origin: PlaybackOrigin,
 }
 
-#[derive(Clone, Debug, Matcher)]
+impl QueueEntry {
+    pub fn playback_item(&self) -> PlaybackItem {
+        PlaybackItem {
+            item_id: self.item.id(),
+            norm_level: match self.origin {
+                PlaybackOrigin::Album(_) => NormalizationLevel::Album,
+                _ => NormalizationLevel::Track,
+            },
+        }
+    }
+
+    /// Artist and album of the entry, for the smart shuffle.
+    pub fn item_group(&self) -> ItemGroup {
+        let id = |id: &str| ItemId::from_base62(id, ItemIdType::Unknown);
+        match &self.item {
+            Playable::Track(track) => ItemGroup {
+                artist: track.artists.front().and_then(|artist| id(&artist.id)),
+                album: track.album.as_ref().and_then(|album| id(&album.id)),
+            },
+            Playable::Episode(_) => ItemGroup::default(),
+        }
+    }
+
+    /// Only the origin is stored along, the item itself is fetched again when
+    /// restoring it.
+    pub fn to_saved(&self) -> Option<SavedItem> {
+        SavedItem::new(
+            self.playback_item(),
+            serde_json::to_value(&self.origin).ok(),
+        )
+    }
+
+    pub fn saved_origin(saved: &SavedItem) -> Option<PlaybackOrigin> {
+        let metadata = saved.metadata.as_ref()?;
+        // Earlier snapshots stored the whole entry.
+        let origin = metadata.get("origin").unwrap_or(metadata);
+        PlaybackOrigin::deserialize(origin).ok()
+    }
+}
+
+/// Queue and play-head of the last run, with the items fetched again.
+pub struct RestoredPlayback {
+    pub queue: Vector<QueueEntry>,
+    /// Index of the current entry in `queue`.
+    pub position: usize,
+    pub progress: Duration,
+    pub behavior: QueueBehavior,
+}
+
+#[derive(Clone, Debug, Matcher, Serialize, Deserialize)]
 pub enum Playable {
     Track(Arc<Track>),
     Episode(Arc<Episode>),
@@ -77,10 +148,35 @@ pub enum QueueBehavior {
     #[default]
     Sequential,
     Random,
+    SmartShuffle,
     LoopTrack,
     LoopAll,
 }
 
+impl From<QueueBehavior> for queue::QueueBehavior {
+    fn from(behavior: QueueBehavior) -> Self {
+        match behavior {
+            QueueBehavior::Sequential => Self::Sequential,
+            QueueBehavior::Random => Self::Random,
+            QueueBehavior::SmartShuffle => Self::SmartShuffle,
+            QueueBehavior::LoopTrack => Self::LoopTrack,
+            QueueBehavior::LoopAll => Self::LoopAll,
+        }
+    }
+}
+
+impl From<queue::QueueBehavior> for QueueBehavior {
+    fn from(behavior: queue::QueueBehavior) -> Self {
+        match behavior {
+            queue::QueueBehavior::Sequential => Self::Sequential,
+            queue::QueueBehavior::Random => Self::Random,
+            queue::QueueBehavior::SmartShuffle => Self::SmartShuffle,
+            queue::QueueBehavior::LoopTrack => Self::LoopTrack,
+            queue::QueueBehavior::LoopAll => Self::LoopAll,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Data, Eq, PartialEq)]
 pub enum PlaybackState {
     Loading,
@@ -94,6 +190,8 @@ pub struct NowPlaying {
     pub item: Playable,
     pub origin: PlaybackOrigin,
     pub progress: Duration,
+    /// Section of the item that is being looped.
+    pub looped: Option<(Duration, Duration)>,
 
     // Although keeping a ref to the `Library` here is a bit of a hack, it dramatically
     // simplifies displaying the track context menu in the playback bar.
@@ -140,7 +238,7 @@ impl NowPlaying {
     }
 }
 
-#[derive(Clone, Debug, Data)]
+#[derive(Clone, Debug, Data, Serialize, Deserialize)]
 pub enum PlaybackOrigin {
     Home,
     Library,
@@ -1,7 +1,10 @@
 use std::{convert::TryFrom, sync::Arc, time::Duration};
 
 use druid::{im::Vector, Data, Lens};
-use psst_core::item_id::{ItemId, ItemIdType};
+use psst_core::{
+    item_id::{ItemId, ItemIdType},
+    player::resume,
+};
 use serde::{Deserialize, Serialize};
 use time::{macros::format_description, Date};
 
@@ -112,6 +115,15 @@ pub struct ResumePoint {
     pub resume_position: Duration,
 }
 
+impl From<resume::ResumePoint> for ResumePoint {
+    fn from(point: resume::ResumePoint) -> Self {
+        Self {
+            fully_played: point.finished,
+            resume_position: point.position(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Hash, Deserialize, Serialize)]
 #[serde(try_from = "String")]
 #[serde(into = "String")]
@@ -14,29 +14,37 @@ use time::{Date, Month};
 #[derive(Clone, Data, Lens)]
 pub struct Cached<T: Data> {
     pub data: T,
+    /// When the data was fetched from the API.
     #[data(ignore)]
-    pub cached_at: Option<SystemTime>,
+    pub fetched_at: SystemTime,
+    /// The data outlived its time to live and is being refreshed in the
+    /// background.
+    #[data(ignore)]
+    pub stale: bool,
 }
 
 impl<T: Data> Cached<T> {
-    pub fn new(data: T, at: SystemTime) -> Self {
+    pub fn new(data: T, fetched_at: SystemTime, stale: bool) -> Self {
         Self {
             data,
-            cached_at: Some(at),
+            fetched_at,
+            stale,
         }
     }
 
     pub fn fresh(data: T) -> Self {
         Self {
             data,
-            cached_at: None,
+            fetched_at: SystemTime::now(),
+            stale: false,
         }
     }
 
     pub fn map<U: Data>(self, f: impl Fn(T) -> U) -> Cached<U> {
         Cached {
             data: f(self.data),
-            cached_at: self.cached_at,
+            fetched_at: self.fetched_at,
+            stale: self.stale,
         }
     }
 }
@@ -271,6 +271,7 @@ impl AppDelegate<AppState> for Delegate {
         if self.main_window == Some(id) {
             data.config.volume = data.playback.volume;
             data.config.save();
+            Config::save_playback(data.saved_playback());
             ctx.submit_command(commands::CLOSE_ALL_WINDOWS);
             ctx.submit_command(commands::QUIT_APP);
         }
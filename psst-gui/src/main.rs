@@ -57,6 +57,7 @@ fn main() {
         paginated_limit,
     )
     .install_as_global();
+    WebApi::global().set_offline(state.config.offline_mode);
 
     let delegate;
     let launcher;
@@ -8,15 +8,15 @@ use druid::{
 use crate::{
     cmd,
     data::{
-        Album, AlbumDetail, AlbumLink, AppState, ArtistLink, Cached, Ctx, Library, Nav, Playable,
-        PlaybackOrigin, WithCtx,
+        Album, AlbumDetail, AlbumLink, AppState, ArtistLink, Cached, CommonCtx, Ctx, Nav,
+        OfflineCollection, Playable, PlaybackOrigin, WithCtx,
     },
     ui::playable::PlayableIter,
     webapi::WebApi,
     widget::{icons, Async, MyWidgetExt, RemoteImage},
 };
 
-use super::{artist, library, playable, theme, track, utils};
+use super::{artist, library, offline, playable, theme, track, utils};
 
 pub const LOAD_DETAIL: Selector<AlbumLink> = Selector::new("app.album.load-detail");
 
@@ -186,10 +186,11 @@ pub fn album_widget(horizontal: bool) -> impl Widget<WithCtx<Arc<Album>>> {
 }
 
 fn album_ctx_menu(album: &WithCtx<Arc<Album>>) -> Menu<AppState> {
-    album_menu(&album.data, &album.ctx.library)
+    album_menu(&album.data, &album.ctx)
 }
 
-fn album_menu(album: &Arc<Album>, library: &Arc<Library>) -> Menu<AppState> {
+fn album_menu(album: &Arc<Album>, ctx: &CommonCtx) -> Menu<AppState> {
+    let library = &ctx.library;
     let mut menu = Menu::empty();
 
     for artist_link in &album.artists {
@@ -233,6 +234,11 @@ fn album_menu(album: &Arc<Album>, library: &Arc<Library>) -> Menu<AppState> {
         );
     }
 
+    menu = menu.entry(offline::menu_item(
+        OfflineCollection::Album(album.link()),
+        &ctx.offline,
+    ));
+
     menu
 }
 
@@ -45,10 +45,24 @@ pub fn playable_widget() -> impl Widget<PlayRow<Arc<Episode>>> {
         .with_text_color(theme::PLACEHOLDER_COLOR)
         .lens(PlayRow::item);
 
+    let progress = Label::<Arc<Episode>>::dynamic(|episode, _| match &episode.resume_point {
+        Some(point) if point.fully_played => "Played".to_string(),
+        Some(point) if !point.resume_position.is_zero() => {
+            let left = episode.duration.saturating_sub(point.resume_position);
+            format!("{} left", utils::as_human(left))
+        }
+        _ => String::new(),
+    })
+    .with_text_size(theme::TEXT_SIZE_SMALL)
+    .with_text_color(theme::GREY_300)
+    .lens(PlayRow::item);
+
     let top_row = Flex::row()
         .cross_axis_alignment(CrossAxisAlignment::Center)
         .with_flex_child(FillBetween::new(release, is_playing), 1.0)
         .with_default_spacer()
+        .with_child(progress)
+        .with_default_spacer()
         .with_child(duration);
 
     let content = Flex::row()
@@ -7,13 +7,13 @@ use crate::{
         AfterDelay, AlertCleanupController, NavController, SessionController, SortController,
     },
     data::{
-        config::SortOrder, Alert, AlertStyle, AppState, Config, Nav, Playable, Playback, Route,
-        ALERT_DURATION,
+        config::SortOrder, Alert, AlertStyle, AppState, Config, Nav, OfflineCollection, Playable,
+        Playback, Route, ALERT_DURATION,
     },
     webapi::WebApi,
     widget::{
         icons, icons::SvgIcon, Border, Empty, MyWidgetExt, Overlay, RemoteImage, ThemeScope,
-        ViewDispatcher,
+        ViewDispatcher, Visualizer,
     },
 };
 use credits::TrackCredits;
@@ -37,6 +37,7 @@ pub mod home;
 pub mod library;
 pub mod lyrics;
 pub mod menu;
+pub mod offline;
 pub mod playable;
 pub mod playback;
 pub mod playlist;
@@ -198,7 +199,7 @@ impl<W: Widget<AppState>> Controller<AppState, W> for ArtworkController {
 }
 
 pub fn artwork_widget() -> impl Widget<AppState> {
-    RemoteImage::new(utils::placeholder_widget(), move |data: &AppState, _| {
+    let artwork = RemoteImage::new(utils::placeholder_widget(), move |data: &AppState, _| {
         data.playback
             .now_playing
             .as_ref()
@@ -206,8 +207,20 @@ pub fn artwork_widget() -> impl Widget<AppState> {
             .map(|url| url.into())
     })
     .expand()
-    .background(theme::BACKGROUND_DARK)
-    .controller(ArtworkController)
+    .background(theme::BACKGROUND_DARK);
+
+    let visualizer = Flex::column()
+        .must_fill_main_axis(true)
+        .with_flex_spacer(1.0)
+        .with_child(
+            Visualizer::new()
+                .expand_width()
+                .fix_height(theme::grid(6.0))
+                .padding(theme::grid(2.0))
+                .background(Color::BLACK.with_alpha(0.4)),
+        );
+
+    Overlay::bottom(artwork, visualizer).controller(ArtworkController)
 }
 
 fn root_widget() -> impl Widget<AppState> {
@@ -397,7 +410,14 @@ fn sidebar_menu_widget() -> impl Widget<AppState> {
     Flex::column()
         .with_default_spacer()
         .with_child(sidebar_link_widget("Home", Nav::Home))
-        .with_child(sidebar_link_widget("Tracks", Nav::SavedTracks))
+        .with_child(
+            sidebar_link_widget("Tracks", Nav::SavedTracks).context_menu(|data: &AppState| {
+                Menu::empty().entry(offline::menu_item(
+                    OfflineCollection::SavedTracks,
+                    &data.common_ctx.offline,
+                ))
+            }),
+        )
         .with_child(sidebar_link_widget("Albums", Nav::SavedAlbums))
         .with_child(sidebar_link_widget("Podcasts", Nav::SavedShows))
         .with_child(search::input_widget().padding((theme::grid(1.0), theme::grid(1.0))))
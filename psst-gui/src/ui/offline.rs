@@ -0,0 +1,37 @@
+use druid::{LocalizedString, MenuItem, Selector};
+use psst_core::item_id::ItemId;
+
+use crate::data::{AppState, Offline, OfflineCollection};
+
+pub const MAKE_AVAILABLE_OFFLINE: Selector<OfflineCollection> =
+    Selector::new("app.offline.make-available");
+pub const REMOVE_FROM_OFFLINE: Selector<OfflineCollection> = Selector::new("app.offline.remove");
+
+// Sent from the background threads.
+pub const TRACKS_LOADED: Selector<(OfflineCollection, Result<Vec<ItemId>, String>)> =
+    Selector::new("app.offline.tracks-loaded");
+pub const ITEM_DOWNLOADED: Selector<(ItemId, bool)> = Selector::new("app.offline.item-downloaded");
+
+pub fn menu_item(collection: OfflineCollection, offline: &Offline) -> MenuItem<AppState> {
+    if let Some(progress) = offline.progress(&collection) {
+        MenuItem::new(
+            LocalizedString::new("menu-item-cancel-download").with_placeholder(format!(
+                "Cancel Download ({} of {} Tracks Done)",
+                progress.downloaded + progress.failed,
+                progress.total
+            )),
+        )
+        .command(REMOVE_FROM_OFFLINE.with(collection))
+    } else if offline.contains(&collection) {
+        MenuItem::new(
+            LocalizedString::new("menu-item-remove-download").with_placeholder("Remove Download"),
+        )
+        .command(REMOVE_FROM_OFFLINE.with(collection))
+    } else {
+        MenuItem::new(
+            LocalizedString::new("menu-item-make-available-offline")
+                .with_placeholder("Make Available Offline"),
+        )
+        .command(MAKE_AVAILABLE_OFFLINE.with(collection))
+    }
+}
@@ -4,21 +4,22 @@ use druid::{
     kurbo::{Affine, BezPath},
     widget::{CrossAxisAlignment, Either, Flex, Label, LineBreaking, Spinner, ViewSwitcher},
     BoxConstraints, Cursor, Data, Env, Event, EventCtx, LayoutCtx, LensExt, LifeCycle,
-    LifeCycleCtx, MouseButton, PaintCtx, Point, Rect, RenderContext, Size, UpdateCtx, Widget,
-    WidgetExt, WidgetPod,
+    LifeCycleCtx, Menu, MenuItem, MouseButton, PaintCtx, Point, Rect, RenderContext, Size,
+    UpdateCtx, Widget, WidgetExt, WidgetPod,
 };
 use itertools::Itertools;
+use psst_core::player::sleep::SleepMode;
 
 use crate::{
     cmd::{self, ADD_TO_QUEUE, SHOW_ARTWORK, TOGGLE_LYRICS},
     controller::PlaybackController,
     data::{
         AppState, AudioAnalysis, Episode, NowPlaying, Playable, PlayableMatcher, Playback,
-        PlaybackOrigin, PlaybackState, QueueBehavior, ShowLink, Track,
+        PlaybackOrigin, PlaybackState, QueueBehavior, ShowLink, SleepTimer, Track,
     },
     widget::{
         icons::{self, SvgIcon},
-        Empty, Maybe, MyWidgetExt, RemoteImage,
+        Empty, Maybe, MyWidgetExt, RemoteImage, Visualizer,
     },
 };
 
@@ -122,6 +123,7 @@ fn playing_item_widget() -> impl Widget<NowPlaying> {
             ),
             1.0,
         )
+        .with_child(Visualizer::new().padding_left(theme::grid(1.0)))
         .with_child(ViewSwitcher::new(
             |now_playing: &NowPlaying, _| {
                 now_playing.item.track().is_some() && now_playing.library.saved_tracks.is_resolved()
@@ -204,6 +206,11 @@ fn player_widget() -> impl Widget<Playback> {
         .with_default_spacer()
         .with_child(queue_behavior_widget())
         .with_default_spacer()
+        .with_child(speed_widget())
+        .with_child(trim_silence_widget())
+        .with_child(limiter_widget())
+        .with_child(sleep_timer_widget())
+        .with_default_spacer()
         .with_child(Maybe::or_empty(durations_widget).lens(Playback::now_playing))
         .with_child(
             small_button_widget(&icons::MUSIC_NOTE)
@@ -253,7 +260,14 @@ fn queue_behavior_widget() -> impl Widget<Playback> {
     ViewSwitcher::new(
         |playback: &Playback, _| playback.queue_behavior,
         |behavior, _, _| {
-            faded_button_widget(queue_behavior_icon(behavior))
+            // Both shuffles share the icon, the smart one is highlighted.
+            let button = match behavior {
+                QueueBehavior::SmartShuffle => {
+                    small_button_widget(queue_behavior_icon(behavior)).boxed()
+                }
+                _ => faded_button_widget(queue_behavior_icon(behavior)).boxed(),
+            };
+            button
                 .on_left_click(|ctx, _, playback: &mut Playback, _| {
                     ctx.submit_command(
                         cmd::PLAY_QUEUE_BEHAVIOR
@@ -268,7 +282,8 @@ fn queue_behavior_widget() -> impl Widget<Playback> {
 fn cycle_queue_behavior(qb: &QueueBehavior) -> QueueBehavior {
     match qb {
         QueueBehavior::Sequential => QueueBehavior::Random,
-        QueueBehavior::Random => QueueBehavior::LoopTrack,
+        QueueBehavior::Random => QueueBehavior::SmartShuffle,
+        QueueBehavior::SmartShuffle => QueueBehavior::LoopTrack,
         QueueBehavior::LoopTrack => QueueBehavior::LoopAll,
         QueueBehavior::LoopAll => QueueBehavior::Sequential,
     }
@@ -277,12 +292,148 @@ fn cycle_queue_behavior(qb: &QueueBehavior) -> QueueBehavior {
 fn queue_behavior_icon(qb: &QueueBehavior) -> &'static SvgIcon {
     match qb {
         QueueBehavior::Sequential => &icons::PLAY_SEQUENTIAL,
-        QueueBehavior::Random => &icons::PLAY_SHUFFLE,
+        QueueBehavior::Random | QueueBehavior::SmartShuffle => &icons::PLAY_SHUFFLE,
         QueueBehavior::LoopTrack => &icons::PLAY_LOOP_TRACK,
         QueueBehavior::LoopAll => &icons::PLAY_LOOP_ALL,
     }
 }
 
+/// Speeds to cycle through, the one after the highest is the lowest.
+const SPEEDS: [f64; 9] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0];
+
+fn is_playing_episode(playback: &Playback) -> bool {
+    matches!(
+        playback.now_playing.as_ref().map(|np| &np.item),
+        Some(Playable::Episode(_))
+    )
+}
+
+fn speed_widget() -> impl Widget<Playback> {
+    Either::new(
+        |playback: &Playback, _| is_playing_episode(playback),
+        Flex::row()
+            .with_child(
+                Label::dynamic(|playback: &Playback, _| format!("{}×", playback.speed))
+                    .with_text_size(theme::TEXT_SIZE_SMALL)
+                    .padding(theme::grid(1.0))
+                    .link()
+                    .rounded(theme::BUTTON_BORDER_RADIUS)
+                    .on_left_click(|ctx, _, playback: &mut Playback, _| {
+                        let speed = SPEEDS
+                            .into_iter()
+                            .find(|&speed| speed > playback.speed)
+                            .unwrap_or(SPEEDS[0]);
+                        ctx.submit_command(cmd::PLAY_SPEED.with(speed));
+                    }),
+            )
+            .with_default_spacer(),
+        Empty,
+    )
+}
+
+fn trim_silence_widget() -> impl Widget<Playback> {
+    Either::new(
+        |playback: &Playback, _| is_playing_episode(playback),
+        Flex::row()
+            .with_child(
+                Label::dynamic(|playback: &Playback, _| {
+                    if !playback.trim_silence {
+                        "Trim silence".to_string()
+                    } else if playback.time_saved.as_secs() == 0 {
+                        "Trimming silence".to_string()
+                    } else {
+                        format!(
+                            "Saved {}",
+                            utils::as_minutes_and_seconds(playback.time_saved)
+                        )
+                    }
+                })
+                .with_text_size(theme::TEXT_SIZE_SMALL)
+                .env_scope(|env, playback: &Playback| {
+                    if !playback.trim_silence {
+                        env.set(theme::TEXT_COLOR, env.get(theme::PLACEHOLDER_COLOR));
+                    }
+                })
+                .padding(theme::grid(1.0))
+                .link()
+                .rounded(theme::BUTTON_BORDER_RADIUS)
+                .on_left_click(|ctx, _, playback: &mut Playback, _| {
+                    ctx.submit_command(cmd::PLAY_TRIM_SILENCE.with(!playback.trim_silence));
+                }),
+            )
+            .with_default_spacer(),
+        Empty,
+    )
+}
+
+fn limiter_widget() -> impl Widget<Playback> {
+    Either::new(
+        |playback: &Playback, _| playback.gain_reduction > 0.0,
+        Flex::row()
+            .with_child(
+                Label::dynamic(|playback: &Playback, _| {
+                    format!("Limiting {:.1} dB", playback.gain_reduction)
+                })
+                .with_text_size(theme::TEXT_SIZE_SMALL)
+                .padding(theme::grid(1.0)),
+            )
+            .with_default_spacer(),
+        Empty,
+    )
+}
+
+fn sleep_timer_widget() -> impl Widget<Playback> {
+    Label::dynamic(|playback: &Playback, _| match &playback.sleep_timer {
+        None => "Sleep".to_string(),
+        Some(SleepTimer {
+            remaining: Some(remaining),
+            ..
+        }) => format!("Sleep in {}", utils::as_minutes_and_seconds(*remaining)),
+        Some(SleepTimer {
+            mode: SleepMode::EndOfQueue,
+            ..
+        }) => "Sleep after queue".to_string(),
+        Some(_) => "Sleep after this".to_string(),
+    })
+    .with_text_size(theme::TEXT_SIZE_SMALL)
+    .env_scope(|env, playback: &Playback| {
+        if playback.sleep_timer.is_none() {
+            env.set(theme::TEXT_COLOR, env.get(theme::PLACEHOLDER_COLOR));
+        }
+    })
+    .padding(theme::grid(1.0))
+    .link()
+    .rounded(theme::BUTTON_BORDER_RADIUS)
+    .on_left_click(|ctx, event, playback: &mut Playback, _| {
+        ctx.show_context_menu(sleep_timer_menu(playback), event.window_pos);
+    })
+}
+
+fn sleep_timer_menu(playback: &Playback) -> Menu<AppState> {
+    let mut menu = Menu::empty();
+    for minutes in [15, 30, 45, 60, 90] {
+        let mode = SleepMode::After(Duration::from_secs(minutes * 60));
+        menu = menu.entry(
+            MenuItem::new(format!("In {} Minutes", minutes))
+                .command(cmd::PLAY_SLEEP_TIMER.with(Some(mode))),
+        );
+    }
+    menu = menu.entry(
+        MenuItem::new("After This Track or Episode")
+            .command(cmd::PLAY_SLEEP_TIMER.with(Some(SleepMode::EndOfTrack))),
+    );
+    menu = menu.entry(
+        MenuItem::new("After the Queue")
+            .command(cmd::PLAY_SLEEP_TIMER.with(Some(SleepMode::EndOfQueue))),
+    );
+    if playback.sleep_timer.is_some() {
+        menu = menu
+            .separator()
+            .entry(MenuItem::new("Cancel Sleep Timer").command(cmd::PLAY_SLEEP_TIMER.with(None)));
+    }
+    menu
+}
+
 fn small_button_widget<T: Data>(svg: &SvgIcon) -> impl Widget<T> {
     svg.scale((theme::grid(2.0), theme::grid(2.0)))
         .padding(theme::grid(1.0))
@@ -398,32 +549,79 @@ where
     }
 }
 
+/// Distance from a loop marker, in pixels, at which it can be dragged.
+const LOOP_MARKER_GRAB_WIDTH: f64 = 4.0;
+
+/// Besides seeking on click, the seek bar shows the looped section of the item.
+/// Its markers can be dragged, and a new section is selected by dragging with
+/// Shift held.  Right click stops looping.
 struct SeekBar {
     loudness_path: BezPath,
+    /// Loop being edited, as fractions of the item duration.  The first one
+    /// stays in place, the second one follows the mouse.
+    loop_drag: Option<(f64, f64)>,
 }
 
 impl SeekBar {
     fn new() -> Self {
         Self {
             loudness_path: BezPath::new(),
+            loop_drag: None,
+        }
+    }
+
+    fn looped_fractions(data: &NowPlaying) -> Option<(f64, f64)> {
+        let duration = data.item.duration().as_secs_f64();
+        data.looped
+            .map(|(start, end)| (start.as_secs_f64() / duration, end.as_secs_f64() / duration))
+    }
+
+    /// Section being dragged, or the looped one.
+    fn shown_loop(&self, data: &NowPlaying) -> Option<(f64, f64)> {
+        match self.loop_drag {
+            Some((fixed, moving)) => Some((fixed.min(moving), fixed.max(moving))),
+            None => Self::looped_fractions(data),
         }
     }
 }
 
 impl Widget<NowPlaying> for SeekBar {
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut NowPlaying, _env: &Env) {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut NowPlaying, _env: &Env) {
         match event {
-            Event::MouseMove(_) => {
+            Event::MouseMove(mouse) => {
                 ctx.set_cursor(&Cursor::Pointer);
+                if let Some((_, moving)) = &mut self.loop_drag {
+                    *moving = (mouse.pos.x / ctx.size().width).clamp(0.0, 1.0);
+                    ctx.request_paint();
+                }
             }
             Event::MouseDown(mouse) => {
                 if mouse.button == MouseButton::Left {
                     ctx.set_active(true);
+                    let width = ctx.size().width;
+                    let fraction = mouse.pos.x / width;
+                    let grab = LOOP_MARKER_GRAB_WIDTH / width;
+                    self.loop_drag = match Self::looped_fractions(data) {
+                        Some((start, end)) if (fraction - start).abs() <= grab => {
+                            Some((end, start))
+                        }
+                        Some((start, end)) if (fraction - end).abs() <= grab => Some((start, end)),
+                        _ if mouse.mods.shift() => Some((fraction, fraction)),
+                        _ => None,
+                    };
+                } else if mouse.button == MouseButton::Right && data.looped.is_some() {
+                    ctx.submit_command(cmd::PLAY_LOOP.with(None));
                 }
             }
             Event::MouseUp(mouse) => {
                 if ctx.is_active() && mouse.button == MouseButton::Left {
-                    if ctx.is_hot() {
+                    if let Some((fixed, moving)) = self.loop_drag.take() {
+                        if fixed != moving {
+                            let section = (fixed.min(moving), fixed.max(moving));
+                            ctx.submit_command(cmd::PLAY_LOOP.with(Some(section)));
+                        }
+                        ctx.request_paint();
+                    } else if ctx.is_hot() {
                         let fraction = mouse.pos.x / ctx.size().width;
                         ctx.submit_command(cmd::PLAY_SEEK.with(fraction));
                     }
@@ -480,6 +678,9 @@ impl Widget<NowPlaying> for SeekBar {
         } else {
             paint_audio_analysis(ctx, data, &self.loudness_path, env)
         }
+        if let Some(section) = self.shown_loop(data) {
+            paint_loop(ctx, section, env);
+        }
     }
 }
 
@@ -592,3 +793,28 @@ fn paint_progress_bar(ctx: &mut PaintCtx, data: &NowPlaying, env: &Env) {
         &remaining_color,
     );
 }
+
+fn paint_loop(ctx: &mut PaintCtx, (start, end): (f64, f64), env: &Env) {
+    const MARKER_WIDTH: f64 = 2.0;
+
+    let bounds = ctx.size();
+    let start = bounds.width * start;
+    let end = bounds.width * end;
+    let color = env.get(theme::BLUE_100);
+
+    ctx.fill(
+        Rect::new(start, 0.0, end, bounds.height),
+        &color.clone().with_alpha(0.3),
+    );
+    for x in [start, end] {
+        ctx.fill(
+            Rect::new(
+                x - MARKER_WIDTH / 2.0,
+                0.0,
+                x + MARKER_WIDTH / 2.0,
+                bounds.height,
+            ),
+            &color,
+        );
+    }
+}
@@ -19,15 +19,15 @@ use crate::{
     cmd,
     data::{
         config::{SortCriteria, SortOrder},
-        AppState, Ctx, Library, Nav, Playlist, PlaylistAddTrack, PlaylistDetail, PlaylistLink,
-        PlaylistRemoveTrack, PlaylistTracks, Track,
+        AppState, Ctx, Library, Nav, OfflineCollection, Playlist, PlaylistAddTrack,
+        PlaylistDetail, PlaylistLink, PlaylistRemoveTrack, PlaylistTracks, Track,
     },
     error::Error,
     webapi::WebApi,
     widget::{Async, MyWidgetExt, RemoteImage, icons, Empty},
 };
 
-use super::{playable, theme, track, utils};
+use super::{offline, playable, theme, track, utils};
 
 use crate::controller::OnCommand;
 
@@ -560,7 +560,8 @@ fn sort_playlist(data: &AppState, result: Result<Vector<Arc<Track>>, Error>) ->
 }
 
 fn playlist_menu_ctx(playlist: &WithCtx<Playlist>) -> Menu<AppState> {
-    let library = &playlist.ctx.library;
+    let ctx = &playlist.ctx;
+    let library = &ctx.library;
     let playlist = &playlist.data;
 
     let mut menu = Menu::empty();
@@ -635,6 +636,11 @@ fn playlist_menu_ctx(playlist: &WithCtx<Playlist>) -> Menu<AppState> {
         );
     }
 
+    menu = menu.entry(offline::menu_item(
+        OfflineCollection::Playlist(playlist.link()),
+        &ctx.offline,
+    ));
+
     menu
 }
 
@@ -5,21 +5,26 @@ use std::time::Duration;
 use crate::{
     cmd,
     data::{
-        AppState, AudioQuality, Authentication, Config, Preferences, PreferencesTab, Promise,
-        SliderScrollScale, Theme,
+        AppState, AudioQuality, Authentication, Config, Equalizer, EqualizerBand, Preferences,
+        PreferencesTab, Promise, ResamplingQuality, SliderScrollScale, Theme,
     },
     widget::{icons, Async, Border, Checkbox, MyWidgetExt},
 };
 use druid::{
     text::ParseFormatter,
     widget::{
-        Button, Controller, CrossAxisAlignment, Flex, Label, LineBreaking, MainAxisAlignment,
+        Button, Controller, CrossAxisAlignment, Flex, Label, LineBreaking, List, MainAxisAlignment,
         RadioGroup, SizedBox, Slider, TextBox, ViewSwitcher,
     },
     Color, Data, Env, Event, EventCtx, Insets, Lens, LensExt, LifeCycle, LifeCycleCtx, Selector,
     Widget, WidgetExt,
 };
-use psst_core::{connection::Credentials, lastfm, oauth, session::SessionConfig};
+use psst_core::{
+    audio::{equalizer::EqualizerPreset, output::DefaultAudioOutput},
+    connection::Credentials,
+    lastfm, oauth,
+    session::SessionConfig,
+};
 
 use super::{icons::SvgIcon, theme};
 
@@ -217,6 +222,145 @@ fn general_tab_widget() -> impl Widget<AppState> {
 
     col = col.with_spacer(theme::grid(3.0));
 
+    // Audio output
+    col = col
+        .with_child(Label::new("Audio output").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(output_device_widget())
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            Checkbox::new("Dither, if the device takes integer samples (requires restart)")
+                .lens(AppState::config.then(Config::dither)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Checkbox::new("Bit-perfect, play at the sample rate of the track (requires restart)")
+                .lens(AppState::config.then(Config::bit_perfect)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(Label::new("Resampling quality (requires restart)"))
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            RadioGroup::column(vec![
+                ("Best", ResamplingQuality::Best),
+                ("Medium", ResamplingQuality::Medium),
+                ("Fastest", ResamplingQuality::Fastest),
+                ("Linear, lowest CPU use", ResamplingQuality::Linear),
+            ])
+            .lens(AppState::config.then(Config::resampling_quality)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Checkbox::new("Mono audio, play the same sound on every speaker")
+                .lens(AppState::config.then(Config::mono_audio)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Flex::row()
+                .with_child(Label::new("L"))
+                .with_spacer(theme::grid(0.5))
+                .with_child(
+                    Slider::new()
+                        .with_range(-1.0, 1.0)
+                        .lens(AppState::config.then(Config::balance)),
+                )
+                .with_spacer(theme::grid(0.5))
+                .with_child(Label::new("R"))
+                .with_spacer(theme::grid(1.0))
+                .with_child(Label::new("Balance")),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Slider::new()
+                        .with_range(0.0, 6.0)
+                        .lens(AppState::config.then(Config::pregain)),
+                )
+                .with_spacer(theme::grid(1.0))
+                .with_child(
+                    Label::dynamic(|config: &Config, _| {
+                        format!("Pregain +{:.1} dB (requires restart)", config.pregain)
+                    })
+                    .lens(AppState::config),
+                ),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Checkbox::new("Limit the peaks, instead of lowering loud tracks")
+                .lens(AppState::config.then(Config::limit_peaks)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Slider::new()
+                        .with_range(-6.0, 0.0)
+                        .lens(AppState::config.then(Config::limiter_ceiling)),
+                )
+                .with_spacer(theme::grid(1.0))
+                .with_child(
+                    Label::dynamic(|config: &Config, _| {
+                        format!("Ceiling {:.1} dBTP", config.limiter_ceiling)
+                    })
+                    .lens(AppState::config),
+                ),
+        );
+
+    col = col.with_spacer(theme::grid(3.0));
+
+    // Offline mode
+    col = col
+        .with_child(Label::new("Offline").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            Checkbox::new("Offline mode, only play downloaded tracks")
+                .lens(AppState::config.then(Config::offline_mode)),
+        );
+
+    col = col.with_spacer(theme::grid(3.0));
+
+    // Podcasts
+    col = col
+        .with_child(Label::new("Podcasts").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            Checkbox::new("Shorten long pauses, unless changed for the show")
+                .lens(AppState::config.then(Config::trim_silence)),
+        );
+
+    col = col.with_spacer(theme::grid(3.0));
+
+    // Sleep timer
+    col = col
+        .with_child(Label::new("Sleep Timer").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Slider::new()
+                        .with_range(0.0, 120.0)
+                        .lens(AppState::config.then(Config::sleep_timer_fade)),
+                )
+                .with_spacer(theme::grid(1.0))
+                .with_child(
+                    Label::dynamic(|config: &Config, _| {
+                        format!("Fade out over {:.0} s", config.sleep_timer_fade)
+                    })
+                    .lens(AppState::config),
+                ),
+        );
+
+    col = col.with_spacer(theme::grid(3.0));
+
+    // Equalizer
+    col = col
+        .with_child(Label::new("Equalizer").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(equalizer_widget().lens(AppState::config.then(Config::equalizer)));
+
+    col = col.with_spacer(theme::grid(3.0));
+
     // Sliders
     col = col
         .with_child(Label::new("Slider Scrolling").with_font(theme::UI_FONT_MEDIUM))
@@ -276,6 +420,74 @@ fn general_tab_widget() -> impl Widget<AppState> {
     col
 }
 
+fn output_device_widget() -> impl Widget<AppState> {
+    // Devices are listed each time the tab is opened, so newly connected ones
+    // show up after switching tabs.
+    let devices = DefaultAudioOutput::devices().unwrap_or_else(|err| {
+        log::error!("failed to list audio devices: {}", err);
+        Vec::new()
+    });
+    let options = std::iter::once(("System default".to_string(), None))
+        .chain(devices.into_iter().map(|name| (name.clone(), Some(name))));
+    RadioGroup::column(options).lens(AppState::config.then(Config::output_device))
+}
+
+fn equalizer_widget() -> impl Widget<Equalizer> {
+    let mut presets = Flex::row();
+    for preset in EqualizerPreset::ALL {
+        presets = presets
+            .with_child(Button::new(preset.name()).on_click(
+                move |_, equalizer: &mut Equalizer, _| {
+                    equalizer.apply_preset(preset);
+                },
+            ))
+            .with_spacer(theme::grid(0.5));
+    }
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Checkbox::new("Enable equalizer").lens(Equalizer::enabled))
+        .with_spacer(theme::grid(1.5))
+        .with_child(presets)
+        .with_spacer(theme::grid(1.5))
+        .with_child(List::new(equalizer_band_widget).lens(Equalizer::bands))
+}
+
+fn equalizer_band_widget() -> impl Widget<EqualizerBand> {
+    Flex::row()
+        .with_child(
+            TextBox::new()
+                .with_formatter(ParseFormatter::with_format_fn(|hz: &f64| {
+                    format!("{:.0}", hz)
+                }))
+                .lens(EqualizerBand::frequency)
+                .fix_width(theme::grid(8.0)),
+        )
+        .with_spacer(theme::grid(0.5))
+        .with_child(Label::new("Hz"))
+        .with_spacer(theme::grid(1.5))
+        .with_child(
+            Slider::new()
+                .with_range(-12.0, 12.0)
+                .lens(EqualizerBand::gain),
+        )
+        .with_child(
+            SizedBox::new(Label::dynamic(|band: &EqualizerBand, _| {
+                format!("{:+.1} dB", band.gain)
+            }))
+            .width(theme::grid(8.0)),
+        )
+        .with_spacer(theme::grid(1.0))
+        .with_child(Slider::new().with_range(0.3, 4.0).lens(EqualizerBand::q))
+        .with_child(
+            SizedBox::new(Label::dynamic(|band: &EqualizerBand, _| {
+                format!("Q {:.2}", band.q)
+            }))
+            .width(theme::grid(8.0)),
+        )
+        .padding((0.0, theme::grid(0.25)))
+}
+
 struct CacheController {
     thread: Option<JoinHandle<()>>,
 }
@@ -849,8 +1061,45 @@ fn cache_tab_widget() -> impl Widget<AppState> {
                 )
         );
 
-    col.controller(CacheController::new())
-        .lens(AppState::preferences)
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(col.controller(CacheController::new()).lens(AppState::preferences))
+        .with_spacer(theme::grid(3.0))
+        .with_child(cache_limit_widget())
+}
+
+fn cache_limit_widget() -> impl Widget<AppState> {
+    const GB: u64 = 1_000_000_000;
+
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Core Cache Limit").with_font(theme::UI_FONT_MEDIUM))
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            RadioGroup::column(vec![
+                ("Unlimited", None),
+                ("1 GB", Some(GB)),
+                ("2 GB", Some(2 * GB)),
+                ("5 GB", Some(5 * GB)),
+                ("10 GB", Some(10 * GB)),
+            ])
+            .lens(AppState::config.then(Config::cache_size_limit)),
+        )
+        .with_spacer(theme::grid(2.0))
+        .with_child(
+            Label::dynamic(|data: &AppState, _| match &data.preferences.cache_stats {
+                Some(stats) => format!(
+                    "Last cleanup removed {} entries ({:.2} MB), {:.2} MB kept for offline use",
+                    stats.evicted_entries,
+                    stats.evicted_size as f64 / 1e6_f64,
+                    stats.pinned_size as f64 / 1e6_f64
+                ),
+                None => "Least recently used tracks are removed when over the limit. \
+                         Downloads for offline use are always kept."
+                    .to_string(),
+            })
+            .with_line_break_mode(LineBreaking::WordWrap),
+        )
 }
 
 fn about_tab_widget() -> impl Widget<AppState> {
@@ -67,9 +67,12 @@ fn async_episodes_widget() -> impl Widget<AppState> {
         |d| WebApi::global().get_show_episodes(&d.id),
         |_, data, d| data.show_detail.episodes.defer(d),
         |_, data, (d, r)| {
-            let r = r.map(|episodes| ShowEpisodes {
-                show: d.clone(),
-                episodes,
+            let r = r.map(|mut episodes| {
+                data.apply_resume_points(&mut episodes);
+                ShowEpisodes {
+                    show: d.clone(),
+                    episodes,
+                }
             });
             data.show_detail.episodes.update((d, r))
         },
@@ -3,8 +3,9 @@ use std::{
     fs::{self, File},
     hash::{Hash, Hasher},
     num::NonZeroUsize,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 use druid::image;
@@ -67,7 +68,42 @@ impl WebApiCache {
         self.key(bucket, key).and_then(|path| File::open(path).ok())
     }
 
+    /// Get an entry together with its freshness.  Entries emptied by an
+    /// invalidation are treated as missing.
+    pub fn get_entry(&self, bucket: &str, key: &str) -> Option<CacheEntry> {
+        let file = self.get(bucket, key)?;
+        let metadata = file.metadata().ok()?;
+        if metadata.len() == 0 {
+            return None;
+        }
+        let fetched_at = metadata.modified().ok()?;
+        let stale = fetched_at
+            .elapsed()
+            .is_ok_and(|age| age > bucket_ttl(bucket));
+        let validator = self
+            .key(bucket, &validator_key(key))
+            .and_then(|path| fs::read_to_string(path).ok());
+        Some(CacheEntry {
+            file,
+            fetched_at,
+            stale,
+            validator,
+        })
+    }
+
     pub fn set(&self, bucket: &str, key: &str, value: &[u8]) {
+        self.set_with_validator(bucket, key, value, None);
+    }
+
+    /// Store an entry along with the ETag or snapshot ID it can be revalidated
+    /// with.
+    pub fn set_with_validator(
+        &self,
+        bucket: &str,
+        key: &str,
+        value: &[u8],
+        validator: Option<&str>,
+    ) {
         if let Some(path) = self.bucket(bucket) {
             if let Err(err) = mkdir_if_not_exists(&path) {
                 log::error!("failed to create WebAPI cache bucket: {:?}", err);
@@ -78,6 +114,35 @@ impl WebApiCache {
                 log::error!("failed to save to WebAPI cache: {:?}", err);
             }
         }
+        if let Some(path) = self.key(bucket, &validator_key(key)) {
+            let result = match validator {
+                Some(validator) => fs::write(path, validator),
+                None => fs::remove_file(path).or_else(|err| {
+                    if err.kind() == std::io::ErrorKind::NotFound {
+                        Ok(())
+                    } else {
+                        Err(err)
+                    }
+                }),
+            };
+            if let Err(err) = result {
+                log::error!("failed to save validator to WebAPI cache: {:?}", err);
+            }
+        }
+    }
+
+    /// Mark an entry as fetched just now, after the server confirmed that it
+    /// has not changed.
+    pub fn touch(&self, bucket: &str, key: &str) {
+        let result = self.key(bucket, key).map(|path| {
+            fs::OpenOptions::new()
+                .append(true)
+                .open(path)?
+                .set_modified(SystemTime::now())
+        });
+        if let Some(Err(err)) = result {
+            log::error!("failed to refresh WebAPI cache entry: {:?}", err);
+        }
     }
 
     /// Get cache statistics including size and entry counts
@@ -97,6 +162,10 @@ impl WebApiCache {
                     } else if path.is_dir() {
                         if let Ok(dir_entries) = fs::read_dir(path) {
                             for dir_entry in dir_entries.flatten() {
+                                // Validators belong to their entries.
+                                if is_validator(&dir_entry.path()) {
+                                    continue;
+                                }
                                 if let Ok(metadata) = fs::metadata(dir_entry.path()) {
                                     total_size += metadata.len();
                                     total_entries += 1;
@@ -149,6 +218,46 @@ impl WebApiCache {
     }
 }
 
+pub struct CacheEntry {
+    pub file: File,
+    pub fetched_at: SystemTime,
+    /// The entry outlived the time to live of its bucket, and should be
+    /// revalidated.
+    pub stale: bool,
+    /// ETag or snapshot ID of the entry, if any.
+    pub validator: Option<String>,
+}
+
+/// How long entries of a bucket are served without revalidation.
+fn bucket_ttl(bucket: &str) -> Duration {
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    let secs = match bucket {
+        // The library is often changed from other clients, playlists are
+        // revalidated cheaply through their snapshot ID.
+        "playlists" | "playlist" | "playlist-tracks" | "saved-tracks" | "user-top-tracks" => HOUR,
+        "artist" | "artist-albums" | "artist-top-tracks" | "related-artists" | "artist-info" => {
+            7 * DAY
+        }
+        "User_info" => DAY,
+        // Albums, tracks and lyrics hardly ever change.
+        _ => 30 * DAY,
+    };
+    Duration::from_secs(secs)
+}
+
+const VALIDATOR_EXTENSION: &str = "validator";
+
+fn validator_key(key: &str) -> String {
+    format!("{}.{}", key, VALIDATOR_EXTENSION)
+}
+
+fn is_validator(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == VALIDATOR_EXTENSION)
+}
+
 #[derive(Debug, Clone)]
 pub struct CacheStats {
     pub total_size: u64,
@@ -211,4 +320,39 @@ mod tests {
         assert_eq!(stats_after_clear.total_entries, 0);
         assert_eq!(stats_after_clear.total_size, 0);
     }
+
+    #[test]
+    fn test_cache_entry_freshness() {
+        let temp_dir = tempdir().unwrap();
+        let cache = WebApiCache::new(Some(temp_dir.path().to_path_buf()));
+
+        cache.set_with_validator("playlist", "key", b"data", Some("etag"));
+        let entry = cache.get_entry("playlist", "key").unwrap();
+        assert!(!entry.stale);
+        assert_eq!(entry.validator.as_deref(), Some("etag"));
+
+        // Entries older than the bucket TTL are stale, until touched.
+        File::options()
+            .append(true)
+            .open(temp_dir.path().join("playlist").join("key"))
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert!(cache.get_entry("playlist", "key").unwrap().stale);
+        cache.touch("playlist", "key");
+        assert!(!cache.get_entry("playlist", "key").unwrap().stale);
+
+        // Validators are not counted as entries of their own.
+        let stats = cache.get_stats();
+        assert_eq!(stats.total_entries, 1);
+        assert_eq!(stats.total_size, 4);
+
+        // Storing without a validator drops the old one.
+        cache.set("playlist", "key", b"data");
+        assert_eq!(cache.get_entry("playlist", "key").unwrap().validator, None);
+
+        // Invalidated entries are treated as missing.
+        cache.set("playlist", "key", &[]);
+        assert!(cache.get_entry("playlist", "key").is_none());
+    }
 }
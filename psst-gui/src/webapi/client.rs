@@ -1,9 +1,12 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Display,
     io::{self, Read},
     path::PathBuf,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
     time::Duration,
 };
@@ -31,8 +34,9 @@ use crate::{
     data::{
         self, utils::sanitize_html_string, Album, AlbumType, Artist, ArtistAlbums, ArtistInfo,
         ArtistLink, ArtistStats, AudioAnalysis, Cached, Episode, EpisodeId, EpisodeLink, Image,
-        MixedView, Nav, Page, Playlist, PublicUser, Range, Recommendations, RecommendationsRequest,
-        SearchResults, SearchTopic, Show, SpotifyUrl, Track, TrackLines, UserProfile,
+        MixedView, Nav, OfflineCollection, Page, Playlist, PublicUser, Range, Recommendations,
+        RecommendationsRequest, SearchResults, SearchTopic, Show, SpotifyUrl, Track, TrackId,
+        TrackLines, UserProfile,
     },
     error::Error,
     ui::credits::TrackCredits,
@@ -49,6 +53,9 @@ pub struct WebApi {
     token_provider: TokenProvider,
     local_track_manager: Mutex<LocalTrackManager>,
     paginated_limit: usize,
+    offline: AtomicBool,
+    /// Cache entries being refreshed in the background, as `bucket/key`.
+    refreshing: Mutex<HashSet<String>>,
 }
 
 impl WebApi {
@@ -70,9 +77,21 @@ impl WebApi {
             token_provider: TokenProvider::new(),
             local_track_manager: Mutex::new(LocalTrackManager::new()),
             paginated_limit,
+            offline: AtomicBool::new(false),
+            refreshing: Mutex::new(HashSet::new()),
         }
     }
 
+    /// In offline mode, only cached responses are served and all other
+    /// requests fail right away, instead of waiting for the network.
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
     fn access_token(&self) -> Result<String, Error> {
         self.token_provider
             .get(&self.session)
@@ -81,22 +100,24 @@ impl WebApi {
     }
 
     fn request(&self, request: &RequestBuilder) -> Result<Response<Body>, Error> {
+        if self.is_offline() {
+            return Err(Error::WebApiError(
+                "Not available in offline mode".to_string(),
+            ));
+        }
         let token = self.access_token()?;
         let request = request.clone().query("market", "from_token");
 
         match request.get_method() {
             Method::Get => {
-                let mut req = self
-                    .agent
-                    .get(request.build())
-                    .header("Authorization", &format!("Bearer {}", token));
+                let mut req = self.agent.get(request.build());
+                if !request.get_headers().contains_key("Authorization") {
+                    req = req.header("Authorization", &format!("Bearer {}", token));
+                }
                 for header in request.get_headers() {
                     req = req.header(header.0, header.1);
                 }
-                self.agent
-                    .get(request.build())
-                    .header("Authorization", &format!("Bearer {}", token))
-                    .call()
+                req.call()
                     .map_err(|err| Error::WebApiError(err.to_string()))
             }
             Method::Post => self
@@ -156,31 +177,128 @@ impl WebApi {
     }
 
     /// Send a request using `self.load()`, but only if it isn't already present
-    /// in cache.
+    /// in cache.  Stale entries are served as well, and revalidated with their
+    /// ETag in the background.
     fn load_cached<T: Data + DeserializeOwned>(
         &self,
         request: &RequestBuilder,
         bucket: &str,
         key: &str,
     ) -> Result<Cached<T>, Error> {
-        if let Some(file) = self.cache.get(bucket, key) {
-            let cached_at = file.metadata()?.modified()?;
-            let value = serde_json::from_reader(file)?;
-            Ok(Cached::new(value, cached_at))
+        if let Some(entry) = self.cache.get_entry(bucket, key) {
+            let value = serde_json::from_reader(entry.file)?;
+            if entry.stale {
+                let request = request.clone();
+                let etag = entry.validator;
+                self.refresh_in_background(bucket, key, move |webapi, bucket, key| {
+                    webapi
+                        .fetch_into_cache(&request, bucket, key, etag.as_deref())
+                        .map(|_| ())
+                });
+            }
+            Ok(Cached::new(value, entry.fetched_at, entry.stale))
         } else {
-            let response = Self::with_retry(|| self.request(request))?;
-            let body = {
-                let mut reader = response.into_body().into_reader();
-                let mut body = Vec::new();
-                reader.read_to_end(&mut body)?;
-                body
-            };
+            let body = self
+                .fetch_into_cache(request, bucket, key, None)?
+                .unwrap_or_default();
             let value = serde_json::from_slice(&body)?;
-            self.cache.set(bucket, key, &body);
             Ok(Cached::fresh(value))
         }
     }
 
+    /// Send a request and store the response body in the cache, along with its
+    /// ETag.  When `etag` is given, the body is only sent if it changed, and
+    /// `None` is returned if it did not.
+    fn fetch_into_cache(
+        &self,
+        request: &RequestBuilder,
+        bucket: &str,
+        key: &str,
+        etag: Option<&str>,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let request = match etag {
+            Some(etag) => request.clone().header("If-None-Match", etag),
+            None => request.clone(),
+        };
+        let response = Self::with_retry(|| self.request(&request))?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            self.cache.touch(bucket, key);
+            return Ok(None);
+        }
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        let body = {
+            let mut reader = response.into_body().into_reader();
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body)?;
+            body
+        };
+        self.cache
+            .set_with_validator(bucket, key, &body, etag.as_deref());
+        Ok(Some(body))
+    }
+
+    /// Like `load_cached`, but for values put together from several requests.
+    /// `fetch` loads the value from the API, and refreshes stale entries in the
+    /// background.
+    fn load_cached_with<T: Data + Serialize + DeserializeOwned>(
+        &self,
+        bucket: &str,
+        key: &str,
+        fetch: fn(&WebApi, &str) -> Result<T, Error>,
+    ) -> Result<Cached<T>, Error> {
+        if let Some(entry) = self.cache.get_entry(bucket, key) {
+            if let Ok(value) = serde_json::from_reader(entry.file) {
+                if entry.stale {
+                    self.refresh_in_background(bucket, key, move |webapi, bucket, key| {
+                        webapi.store(bucket, key, &fetch(webapi, key)?, None);
+                        Ok(())
+                    });
+                }
+                return Ok(Cached::new(value, entry.fetched_at, entry.stale));
+            }
+        }
+        let value = fetch(self, key)?;
+        self.store(bucket, key, &value, None);
+        Ok(Cached::fresh(value))
+    }
+
+    fn store<T: Serialize>(&self, bucket: &str, key: &str, value: &T, validator: Option<&str>) {
+        match serde_json::to_vec(value) {
+            Ok(json) => self.cache.set_with_validator(bucket, key, &json, validator),
+            Err(err) => log::error!("failed to serialize {}/{}: {}", bucket, key, err),
+        }
+    }
+
+    /// Run `refresh` for a stale cache entry on a background thread, unless the
+    /// entry is already being refreshed.
+    fn refresh_in_background(
+        &self,
+        bucket: &str,
+        key: &str,
+        refresh: impl FnOnce(&WebApi, &str, &str) -> Result<(), Error> + Send + 'static,
+    ) {
+        if self.is_offline() {
+            return;
+        }
+        let id = format!("{}/{}", bucket, key);
+        if !self.refreshing.lock().insert(id.clone()) {
+            return;
+        }
+        let bucket = bucket.to_string();
+        let key = key.to_string();
+        thread::spawn(move || {
+            let webapi = Self::global();
+            if let Err(err) = refresh(&webapi, &bucket, &key) {
+                log::warn!("failed to refresh {}: {}", id, err);
+            }
+            webapi.refreshing.lock().remove(&id);
+        });
+    }
+
     /// Iterate a paginated result set by sending `request` with added
     /// pagination parameters.  Mostly used through `load_all_pages`.
     fn for_all_pages<T: DeserializeOwned + Clone>(
@@ -664,29 +782,37 @@ impl WebApi {
 
     /// Clear cache entries for a specific playlist
     pub fn clear_playlist_cache(&self, playlist_id: &str) {
-        self.cache.set("playlist", playlist_id, &[]);
-        self.cache.set("playlist-tracks", playlist_id, &[]);
+        self.invalidate("playlist", playlist_id);
+        self.invalidate("playlist-tracks", playlist_id);
     }
 
     /// Clear cache entries for a specific artist
     pub fn clear_artist_cache(&self, artist_id: &str) {
-        self.cache.set("artist", artist_id, &[]);
-        self.cache.set("artist-albums", artist_id, &[]);
-        self.cache.set("artist-top-tracks", artist_id, &[]);
-        self.cache.set("related-artists", artist_id, &[]);
-        self.cache.set("artist-info", artist_id, &[]);
+        self.invalidate("artist", artist_id);
+        self.invalidate("artist-albums", artist_id);
+        self.invalidate("artist-top-tracks", artist_id);
+        self.invalidate("related-artists", artist_id);
+        self.invalidate("artist-info", artist_id);
     }
 
     /// Clear cache entries for a specific track
     pub fn clear_track_cache(&self, track_id: &str) {
-        self.cache.set("track", track_id, &[]);
+        self.invalidate("track", track_id);
     }
 
     /// Clear user-specific cache entries
     pub fn clear_user_cache(&self) {
-        self.cache.set("saved-tracks", "user", &[]);
-        self.cache.set("user-top-tracks", "user", &[]);
-        self.cache.set("playlists", "user", &[]);
+        self.invalidate("saved-tracks", "user");
+        self.invalidate("user-top-tracks", "user");
+        self.invalidate("playlists", "user");
+    }
+
+    /// Mark a cache entry as stale, so it gets loaded again next time.
+    fn invalidate(&self, bucket: &str, key: &str) {
+        // There is no way to load the data again while offline, keep it.
+        if !self.is_offline() {
+            self.cache.set(bucket, key, &[]);
+        }
     }
 
     /// Get cache statistics
@@ -703,19 +829,25 @@ impl WebApi {
     pub fn refresh_favorite_playlists(&self, favorite_ids: &[Arc<str>]) -> Result<(), Error> {
         for playlist_id in favorite_ids {
             // Refresh playlist metadata
-            if let Ok(playlist) = self.get_playlist(playlist_id) {
-                // Cache the updated playlist data
-                if let Ok(json) = serde_json::to_vec(&playlist) {
-                    self.cache.set("playlist", playlist_id, &json);
-                }
+            let request =
+                &RequestBuilder::new(format!("v1/playlists/{}", playlist_id), Method::Get, None);
+            let etag = self
+                .cache
+                .get_entry("playlist", playlist_id)
+                .and_then(|entry| entry.validator);
+            if let Err(err) =
+                self.fetch_into_cache(request, "playlist", playlist_id, etag.as_deref())
+            {
+                log::warn!("failed to refresh playlist {}: {}", playlist_id, err);
             }
 
             // Refresh playlist tracks
-            if let Ok(tracks) = self.get_playlist_tracks(playlist_id) {
-                // Cache the updated tracks data
-                if let Ok(json) = serde_json::to_vec(&tracks) {
-                    self.cache.set("playlist-tracks", playlist_id, &json);
-                }
+            let snapshot_id = self
+                .cache
+                .get_entry("playlist-tracks", playlist_id)
+                .and_then(|entry| entry.validator);
+            if let Err(err) = self.revalidate_playlist_tracks(playlist_id, snapshot_id.as_deref()) {
+                log::warn!("failed to refresh playlist tracks {}: {}", playlist_id, err);
             }
         }
         Ok(())
@@ -732,26 +864,13 @@ impl WebApi {
 
     // https://developer.spotify.com/documentation/web-api/reference/get-users-top-artists-and-tracks
     pub fn get_user_top_tracks(&self) -> Result<Vector<Arc<Track>>, Error> {
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("user-top-tracks", "user") {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(file) {
-                    return Ok(Cached::new(tracks, cached_at).data);
-                }
-            }
-        }
-
-        let request = &RequestBuilder::new("v1/me/top/tracks", Method::Get, None)
-            .query("limit", "50")
-            .query("time_range", "short_term");
-        let tracks: Vector<Arc<Track>> = self.load_all_pages(request)?;
-
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&tracks) {
-            self.cache.set("user-top-tracks", "user", &json);
-        }
-
-        Ok(tracks)
+        let result = self.load_cached_with("user-top-tracks", "user", |webapi, _| {
+            let request = &RequestBuilder::new("v1/me/top/tracks", Method::Get, None)
+                .query("limit", "50")
+                .query("time_range", "short_term");
+            webapi.load_all_pages(request)
+        })?;
+        Ok(result.data)
     }
 
     pub fn get_user_top_artist(&self) -> Result<Vector<Artist>, Error> {
@@ -780,15 +899,11 @@ impl WebApi {
 
     // https://developer.spotify.com/documentation/web-api/reference/get-an-artists-albums/
     pub fn get_artist_albums(&self, id: &str) -> Result<ArtistAlbums, Error> {
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("artist-albums", id) {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(albums) = serde_json::from_reader::<_, ArtistAlbums>(file) {
-                    return Ok(Cached::new(albums, cached_at).data);
-                }
-            }
-        }
+        let result = self.load_cached_with("artist-albums", id, Self::fetch_artist_albums)?;
+        Ok(result.data)
+    }
 
+    fn fetch_artist_albums(&self, id: &str) -> Result<ArtistAlbums, Error> {
         let request = &RequestBuilder::new(format!("v1/artists/{}/albums", id), Method::Get, None)
             .query("market", "from_token");
         let result: Vector<Arc<Album>> = self.load_all_pages(request)?;
@@ -830,40 +945,23 @@ impl WebApi {
             }
         }
 
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&artist_albums) {
-            self.cache.set("artist-albums", id, &json);
-        }
-
         Ok(artist_albums)
     }
 
     // https://developer.spotify.com/documentation/web-api/reference/get-an-artists-top-tracks
     pub fn get_artist_top_tracks(&self, id: &str) -> Result<Vector<Arc<Track>>, Error> {
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("artist-top-tracks", id) {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(file) {
-                    return Ok(Cached::new(tracks, cached_at).data);
-                }
-            }
-        }
-
         #[derive(Deserialize)]
         struct Tracks {
             tracks: Vector<Arc<Track>>,
         }
-        let request =
-            &RequestBuilder::new(format!("v1/artists/{}/top-tracks", id), Method::Get, None)
-                .query("market", "from_token");
-        let result: Tracks = self.load(request)?;
-
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&result.tracks) {
-            self.cache.set("artist-top-tracks", id, &json);
-        }
-
-        Ok(result.tracks)
+        let result = self.load_cached_with("artist-top-tracks", id, |webapi, id| {
+            let request =
+                &RequestBuilder::new(format!("v1/artists/{}/top-tracks", id), Method::Get, None)
+                    .query("market", "from_token");
+            let result: Tracks = webapi.load(request)?;
+            Ok(result.tracks)
+        })?;
+        Ok(result.data)
     }
 
     // https://developer.spotify.com/documentation/web-api/reference/get-an-artists-related-artists
@@ -1013,6 +1111,26 @@ impl WebApi {
     }
 }
 
+/// Offline collection endpoints.
+impl WebApi {
+    /// Load the tracks of a collection that is being made available offline.
+    /// Everything is loaded through the cached endpoints, so the collection can
+    /// be browsed without a connection afterwards.
+    pub fn get_offline_collection_tracks(
+        &self,
+        collection: &OfflineCollection,
+    ) -> Result<Vector<Arc<Track>>, Error> {
+        match collection {
+            OfflineCollection::Album(link) => Ok(self.get_album(&link.id)?.data.tracks.clone()),
+            OfflineCollection::Playlist(link) => {
+                self.get_playlist(&link.id)?;
+                self.get_playlist_tracks(&link.id)
+            }
+            OfflineCollection::SavedTracks => self.get_saved_tracks(),
+        }
+    }
+}
+
 /// Show endpoints. (Podcasts)
 impl WebApi {
     // https://developer.spotify.com/documentation/web-api/reference/get-multiple-episodes
@@ -1062,6 +1180,24 @@ impl WebApi {
         Ok(result.data)
     }
 
+    // https://developer.spotify.com/documentation/web-api/reference/get-several-tracks
+    pub fn get_tracks(
+        &self,
+        ids: impl IntoIterator<Item = TrackId>,
+    ) -> Result<Vector<Arc<Track>>, Error> {
+        #[derive(Deserialize)]
+        struct Tracks {
+            // Unknown IDs come back as nulls.
+            tracks: Vec<Option<Arc<Track>>>,
+        }
+
+        let request = &RequestBuilder::new("v1/tracks", Method::Get, None)
+            .query("market", "from_token")
+            .query("ids", ids.into_iter().map(|id| id.0.to_base62()).join(","));
+        let result: Tracks = self.load(request)?;
+        Ok(result.tracks.into_iter().flatten().collect())
+    }
+
     pub fn get_track_credits(&self, track_id: &str) -> Result<TrackCredits, Error> {
         let request = &RequestBuilder::new(
             format!("track-credits-view/v0/experimental/{}/credits", track_id),
@@ -1139,33 +1275,20 @@ impl WebApi {
 
     // https://developer.spotify.com/documentation/web-api/reference/get-users-saved-tracks/
     pub fn get_saved_tracks(&self) -> Result<Vector<Arc<Track>>, Error> {
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("saved-tracks", "user") {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(file) {
-                    return Ok(Cached::new(tracks, cached_at).data);
-                }
-            }
-        }
-
         #[derive(Clone, Deserialize)]
         struct SavedTrack {
             track: Arc<Track>,
         }
-        let request =
-            &RequestBuilder::new("v1/me/tracks", Method::Get, None).query("market", "from_token");
-        let tracks: Vector<Arc<Track>> = self
-            .load_all_pages(request)?
-            .into_iter()
-            .map(|item: SavedTrack| item.track)
-            .collect();
-
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&tracks) {
-            self.cache.set("saved-tracks", "user", &json);
-        }
-
-        Ok(tracks)
+        let result = self.load_cached_with("saved-tracks", "user", |webapi, _| {
+            let request = &RequestBuilder::new("v1/me/tracks", Method::Get, None)
+                .query("market", "from_token");
+            Ok(webapi
+                .load_all_pages(request)?
+                .into_iter()
+                .map(|item: SavedTrack| item.track)
+                .collect())
+        })?;
+        Ok(result.data)
     }
 
     // https://developer.spotify.com/documentation/web-api/reference/get-users-saved-shows
@@ -1354,28 +1477,16 @@ impl WebApi {
     // https://developer.spotify.com/documentation/web-api/reference/get-a-list-of-current-users-playlists
     pub fn get_playlists(&self) -> Result<Vector<Playlist>, Error> {
         log::info!("WebApi: get_playlists() called");
-        
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("playlists", "user") {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(playlists) = serde_json::from_reader::<_, Vector<Playlist>>(file) {
-                    log::info!("WebApi: get_playlists() returning cached data");
-                    return Ok(Cached::new(playlists, cached_at).data);
-                }
-            }
-        }
-
-        log::info!("WebApi: get_playlists() making network request");
-        let request = &RequestBuilder::new("v1/me/playlists", Method::Get, None);
-        let playlists: Vector<Playlist> = self.load_all_pages(request)?;
-
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&playlists) {
-            self.cache.set("playlists", "user", &json);
-        }
-
-        log::info!("WebApi: get_playlists() completed, got {} playlists", playlists.len());
-        Ok(playlists)
+        let result = self.load_cached_with("playlists", "user", |webapi, _| {
+            log::info!("WebApi: get_playlists() making network request");
+            let request = &RequestBuilder::new("v1/me/playlists", Method::Get, None);
+            webapi.load_all_pages(request)
+        })?;
+        log::info!(
+            "WebApi: get_playlists() completed, got {} playlists",
+            result.data.len()
+        );
+        Ok(result.data)
     }
 
     pub fn follow_playlist(&self, id: &str) -> Result<(), Error> {
@@ -1406,7 +1517,51 @@ impl WebApi {
     // https://developer.spotify.com/documentation/web-api/reference/get-playlists-tracks
     pub fn get_playlist_tracks(&self, id: &str) -> Result<Vector<Arc<Track>>, Error> {
         log::info!("WebApi: get_playlist_tracks({}) called", id);
-        
+
+        if let Some(entry) = self.cache.get_entry("playlist-tracks", id) {
+            if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(entry.file) {
+                log::info!("WebApi: get_playlist_tracks({}) returning cached data", id);
+                if entry.stale {
+                    let snapshot_id = entry.validator;
+                    self.refresh_in_background("playlist-tracks", id, move |webapi, _, id| {
+                        webapi.revalidate_playlist_tracks(id, snapshot_id.as_deref())
+                    });
+                }
+                return Ok(tracks);
+            }
+        }
+
+        let snapshot_id = self.get_playlist_snapshot_id(id)?;
+        let tracks = self.fetch_playlist_tracks(id)?;
+        self.store("playlist-tracks", id, &tracks, Some(&snapshot_id));
+        Ok(tracks)
+    }
+
+    /// Reload the cached tracks of a playlist, unless its snapshot ID shows
+    /// that the playlist did not change.
+    fn revalidate_playlist_tracks(&self, id: &str, snapshot_id: Option<&str>) -> Result<(), Error> {
+        let current = self.get_playlist_snapshot_id(id)?;
+        if snapshot_id == Some(current.as_str()) {
+            self.cache.touch("playlist-tracks", id);
+        } else {
+            let tracks = self.fetch_playlist_tracks(id)?;
+            self.store("playlist-tracks", id, &tracks, Some(&current));
+        }
+        Ok(())
+    }
+
+    fn get_playlist_snapshot_id(&self, id: &str) -> Result<String, Error> {
+        #[derive(Deserialize)]
+        struct Snapshot {
+            snapshot_id: String,
+        }
+        let request = &RequestBuilder::new(format!("v1/playlists/{}", id), Method::Get, None)
+            .query("fields", "snapshot_id");
+        let result: Snapshot = self.load(request)?;
+        Ok(result.snapshot_id)
+    }
+
+    fn fetch_playlist_tracks(&self, id: &str) -> Result<Vector<Arc<Track>>, Error> {
         #[derive(Clone, Deserialize)]
         struct PlaylistItem {
             track: OptionalTrack,
@@ -1427,16 +1582,6 @@ impl WebApi {
                 .query("marker", "from_token")
                 .query("additional_types", "track");
 
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("playlist-tracks", id) {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(file) {
-                    log::info!("WebApi: get_playlist_tracks({}) returning cached data", id);
-                    return Ok(Cached::new(tracks, cached_at).data);
-                }
-            }
-        }
-
         log::info!("WebApi: get_playlist_tracks({}) making network request", id);
         let result: Vector<PlaylistItem> = self.load_all_pages(request)?;
 
@@ -1455,11 +1600,6 @@ impl WebApi {
             })
             .collect();
 
-        // Cache the result
-        if let Ok(json) = serde_json::to_vec(&tracks) {
-            self.cache.set("playlist-tracks", id, &json);
-        }
-
         log::info!("WebApi: get_playlist_tracks({}) completed, got {} tracks", id, tracks.len());
         Ok(tracks)
     }
@@ -1509,16 +1649,7 @@ impl WebApi {
     }
 
     pub fn is_track_in_playlist(&self, playlist_id: &str, track_id: &str) -> Result<bool, Error> {
-        // Try to get from cache first
-        if let Some(file) = self.cache.get("playlist-tracks", playlist_id) {
-            if let Ok(cached_at) = file.metadata()?.modified() {
-                if let Ok(tracks) = serde_json::from_reader::<_, Vector<Arc<Track>>>(file) {
-                    return Ok(tracks.iter().any(|track| track.id.0.to_base62() == track_id));
-                }
-            }
-        }
-
-        // If not in cache, fetch the playlist tracks
+        // Served from the cache if available.
         let tracks = self.get_playlist_tracks(playlist_id)?;
         Ok(tracks.iter().any(|track| track.id.0.to_base62() == track_id))
     }
@@ -0,0 +1,128 @@
+use druid::{widget::prelude::*, Point, Rect};
+use psst_core::audio::analyzer::{Analysis, BANDS};
+
+use crate::{cmd, ui::theme};
+
+/// Lowest level that still shows, in dBFS.
+const RANGE_DB: f64 = -60.0;
+/// How fast the bars and the meter fall, in dB per second.
+const FALL_DB_PER_SEC: f64 = 40.0;
+const MIN_BAR_WIDTH: f64 = 2.0;
+const BAR_GAP: f64 = 1.0;
+/// Level above which the peak of the meter shows as clipping.
+const CLIP_DB: f64 = -0.1;
+
+/// Spectrum bars with a level meter on the side, drawn from the analyses of
+/// the played signal sent with `cmd::AUDIO_ANALYZED`.  Rises right away and
+/// falls off smoothly.
+pub struct Visualizer {
+    /// Levels the bars are falling towards, in dBFS.
+    target: Analysis,
+    /// Displayed levels of the bands, in dBFS.
+    bands: Vec<f64>,
+    rms_db: f64,
+    peak_db: f64,
+}
+
+impl Visualizer {
+    pub fn new() -> Self {
+        Self {
+            target: Analysis {
+                bands: vec![RANGE_DB as f32; BANDS],
+                rms_db: RANGE_DB as f32,
+                peak_db: RANGE_DB as f32,
+            },
+            bands: vec![RANGE_DB; BANDS],
+            rms_db: RANGE_DB,
+            peak_db: RANGE_DB,
+        }
+    }
+
+    /// Rise to the targets, or fall towards them by `fall` dB.  Returns true if
+    /// any of the levels is still falling.
+    fn approach(&mut self, fall: f64) -> bool {
+        fn step(level: &mut f64, target: f32, fall: f64) -> bool {
+            let target = (target as f64).max(RANGE_DB);
+            *level = target.max(*level - fall);
+            *level > target
+        }
+        let mut falling = step(&mut self.rms_db, self.target.rms_db, fall);
+        falling |= step(&mut self.peak_db, self.target.peak_db, fall);
+        for (level, &target) in self.bands.iter_mut().zip(&self.target.bands) {
+            falling |= step(level, target, fall);
+        }
+        falling
+    }
+
+    fn height(level: f64) -> f64 {
+        (1.0 - level / RANGE_DB).clamp(0.0, 1.0)
+    }
+}
+
+impl<T: Data> Widget<T> for Visualizer {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut T, _env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(cmd::AUDIO_ANALYZED) => {
+                self.target = cmd.get_unchecked(cmd::AUDIO_ANALYZED).clone();
+                if self.approach(0.0) {
+                    ctx.request_anim_frame();
+                }
+                ctx.request_paint();
+            }
+            Event::AnimFrame(interval) => {
+                let fall = FALL_DB_PER_SEC * (*interval as f64 / 1e9);
+                if self.approach(fall) {
+                    ctx.request_anim_frame();
+                }
+                ctx.request_paint();
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(&mut self, _ctx: &mut LifeCycleCtx, _event: &LifeCycle, _data: &T, _env: &Env) {}
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, _old_data: &T, _data: &T, _env: &Env) {}
+
+    fn layout(&mut self, _ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &T, _env: &Env) -> Size {
+        bc.constrain(Size::new(theme::grid(12.0), theme::grid(4.0)))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &T, env: &Env) {
+        let size = ctx.size();
+        let meter_width = theme::grid(0.5);
+        let spectrum_width = size.width - meter_width - theme::grid(0.5);
+
+        // Fit as many bars as there is room for, each showing the loudest of
+        // the bands it covers.
+        let bars =
+            (((spectrum_width + BAR_GAP) / (MIN_BAR_WIDTH + BAR_GAP)) as usize).clamp(1, BANDS);
+        let bar_width = (spectrum_width + BAR_GAP) / bars as f64 - BAR_GAP;
+        let bar_color = env.get(theme::GREY_400);
+        for bar in 0..bars {
+            let level = self.bands[bar * BANDS / bars..(bar + 1) * BANDS / bars]
+                .iter()
+                .copied()
+                .fold(RANGE_DB, f64::max);
+            let height = Self::height(level) * size.height;
+            let x = bar as f64 * (bar_width + BAR_GAP);
+            let rect = Rect::from_origin_size(
+                Point::new(x, size.height - height),
+                Size::new(bar_width, height),
+            );
+            ctx.fill(rect, &bar_color);
+        }
+
+        let x = size.width - meter_width;
+        let rms = Self::height(self.rms_db) * size.height;
+        let rect = Rect::new(x, size.height - rms, size.width, size.height);
+        ctx.fill(rect, &env.get(theme::BLUE_100));
+        let peak = size.height - Self::height(self.peak_db) * size.height;
+        let peak_color = if self.peak_db > CLIP_DB {
+            env.get(theme::RED)
+        } else {
+            env.get(theme::GREY_100)
+        };
+        ctx.fill(Rect::new(x, peak, size.width, peak + 1.0), &peak_color);
+    }
+}
@@ -6,5 +6,6 @@ pb-rs \
 	"proto/authentication.proto" \
 	"proto/keyexchange.proto" \
 	"proto/mercury.proto" \
-	"proto/metadata.proto"
+	"proto/metadata.proto" \
+	"proto/spirc.proto"
 rm src/mod.rs
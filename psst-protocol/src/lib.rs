@@ -2,3 +2,4 @@ pub mod authentication;
 pub mod keyexchange;
 pub mod mercury;
 pub mod metadata;
+pub mod spirc;